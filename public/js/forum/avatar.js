@@ -0,0 +1,11 @@
+// Uploads the chosen avatar. The image is sent as the body of the request, and the server redirects back to the profile page.
+document.getElementById('avatar-upload').addEventListener('click', async () => {
+  const file = document.getElementById('avatar-file').files[0];
+  if (file === undefined) return;
+  const response = await fetch('/auth/account/profile/avatar', {
+    method: 'POST',
+    headers: { 'Content-Type': file.type || 'application/octet-stream' },
+    body: file,
+  });
+  location.href = response.url;
+});
@@ -0,0 +1,13 @@
+// Displays the error message for the `err` query parameter.
+// Pages define their messages in the PAGE_ERRORS object before this script is loaded.
+// A {wait} in a message is replaced with the `wait` query parameter.
+window.addEventListener('load', () => {
+  const url = new URL(location.href);
+  const err = url.searchParams.get('err');
+  const errorDoc = document.getElementById('page-error');
+  if (err == null || errorDoc == null || window.PAGE_ERRORS === undefined) return;
+  if (PAGE_ERRORS[err] === undefined) return;
+  const wait = url.searchParams.get('wait') || 'a few';
+  errorDoc.getElementsByTagName('span')[0].textContent = PAGE_ERRORS[err].replace('{wait}', wait);
+  errorDoc.style.display = 'block';
+});
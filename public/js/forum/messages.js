@@ -0,0 +1,22 @@
+// Shows the number of conversations with unread messages in the navigation bar and the title of the page.
+const MESSAGE_POLL_INTERVAL = 60000;
+const pageTitle = document.title;
+
+async function updateUnreadMessages() {
+  const badge = document.getElementById('unread-messages');
+  if (badge == null) return;
+  try {
+    const response = await fetch('/messages/unread', { credentials: 'same-origin' });
+    const { unread } = await response.json();
+    badge.textContent = unread;
+    badge.style.display = unread > 0 ? 'inline-block' : 'none';
+    document.title = unread > 0 ? `(${unread}) ${pageTitle}` : pageTitle;
+  } catch (err) {
+    // Try again on the next poll.
+  }
+}
+
+window.addEventListener('load', () => {
+  updateUnreadMessages();
+  setInterval(updateUnreadMessages, MESSAGE_POLL_INTERVAL);
+});
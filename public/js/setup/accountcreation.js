@@ -0,0 +1,25 @@
+window.addEventListener('load', () => {
+  const url = new URL(location.href);
+  const err = url.searchParams.get('err');
+  const errorDoc = document.getElementById('account-error');
+  if (err == null) return;
+  const messages = {
+    '1': 'A username is required.',
+    '2': 'A password is required.',
+    '3': 'The passwords do not match.',
+    '4': 'An email is required.',
+    '5': 'The password is too short. Hover over the i icon for the password requirements.',
+    '6': 'The password is too long. Hover over the i icon for the password requirements.',
+    '7': 'The password does not contain enough uppercase letters.',
+    '8': 'The password does not contain enough lowercase letters.',
+    '9': 'The password does not contain enough digits.',
+    '10': 'The password does not contain enough special characters.',
+    '11': 'The password is too common. Please choose a different password.',
+    '12': 'The password is too easy to guess. Try a longer password or one made of several unrelated words.',
+    '13': 'The username is already taken by an account in the existing database.',
+    '14': 'The accounts of the existing database could not be read. More information on the error is specified in the console.',
+  };
+  if (messages[err] === undefined) return;
+  errorDoc.getElementsByTagName('span')[0].textContent = messages[err];
+  errorDoc.style.display = 'block';
+});
@@ -0,0 +1,16 @@
+window.addEventListener('load', () => {
+  const url = new URL(location.href);
+  const err = url.searchParams.get('err');
+  const errorDoc = document.getElementById('migrate-error');
+  if (err == null) return;
+  switch (err) {
+    case '1':
+      errorDoc.getElementsByTagName('span')[0].textContent = 'The existing database could not be read. More information on the error is specified in the console.';
+      errorDoc.style.display = 'block';
+      break;
+    case '2':
+      errorDoc.getElementsByTagName('span')[0].textContent = 'The existing database was created by a newer version of ForumRS and cannot be migrated.';
+      errorDoc.style.display = 'block';
+      break;
+  }
+});
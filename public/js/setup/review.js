@@ -0,0 +1,24 @@
+window.addEventListener('load', () => {
+  const url = new URL(location.href);
+  const err = url.searchParams.get('err');
+  const errorDoc = document.getElementById('review-error');
+  if (err == null) return;
+  switch (err) {
+    case '1':
+      errorDoc.getElementsByTagName('span')[0].textContent = 'The database could not be created. More information on the error is specified in the console.';
+      errorDoc.style.display = 'block';
+      break;
+    case '2':
+      errorDoc.getElementsByTagName('span')[0].textContent = 'The existing database was created by a newer version of ForumRS and cannot be migrated.';
+      errorDoc.style.display = 'block';
+      break;
+    case '3':
+      errorDoc.getElementsByTagName('span')[0].textContent = 'The existing database does not have an admin account. Please create one before finishing the setup.';
+      errorDoc.style.display = 'block';
+      break;
+    case '4':
+      errorDoc.getElementsByTagName('span')[0].textContent = 'The existing database could not be migrated. More information on the error is specified in the console.';
+      errorDoc.style.display = 'block';
+      break;
+  }
+});
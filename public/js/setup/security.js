@@ -1,12 +1,21 @@
-document.getElementById("useSSL").addEventListener('change', evt => {
-  document.getElementById("privateKey").disabled = !evt.target.checked;
-  document.getElementById("publicKey").disabled = !evt.target.checked;
-});
+/**
+ * Enable the key inputs only when the feature they belong to is turned on.
+ */
+function updateKeyInputs() {
+  const useSSL = document.getElementById("useSSL").checked;
+  document.getElementById("privateKey").disabled = !useSSL;
+  document.getElementById("publicKey").disabled = !useSSL;
 
-document.getElementById("useCaptch").addEventListener('change', evt => {
-  document.getElementById("siteKey").disabled = !evt.target.checked;
-  document.getElementById("secretKey").disabled = !evt.target.checked;
-});
+  const useCaptch = document.getElementById("useCaptch").checked;
+  document.getElementById("siteKey").disabled = !useCaptch;
+  document.getElementById("secretKey").disabled = !useCaptch;
+}
+
+document.getElementById("useSSL").addEventListener('change', updateKeyInputs);
+document.getElementById("useCaptch").addEventListener('change', updateKeyInputs);
+
+// The saved settings may have a feature turned off.
+updateKeyInputs();
 
 window.addEventListener('load', () => {
   const url = new URL(location.href);
@@ -65,6 +65,19 @@ function swapDBSettings(desired) {
 }
 
 
+// Show the storage format that was saved when the stage is visited again.
+switch (document.getElementById("dbType").dataset.selected) {
+  case "MySQL":
+    swapDBSettings(mysqlConfig);
+    break;
+  case "PostgreSQL":
+    swapDBSettings(postgreConfig);
+    break;
+  default:
+    swapDBSettings(sqliteConfig);
+    break;
+}
+
 window.addEventListener('load', () => {
   const url = new URL(location.href);
   const err = url.searchParams.get('err');
@@ -0,0 +1,368 @@
+use std::fmt;
+use std::fmt::Formatter;
+
+use actix_web::{HttpRequest, HttpResponse, ResponseError, web};
+use actix_web::http::StatusCode;
+use schemars::JsonSchema;
+use serde::Serialize;
+
+use crate::auth::api_token;
+use crate::auth::api_token::Scope;
+use crate::auth::session;
+use crate::schema::database::Database;
+use crate::schema::tables::{ApiTokens, User, Users};
+use crate::settings::SettingsManager;
+
+pub mod openapi;
+pub mod v1;
+
+/// The default amount of items in a page.
+pub const DEFAULT_PAGE_LIMIT: i64 = 25;
+/// The maximum amount of items in a page.
+pub const MAX_PAGE_LIMIT: i64 = 100;
+
+/// The body of an API error.
+#[derive(Serialize, JsonSchema, Debug)]
+pub struct ApiErrorBody {
+    /// A machine readable error code. (ex: not_found)
+    pub code: String,
+    /// A human readable description of the error.
+    pub message: String,
+}
+
+/// The error object returned by every API endpoint when a request fails.
+#[derive(Serialize, JsonSchema, Debug)]
+pub struct ApiError {
+    pub error: ApiErrorBody,
+    #[serde(skip)]
+    status: u16,
+}
+
+impl ApiError {
+    pub fn new(status: StatusCode, code: &str, message: &str) -> ApiError {
+        ApiError {
+            error: ApiErrorBody {
+                code: code.to_string(),
+                message: message.to_string(),
+            },
+            status: status.as_u16(),
+        }
+    }
+
+    pub fn bad_request(message: &str) -> ApiError {
+        ApiError::new(StatusCode::BAD_REQUEST, "bad_request", message)
+    }
+
+    pub fn unauthorized() -> ApiError {
+        ApiError::new(StatusCode::UNAUTHORIZED, "unauthorized", "You must be logged in to do this.")
+    }
+
+    pub fn invalid_token() -> ApiError {
+        ApiError::new(StatusCode::UNAUTHORIZED, "invalid_token", "The API token is invalid or has been revoked.")
+    }
+
+    pub fn insufficient_scope(scope: Scope) -> ApiError {
+        ApiError::new(StatusCode::FORBIDDEN, "insufficient_scope", &format!("This requires the {} scope.", scope))
+    }
+
+    pub fn forbidden(message: &str) -> ApiError {
+        ApiError::new(StatusCode::FORBIDDEN, "forbidden", message)
+    }
+
+    pub fn not_found(message: &str) -> ApiError {
+        ApiError::new(StatusCode::NOT_FOUND, "not_found", message)
+    }
+
+    pub fn internal() -> ApiError {
+        ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, "internal_error", "An internal error has occurred.")
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.error.code, self.error.message)
+    }
+}
+
+impl ResponseError for ApiError {
+    fn status_code(&self) -> StatusCode {
+        StatusCode::from_u16(self.status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR)
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let mut builder = HttpResponse::build(self.status_code());
+        if self.status_code() == StatusCode::UNAUTHORIZED {
+            builder.header("WWW-Authenticate", "Bearer");
+        }
+        builder.json(self)
+    }
+}
+
+impl From<sqlx::Error> for ApiError {
+    fn from(err: sqlx::Error) -> Self {
+        println!("[ERROR] A database error occurred in the API: {:?}", err);
+        ApiError::internal()
+    }
+}
+
+/// The result of an API endpoint.
+pub type ApiResult = Result<HttpResponse, ApiError>;
+
+/// A page of items. Pass `next_cursor` as the `cursor` query parameter to get the next page.
+#[derive(Serialize, JsonSchema)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    /// The cursor of the next page. This is null on the last page.
+    pub next_cursor: Option<String>,
+}
+
+impl<T> Page<T> {
+    /// Build a page from the items that were fetched.
+    /// The cursor of the last item is used as the next cursor if the page is full.
+    pub fn new(items: Vec<T>, limit: i64, cursor: impl Fn(&T) -> String) -> Page<T> {
+        let next_cursor = if items.len() as i64 >= limit { items.last().map(|item| cursor(item)) } else { None };
+        Page {
+            items,
+            next_cursor,
+        }
+    }
+
+    /// Convert the items of the page.
+    pub fn map<U>(self, f: impl FnMut(T) -> U) -> Page<U> {
+        Page {
+            items: self.items.into_iter().map(f).collect(),
+            next_cursor: self.next_cursor,
+        }
+    }
+}
+
+/// Read a pagination cursor. Cursors are opaque to clients but are the id of the last item.
+pub fn parse_cursor(cursor: &Option<String>) -> Result<Option<i32>, ApiError> {
+    match cursor {
+        Some(cursor) => cursor.parse::<i32>().map(Some).map_err(|_| ApiError::bad_request("Invalid cursor.")),
+        None => Ok(None)
+    }
+}
+
+/// Clamp the page limit requested by a client.
+pub fn page_limit(limit: Option<i64>) -> i64 {
+    limit.unwrap_or(DEFAULT_PAGE_LIMIT).max(1).min(MAX_PAGE_LIMIT)
+}
+
+/// Open a connection to the database for an API request.
+pub async fn open_database() -> Result<Database, ApiError> {
+    Database::connect(&SettingsManager::get_settings()).await.map_err(ApiError::from)
+}
+
+/// The user making an API request and the scopes the request was granted.
+pub struct ApiCaller {
+    pub user: User,
+    pub scopes: Vec<Scope>,
+}
+
+impl ApiCaller {
+    /// Check if the request is allowed to do something that requires a scope.
+    pub fn has_scope(&self, scope: Scope) -> bool {
+        api_token::allows(&self.scopes, scope, &self.user)
+    }
+}
+
+/**
+   Get the user making an API request.
+
+   Requests are authenticated with an `Authorization: Bearer` API token, or with the session cookie of
+   a logged in user. Session requests are granted every scope the role of the user allows.
+
+   ## Params
+   required - The scope the endpoint needs.
+*/
+pub async fn authenticate(db: &mut Database, req: &HttpRequest, required: Scope) -> Result<ApiCaller, ApiError> {
+    let caller = match api_token::bearer_token(req) {
+        Some(token) => {
+            let token = ApiTokens::get_by_hash(db, api_token::hash_token(token.as_str()).as_str()).await?
+                .ok_or_else(ApiError::invalid_token)?;
+            let user = Users::get_by_uuid(db, token.user_uuid).await?
+                .filter(|user| !user.is_banned)
+                .ok_or_else(ApiError::invalid_token)?;
+            ApiTokens::touch(db, token.uuid).await?;
+            ApiCaller {
+                user,
+                scopes: api_token::parse_scopes(token.scopes.as_str()).unwrap_or_default(),
+            }
+        }
+        None => {
+            let user = session::current_user(db, req).await.ok_or_else(ApiError::unauthorized)?;
+            let scopes = api_token::scopes_for(&user);
+            ApiCaller { user, scopes }
+        }
+    };
+
+    if !caller.has_scope(required) {
+        return Err(ApiError::insufficient_scope(required));
+    }
+    Ok(caller)
+}
+
+/**
+   Get the user making a request to an endpoint that guests can also use.
+
+   Requests without a token or session cookie are made as a guest. An invalid token is still an error so
+   clients notice a revoked token, but an expired session cookie is treated as a guest like the forum pages do.
+*/
+pub async fn viewer(db: &mut Database, req: &HttpRequest) -> Result<Option<User>, ApiError> {
+    let has_token = api_token::bearer_token(req).is_some();
+    if !has_token && session::session_id(req).is_none() {
+        return Ok(None);
+    }
+
+    match authenticate(db, req, Scope::Read).await {
+        Ok(caller) => Ok(Some(caller.user)),
+        Err(err) if has_token => Err(err),
+        Err(_) => Ok(None)
+    }
+}
+
+/// Build the `/api/v1` scope with every endpoint of the API.
+pub fn v1_scope() -> actix_web::Scope {
+    web::scope("/api/v1")
+        .app_data(web::JsonConfig::default().error_handler(|err, _| ApiError::bad_request(&err.to_string()).into()))
+        .app_data(web::QueryConfig::default().error_handler(|err, _| ApiError::bad_request(&err.to_string()).into()))
+        .app_data(web::PathConfig::default().error_handler(|err, _| ApiError::bad_request(&err.to_string()).into()))
+        .app_data(web::PayloadConfig::new(SettingsManager::get_settings().attachment_settings.max_size))
+        .service(v1::forums)
+        .service(v1::forum_threads)
+        .service(v1::create_thread)
+        .service(v1::thread)
+        .service(v1::thread_posts)
+        .service(v1::create_post)
+        .service(v1::edit_post)
+        .service(v1::user)
+        .service(v1::search_threads)
+        .service(v1::search_posts)
+        .service(v1::attach_to_thread)
+        .service(v1::attach_to_post)
+        .service(v1::thread_attachments)
+        .service(v1::delete_attachment)
+        .service(openapi::openapi_document)
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::cookie::Cookie;
+    use actix_web::test::TestRequest;
+    use uuid::Uuid;
+
+    use crate::auth::session::SESSION_COOKIE;
+    use crate::schema::tables::{Sessions, Table};
+    use crate::settings::SqlSettings;
+
+    use super::*;
+
+    /// Open an empty database with the tables used to authenticate.
+    async fn open_test_database() -> Database {
+        let mut db = Database::new_sqlite(&SqlSettings { file_location: ":memory:".to_string() }).await.unwrap();
+        Users::create(&mut db).await.unwrap();
+        Sessions::create(&mut db).await.unwrap();
+        ApiTokens::create(&mut db).await.unwrap();
+        db
+    }
+
+    /// Insert a user with a token that has the scopes, and return the user and the token.
+    async fn user_with_token(db: &mut Database, username: &str, is_banned: bool, scopes: &str) -> (Uuid, String) {
+        let user_uuid = Uuid::new_v4();
+        Users::insert(db, user_uuid, username.to_string(), format!("{}@example.com", username), String::new(), is_banned, false).await;
+        let token = api_token::generate_token();
+        ApiTokens::insert(db, Uuid::new_v4(), user_uuid, "Test".to_string(), api_token::hash_token(token.as_str()), scopes.to_string(), None).await.unwrap();
+        (user_uuid, token)
+    }
+
+    fn bearer(token: &str) -> HttpRequest {
+        TestRequest::default().header("Authorization", format!("Bearer {}", token)).to_http_request()
+    }
+
+    #[test]
+    fn full_pages_have_a_cursor() {
+        let page = Page::new(vec![3, 5, 8], 3, |id| id.to_string());
+        assert_eq!(page.next_cursor, Some("8".to_string()));
+
+        let page = Page::new(vec![3, 5], 3, |id| id.to_string());
+        assert_eq!(page.next_cursor, None);
+
+        let page = Page::new(Vec::<i32>::new(), 3, |id| id.to_string());
+        assert_eq!(page.next_cursor, None);
+    }
+
+    #[test]
+    fn mapping_a_page_keeps_the_cursor() {
+        let page = Page::new(vec![1, 2], 2, |id| id.to_string()).map(|id| id * 10);
+        assert_eq!(page.items, vec![10, 20]);
+        assert_eq!(page.next_cursor, Some("2".to_string()));
+    }
+
+    #[test]
+    fn cursors_are_parsed_and_limits_clamped() {
+        assert_eq!(parse_cursor(&None).unwrap(), None);
+        assert_eq!(parse_cursor(&Some("42".to_string())).unwrap(), Some(42));
+        assert_eq!(parse_cursor(&Some("abc".to_string())).err().unwrap().error.code, "bad_request");
+
+        assert_eq!(page_limit(None), DEFAULT_PAGE_LIMIT);
+        assert_eq!(page_limit(Some(0)), 1);
+        assert_eq!(page_limit(Some(1000)), MAX_PAGE_LIMIT);
+    }
+
+    #[actix_rt::test]
+    async fn tokens_are_limited_to_their_scopes() {
+        let mut db = open_test_database().await;
+        let (user_uuid, token) = user_with_token(&mut db, "reader", false, "read").await;
+
+        let caller = authenticate(&mut db, &bearer(token.as_str()), Scope::Read).await.unwrap();
+        assert_eq!(caller.user.uuid, user_uuid);
+        assert_eq!(caller.scopes, vec![Scope::Read]);
+        assert!(ApiTokens::get_by_user(&mut db, user_uuid).await.unwrap()[0].last_used > 0);
+
+        let err = authenticate(&mut db, &bearer(token.as_str()), Scope::Post).await.err().unwrap();
+        assert_eq!(err.status_code(), StatusCode::FORBIDDEN);
+        assert_eq!(err.error.code, "insufficient_scope");
+
+        db.close().await;
+    }
+
+    #[actix_rt::test]
+    async fn unknown_and_banned_tokens_are_invalid() {
+        let mut db = open_test_database().await;
+        let (_, banned) = user_with_token(&mut db, "banned", true, "read post").await;
+
+        for token in &[banned, api_token::generate_token()] {
+            let err = authenticate(&mut db, &bearer(token.as_str()), Scope::Read).await.err().unwrap();
+            assert_eq!(err.status_code(), StatusCode::UNAUTHORIZED);
+            assert_eq!(err.error.code, "invalid_token");
+            // Clients can tell a bad token apart from a missing one, so a viewer with a bad token is an error too.
+            assert!(viewer(&mut db, &bearer(token.as_str())).await.is_err());
+        }
+
+        db.close().await;
+    }
+
+    #[actix_rt::test]
+    async fn sessions_get_the_scopes_of_the_role() {
+        let mut db = open_test_database().await;
+        let (user_uuid, _) = user_with_token(&mut db, "member", false, "read").await;
+        let session_uuid = Uuid::new_v4();
+        Sessions::insert(&mut db, session_uuid, user_uuid).await.unwrap();
+        let req = TestRequest::default().cookie(Cookie::new(SESSION_COOKIE, session_uuid.to_string())).to_http_request();
+
+        let caller = authenticate(&mut db, &req, Scope::Post).await.unwrap();
+        assert_eq!(caller.scopes, vec![Scope::Read, Scope::Post]);
+        assert_eq!(authenticate(&mut db, &req, Scope::Moderate).await.err().unwrap().error.code, "insufficient_scope");
+
+        let guest = TestRequest::default().to_http_request();
+        assert_eq!(authenticate(&mut db, &guest, Scope::Read).await.err().unwrap().error.code, "unauthorized");
+        assert!(viewer(&mut db, &guest).await.unwrap().is_none());
+
+        // An expired session cookie is treated as a guest.
+        let expired = TestRequest::default().cookie(Cookie::new(SESSION_COOKIE, Uuid::new_v4().to_string())).to_http_request();
+        assert!(viewer(&mut db, &expired).await.unwrap().is_none());
+
+        db.close().await;
+    }
+}
@@ -0,0 +1,197 @@
+use actix_web::{get, HttpResponse, Responder};
+use schemars::gen::{SchemaGenerator, SchemaSettings};
+use schemars::JsonSchema;
+use serde_json::{json, Map, Value};
+
+use crate::api::{ApiError, Page};
+use crate::api::v1::{ApiAttachment, ApiForum, ApiPost, ApiThread, ApiUser, CreateThreadRequest, PostContentRequest};
+use crate::settings::SettingsManager;
+
+/// A parameter of an operation.
+struct Param {
+    name: &'static str,
+    location: &'static str,
+    description: &'static str,
+    required: bool,
+    schema: Value,
+}
+
+impl Param {
+    fn path(name: &'static str, description: &'static str, schema: Value) -> Param {
+        Param { name, location: "path", description, required: true, schema }
+    }
+
+    fn query(name: &'static str, description: &'static str, required: bool, schema: Value) -> Param {
+        Param { name, location: "query", description, required, schema }
+    }
+}
+
+/// Build the schema reference of a type, adding its definition to the generator.
+fn schema_of<T: JsonSchema>(generator: &mut SchemaGenerator) -> Value {
+    serde_json::to_value(generator.subschema_for::<T>()).unwrap()
+}
+
+/// Build an operation object of the document.
+fn operation(summary: &str, params: Vec<Param>, body: Option<Value>, status: &str, response: Value, error: &Value, authenticated: bool) -> Value {
+    let mut operation = Map::new();
+    operation.insert("summary".to_string(), json!(summary));
+    operation.insert("parameters".to_string(), Value::Array(params.into_iter().map(|param| json!({
+        "name": param.name,
+        "in": param.location,
+        "description": param.description,
+        "required": param.required,
+        "schema": param.schema,
+    })).collect()));
+
+    if let Some(body) = body {
+        operation.insert("requestBody".to_string(), json!({
+            "required": true,
+            "content": {"application/json": {"schema": body}}
+        }));
+    }
+
+    let mut responses = Map::new();
+    responses.insert(status.to_string(), json!({"description": "Success", "content": {"application/json": {"schema": response}}}));
+    responses.insert("default".to_string(), json!({"description": "Error", "content": {"application/json": {"schema": error}}}));
+    operation.insert("responses".to_string(), Value::Object(responses));
+
+    if authenticated {
+        operation.insert("security".to_string(), json!([{"session": []}, {"bearer": []}]));
+    }
+
+    Value::Object(operation)
+}
+
+/// Build an operation that uploads a file. The body of the request is the content of the file.
+fn upload_operation(summary: &str, params: Vec<Param>, response: Value, error: &Value) -> Value {
+    let mut operation = operation(summary, params, None, "201", response, error, true);
+    operation["requestBody"] = json!({
+        "required": true,
+        "content": {"*/*": {"schema": {"type": "string", "format": "binary"}}}
+    });
+    operation
+}
+
+/**
+   Build the OpenAPI document of the v1 API.
+
+   The schemas of the request and response bodies are generated from the types the handlers use, so the
+   document stays in sync with the handlers.
+*/
+pub fn build_document() -> Value {
+    let mut generator = SchemaSettings::openapi3().into_generator();
+
+    let error = schema_of::<ApiError>(&mut generator);
+    let forums = schema_of::<Vec<ApiForum>>(&mut generator);
+    let thread = schema_of::<ApiThread>(&mut generator);
+    let thread_page = schema_of::<Page<ApiThread>>(&mut generator);
+    let post = schema_of::<ApiPost>(&mut generator);
+    let post_page = schema_of::<Page<ApiPost>>(&mut generator);
+    let user = schema_of::<ApiUser>(&mut generator);
+    let attachment = schema_of::<ApiAttachment>(&mut generator);
+    let attachments = schema_of::<Vec<ApiAttachment>>(&mut generator);
+    let create_thread = schema_of::<CreateThreadRequest>(&mut generator);
+    let post_content = schema_of::<PostContentRequest>(&mut generator);
+    let uuid = schema_of::<uuid::Uuid>(&mut generator);
+    let string = schema_of::<String>(&mut generator);
+    let limit = schema_of::<Option<i64>>(&mut generator);
+
+    let page_params = || vec![
+        Param::query("cursor", "The next_cursor of the previous page.", false, string.clone()),
+        Param::query("limit", "The maximum amount of items to return. (1-100, default 25)", false, limit.clone()),
+    ];
+    let search_params = || {
+        let mut params = vec![Param::query("q", "The text to search for.", true, string.clone())];
+        params.extend(page_params());
+        params
+    };
+    let with_page_params = |mut params: Vec<Param>| {
+        params.extend(page_params());
+        params
+    };
+    let upload_params = |description: &'static str| vec![
+        Param::path("uuid", description, uuid.clone()),
+        Param::query("name", "The name of the file.", true, string.clone()),
+    ];
+    let mut delete_attachment = operation("Delete an attachment.", vec![Param::path("uuid", "The uuid of the attachment.", uuid.clone())],
+                                          None, "204", json!({}), &error, true);
+    delete_attachment["responses"]["204"] = json!({"description": "Success"});
+
+    let paths = json!({
+        "/forums": {
+            "get": operation("List every forum.", vec![], None, "200", forums, &error, false)
+        },
+        "/forums/{uuid}/threads": {
+            "get": operation("List the threads in a forum, newest first.",
+                             with_page_params(vec![Param::path("uuid", "The uuid of the forum.", uuid.clone())]),
+                             None, "200", thread_page.clone(), &error, false),
+            "post": operation("Create a thread in a forum.",
+                              vec![Param::path("uuid", "The uuid of the forum.", uuid.clone())],
+                              Some(create_thread), "201", thread.clone(), &error, true)
+        },
+        "/threads/{uuid}": {
+            "get": operation("Get a thread.", vec![Param::path("uuid", "The uuid of the thread.", uuid.clone())],
+                             None, "200", thread, &error, false)
+        },
+        "/threads/{uuid}/posts": {
+            "get": operation("List the posts in a thread, oldest first.",
+                             with_page_params(vec![Param::path("uuid", "The uuid of the thread.", uuid.clone())]),
+                             None, "200", post_page.clone(), &error, false),
+            "post": operation("Reply to a thread.", vec![Param::path("uuid", "The uuid of the thread.", uuid.clone())],
+                              Some(post_content.clone()), "201", post.clone(), &error, true)
+        },
+        "/threads/{uuid}/attachments": {
+            "get": operation("List the files attached to a thread and its posts, oldest first.",
+                             vec![Param::path("uuid", "The uuid of the thread.", uuid.clone())],
+                             None, "200", attachments, &error, false),
+            "post": upload_operation("Attach a file to a thread.", upload_params("The uuid of the thread."),
+                                     attachment.clone(), &error)
+        },
+        "/posts/{uuid}": {
+            "patch": operation("Edit a post.", vec![Param::path("uuid", "The uuid of the post.", uuid.clone())],
+                               Some(post_content), "200", post, &error, true)
+        },
+        "/posts/{uuid}/attachments": {
+            "post": upload_operation("Attach a file to a post.", upload_params("The uuid of the post."), attachment, &error)
+        },
+        "/attachments/{uuid}": {
+            "delete": delete_attachment
+        },
+        "/users/{username}": {
+            "get": operation("Get the public profile of a user.",
+                             vec![Param::path("username", "The username of the user.", string.clone())],
+                             None, "200", user, &error, false)
+        },
+        "/search/threads": {
+            "get": operation("Search the names and content of threads.", search_params(), None, "200", thread_page, &error, false)
+        },
+        "/search/posts": {
+            "get": operation("Search the content of posts.", search_params(), None, "200", post_page, &error, false)
+        },
+    });
+
+    let settings = SettingsManager::get_settings();
+
+    json!({
+        "openapi": "3.0.3",
+        "info": {
+            "title": format!("{} API", settings.name),
+            "version": "1.0.0"
+        },
+        "servers": [{"url": format!("{}/api/v1", settings.base_url())}],
+        "paths": paths,
+        "components": {
+            "schemas": generator.take_definitions(),
+            "securitySchemes": {
+                "session": {"type": "apiKey", "in": "cookie", "name": "session"},
+                "bearer": {"type": "http", "scheme": "bearer", "description": "A personal API token or a token issued to an OAuth application."}
+            }
+        }
+    })
+}
+
+/// The OpenAPI document of the v1 API.
+#[get("/openapi.json")]
+pub async fn openapi_document() -> impl Responder {
+    HttpResponse::Ok().json(build_document())
+}
@@ -0,0 +1,665 @@
+use std::collections::HashMap;
+
+use actix_web::{delete, get, HttpRequest, HttpResponse, patch, post, web};
+use actix_web::error::BlockingError;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use uuid::Uuid;
+
+use crate::api::{ApiCaller, ApiError, ApiResult, authenticate, open_database, Page, page_limit, parse_cursor, viewer};
+use crate::attachments;
+use crate::attachments::storage;
+use crate::auth::api_token::Scope;
+use crate::profiles;
+use crate::schema::database::Database;
+use crate::schema::tables::{Attachment, Attachments, Forum, Forums, Post, Posts, Profile, Profiles, Thread, Threads, User, Users};
+use crate::settings::SettingsManager;
+use crate::webhooks;
+use crate::webhooks::WebhookEvent;
+
+/// The maximum length of a thread name.
+pub const MAX_THREAD_NAME_LENGTH: usize = 100;
+/// The maximum length of thread and post content.
+const MAX_CONTENT_LENGTH: usize = 50000;
+
+/// A forum.
+#[derive(Serialize, JsonSchema)]
+pub struct ApiForum {
+    pub uuid: Uuid,
+    pub name: String,
+    pub caption: String,
+}
+
+impl From<Forum> for ApiForum {
+    fn from(forum: Forum) -> Self {
+        ApiForum {
+            uuid: forum.uuid,
+            name: forum.name,
+            caption: forum.caption,
+        }
+    }
+}
+
+/// A thread.
+#[derive(Serialize, JsonSchema)]
+pub struct ApiThread {
+    pub uuid: Uuid,
+    pub forum_uuid: Uuid,
+    pub name: String,
+    pub content: String,
+    /// The uuid of the user that created the thread.
+    pub creator: Uuid,
+    pub locked: bool,
+    /// If the thread is shown before the other threads of its forum.
+    pub pinned: bool,
+    /// The unix time the thread was created.
+    pub created_at: i64,
+}
+
+impl From<Thread> for ApiThread {
+    fn from(thread: Thread) -> Self {
+        ApiThread {
+            uuid: thread.uuid,
+            forum_uuid: thread.forum_uuid,
+            name: thread.name,
+            content: thread.content,
+            creator: thread.creator,
+            locked: thread.locked,
+            pinned: thread.pinned,
+            created_at: thread.date,
+        }
+    }
+}
+
+/// A reply to a thread.
+#[derive(Serialize, JsonSchema)]
+pub struct ApiPost {
+    pub uuid: Uuid,
+    pub thread_uuid: Uuid,
+    /// The uuid of the user that created the post.
+    pub creator: Uuid,
+    pub content: String,
+    /// The unix time the post was created.
+    pub created_at: i64,
+}
+
+impl From<Post> for ApiPost {
+    fn from(post: Post) -> Self {
+        ApiPost {
+            uuid: post.uuid,
+            thread_uuid: post.thread_uuid,
+            creator: post.creator,
+            content: post.content,
+            created_at: post.date,
+        }
+    }
+}
+
+/// A file attached to a thread or post.
+#[derive(Serialize, JsonSchema)]
+pub struct ApiAttachment {
+    pub uuid: Uuid,
+    pub thread_uuid: Uuid,
+    /// The post the file is attached to. Null if it is attached to the thread itself.
+    pub post_uuid: Option<Uuid>,
+    /// The uuid of the user that uploaded the file.
+    pub uploader: Uuid,
+    pub file_name: String,
+    pub content_type: String,
+    /// The size of the file in bytes.
+    pub size: i64,
+    pub downloads: i64,
+    /// The location the file can be downloaded from.
+    pub url: String,
+    /// The location of the thumbnail of an image. Null if the file is not an image.
+    pub thumbnail_url: Option<String>,
+    /// The unix time the file was uploaded.
+    pub created_at: i64,
+}
+
+impl From<Attachment> for ApiAttachment {
+    fn from(attachment: Attachment) -> Self {
+        ApiAttachment {
+            uuid: attachment.uuid,
+            thread_uuid: attachment.thread_uuid,
+            post_uuid: attachment.post_uuid,
+            uploader: attachment.uploader,
+            file_name: attachment.file_name,
+            content_type: attachment.content_type,
+            size: attachment.size,
+            downloads: attachment.downloads,
+            url: format!("/attachments/{}", attachment.uuid),
+            thumbnail_url: attachment.thumbnail_key.map(|_| format!("/attachments/{}/thumbnail", attachment.uuid)),
+            created_at: attachment.created,
+        }
+    }
+}
+
+/// The public profile of a user.
+#[derive(Serialize, JsonSchema)]
+pub struct ApiUser {
+    pub uuid: Uuid,
+    pub username: String,
+    pub is_admin: bool,
+    pub is_moderator: bool,
+    /// The unix time the user joined.
+    pub joined_at: i64,
+    /// The amount of threads and posts the user has created.
+    pub post_count: i64,
+    /// The url of the avatar of the user. Uploaded avatars are relative to the forum. Null if the user has no avatar.
+    pub avatar_url: Option<String>,
+    pub bio: String,
+    pub signature: String,
+    pub location: String,
+    pub website: String,
+}
+
+impl ApiUser {
+    fn new(user: User, profile: Profile, post_count: i64) -> ApiUser {
+        ApiUser {
+            avatar_url: profiles::avatar_url(&user, &profile, &SettingsManager::get_settings().profile_settings),
+            uuid: user.uuid,
+            username: user.username,
+            is_admin: user.is_admin,
+            is_moderator: user.is_moderator,
+            joined_at: user.date,
+            post_count,
+            bio: profile.bio,
+            signature: profile.signature,
+            location: profile.location,
+            website: profile.website,
+        }
+    }
+}
+
+/// The query parameters for paginated endpoints.
+#[derive(Deserialize, JsonSchema)]
+pub struct PageQuery {
+    /// The `next_cursor` of the previous page.
+    pub cursor: Option<String>,
+    /// The maximum amount of items to return. (1-100, default 25)
+    pub limit: Option<i64>,
+}
+
+/// The query parameters for the search endpoints.
+#[derive(Deserialize, JsonSchema)]
+pub struct SearchQuery {
+    /// The text to search for.
+    pub q: String,
+    /// The `next_cursor` of the previous page.
+    pub cursor: Option<String>,
+    /// The maximum amount of items to return. (1-100, default 25)
+    pub limit: Option<i64>,
+}
+
+/// The request body to create a thread.
+#[derive(Deserialize, JsonSchema)]
+pub struct CreateThreadRequest {
+    pub name: String,
+    pub content: String,
+}
+
+/// The request body to create or edit a post.
+#[derive(Deserialize, JsonSchema)]
+pub struct PostContentRequest {
+    pub content: String,
+}
+
+/// The query parameters to upload an attachment.
+#[derive(Deserialize, JsonSchema)]
+pub struct UploadQuery {
+    /// The name of the file.
+    pub name: String,
+}
+
+/// Validate the content of a thread or post.
+fn validate_content(content: &str) -> Result<(), ApiError> {
+    if content.trim().is_empty() {
+        return Err(ApiError::bad_request("The content cannot be empty."));
+    }
+    if content.len() > MAX_CONTENT_LENGTH {
+        return Err(ApiError::bad_request(&format!("The content cannot be longer than {} characters.", MAX_CONTENT_LENGTH)));
+    }
+    Ok(())
+}
+
+/// Get a forum the user (or a guest when None) can see. Forums that are hidden from the user look like they do not exist.
+async fn visible_forum(db: &mut Database, forum_uuid: Uuid, user: Option<&User>) -> Result<Forum, ApiError> {
+    Forums::get_by_uuid(db, forum_uuid).await?
+        .filter(|forum| forum.visible_to(user))
+        .ok_or_else(|| ApiError::not_found("The forum does not exist."))
+}
+
+/// Get a thread in a forum the user (or a guest when None) can see.
+async fn visible_thread(db: &mut Database, thread_uuid: Uuid, user: Option<&User>) -> Result<Thread, ApiError> {
+    let thread = Threads::get_by_uuid(db, thread_uuid).await?
+        .ok_or_else(|| ApiError::not_found("The thread does not exist."))?;
+    match Forums::get_by_uuid(db, thread.forum_uuid).await? {
+        Some(forum) if forum.visible_to(user) => Ok(thread),
+        _ => Err(ApiError::not_found("The thread does not exist."))
+    }
+}
+
+/// Get the visibility of every forum to the user (or a guest when None), keyed by the forum uuid.
+async fn forum_visibility(db: &mut Database, user: Option<&User>) -> Result<HashMap<Uuid, bool>, ApiError> {
+    Ok(Forums::get_all(db).await?.into_iter().map(|forum| (forum.uuid, forum.visible_to(user))).collect())
+}
+
+/// List every forum.
+#[get("/forums")]
+pub async fn forums(req: HttpRequest) -> ApiResult {
+    let mut db = open_database().await?;
+    let user = viewer(&mut db, &req).await?;
+    let forums: Vec<ApiForum> = Forums::get_all(&mut db).await?.into_iter()
+        .filter(|forum| forum.visible_to(user.as_ref()))
+        .map(ApiForum::from)
+        .collect();
+    db.close().await;
+
+    Ok(HttpResponse::Ok().json(forums))
+}
+
+/// List the threads in a forum, newest first.
+#[get("/forums/{uuid}/threads")]
+pub async fn forum_threads(path: web::Path<Uuid>, query: web::Query<PageQuery>, req: HttpRequest) -> ApiResult {
+    let forum_uuid = path.into_inner();
+    let limit = page_limit(query.limit);
+    let cursor = parse_cursor(&query.cursor)?;
+
+    let mut db = open_database().await?;
+    let user = viewer(&mut db, &req).await?;
+    visible_forum(&mut db, forum_uuid, user.as_ref()).await?;
+    let threads = Threads::get_by_forum(&mut db, forum_uuid, cursor, limit).await?;
+    db.close().await;
+
+    let page = Page::new(threads, limit, |thread| thread.id.to_string());
+    Ok(HttpResponse::Ok().json(page.map(ApiThread::from)))
+}
+
+/// Create a thread in a forum.
+#[post("/forums/{uuid}/threads")]
+pub async fn create_thread(path: web::Path<Uuid>, body: web::Json<CreateThreadRequest>, req: HttpRequest) -> ApiResult {
+    let forum_uuid = path.into_inner();
+
+    if body.name.trim().is_empty() || body.name.len() > MAX_THREAD_NAME_LENGTH {
+        return Err(ApiError::bad_request(&format!("The name must be between 1 and {} characters.", MAX_THREAD_NAME_LENGTH)));
+    }
+    validate_content(body.content.as_str())?;
+
+    let mut db = open_database().await?;
+    let user = authenticate(&mut db, &req, Scope::Post).await?.user;
+    visible_forum(&mut db, forum_uuid, Some(&user)).await?;
+
+    let uuid = Uuid::new_v4();
+    Threads::insert(&mut db, uuid, forum_uuid, body.name.trim().to_string(), body.content.clone(), user.uuid).await?;
+    let thread = ApiThread::from(Threads::get_by_uuid(&mut db, uuid).await?.ok_or_else(ApiError::internal)?);
+    webhooks::trigger(&mut db, WebhookEvent::ThreadCreated, json!(thread)).await;
+    db.close().await;
+
+    Ok(HttpResponse::Created().json(thread))
+}
+
+/// Get a thread.
+#[get("/threads/{uuid}")]
+pub async fn thread(path: web::Path<Uuid>, req: HttpRequest) -> ApiResult {
+    let mut db = open_database().await?;
+    let user = viewer(&mut db, &req).await?;
+    let thread = visible_thread(&mut db, path.into_inner(), user.as_ref()).await?;
+    db.close().await;
+
+    Ok(HttpResponse::Ok().json(ApiThread::from(thread)))
+}
+
+/// List the posts in a thread, oldest first.
+#[get("/threads/{uuid}/posts")]
+pub async fn thread_posts(path: web::Path<Uuid>, query: web::Query<PageQuery>, req: HttpRequest) -> ApiResult {
+    let thread_uuid = path.into_inner();
+    let limit = page_limit(query.limit);
+    let cursor = parse_cursor(&query.cursor)?;
+
+    let mut db = open_database().await?;
+    let user = viewer(&mut db, &req).await?;
+    visible_thread(&mut db, thread_uuid, user.as_ref()).await?;
+    let posts = Posts::get_by_thread(&mut db, thread_uuid, cursor, limit).await?;
+    db.close().await;
+
+    let page = Page::new(posts, limit, |post| post.id.to_string());
+    Ok(HttpResponse::Ok().json(page.map(ApiPost::from)))
+}
+
+/// Reply to a thread.
+#[post("/threads/{uuid}/posts")]
+pub async fn create_post(path: web::Path<Uuid>, body: web::Json<PostContentRequest>, req: HttpRequest) -> ApiResult {
+    let thread_uuid = path.into_inner();
+    validate_content(body.content.as_str())?;
+
+    let mut db = open_database().await?;
+    let user = authenticate(&mut db, &req, Scope::Post).await?.user;
+
+    let thread = visible_thread(&mut db, thread_uuid, Some(&user)).await?;
+    if thread.locked {
+        return Err(ApiError::forbidden("The thread is locked."));
+    }
+
+    let uuid = Uuid::new_v4();
+    Posts::insert(&mut db, uuid, thread_uuid, user.uuid, body.content.clone()).await?;
+    let post = ApiPost::from(Posts::get_by_uuid(&mut db, uuid).await?.ok_or_else(ApiError::internal)?);
+    webhooks::trigger(&mut db, WebhookEvent::PostCreated, json!(post)).await;
+    db.close().await;
+
+    Ok(HttpResponse::Created().json(post))
+}
+
+/// Check if the caller can edit a post. Posts in a locked thread can only be edited with the moderate scope.
+fn check_can_edit(caller: &ApiCaller, thread: &Thread, post: &Post) -> Result<(), ApiError> {
+    let moderator = caller.has_scope(Scope::Moderate);
+    if post.creator != caller.user.uuid && !moderator {
+        return Err(ApiError::forbidden("You cannot edit this post."));
+    }
+    if thread.locked && !moderator {
+        return Err(ApiError::forbidden("The thread is locked."));
+    }
+    Ok(())
+}
+
+/// Edit a post. Only the creator of the post and requests with the moderate scope can edit a post.
+#[patch("/posts/{uuid}")]
+pub async fn edit_post(path: web::Path<Uuid>, body: web::Json<PostContentRequest>, req: HttpRequest) -> ApiResult {
+    let post_uuid = path.into_inner();
+    validate_content(body.content.as_str())?;
+
+    let mut db = open_database().await?;
+    let caller = authenticate(&mut db, &req, Scope::Post).await?;
+
+    let post = Posts::get_by_uuid(&mut db, post_uuid).await?
+        .ok_or_else(|| ApiError::not_found("The post does not exist."))?;
+    let thread = visible_thread(&mut db, post.thread_uuid, Some(&caller.user)).await?;
+    check_can_edit(&caller, &thread, &post)?;
+
+    Posts::update_content(&mut db, post_uuid, body.content.clone()).await?;
+    let post = Posts::get_by_uuid(&mut db, post_uuid).await?.ok_or_else(ApiError::internal)?;
+    db.close().await;
+
+    Ok(HttpResponse::Ok().json(ApiPost::from(post)))
+}
+
+/// Get the public profile of a user.
+#[get("/users/{username}")]
+pub async fn user(path: web::Path<String>) -> ApiResult {
+    let mut db = open_database().await?;
+    let user = Users::get_by_username(&mut db, path.as_str()).await?
+        .ok_or_else(|| ApiError::not_found("The user does not exist."))?;
+    let post_count = Users::count_content(&mut db, user.uuid).await?;
+    let profile = Profiles::get(&mut db, user.uuid).await?.unwrap_or_else(|| Profile::empty(user.uuid));
+    db.close().await;
+
+    Ok(HttpResponse::Ok().json(ApiUser::new(user, profile, post_count)))
+}
+
+/// Search the names and content of threads, newest first.
+#[get("/search/threads")]
+pub async fn search_threads(query: web::Query<SearchQuery>, req: HttpRequest) -> ApiResult {
+    if query.q.trim().is_empty() {
+        return Err(ApiError::bad_request("The search text cannot be empty."));
+    }
+    let limit = page_limit(query.limit);
+    let cursor = parse_cursor(&query.cursor)?;
+
+    let mut db = open_database().await?;
+    let user = viewer(&mut db, &req).await?;
+    let visibility = forum_visibility(&mut db, user.as_ref()).await?;
+    let threads = Threads::search(&mut db, query.q.trim(), cursor, limit).await?;
+    db.close().await;
+
+    // The cursor is taken before hidden threads are removed so the next page continues after them.
+    let mut page = Page::new(threads, limit, |thread| thread.id.to_string());
+    page.items.retain(|thread| visibility.get(&thread.forum_uuid).copied().unwrap_or(false));
+    Ok(HttpResponse::Ok().json(page.map(ApiThread::from)))
+}
+
+/// Search the content of posts, newest first.
+#[get("/search/posts")]
+pub async fn search_posts(query: web::Query<SearchQuery>, req: HttpRequest) -> ApiResult {
+    if query.q.trim().is_empty() {
+        return Err(ApiError::bad_request("The search text cannot be empty."));
+    }
+    let limit = page_limit(query.limit);
+    let cursor = parse_cursor(&query.cursor)?;
+
+    let mut db = open_database().await?;
+    let user = viewer(&mut db, &req).await?;
+    let visibility = forum_visibility(&mut db, user.as_ref()).await?;
+    let posts = Posts::search(&mut db, query.q.trim(), cursor, limit).await?;
+    let mut thread_forums = HashMap::new();
+    for post in &posts {
+        if !thread_forums.contains_key(&post.thread_uuid) {
+            let forum_uuid = Threads::get_by_uuid(&mut db, post.thread_uuid).await?.map(|thread| thread.forum_uuid);
+            thread_forums.insert(post.thread_uuid, forum_uuid);
+        }
+    }
+    db.close().await;
+
+    // The cursor is taken before hidden posts are removed so the next page continues after them.
+    let mut page = Page::new(posts, limit, |post| post.id.to_string());
+    page.items.retain(|post| thread_forums.get(&post.thread_uuid).copied().flatten()
+        .and_then(|forum_uuid| visibility.get(&forum_uuid).copied())
+        .unwrap_or(false));
+    Ok(HttpResponse::Ok().json(page.map(ApiPost::from)))
+}
+
+/**
+   Attach an uploaded file to a thread or one of its posts.
+
+   Only the creator of the thread or post and requests with the moderate scope can attach files. The file is
+   checked and processed off the request thread, then stored before it is recorded in the database.
+*/
+async fn attach(db: &mut Database, caller: &ApiCaller, thread: &Thread, post: Option<&Post>, name: &str, content_type: &str,
+                body: web::Bytes) -> ApiResult {
+    let settings = SettingsManager::get_settings().attachment_settings;
+    if !settings.enabled {
+        return Err(ApiError::forbidden("Attachments are disabled."));
+    }
+
+    let creator = post.map(|post| post.creator).unwrap_or(thread.creator);
+    let moderator = caller.has_scope(Scope::Moderate);
+    if creator != caller.user.uuid && !moderator {
+        return Err(ApiError::forbidden("You cannot attach files to this."));
+    }
+    if thread.locked && !moderator {
+        return Err(ApiError::forbidden("The thread is locked."));
+    }
+
+    let post_uuid = post.map(|post| post.uuid);
+    if Attachments::count(db, thread.uuid, post_uuid).await? >= settings.max_per_post {
+        return Err(ApiError::bad_request(&format!("At most {} files can be attached.", settings.max_per_post)));
+    }
+
+    let (name, content_type, process_settings) = (name.to_string(), content_type.to_string(), settings.clone());
+    let file = web::block(move || attachments::process(name.as_str(), content_type.as_str(), body.to_vec(), &process_settings)).await
+        .map_err(|err| match err {
+            BlockingError::Error(message) => ApiError::bad_request(message.as_str()),
+            BlockingError::Canceled => ApiError::internal()
+        })?;
+
+    let uuid = Uuid::new_v4();
+    let storage = storage::from_settings(&settings);
+    let attachment = Attachment {
+        id: 0,
+        uuid,
+        thread_uuid: thread.uuid,
+        post_uuid,
+        uploader: caller.user.uuid,
+        file_name: file.file_name,
+        content_type: file.content_type,
+        size: file.data.len() as i64,
+        storage_key: attachments::storage_key(uuid, false),
+        thumbnail_key: file.thumbnail.as_ref().map(|_| attachments::storage_key(uuid, true)),
+        downloads: 0,
+        created: 0,
+    };
+
+    let mut stored = storage.put(attachment.storage_key.as_str(), attachment.content_type.as_str(), file.data).await;
+    if let (Ok(_), Some(thumbnail), Some(key)) = (&stored, file.thumbnail, &attachment.thumbnail_key) {
+        stored = storage.put(key.as_str(), "image/png", thumbnail).await;
+    }
+    if let Err(err) = stored {
+        println!("[ERROR] Unable to store an attachment: {}", err);
+        attachments::delete_files(storage.as_ref(), &attachment).await;
+        return Err(ApiError::internal());
+    }
+
+    if let Err(err) = Attachments::insert(db, &attachment).await {
+        attachments::delete_files(storage.as_ref(), &attachment).await;
+        return Err(err.into());
+    }
+    let attachment = Attachments::get_by_uuid(db, uuid).await?.ok_or_else(ApiError::internal)?;
+
+    Ok(HttpResponse::Created().json(ApiAttachment::from(attachment)))
+}
+
+/// Get the content type of an upload from its header.
+fn upload_content_type(req: &HttpRequest) -> String {
+    req.headers().get("Content-Type").and_then(|value| value.to_str().ok()).unwrap_or_default().to_string()
+}
+
+/// Attach a file to a thread. The body is the content of the file.
+#[post("/threads/{uuid}/attachments")]
+pub async fn attach_to_thread(path: web::Path<Uuid>, query: web::Query<UploadQuery>, body: web::Bytes, req: HttpRequest) -> ApiResult {
+    let mut db = open_database().await?;
+    let caller = authenticate(&mut db, &req, Scope::Post).await?;
+
+    let thread = visible_thread(&mut db, path.into_inner(), Some(&caller.user)).await?;
+    let result = attach(&mut db, &caller, &thread, None, query.name.as_str(), upload_content_type(&req).as_str(), body).await;
+    db.close().await;
+    result
+}
+
+/// Attach a file to a post. The body is the content of the file.
+#[post("/posts/{uuid}/attachments")]
+pub async fn attach_to_post(path: web::Path<Uuid>, query: web::Query<UploadQuery>, body: web::Bytes, req: HttpRequest) -> ApiResult {
+    let mut db = open_database().await?;
+    let caller = authenticate(&mut db, &req, Scope::Post).await?;
+
+    let post = Posts::get_by_uuid(&mut db, path.into_inner()).await?
+        .ok_or_else(|| ApiError::not_found("The post does not exist."))?;
+    let thread = visible_thread(&mut db, post.thread_uuid, Some(&caller.user)).await?;
+    let result = attach(&mut db, &caller, &thread, Some(&post), query.name.as_str(), upload_content_type(&req).as_str(), body).await;
+    db.close().await;
+    result
+}
+
+/// List the files attached to a thread and its posts, oldest first.
+#[get("/threads/{uuid}/attachments")]
+pub async fn thread_attachments(path: web::Path<Uuid>, req: HttpRequest) -> ApiResult {
+    let thread_uuid = path.into_inner();
+
+    let mut db = open_database().await?;
+    let user = viewer(&mut db, &req).await?;
+    visible_thread(&mut db, thread_uuid, user.as_ref()).await?;
+    let attachments: Vec<ApiAttachment> = Attachments::get_by_thread(&mut db, thread_uuid).await?.into_iter().map(ApiAttachment::from).collect();
+    db.close().await;
+
+    Ok(HttpResponse::Ok().json(attachments))
+}
+
+/// Delete an attachment. Only the uploader and requests with the moderate scope can delete an attachment.
+#[delete("/attachments/{uuid}")]
+pub async fn delete_attachment(path: web::Path<Uuid>, req: HttpRequest) -> ApiResult {
+    let mut db = open_database().await?;
+    let caller = authenticate(&mut db, &req, Scope::Post).await?;
+
+    let attachment = Attachments::get_by_uuid(&mut db, path.into_inner()).await?
+        .ok_or_else(|| ApiError::not_found("The attachment does not exist."))?;
+    if attachment.uploader != caller.user.uuid && !caller.has_scope(Scope::Moderate) {
+        return Err(ApiError::forbidden("You cannot delete this attachment."));
+    }
+
+    Attachments::delete(&mut db, attachment.uuid).await?;
+    db.close().await;
+    attachments::delete_files(storage::from_settings(&SettingsManager::get_settings().attachment_settings).as_ref(), &attachment).await;
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn caller(is_moderator: bool, scopes: Vec<Scope>) -> ApiCaller {
+        ApiCaller {
+            user: User {
+                id: 1,
+                uuid: Uuid::new_v4(),
+                username: "tester".to_string(),
+                email: "tester@example.com".to_string(),
+                password: String::new(),
+                is_banned: false,
+                is_admin: false,
+                is_moderator,
+                password_reset: false,
+                date: 0,
+            },
+            scopes,
+        }
+    }
+
+    fn thread(locked: bool) -> Thread {
+        Thread {
+            id: 1,
+            uuid: Uuid::new_v4(),
+            forum_uuid: Uuid::new_v4(),
+            name: "Thread".to_string(),
+            content: "Content".to_string(),
+            creator: Uuid::new_v4(),
+            locked,
+            pinned: false,
+            date: 0,
+        }
+    }
+
+    fn post(thread: &Thread, creator: Uuid) -> Post {
+        Post {
+            id: 1,
+            uuid: Uuid::new_v4(),
+            thread_uuid: thread.uuid,
+            creator,
+            content: "Content".to_string(),
+            date: 0,
+        }
+    }
+
+    #[test]
+    fn creators_can_edit_their_posts_in_open_threads() {
+        let creator = caller(false, vec![Scope::Read, Scope::Post]);
+        let thread = thread(false);
+        assert!(check_can_edit(&creator, &thread, &post(&thread, creator.user.uuid)).is_ok());
+
+        let other = caller(false, vec![Scope::Read, Scope::Post]);
+        let err = check_can_edit(&other, &thread, &post(&thread, creator.user.uuid)).err().unwrap();
+        assert_eq!(err.error.message, "You cannot edit this post.");
+    }
+
+    #[test]
+    fn locked_threads_need_the_moderate_scope() {
+        let creator = caller(false, vec![Scope::Read, Scope::Post]);
+        let thread = thread(true);
+        let err = check_can_edit(&creator, &thread, &post(&thread, creator.user.uuid)).err().unwrap();
+        assert_eq!(err.error.code, "forbidden");
+        assert_eq!(err.error.message, "The thread is locked.");
+
+        // A moderator using a token without the moderate scope is treated like any other user.
+        let moderator = caller(true, vec![Scope::Read, Scope::Post]);
+        assert!(check_can_edit(&moderator, &thread, &post(&thread, moderator.user.uuid)).is_err());
+
+        let moderator = caller(true, vec![Scope::Moderate]);
+        assert!(check_can_edit(&moderator, &thread, &post(&thread, creator.user.uuid)).is_ok());
+    }
+
+    #[test]
+    fn content_must_not_be_blank_or_too_long() {
+        assert!(validate_content("Hello").is_ok());
+        assert!(validate_content("   ").is_err());
+        assert!(validate_content("a".repeat(MAX_CONTENT_LENGTH + 1).as_str()).is_err());
+    }
+}
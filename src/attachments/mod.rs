@@ -0,0 +1,244 @@
+use std::io::Cursor;
+
+use image::{DynamicImage, ImageFormat, ImageOutputFormat};
+use uuid::Uuid;
+
+use crate::attachments::storage::AttachmentStorage;
+use crate::schema::tables::Attachment;
+use crate::settings::AttachmentSettings;
+
+pub mod s3;
+pub mod storage;
+
+/// The maximum length of the name of an attached file.
+pub const MAX_FILE_NAME_LENGTH: usize = 100;
+/// The largest image that is decoded, in pixels. Larger images are refused so a small file cannot expand into a huge image.
+const MAX_IMAGE_PIXELS: u64 = 40_000_000;
+/// The quality JPEG images are stored with.
+const JPEG_QUALITY: u8 = 90;
+
+/// A file that has been checked and is ready to be stored.
+pub struct ProcessedFile {
+    pub file_name: String,
+    pub content_type: String,
+    pub data: Vec<u8>,
+    /// The thumbnail of an image as a PNG. None if the file is not an image.
+    pub thumbnail: Option<Vec<u8>>,
+}
+
+/// Clean the name of an uploaded file so it can be shown and sent in a header. Only the last part of a path is kept.
+pub fn clean_file_name(name: &str) -> String {
+    let name = name.rsplit(|c| c == '/' || c == '\\').next().unwrap_or_default();
+    let name: String = name.chars()
+        .filter(|c| !c.is_control() && !matches!(c, '"' | '<' | '>' | ':' | '*' | '?' | '|'))
+        .take(MAX_FILE_NAME_LENGTH)
+        .collect();
+    let name = name.trim().trim_start_matches('.');
+    if name.is_empty() { String::from("file") } else { name.to_string() }
+}
+
+/// Get the key of a file in the attachment storage. The first two characters spread the files over directories.
+pub fn storage_key(attachment_uuid: Uuid, thumbnail: bool) -> String {
+    let id = attachment_uuid.to_simple().to_string();
+    let suffix = if thumbnail { "-thumbnail" } else { "" };
+    format!("{}/{}{}", &id[..2], id, suffix)
+}
+
+/// Format the size of a file for people to read, such as `1.5 MiB`.
+pub fn format_size(size: i64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    if size < 1024 {
+        return format!("{} B", size);
+    }
+    let mut value = size as f64 / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", value, UNITS[unit])
+}
+
+/// Decode an image. The size is checked first, so images that would take too much memory are refused.
+pub fn decode_image(data: &[u8], format: ImageFormat) -> Result<DynamicImage, String> {
+    let (width, height) = image::io::Reader::with_format(Cursor::new(data), format).into_dimensions()
+        .map_err(|_| String::from("The image could not be read."))?;
+    if width as u64 * height as u64 > MAX_IMAGE_PIXELS {
+        return Err(String::from("The image is too large."));
+    }
+    image::load_from_memory_with_format(data, format).map_err(|_| String::from("The image could not be read."))
+}
+
+/// Encode an image, which writes none of the metadata the original file had.
+pub fn encode(image: &DynamicImage, format: ImageOutputFormat) -> Result<Vec<u8>, String> {
+    let mut data = Vec::new();
+    image.write_to(&mut data, format).map_err(|err| format!("Unable to encode the image: {}", err))?;
+    Ok(data)
+}
+
+/**
+   Check an uploaded file and prepare it to be stored.
+
+   The type of images is read from the file instead of trusting the type sent with it. PNG and JPEG images are
+   re-encoded to strip their metadata, and WebP images are converted to PNG as they cannot be encoded. GIF
+   images are kept as they are so animations keep working; GIF has no EXIF metadata to strip.
+
+   This decodes images, so it should not run on the thread that handles requests.
+
+   ## Params
+   declared_type - The MIME type the client sent for the file.
+*/
+pub fn process(file_name: &str, declared_type: &str, data: Vec<u8>, settings: &AttachmentSettings) -> Result<ProcessedFile, String> {
+    if data.is_empty() {
+        return Err(String::from("The file is empty."));
+    }
+    if data.len() > settings.max_size {
+        return Err(format!("The file cannot be larger than {} bytes.", settings.max_size));
+    }
+
+    let mut file_name = clean_file_name(file_name);
+    let declared_type = declared_type.split(';').next().unwrap_or_default().trim().to_lowercase();
+    let format = image::guess_format(&data).ok()
+        .filter(|format| matches!(format, ImageFormat::Png | ImageFormat::Jpeg | ImageFormat::Gif | ImageFormat::WebP));
+
+    let content_type = match format {
+        Some(ImageFormat::Png) => "image/png".to_string(),
+        Some(ImageFormat::Jpeg) => "image/jpeg".to_string(),
+        Some(ImageFormat::Gif) => "image/gif".to_string(),
+        Some(_) => "image/webp".to_string(),
+        None if declared_type.starts_with("image/") => return Err(String::from("The image could not be read.")),
+        None if declared_type.is_empty() => "application/octet-stream".to_string(),
+        None => declared_type
+    };
+    if !settings.allowed_types.iter().any(|allowed| allowed.eq_ignore_ascii_case(content_type.as_str())) {
+        return Err(format!("Files of the type {} cannot be attached.", content_type));
+    }
+
+    let format = match format {
+        Some(format) => format,
+        None => return Ok(ProcessedFile { file_name, content_type, data, thumbnail: None })
+    };
+
+    let image = decode_image(&data, format)?;
+
+    let (content_type, data) = match format {
+        ImageFormat::Png => (content_type, encode(&image, ImageOutputFormat::Png)?),
+        ImageFormat::Jpeg => (content_type, encode(&image, ImageOutputFormat::Jpeg(JPEG_QUALITY))?),
+        ImageFormat::Gif => (content_type, data),
+        _ => {
+            file_name = format!("{}.png", file_name.rsplitn(2, '.').last().unwrap_or_default());
+            ("image/png".to_string(), encode(&image, ImageOutputFormat::Png)?)
+        }
+    };
+    let thumbnail = encode(&image.thumbnail(settings.thumbnail_size, settings.thumbnail_size), ImageOutputFormat::Png)?;
+
+    Ok(ProcessedFile { file_name, content_type, data, thumbnail: Some(thumbnail) })
+}
+
+/// Delete the files of an attachment from the storage. Errors are logged, as the attachment is already gone from the database.
+pub async fn delete_files(storage: &dyn AttachmentStorage, attachment: &Attachment) {
+    for key in std::iter::once(&attachment.storage_key).chain(attachment.thumbnail_key.iter()) {
+        if let Err(err) = storage.delete(key.as_str()).await {
+            println!("[WARN] Unable to delete the attachment file {}: {}", key, err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{ImageBuffer, Rgb};
+
+    use super::*;
+
+    /// A 1x1 lossy WebP image.
+    const WEBP: [u8; 42] = [
+        0x52, 0x49, 0x46, 0x46, 0x22, 0x00, 0x00, 0x00, 0x57, 0x45, 0x42, 0x50, 0x56, 0x50, 0x38, 0x20, 0x16, 0x00, 0x00, 0x00,
+        0x30, 0x01, 0x00, 0x9d, 0x01, 0x2a, 0x01, 0x00, 0x01, 0x00, 0x0e, 0xc0, 0xfe, 0x25, 0xa4, 0x00, 0x03, 0x70, 0x00, 0x00,
+        0x00, 0x00,
+    ];
+
+    fn settings() -> AttachmentSettings {
+        let mut settings = AttachmentSettings::default();
+        settings.allowed_types.push("image/webp".to_string());
+        settings.thumbnail_size = 4;
+        settings
+    }
+
+    fn image() -> DynamicImage {
+        DynamicImage::ImageRgb8(ImageBuffer::from_fn(8, 8, |x, y| Rgb([(x * 30) as u8, (y * 30) as u8, 128])))
+    }
+
+    /// Encode a JPEG and insert an APP1 EXIF segment with a marker string right after the start of the image.
+    fn jpeg_with_exif(marker: &[u8]) -> Vec<u8> {
+        let jpeg = encode(&image(), ImageOutputFormat::Jpeg(90)).unwrap();
+        let mut exif = b"Exif\0\0".to_vec();
+        exif.extend_from_slice(marker);
+        let length = (exif.len() + 2) as u16;
+
+        let mut data = jpeg[..2].to_vec();
+        data.extend_from_slice(&[0xFF, 0xE1]);
+        data.extend_from_slice(&length.to_be_bytes());
+        data.extend_from_slice(&exif);
+        data.extend_from_slice(&jpeg[2..]);
+        data
+    }
+
+    fn contains(data: &[u8], part: &[u8]) -> bool {
+        data.windows(part.len()).any(|window| window == part)
+    }
+
+    #[test]
+    fn declared_image_that_is_not_an_image_is_refused() {
+        let result = process("photo.png", "image/png", b"this is not an image".to_vec(), &settings());
+        assert_eq!(result.err(), Some(String::from("The image could not be read.")));
+    }
+
+    #[test]
+    fn type_is_read_from_the_file() {
+        let png = encode(&image(), ImageOutputFormat::Png).unwrap();
+        let file = process("photo.txt", "text/plain", png, &settings()).unwrap();
+        assert_eq!(file.content_type, "image/png");
+        assert!(file.thumbnail.is_some());
+    }
+
+    #[test]
+    fn types_that_are_not_allowed_are_refused() {
+        assert!(process("run.exe", "application/x-msdownload", vec![0x4d, 0x5a], &settings()).is_err());
+        assert!(process("empty.txt", "text/plain", Vec::new(), &settings()).is_err());
+    }
+
+    #[test]
+    fn exif_is_removed_from_jpeg_images() {
+        let marker = b"GPS location of the photo";
+        let data = jpeg_with_exif(marker);
+        assert!(contains(&data, marker));
+
+        let file = process("photo.jpg", "image/jpeg", data, &settings()).unwrap();
+        assert_eq!(file.content_type, "image/jpeg");
+        assert!(!contains(&file.data, b"Exif"));
+        assert!(!contains(&file.data, marker));
+    }
+
+    #[test]
+    fn webp_images_are_converted_to_png() {
+        let file = process("photo.final.webp", "image/webp", WEBP.to_vec(), &settings()).unwrap();
+        assert_eq!(file.file_name, "photo.final.png");
+        assert_eq!(file.content_type, "image/png");
+        assert_eq!(image::guess_format(&file.data).unwrap(), ImageFormat::Png);
+    }
+
+    #[test]
+    fn file_names_are_cleaned() {
+        assert_eq!(clean_file_name("../../etc/passwd"), "passwd");
+        assert_eq!(clean_file_name("C:\\Users\\me\\<report>.pdf"), "report.pdf");
+        assert_eq!(clean_file_name(".hidden"), "hidden");
+        assert_eq!(clean_file_name("..."), "file");
+    }
+
+    #[test]
+    fn sizes_are_formatted() {
+        assert_eq!(format_size(512), "512 B");
+        assert_eq!(format_size(1536), "1.5 KiB");
+        assert_eq!(format_size(3 * 1024 * 1024), "3.0 MiB");
+    }
+}
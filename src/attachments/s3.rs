@@ -0,0 +1,294 @@
+use std::time::Duration;
+
+use actix_web::client::{Client, ClientRequest};
+use actix_web::http::StatusCode;
+use async_trait::async_trait;
+use chrono::Utc;
+use hmac::{Hmac, Mac, NewMac};
+use sha2::{Digest, Sha256};
+
+use crate::attachments::storage::AttachmentStorage;
+use crate::settings::S3Settings;
+
+/// How long to wait for the storage server to respond.
+const TIMEOUT: Duration = Duration::from_secs(30);
+/// The largest file that is read back from the storage server.
+const MAX_DOWNLOAD_SIZE: usize = 1024 * 1024 * 1024;
+
+/// Compute the HMAC-SHA256 of a message.
+fn hmac_sha256(key: &[u8], message: &str) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).unwrap();
+    mac.update(message.as_bytes());
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// Derive the key requests are signed with from the secret key, the date (`YYYYMMDD`), the region and the service.
+fn signing_key(secret_key: &str, date: &str, region: &str, service: &str) -> Vec<u8> {
+    let date_key = hmac_sha256(format!("AWS4{}", secret_key).as_bytes(), date);
+    let region_key = hmac_sha256(&date_key, region);
+    let service_key = hmac_sha256(&region_key, service);
+    hmac_sha256(&service_key, "aws4_request")
+}
+
+/**
+   Stores files in a bucket of an S3 compatible object storage, such as Amazon S3 or MinIO.
+
+   Requests are signed with AWS Signature Version 4. Self hosted servers usually need `path_style`, as the
+   bucket cannot be part of their host name.
+*/
+pub struct S3Storage {
+    settings: S3Settings,
+}
+
+impl S3Storage {
+    pub fn new(settings: S3Settings) -> S3Storage {
+        S3Storage { settings }
+    }
+
+    /// Get the host, the path and the url of an object.
+    fn location(&self, key: &str) -> (String, String, String) {
+        let endpoint = self.settings.endpoint.trim_end_matches('/');
+        let (scheme, host) = match endpoint.find("://") {
+            Some(index) => (&endpoint[..index], &endpoint[index + 3..]),
+            None => ("https", endpoint)
+        };
+        let key = key.split('/').map(|part| urlencoding::encode(part).into_owned()).collect::<Vec<String>>().join("/");
+
+        let (host, path) = if self.settings.path_style {
+            (host.to_string(), format!("/{}/{}", self.settings.bucket, key))
+        } else {
+            (format!("{}.{}", self.settings.bucket, host), format!("/{}", key))
+        };
+        let url = format!("{}://{}{}", scheme, host, path);
+        (host, path, url)
+    }
+
+    /// Build a signed request for an object.
+    fn request(&self, client: &Client, method: &str, key: &str, content_type: Option<&str>, payload: &[u8]) -> ClientRequest {
+        let (host, path, url) = self.location(key);
+        let now = Utc::now();
+        let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+        let date = now.format("%Y%m%d").to_string();
+        let payload_hash = hex::encode(Sha256::digest(payload));
+
+        let mut headers = vec![("host", host), ("x-amz-content-sha256", payload_hash.clone()), ("x-amz-date", amz_date.clone())];
+        if let Some(content_type) = content_type {
+            headers.insert(0, ("content-type", content_type.to_string()));
+        }
+        let canonical_headers: String = headers.iter().map(|(name, value)| format!("{}:{}\n", name, value.trim())).collect();
+        let signed_headers = headers.iter().map(|(name, _)| *name).collect::<Vec<&str>>().join(";");
+
+        let canonical_request = format!("{}\n{}\n\n{}\n{}\n{}", method, path, canonical_headers, signed_headers, payload_hash);
+        let scope = format!("{}/{}/s3/aws4_request", date, self.settings.region);
+        let string_to_sign = format!("AWS4-HMAC-SHA256\n{}\n{}\n{}", amz_date, scope, hex::encode(Sha256::digest(canonical_request.as_bytes())));
+
+        let signing_key = signing_key(self.settings.secret_key.as_str(), &date, &self.settings.region, "s3");
+        let signature = hex::encode(hmac_sha256(&signing_key, &string_to_sign));
+        let authorization = format!("AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+                                    self.settings.access_key, scope, signed_headers, signature);
+
+        let mut request = match method {
+            "PUT" => client.put(url.as_str()),
+            "DELETE" => client.delete(url.as_str()),
+            _ => client.get(url.as_str())
+        };
+        for (name, value) in headers {
+            request = request.header(name, value);
+        }
+        request.header("Authorization", authorization)
+    }
+}
+
+#[async_trait(?Send)]
+impl AttachmentStorage for S3Storage {
+    async fn put(&self, key: &str, content_type: &str, data: Vec<u8>) -> Result<(), String> {
+        let client = Client::builder().timeout(TIMEOUT).finish();
+        let response = self.request(&client, "PUT", key, Some(content_type), &data)
+            .send_body(data).await
+            .map_err(|err| format!("Unable to upload {} to S3: {}", key, err))?;
+
+        if !response.status().is_success() {
+            return Err(format!("Unable to upload {} to S3: the server responded with {}.", key, response.status()));
+        }
+        Ok(())
+    }
+
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>, String> {
+        let client = Client::builder().timeout(TIMEOUT).finish();
+        let mut response = self.request(&client, "GET", key, None, &[])
+            .send().await
+            .map_err(|err| format!("Unable to download {} from S3: {}", key, err))?;
+
+        match response.status() {
+            StatusCode::NOT_FOUND => Ok(None),
+            status if status.is_success() => {
+                let body = response.body().limit(MAX_DOWNLOAD_SIZE).await
+                    .map_err(|err| format!("Unable to download {} from S3: {}", key, err))?;
+                Ok(Some(body.to_vec()))
+            }
+            status => Err(format!("Unable to download {} from S3: the server responded with {}.", key, status))
+        }
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), String> {
+        let client = Client::builder().timeout(TIMEOUT).finish();
+        let response = self.request(&client, "DELETE", key, None, &[])
+            .send().await
+            .map_err(|err| format!("Unable to delete {} from S3: {}", key, err))?;
+
+        if !response.status().is_success() && response.status() != StatusCode::NOT_FOUND {
+            return Err(format!("Unable to delete {} from S3: the server responded with {}.", key, response.status()));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+
+    use actix_web::{App, HttpRequest, HttpResponse, HttpServer, web};
+    use actix_web::dev::Server;
+    use actix_web::web::Bytes;
+
+    use super::*;
+
+    const ACCESS_KEY: &str = "AKIDEXAMPLE";
+    const SECRET_KEY: &str = "secret";
+    const REGION: &str = "us-east-1";
+
+    fn settings(endpoint: &str, path_style: bool) -> S3Settings {
+        S3Settings {
+            endpoint: endpoint.to_string(),
+            bucket: "forum".to_string(),
+            region: REGION.to_string(),
+            access_key: ACCESS_KEY.to_string(),
+            secret_key: SECRET_KEY.to_string(),
+            path_style,
+        }
+    }
+
+    #[test]
+    fn signing_key_matches_the_aws_example() {
+        // The example from the AWS documentation on deriving a Signature Version 4 signing key.
+        let key = signing_key("wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY", "20120215", "us-east-1", "iam");
+        assert_eq!(hex::encode(key), "f4780e2d9f65fa895f9c67b32ce1baf0b0d8a43505a000a1a9e090d414db404d");
+    }
+
+    #[test]
+    fn location_uses_the_bucket_host_or_path() {
+        let storage = S3Storage::new(settings("https://s3.example.com/", false));
+        assert_eq!(storage.location("ab/abc def"), ("forum.s3.example.com".to_string(), "/ab/abc%20def".to_string(),
+                                                    "https://forum.s3.example.com/ab/abc%20def".to_string()));
+
+        let storage = S3Storage::new(settings("127.0.0.1:9000", true));
+        assert_eq!(storage.location("ab/abc"), ("127.0.0.1:9000".to_string(), "/forum/ab/abc".to_string(),
+                                                "https://127.0.0.1:9000/forum/ab/abc".to_string()));
+    }
+
+    /**
+       Check the signature of a request the way an S3 server does.
+
+       ## Returns
+       Why the request is refused, if it is.
+    */
+    fn verify(req: &HttpRequest, body: &[u8]) -> Result<(), String> {
+        let header = |name: &str| req.headers().get(name).and_then(|value| value.to_str().ok()).map(str::to_string)
+            .ok_or_else(|| format!("Missing the {} header.", name));
+
+        let payload_hash = header("x-amz-content-sha256")?;
+        if payload_hash != hex::encode(Sha256::digest(body)) {
+            return Err(String::from("The payload hash does not match the body."));
+        }
+
+        let authorization = header("authorization")?;
+        let fields: HashMap<&str, &str> = authorization.trim_start_matches("AWS4-HMAC-SHA256 ").split(", ")
+            .filter_map(|field| field.split_once('='))
+            .collect();
+        let credential: Vec<&str> = fields.get("Credential").ok_or("Missing the credential.")?.split('/').collect();
+        if credential.len() != 5 || credential[0] != ACCESS_KEY || credential[2..] != [REGION, "s3", "aws4_request"] {
+            return Err(String::from("The credential scope is wrong."));
+        }
+        let signed_headers = *fields.get("SignedHeaders").ok_or("Missing the signed headers.")?;
+
+        let mut canonical_headers = String::new();
+        for name in signed_headers.split(';') {
+            canonical_headers.push_str(format!("{}:{}\n", name, header(name)?.trim()).as_str());
+        }
+        let canonical_request = format!("{}\n{}\n\n{}\n{}\n{}", req.method(), req.path(), canonical_headers, signed_headers, payload_hash);
+        let amz_date = header("x-amz-date")?;
+        let scope = format!("{}/{}/s3/aws4_request", credential[1], REGION);
+        let string_to_sign = format!("AWS4-HMAC-SHA256\n{}\n{}\n{}", amz_date, scope, hex::encode(Sha256::digest(canonical_request.as_bytes())));
+        let expected = hex::encode(hmac_sha256(&signing_key(SECRET_KEY, credential[1], REGION, "s3"), &string_to_sign));
+
+        if fields.get("Signature") != Some(&expected.as_str()) {
+            return Err(String::from("The signature does not match."));
+        }
+        Ok(())
+    }
+
+    /// Start a local stand-in for an S3 server that keeps objects in memory and checks every signature.
+    fn start_stand_in() -> (Server, String, Arc<Mutex<HashMap<String, Vec<u8>>>>) {
+        let objects: Arc<Mutex<HashMap<String, Vec<u8>>>> = Arc::new(Mutex::new(HashMap::new()));
+        let server_objects = objects.clone();
+        let server = HttpServer::new(move || {
+            let objects = server_objects.clone();
+            App::new().default_service(web::to(move |req: HttpRequest, body: Bytes| {
+                let objects = objects.clone();
+                async move {
+                    if let Err(err) = verify(&req, &body) {
+                        return HttpResponse::Forbidden().body(err);
+                    }
+                    let mut objects = objects.lock().unwrap();
+                    let path = req.path().to_string();
+                    match req.method().as_str() {
+                        "PUT" => {
+                            objects.insert(path, body.to_vec());
+                            HttpResponse::Ok().finish()
+                        }
+                        "GET" => match objects.get(&path) {
+                            Some(data) => HttpResponse::Ok().body(data.clone()),
+                            None => HttpResponse::NotFound().finish()
+                        },
+                        "DELETE" => {
+                            objects.remove(&path);
+                            HttpResponse::NoContent().finish()
+                        }
+                        _ => HttpResponse::MethodNotAllowed().finish()
+                    }
+                }
+            }))
+        })
+            .workers(1)
+            .bind("127.0.0.1:0").unwrap();
+        let endpoint = format!("http://{}", server.addrs()[0]);
+        (server.run(), endpoint, objects)
+    }
+
+    #[actix_rt::test]
+    async fn signed_requests_are_accepted() {
+        let (server, endpoint, objects) = start_stand_in();
+        let storage = S3Storage::new(settings(endpoint.as_str(), true));
+
+        storage.put("ab/abc", "image/png", vec![1, 2, 3]).await.unwrap();
+        assert_eq!(objects.lock().unwrap().get("/forum/ab/abc"), Some(&vec![1, 2, 3]));
+        assert_eq!(storage.get("ab/abc").await.unwrap(), Some(vec![1, 2, 3]));
+
+        storage.delete("ab/abc").await.unwrap();
+        assert_eq!(storage.get("ab/abc").await.unwrap(), None);
+
+        server.stop(true).await;
+    }
+
+    #[actix_rt::test]
+    async fn requests_with_the_wrong_secret_are_refused() {
+        let (server, endpoint, objects) = start_stand_in();
+        let storage = S3Storage::new(S3Settings { secret_key: "wrong".to_string(), ..settings(endpoint.as_str(), true) });
+
+        assert!(storage.put("ab/abc", "image/png", vec![1, 2, 3]).await.is_err());
+        assert!(objects.lock().unwrap().is_empty());
+
+        server.stop(true).await;
+    }
+}
@@ -0,0 +1,90 @@
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Component, Path, PathBuf};
+
+use async_trait::async_trait;
+
+use crate::attachments::s3::S3Storage;
+use crate::settings::{AttachmentSettings, AttachmentStorageType};
+
+/**
+   Where the files of attachments are kept.
+
+   Files are identified by a key made of `/` separated parts. ForumRS generates every key, so backends do not
+   need to handle keys chosen by users.
+*/
+#[async_trait(?Send)]
+pub trait AttachmentStorage {
+    /// Store a file, replacing any file with the same key.
+    async fn put(&self, key: &str, content_type: &str, data: Vec<u8>) -> Result<(), String>;
+
+    /// Read a file. None if no file has the key.
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>, String>;
+
+    /// Delete a file. Deleting a file that does not exist is not an error.
+    async fn delete(&self, key: &str) -> Result<(), String>;
+}
+
+/// Get the storage the settings use.
+pub fn from_settings(settings: &AttachmentSettings) -> Box<dyn AttachmentStorage> {
+    match (&settings.storage, &settings.s3_settings) {
+        (AttachmentStorageType::S3, Some(s3_settings)) => Box::new(S3Storage::new(s3_settings.clone())),
+        // The settings are validated, so S3 without its settings never reaches this.
+        _ => Box::new(LocalStorage::new(settings.directory.as_str()))
+    }
+}
+
+/// Stores files in a directory on the server.
+pub struct LocalStorage {
+    directory: PathBuf,
+}
+
+impl LocalStorage {
+    pub fn new(directory: &str) -> LocalStorage {
+        LocalStorage {
+            directory: PathBuf::from(directory),
+        }
+    }
+
+    /// Get the location of a file. Keys that would leave the directory are refused.
+    fn path(&self, key: &str) -> Result<PathBuf, String> {
+        let relative = Path::new(key);
+        if key.is_empty() || !relative.components().all(|component| matches!(component, Component::Normal(_))) {
+            return Err(format!("Invalid attachment key: {}", key));
+        }
+        Ok(self.directory.join(relative))
+    }
+}
+
+#[async_trait(?Send)]
+impl AttachmentStorage for LocalStorage {
+    async fn put(&self, key: &str, _content_type: &str, data: Vec<u8>) -> Result<(), String> {
+        let path = self.path(key)?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(|err| format!("Unable to create {}: {}", parent.display(), err))?;
+        }
+
+        // Write to a temporary file first so a file is never read while it is half written.
+        let partial = path.with_extension("partial");
+        fs::write(&partial, data).map_err(|err| format!("Unable to write {}: {}", partial.display(), err))?;
+        fs::rename(&partial, &path).map_err(|err| format!("Unable to write {}: {}", path.display(), err))
+    }
+
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>, String> {
+        let path = self.path(key)?;
+        match fs::read(&path) {
+            Ok(data) => Ok(Some(data)),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(format!("Unable to read {}: {}", path.display(), err))
+        }
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), String> {
+        let path = self.path(key)?;
+        match fs::remove_file(&path) {
+            Ok(_) => Ok(()),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(()),
+            Err(err) => Err(format!("Unable to delete {}: {}", path.display(), err))
+        }
+    }
+}
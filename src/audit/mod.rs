@@ -0,0 +1,242 @@
+use std::time::Duration;
+
+use actix_web::HttpRequest;
+use actix_web::rt::time::delay_for;
+use serde_json::{Map, Value};
+use sqlx::Error;
+use uuid::Uuid;
+
+use crate::auth::login_protection;
+use crate::schema::database::Database;
+use crate::schema::tables::{AuditLog, current_time};
+use crate::settings::SettingsManager;
+
+/// How often entries past the retention are deleted, in seconds.
+const RETENTION_INTERVAL: u64 = 3600;
+/// The IP recorded for actions taken from the command line.
+pub const CONSOLE_IP: &str = "console";
+
+/// The admin and moderator actions recorded in the audit log.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AuditAction {
+    UserBan,
+    UserUnban,
+    UserRole,
+    TwoFactorReset,
+    ThreadDelete,
+    ThreadLock,
+    ThreadUnlock,
+    ThreadPin,
+    ThreadUnpin,
+    ThreadMove,
+    ThreadMerge,
+    ThreadSplit,
+    PostDelete,
+    MessageDelete,
+    ReportDismiss,
+    SettingsChange,
+    SettingsReload,
+    WebhookCreate,
+    WebhookToggle,
+    WebhookDelete,
+    BackupCreate,
+    BackupDownload,
+}
+
+impl AuditAction {
+    /// Every action.
+    pub const ALL: [AuditAction; 22] = [AuditAction::UserBan, AuditAction::UserUnban, AuditAction::UserRole, AuditAction::TwoFactorReset,
+        AuditAction::ThreadDelete, AuditAction::ThreadLock, AuditAction::ThreadUnlock, AuditAction::ThreadPin, AuditAction::ThreadUnpin,
+        AuditAction::ThreadMove, AuditAction::ThreadMerge, AuditAction::ThreadSplit, AuditAction::PostDelete, AuditAction::MessageDelete, AuditAction::ReportDismiss,
+        AuditAction::SettingsChange, AuditAction::SettingsReload, AuditAction::WebhookCreate, AuditAction::WebhookToggle,
+        AuditAction::WebhookDelete, AuditAction::BackupCreate, AuditAction::BackupDownload];
+
+    /// The name of the action stored in the database and used in filters.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AuditAction::UserBan => "user.ban",
+            AuditAction::UserUnban => "user.unban",
+            AuditAction::UserRole => "user.role",
+            AuditAction::TwoFactorReset => "user.twofactor_reset",
+            AuditAction::ThreadDelete => "thread.delete",
+            AuditAction::ThreadLock => "thread.lock",
+            AuditAction::ThreadUnlock => "thread.unlock",
+            AuditAction::ThreadPin => "thread.pin",
+            AuditAction::ThreadUnpin => "thread.unpin",
+            AuditAction::ThreadMove => "thread.move",
+            AuditAction::ThreadMerge => "thread.merge",
+            AuditAction::ThreadSplit => "thread.split",
+            AuditAction::PostDelete => "post.delete",
+            AuditAction::MessageDelete => "message.delete",
+            AuditAction::ReportDismiss => "report.dismiss",
+            AuditAction::SettingsChange => "settings.change",
+            AuditAction::SettingsReload => "settings.reload",
+            AuditAction::WebhookCreate => "webhook.create",
+            AuditAction::WebhookToggle => "webhook.toggle",
+            AuditAction::WebhookDelete => "webhook.delete",
+            AuditAction::BackupCreate => "backup.create",
+            AuditAction::BackupDownload => "backup.download"
+        }
+    }
+
+    /// A description of the action for the admin pages.
+    pub fn description(&self) -> &'static str {
+        match self {
+            AuditAction::UserBan => "Banned a user",
+            AuditAction::UserUnban => "Unbanned a user",
+            AuditAction::UserRole => "Changed the role of a user",
+            AuditAction::TwoFactorReset => "Reset the two factor authentication of a user",
+            AuditAction::ThreadDelete => "Deleted a thread",
+            AuditAction::ThreadLock => "Locked a thread",
+            AuditAction::ThreadUnlock => "Unlocked a thread",
+            AuditAction::ThreadPin => "Pinned a thread",
+            AuditAction::ThreadUnpin => "Unpinned a thread",
+            AuditAction::ThreadMove => "Moved a thread to another forum",
+            AuditAction::ThreadMerge => "Merged a thread into another",
+            AuditAction::ThreadSplit => "Split posts into a new thread",
+            AuditAction::PostDelete => "Deleted a post",
+            AuditAction::MessageDelete => "Deleted a private message",
+            AuditAction::ReportDismiss => "Dismissed reports",
+            AuditAction::SettingsChange => "Changed settings",
+            AuditAction::SettingsReload => "Reloaded the settings file",
+            AuditAction::WebhookCreate => "Registered a webhook",
+            AuditAction::WebhookToggle => "Enabled or disabled a webhook",
+            AuditAction::WebhookDelete => "Deleted a webhook",
+            AuditAction::BackupCreate => "Made a backup",
+            AuditAction::BackupDownload => "Downloaded a backup"
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<AuditAction> {
+        AuditAction::ALL.iter().copied().find(|action| action.as_str() == value)
+    }
+}
+
+/// Get the IP to record for an action taken through a request.
+pub fn request_ip(req: &HttpRequest) -> String {
+    login_protection::client_ip(req, &SettingsManager::get_settings().login_protection_settings)
+}
+
+/**
+   Add an action to the audit log.
+
+   ## Params
+   actor - The user that took the action, the nil uuid for the command line.<br>
+   ip - Where the action was taken from.<br>
+   action - The action.<br>
+   target - What the action was taken on, usually a uuid.<br>
+   old_value - The state of the target before the action, null if there is nothing to record.<br>
+   new_value - The state of the target after the action, null if there is nothing to record.
+*/
+pub async fn record(db: &mut Database, actor: Uuid, ip: &str, action: AuditAction, target: &str, old_value: Value, new_value: Value) -> Result<(), Error> {
+    let to_text = |value: Value| if value.is_null() { None } else { Some(value.to_string()) };
+    AuditLog::insert(db, actor, action.as_str(), target, to_text(old_value), to_text(new_value), ip).await?;
+    Ok(())
+}
+
+/**
+   Keep only the values that changed between two JSON objects, such as the settings before and after they were saved.
+   Nested objects are compared key by key.
+
+   ## Returns
+   The old and new values of the keys that changed.
+*/
+pub fn changes(old_value: &Value, new_value: &Value) -> (Value, Value) {
+    match (old_value, new_value) {
+        (Value::Object(old_object), Value::Object(new_object)) => {
+            let mut old_changes = Map::new();
+            let mut new_changes = Map::new();
+            let keys = old_object.keys().chain(new_object.keys().filter(|key| !old_object.contains_key(*key)));
+            for key in keys {
+                let old_field = old_object.get(key).unwrap_or(&Value::Null);
+                let new_field = new_object.get(key).unwrap_or(&Value::Null);
+                if old_field != new_field {
+                    let (old_field, new_field) = changes(old_field, new_field);
+                    old_changes.insert(key.clone(), old_field);
+                    new_changes.insert(key.clone(), new_field);
+                }
+            }
+            (Value::Object(old_changes), Value::Object(new_changes))
+        }
+        _ => (old_value.clone(), new_value.clone())
+    }
+}
+
+/// Delete the entries past the retention of the audit settings once an hour. This runs forever and should be spawned once when the forum starts.
+pub async fn run_retention() {
+    loop {
+        delay_for(Duration::from_secs(RETENTION_INTERVAL)).await;
+
+        // The settings are read every time so changes apply without a restart.
+        let settings = SettingsManager::get_settings();
+        let retention_days = settings.audit_settings.retention_days;
+        if retention_days == 0 {
+            continue;
+        }
+
+        let mut db = match Database::connect(&settings).await {
+            Ok(db) => db,
+            Err(err) => {
+                println!("[WARN] Unable to connect to the database to clean up the audit log: {:?}", err);
+                continue;
+            }
+        };
+
+        match AuditLog::delete_older_than(&mut db, current_time() - retention_days as i64 * 86400).await {
+            Ok(done) if done.rows_affected() > 0 => println!("[INFO] Deleted {} audit log entries past the retention.", done.rows_affected()),
+            Ok(_) => {}
+            Err(err) => println!("[ERROR] Unable to clean up the audit log: {:?}", err)
+        }
+        db.close().await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn changes_keep_changed_keys() {
+        let (old, new) = changes(&json!({"name": "Forum", "port": 8080, "ssl": false}), &json!({"name": "Forum", "port": 9090, "ssl": true}));
+        assert_eq!(old, json!({"port": 8080, "ssl": false}));
+        assert_eq!(new, json!({"port": 9090, "ssl": true}));
+    }
+
+    #[test]
+    fn changes_nested_objects() {
+        let old_value = json!({"mail": {"host": "smtp.example.com", "port": 25}, "title": "Forum"});
+        let new_value = json!({"mail": {"host": "smtp.example.com", "port": 587}, "title": "Forum"});
+        let (old, new) = changes(&old_value, &new_value);
+        assert_eq!(old, json!({"mail": {"port": 25}}));
+        assert_eq!(new, json!({"mail": {"port": 587}}));
+    }
+
+    #[test]
+    fn changes_added_and_removed_keys() {
+        let (old, new) = changes(&json!({"removed": 1}), &json!({"added": [1, 2]}));
+        assert_eq!(old, json!({"removed": 1, "added": null}));
+        assert_eq!(new, json!({"removed": null, "added": [1, 2]}));
+    }
+
+    #[test]
+    fn changes_nothing_changed() {
+        let value = json!({"a": {"b": [1, 2, 3]}});
+        assert_eq!(changes(&value, &value), (json!({}), json!({})));
+    }
+
+    #[test]
+    fn changes_not_objects() {
+        assert_eq!(changes(&json!([1]), &json!([1, 2])), (json!([1]), json!([1, 2])));
+        assert_eq!(changes(&json!({"a": 1}), &Value::Null), (json!({"a": 1}), Value::Null));
+    }
+
+    #[test]
+    fn actions_parse() {
+        for action in AuditAction::ALL.iter() {
+            assert_eq!(AuditAction::parse(action.as_str()), Some(*action));
+        }
+        assert_eq!(AuditAction::parse("user.delete"), None);
+    }
+}
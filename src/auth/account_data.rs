@@ -0,0 +1,119 @@
+use serde_json::{json, Value};
+use sqlx::Error;
+use uuid::Uuid;
+
+use crate::attachments;
+use crate::attachments::storage;
+use crate::schema::database::Database;
+use crate::profiles;
+use crate::schema::tables::{ApiTokens, Attachments, AuditLog, Avatar, BannedUsers, ConversationMembers, Conversations, current_time, LoginAttempts, Messages,
+                            OAuthClients, Posts, Profiles, RecoveryCodes, Reports, Sessions, Threads, TwoFactor, User, UserBlocks, Users};
+use crate::settings::{DeletedContent, SettingsManager};
+
+/// The most login attempts included in an export.
+const MAX_LOGIN_ATTEMPTS: i64 = 1000;
+
+/**
+   Collect the personal data the forum stores about a user.
+
+   Secrets such as the password hash, the two factor secret and the hashes of tokens are left out, as they
+   are only useful to log in as the user.
+
+   ## Returns
+   The data as a JSON object.
+*/
+pub async fn export(db: &mut Database, user: &User) -> Result<Value, Error> {
+    let public_profile = Profiles::get(db, user.uuid).await?;
+    let two_factor = TwoFactor::get(db, user.uuid).await?.map(|entry| entry.enabled).unwrap_or(false);
+    let sessions: Vec<Value> = Sessions::get_creation_times(db, user.uuid).await?.into_iter()
+        .map(|created| json!({"created": created}))
+        .collect();
+    let tokens = ApiTokens::get_by_user(db, user.uuid).await?;
+    let applications = OAuthClients::get_by_owner(db, user.uuid).await?;
+    let threads = Threads::get_by_creator(db, user.uuid).await?;
+    let posts = Posts::get_by_creator(db, user.uuid).await?;
+    let attachments = Attachments::get_by_uploader(db, user.uuid).await?;
+    let messages = Messages::get_by_sender(db, user.uuid).await?;
+    let blocked = UserBlocks::get_blocked(db, user.uuid).await?;
+    let reports = Reports::get_by_reporter(db, user.uuid).await?;
+    let audit_log = AuditLog::get_by_actor(db, user.uuid).await?;
+    let login_attempts = LoginAttempts::get_recent(db, Some(user.username.as_str()), MAX_LOGIN_ATTEMPTS).await?;
+
+    // ForumRS does not have notifications yet.
+    Ok(json!({
+        "exported": current_time(),
+        "profile": user,
+        "public_profile": public_profile,
+        "two_factor_enabled": two_factor,
+        "sessions": sessions,
+        "api_tokens": tokens,
+        "applications": applications,
+        "threads": threads,
+        "posts": posts,
+        "attachments": attachments,
+        "messages_sent": messages,
+        "blocked_users": blocked,
+        "reports": reports,
+        "moderation_actions": audit_log,
+        "login_attempts": login_attempts,
+    }))
+}
+
+/**
+   Delete the account of a user and everything that belongs to it.
+
+   The threads, posts and private messages of the user are either kept and shown as written by a deleted user
+   or removed, depending on the policy, along with the files attached to them. The user row is deleted last, so the
+   account still exists if anything fails and the deletion can be tried again.
+
+   The audit log is append-only, so the admin and moderator actions of the user stay in it until they pass its retention.
+*/
+pub async fn delete_account(db: &mut Database, user: &User, content: DeletedContent) -> Result<(), Error> {
+    match content {
+        DeletedContent::Anonymize => {
+            Threads::replace_creator(db, user.uuid, Uuid::nil()).await?;
+            Posts::replace_creator(db, user.uuid, Uuid::nil()).await?;
+            Messages::replace_sender(db, user.uuid, Uuid::nil()).await?;
+        }
+        DeletedContent::Remove => {
+            let storage = storage::from_settings(&SettingsManager::get_settings().attachment_settings);
+            for attachment in Attachments::get_by_content_of(db, user.uuid).await? {
+                Attachments::delete(db, attachment.uuid).await?;
+                attachments::delete_files(storage.as_ref(), &attachment).await;
+            }
+            Posts::delete_by_creator(db, user.uuid).await?;
+            Threads::delete_by_creator(db, user.uuid).await?;
+            Messages::delete_by_sender(db, user.uuid).await?;
+        }
+    }
+    // Files the user attached to the content of others stay with that content.
+    Attachments::replace_uploader(db, user.uuid, Uuid::nil()).await?;
+
+    for application in OAuthClients::get_by_owner(db, user.uuid).await? {
+        ApiTokens::delete_by_client(db, application.uuid).await?;
+        OAuthClients::delete(db, application.uuid, user.uuid).await?;
+    }
+    ConversationMembers::delete_by_user(db, user.uuid).await?;
+    Conversations::delete_empty(db).await?;
+    UserBlocks::delete_by_user(db, user.uuid).await?;
+    // Reports stay in the moderation queue without the reporter.
+    Reports::replace_reporter(db, user.uuid, Uuid::nil()).await?;
+    ApiTokens::delete_by_user(db, user.uuid).await?;
+    TwoFactor::delete(db, user.uuid).await?;
+    RecoveryCodes::delete_all(db, user.uuid).await?;
+    BannedUsers::delete_by_user(db, user.uuid).await?;
+    LoginAttempts::delete_by_username(db, user.username.as_str()).await?;
+    Sessions::delete_by_user(db, user.uuid).await?;
+    if let Some(profile) = Profiles::get(db, user.uuid).await? {
+        if profile.avatar == Avatar::Uploaded {
+            let storage = storage::from_settings(&SettingsManager::get_settings().attachment_settings);
+            if let Err(err) = storage.delete(profiles::avatar_key(user.uuid).as_str()).await {
+                println!("[WARN] Unable to delete the avatar of {}: {}", user.username, err);
+            }
+        }
+        Profiles::delete(db, user.uuid).await?;
+    }
+    Users::delete(db, user.uuid).await?;
+
+    Ok(())
+}
@@ -0,0 +1,226 @@
+use std::fmt;
+use std::fmt::Formatter;
+use std::str::FromStr;
+
+use actix_web::HttpRequest;
+use actix_web::http::header::AUTHORIZATION;
+use rand::Rng;
+use rand::distributions::Alphanumeric;
+use sha2::{Digest, Sha256};
+
+use crate::schema::tables::User;
+
+/// The prefix of every API token so leaked tokens are easy to recognize.
+pub const TOKEN_PREFIX: &str = "frs_";
+/// The amount of random characters in an API token.
+const TOKEN_LENGTH: usize = 40;
+/// The maximum length of the name of a token.
+pub const MAX_TOKEN_NAME_LENGTH: usize = 50;
+
+/**
+   What an API token is allowed to do.
+
+   Scopes are ordered; a token with a scope can also do everything the lower scopes allow.
+   A scope is only effective if the user has the role for it (moderate for moderators, admin for admins).
+*/
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Scope {
+    /// Read content as the user, including forums that are hidden from guests.
+    Read,
+    /// Create threads and posts, and edit your own posts.
+    Post,
+    /// Use the moderation tools.
+    Moderate,
+    /// Use the admin tools.
+    Admin,
+}
+
+impl Scope {
+    /// Every scope, from lowest to highest.
+    pub const ALL: [Scope; 4] = [Scope::Read, Scope::Post, Scope::Moderate, Scope::Admin];
+
+    /// The name of the scope used in forms, OAuth requests and the database.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Scope::Read => "read",
+            Scope::Post => "post",
+            Scope::Moderate => "moderate",
+            Scope::Admin => "admin"
+        }
+    }
+
+    /// A description of the scope for the token and authorization pages.
+    pub fn description(&self) -> &'static str {
+        match self {
+            Scope::Read => "Read content as you.",
+            Scope::Post => "Create threads and posts, and edit your posts.",
+            Scope::Moderate => "Use the moderation tools.",
+            Scope::Admin => "Use the admin tools."
+        }
+    }
+
+    /// Check if a user has the role needed to use the scope.
+    pub fn available_to(&self, user: &User) -> bool {
+        match self {
+            Scope::Read | Scope::Post => true,
+            Scope::Moderate => user.is_moderator || user.is_admin,
+            Scope::Admin => user.is_admin
+        }
+    }
+}
+
+impl fmt::Display for Scope {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl FromStr for Scope {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Scope::ALL.iter().copied().find(|scope| scope.as_str() == s)
+            .ok_or_else(|| format!("Unknown scope: {}", s))
+    }
+}
+
+/// Parse a list of scopes separated by spaces or commas. Duplicates are removed.
+pub fn parse_scopes(scopes: &str) -> Result<Vec<Scope>, String> {
+    let mut parsed = scopes.split(|c| c == ' ' || c == ',')
+        .filter(|scope| !scope.is_empty())
+        .map(Scope::from_str)
+        .collect::<Result<Vec<Scope>, String>>()?;
+    parsed.sort();
+    parsed.dedup();
+    Ok(parsed)
+}
+
+/// Format a list of scopes the way they are stored in the database.
+pub fn format_scopes(scopes: &[Scope]) -> String {
+    scopes.iter().map(Scope::as_str).collect::<Vec<&str>>().join(" ")
+}
+
+/// Every scope a user can grant.
+pub fn scopes_for(user: &User) -> Vec<Scope> {
+    Scope::ALL.iter().copied().filter(|scope| scope.available_to(user)).collect()
+}
+
+/**
+   Check if a set of granted scopes allows an action.
+
+   ## Params
+   granted - The scopes of the token.
+   required - The scope the action needs.
+   user - The owner of the token.
+*/
+pub fn allows(granted: &[Scope], required: Scope, user: &User) -> bool {
+    required.available_to(user) && granted.iter().any(|scope| *scope >= required)
+}
+
+/// Generate a new random API token. Only the hash of the token is stored.
+pub fn generate_token() -> String {
+    let token: String = rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(TOKEN_LENGTH)
+        .map(char::from)
+        .collect();
+    format!("{}{}", TOKEN_PREFIX, token)
+}
+
+/// Hash an API token (or OAuth client secret) for storage and lookups.
+pub fn hash_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.trim().as_bytes()))
+}
+
+/// Get the bearer token from the Authorization header of a request.
+pub fn bearer_token(req: &HttpRequest) -> Option<String> {
+    let header = req.headers().get(AUTHORIZATION)?.to_str().ok()?;
+    let (scheme, token) = header.split_at(header.find(' ')?);
+    if !scheme.eq_ignore_ascii_case("bearer") {
+        return None;
+    }
+    let token = token.trim();
+    if token.is_empty() {
+        return None;
+    }
+    Some(token.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::test::TestRequest;
+    use uuid::Uuid;
+
+    use super::*;
+
+    fn user(is_moderator: bool) -> User {
+        user_with_roles(is_moderator, false)
+    }
+
+    fn user_with_roles(is_moderator: bool, is_admin: bool) -> User {
+        User {
+            id: 1,
+            uuid: Uuid::new_v4(),
+            username: "tester".to_string(),
+            email: "tester@example.com".to_string(),
+            password: String::new(),
+            is_banned: false,
+            is_admin,
+            is_moderator,
+            password_reset: false,
+            date: 0,
+        }
+    }
+
+    #[test]
+    fn parse_scopes_sorts_and_removes_duplicates() {
+        assert_eq!(parse_scopes("post,read post"), Ok(vec![Scope::Read, Scope::Post]));
+        assert_eq!(parse_scopes(""), Ok(vec![]));
+        assert_eq!(parse_scopes("admin read"), Ok(vec![Scope::Read, Scope::Admin]));
+        assert!(parse_scopes("read owner").is_err());
+    }
+
+    #[test]
+    fn higher_scopes_allow_lower_ones() {
+        let member = user(false);
+        assert!(allows(&[Scope::Post], Scope::Read, &member));
+        assert!(!allows(&[Scope::Read], Scope::Post, &member));
+    }
+
+    #[test]
+    fn moderate_needs_the_role() {
+        assert!(!allows(&[Scope::Moderate], Scope::Moderate, &user(false)));
+        assert!(allows(&[Scope::Moderate], Scope::Moderate, &user(true)));
+        assert_eq!(scopes_for(&user(false)), vec![Scope::Read, Scope::Post]);
+    }
+
+    #[test]
+    fn admin_needs_the_admin_role() {
+        assert!(!allows(&[Scope::Admin], Scope::Admin, &user(true)));
+        assert!(allows(&[Scope::Admin], Scope::Admin, &user_with_roles(false, true)));
+        assert!(allows(&[Scope::Admin], Scope::Moderate, &user_with_roles(false, true)));
+        assert!(!allows(&[Scope::Moderate], Scope::Admin, &user_with_roles(false, true)));
+        assert_eq!(scopes_for(&user(true)), vec![Scope::Read, Scope::Post, Scope::Moderate]);
+        assert_eq!(scopes_for(&user_with_roles(false, true)), Scope::ALL.to_vec());
+    }
+
+    #[test]
+    fn tokens_are_prefixed_and_hashed_consistently() {
+        let token = generate_token();
+        assert!(token.starts_with(TOKEN_PREFIX));
+        assert_eq!(token.len(), TOKEN_PREFIX.len() + TOKEN_LENGTH);
+        assert_eq!(hash_token(token.as_str()), hash_token(format!(" {} ", token).as_str()));
+    }
+
+    #[test]
+    fn bearer_token_is_read_from_the_header() {
+        let req = TestRequest::default().header("Authorization", "Bearer frs_abc").to_http_request();
+        assert_eq!(bearer_token(&req), Some("frs_abc".to_string()));
+
+        let req = TestRequest::default().header("Authorization", "Basic dXNlcjpwYXNz").to_http_request();
+        assert_eq!(bearer_token(&req), None);
+
+        let req = TestRequest::default().header("Authorization", "Bearer ").to_http_request();
+        assert_eq!(bearer_token(&req), None);
+    }
+}
@@ -0,0 +1,339 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use actix_web::HttpRequest;
+
+use crate::settings::LoginProtectionSettings;
+
+/// The amount of records kept before stale records are cleared out.
+const MAX_RECORDS_BEFORE_PURGE: usize = 1000;
+/// The most records that are ever kept. Past this the oldest records are dropped, so a flood of
+/// failures from many IPs or accounts inside the attempt window cannot grow the map without bound.
+const MAX_RECORDS: usize = 10000;
+
+/// The failed attempts of a single account or IP.
+struct AttemptRecord {
+    failures: u32,
+    last_failure: Instant,
+    locked_until: Option<Instant>,
+}
+
+/// Why a login attempt is not allowed right now.
+#[derive(Debug, PartialEq)]
+pub enum ThrottleError {
+    /// Too many recent failures. The attempt has to wait for the duration.
+    Backoff(Duration),
+    /// The account or IP is locked for the duration.
+    Locked(Duration),
+}
+
+impl ThrottleError {
+    /// Get the amount of seconds until another attempt is allowed.
+    pub fn retry_after(&self) -> u64 {
+        match self {
+            ThrottleError::Backoff(duration) | ThrottleError::Locked(duration) => duration.as_secs().max(1)
+        }
+    }
+}
+
+/**
+   Tracks failed logins in memory and decides if a new attempt is allowed.
+
+   Records are keyed by a string so the same throttle can track accounts (`account:<name>`) and
+   client IPs (`ip:<address>`). Use [account_key] and [ip_key] to build the keys.
+*/
+pub struct LoginThrottle {
+    records: HashMap<String, AttemptRecord>,
+}
+
+/// Get the throttle key of an account.
+pub fn account_key(username: &str) -> String {
+    format!("account:{}", username.to_lowercase())
+}
+
+/// Get the throttle key of a client IP.
+pub fn ip_key(ip: &str) -> String {
+    format!("ip:{}", ip)
+}
+
+impl LoginThrottle {
+    pub fn new() -> LoginThrottle {
+        LoginThrottle {
+            records: HashMap::new()
+        }
+    }
+
+    /**
+       Check if an attempt is allowed for all of the keys.
+
+       ## Returns
+       An empty result if the attempt is allowed, the longest wait of the keys if not.
+    */
+    pub fn check(&mut self, keys: &[&str], settings: &LoginProtectionSettings) -> Result<(), ThrottleError> {
+        let now = Instant::now();
+        let window = Duration::from_secs(settings.attempt_window_minutes * 60);
+        let mut result: Result<(), ThrottleError> = Ok(());
+
+        for key in keys {
+            let expired = match self.records.get(*key) {
+                Some(record) => now.duration_since(record.last_failure) > window
+                    && record.locked_until.map(|until| until <= now).unwrap_or(true),
+                None => continue
+            };
+            if expired {
+                self.records.remove(*key);
+                continue;
+            }
+
+            let record = self.records.get(*key).unwrap();
+            let error = if let Some(until) = record.locked_until.filter(|until| *until > now) {
+                Some(ThrottleError::Locked(until - now))
+            } else if record.failures > settings.free_attempts {
+                let exponent = (record.failures - settings.free_attempts - 1).min(32);
+                let wait = settings.backoff_base_seconds.saturating_mul(1_u64 << exponent).min(settings.backoff_max_seconds);
+                let allowed_at = record.last_failure + Duration::from_secs(wait);
+                if allowed_at > now { Some(ThrottleError::Backoff(allowed_at - now)) } else { None }
+            } else {
+                None
+            };
+
+            // Keep whichever error makes the client wait the longest, with a lock taking priority.
+            if let Some(error) = error {
+                result = match result {
+                    Ok(()) => Err(error),
+                    Err(current) => {
+                        let keep_current = match (&current, &error) {
+                            (ThrottleError::Locked(_), ThrottleError::Backoff(_)) => true,
+                            (ThrottleError::Backoff(_), ThrottleError::Locked(_)) => false,
+                            _ => current.retry_after() >= error.retry_after()
+                        };
+                        if keep_current { Err(current) } else { Err(error) }
+                    }
+                };
+            }
+        }
+
+        result
+    }
+
+    /**
+       Record a failed attempt for a key.
+
+       ## Params
+       key - The account or ip key.
+       lockout_threshold - The amount of failures before the key is locked. (0 to disable)
+
+       ## Returns
+       If the key has become locked.
+    */
+    pub fn record_failure(&mut self, key: &str, lockout_threshold: u32, settings: &LoginProtectionSettings) -> bool {
+        if self.records.len() > MAX_RECORDS_BEFORE_PURGE {
+            self.purge(settings);
+        }
+        if !self.records.contains_key(key) {
+            self.evict_to(MAX_RECORDS - 1);
+        }
+
+        let now = Instant::now();
+        let record = self.records.entry(key.to_string()).or_insert(AttemptRecord {
+            failures: 0,
+            last_failure: now,
+            locked_until: None,
+        });
+        // A lock that ran out starts a new count, so the key is locked again after another run of failures.
+        if record.locked_until.map(|until| until <= now).unwrap_or(false) {
+            record.failures = 0;
+            record.locked_until = None;
+        }
+        record.failures += 1;
+        record.last_failure = now;
+
+        if lockout_threshold > 0 && record.failures >= lockout_threshold && record.locked_until.is_none() {
+            record.locked_until = Some(now + Duration::from_secs(settings.lockout_minutes * 60));
+            return true;
+        }
+        false
+    }
+
+    /// Clear the failed attempts of a key after a successful login.
+    pub fn record_success(&mut self, key: &str) {
+        self.records.remove(key);
+    }
+
+    /// Remove every record that is outside of the attempt window and is not locked.
+    fn purge(&mut self, settings: &LoginProtectionSettings) {
+        let now = Instant::now();
+        let window = Duration::from_secs(settings.attempt_window_minutes * 60);
+        self.records.retain(|_, record| {
+            now.duration_since(record.last_failure) <= window || record.locked_until.map(|until| until > now).unwrap_or(false)
+        });
+    }
+
+    /// Drop the oldest records until at most `max` are left. Unlocked records go first, so locks are only lost
+    /// when every record left is locked.
+    fn evict_to(&mut self, max: usize) {
+        let now = Instant::now();
+        while self.records.len() > max {
+            let oldest = self.records.iter()
+                .min_by_key(|(_, record)| (record.locked_until.map(|until| until > now).unwrap_or(false), record.last_failure))
+                .map(|(key, _)| key.clone());
+            match oldest {
+                Some(key) => self.records.remove(&key),
+                None => break
+            };
+        }
+    }
+}
+
+/// Get the IP of the client that made a request.
+///
+/// The forwarded headers are only used when `trust_proxy_headers` is enabled, since any client can set them.
+pub fn client_ip(req: &HttpRequest, settings: &LoginProtectionSettings) -> String {
+    if settings.trust_proxy_headers {
+        if let Some(ip) = req.connection_info().realip_remote_addr() {
+            // The address may include a port.
+            return match ip.parse::<std::net::SocketAddr>() {
+                Ok(addr) => addr.ip().to_string(),
+                Err(_) => ip.to_string()
+            };
+        }
+    }
+
+    req.peer_addr().map(|addr| addr.ip().to_string()).unwrap_or_else(|| "unknown".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings() -> LoginProtectionSettings {
+        LoginProtectionSettings {
+            free_attempts: 2,
+            backoff_base_seconds: 10,
+            backoff_max_seconds: 60,
+            account_lockout_threshold: 5,
+            ip_lockout_threshold: 0,
+            lockout_minutes: 15,
+            attempt_window_minutes: 60,
+            trust_proxy_headers: false,
+        }
+    }
+
+    #[test]
+    fn free_attempts_are_allowed() {
+        let settings = settings();
+        let mut throttle = LoginThrottle::new();
+        let key = account_key("Admin");
+        for _ in 0..settings.free_attempts {
+            assert!(!throttle.record_failure(key.as_str(), 5, &settings));
+        }
+        assert_eq!(throttle.check(&[key.as_str()], &settings), Ok(()));
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_the_max() {
+        let settings = settings();
+        let mut throttle = LoginThrottle::new();
+        let key = account_key("admin");
+
+        throttle.record_failure(key.as_str(), 0, &settings);
+        throttle.record_failure(key.as_str(), 0, &settings);
+        throttle.record_failure(key.as_str(), 0, &settings);
+        match throttle.check(&[key.as_str()], &settings) {
+            Err(ThrottleError::Backoff(wait)) => assert!(wait <= Duration::from_secs(10) && wait > Duration::from_secs(8)),
+            other => panic!("expected a backoff, got {:?}", other)
+        }
+
+        for _ in 0..10 {
+            throttle.record_failure(key.as_str(), 0, &settings);
+        }
+        match throttle.check(&[key.as_str()], &settings) {
+            Err(ThrottleError::Backoff(wait)) => assert!(wait <= Duration::from_secs(60) && wait > Duration::from_secs(58)),
+            other => panic!("expected a backoff, got {:?}", other)
+        }
+    }
+
+    #[test]
+    fn locks_at_the_threshold() {
+        let settings = settings();
+        let mut throttle = LoginThrottle::new();
+        let key = account_key("admin");
+        for _ in 0..4 {
+            assert!(!throttle.record_failure(key.as_str(), 5, &settings));
+        }
+        assert!(throttle.record_failure(key.as_str(), 5, &settings));
+        assert!(matches!(throttle.check(&[key.as_str()], &settings), Err(ThrottleError::Locked(_))));
+    }
+
+    #[test]
+    fn locks_again_after_the_lock_expires() {
+        let settings = settings();
+        let mut throttle = LoginThrottle::new();
+        let key = account_key("admin");
+        for _ in 0..5 {
+            throttle.record_failure(key.as_str(), 5, &settings);
+        }
+
+        // Pretend the lock ran out while the failures are still inside the window.
+        let past = Instant::now().checked_sub(Duration::from_secs(1)).unwrap();
+        throttle.records.get_mut(key.as_str()).unwrap().locked_until = Some(past);
+
+        for _ in 0..4 {
+            assert!(!throttle.record_failure(key.as_str(), 5, &settings));
+        }
+        assert!(throttle.record_failure(key.as_str(), 5, &settings));
+        assert!(matches!(throttle.check(&[key.as_str()], &settings), Err(ThrottleError::Locked(_))));
+    }
+
+    #[test]
+    fn success_clears_the_record() {
+        let settings = settings();
+        let mut throttle = LoginThrottle::new();
+        let key = ip_key("127.0.0.1");
+        for _ in 0..4 {
+            throttle.record_failure(key.as_str(), 0, &settings);
+        }
+        throttle.record_success(key.as_str());
+        assert_eq!(throttle.check(&[key.as_str()], &settings), Ok(()));
+    }
+
+    #[test]
+    fn records_are_capped_and_the_oldest_unlocked_go_first() {
+        let settings = settings();
+        let mut throttle = LoginThrottle::new();
+        let locked = account_key("admin");
+        for _ in 0..5 {
+            throttle.record_failure(locked.as_str(), 5, &settings);
+        }
+        // Every one of these is inside the attempt window, so purging alone would keep them all.
+        for i in 1..MAX_RECORDS {
+            throttle.record_failure(ip_key(i.to_string().as_str()).as_str(), 0, &settings);
+        }
+        let oldest = ip_key("1");
+        let now = Instant::now();
+        throttle.records.get_mut(oldest.as_str()).unwrap().last_failure = now.checked_sub(Duration::from_secs(60)).unwrap();
+        throttle.records.get_mut(locked.as_str()).unwrap().last_failure = now.checked_sub(Duration::from_secs(120)).unwrap();
+
+        let newest = ip_key("new");
+        throttle.record_failure(newest.as_str(), 0, &settings);
+        assert_eq!(throttle.records.len(), MAX_RECORDS);
+        assert!(throttle.records.contains_key(newest.as_str()));
+        assert!(!throttle.records.contains_key(oldest.as_str()));
+        assert!(matches!(throttle.check(&[locked.as_str()], &settings), Err(ThrottleError::Locked(_))));
+    }
+
+    #[test]
+    fn longest_wait_wins_and_locks_take_priority() {
+        let settings = settings();
+        let mut throttle = LoginThrottle::new();
+        let account = account_key("admin");
+        let ip = ip_key("127.0.0.1");
+        for _ in 0..5 {
+            throttle.record_failure(account.as_str(), 5, &settings);
+        }
+        for _ in 0..20 {
+            throttle.record_failure(ip.as_str(), 0, &settings);
+        }
+        assert!(matches!(throttle.check(&[ip.as_str(), account.as_str()], &settings), Err(ThrottleError::Locked(_))));
+    }
+}
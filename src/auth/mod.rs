@@ -0,0 +1,6 @@
+pub mod password_policy;
+pub mod two_factor;
+pub mod session;
+pub mod login_protection;
+pub mod api_token;
+pub mod account_data;
@@ -0,0 +1,307 @@
+use std::fmt;
+use std::fmt::Formatter;
+use std::fs;
+
+use crate::settings::PasswordSettings;
+
+/// bcrypt ignores everything in a password past this many bytes, so no password can be longer.
+pub const BCRYPT_MAX_BYTES: u32 = 72;
+
+/// A small list of very common passwords that are always denied when `deny_common` is enabled.
+const COMMON_PASSWORDS: [&str; 30] = [
+    "password", "password1", "password123", "123456", "12345678", "123456789", "1234567890",
+    "qwerty", "qwerty123", "abc123", "111111", "123123", "admin", "admin123", "letmein",
+    "welcome", "welcome1", "monkey", "dragon", "football", "iloveyou", "sunshine", "princess",
+    "master", "trustno1", "baseball", "shadow", "superman", "passw0rd", "p@ssw0rd",
+];
+
+/// The reason a password was rejected by the password policy.
+#[derive(Debug, PartialEq)]
+pub enum PasswordPolicyError {
+    /// The password is shorter than the minimum length.
+    TooShort(u32),
+    /// The password is longer than the maximum length in bytes.
+    TooLong(u32),
+    /// The password does not have enough uppercase letters.
+    MissingUppercase(u32),
+    /// The password does not have enough lowercase letters.
+    MissingLowercase(u32),
+    /// The password does not have enough digits.
+    MissingDigits(u32),
+    /// The password does not have enough special characters.
+    MissingSpecial(u32),
+    /// The password is on the deny list.
+    Common,
+    /// The strength score of the password is too low.
+    TooWeak(u8),
+}
+
+impl PasswordPolicyError {
+    /// Get the error code used in the `err` query parameter of the forms.
+    pub fn error_code(&self) -> u32 {
+        match self {
+            PasswordPolicyError::TooShort(_) => 5,
+            PasswordPolicyError::TooLong(_) => 6,
+            PasswordPolicyError::MissingUppercase(_) => 7,
+            PasswordPolicyError::MissingLowercase(_) => 8,
+            PasswordPolicyError::MissingDigits(_) => 9,
+            PasswordPolicyError::MissingSpecial(_) => 10,
+            PasswordPolicyError::Common => 11,
+            PasswordPolicyError::TooWeak(_) => 12,
+        }
+    }
+}
+
+impl fmt::Display for PasswordPolicyError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            PasswordPolicyError::TooShort(min) => write!(f, "The password must be at least {} characters long.", min),
+            PasswordPolicyError::TooLong(max) => write!(f, "The password cannot be longer than {} bytes. Accented letters and symbols take up more than one byte.", max),
+            PasswordPolicyError::MissingUppercase(min) => write!(f, "The password must contain at least {} uppercase letter(s).", min),
+            PasswordPolicyError::MissingLowercase(min) => write!(f, "The password must contain at least {} lowercase letter(s).", min),
+            PasswordPolicyError::MissingDigits(min) => write!(f, "The password must contain at least {} digit(s).", min),
+            PasswordPolicyError::MissingSpecial(min) => write!(f, "The password must contain at least {} special character(s).", min),
+            PasswordPolicyError::Common => write!(f, "The password is too common. Please choose a different password."),
+            PasswordPolicyError::TooWeak(min) => write!(f, "The password is too easy to guess. It needs a strength score of at least {} out of 4.", min),
+        }
+    }
+}
+
+/**
+   The password policy for the website.
+
+   This should be used any time a password is set (setup, registration, password change and reset)
+   so every password follows the same rules.
+*/
+pub struct PasswordPolicy {
+    settings: PasswordSettings,
+    deny_list: Vec<String>,
+}
+
+impl PasswordPolicy {
+    /// Construct a password policy from the password settings.
+    ///
+    /// If a deny list file is configured but cannot be read, a warning is printed and only the built-in
+    /// list is used.
+    pub fn new(settings: &PasswordSettings) -> PasswordPolicy {
+        let mut deny_list: Vec<String> = Vec::new();
+        if let Some(file) = &settings.deny_list_file {
+            match fs::read_to_string(file) {
+                Ok(contents) => {
+                    deny_list = contents.lines()
+                        .map(|line| line.trim().to_lowercase())
+                        .filter(|line| !line.is_empty())
+                        .collect();
+                }
+                Err(err) => {
+                    println!("[WARN] Unable to read the password deny list {}: {}", file, err);
+                }
+            }
+        }
+
+        PasswordPolicy {
+            settings: settings.clone(),
+            deny_list,
+        }
+    }
+
+    /**
+       Validate a password against the policy.
+
+       ## Params
+       password - The password to validate.
+       user_inputs - Other values from the form (username, email) that should not make the password stronger.
+
+       ## Returns
+       An empty result if ok, the first rule that failed if not.
+    */
+    pub fn validate(&self, password: &str, user_inputs: &[&str]) -> Result<(), PasswordPolicyError> {
+        if (password.chars().count() as u32) < self.settings.min_length {
+            return Err(PasswordPolicyError::TooShort(self.settings.min_length));
+        }
+
+        // The maximum is in bytes, as that is where bcrypt cuts the password off.
+        let max_length = self.settings.max_length.min(BCRYPT_MAX_BYTES);
+        if password.len() as u32 > max_length {
+            return Err(PasswordPolicyError::TooLong(max_length));
+        }
+
+        let uppercase = password.chars().filter(|c| c.is_uppercase()).count() as u32;
+        if uppercase < self.settings.min_uppercase {
+            return Err(PasswordPolicyError::MissingUppercase(self.settings.min_uppercase));
+        }
+
+        let lowercase = password.chars().filter(|c| c.is_lowercase()).count() as u32;
+        if lowercase < self.settings.min_lowercase {
+            return Err(PasswordPolicyError::MissingLowercase(self.settings.min_lowercase));
+        }
+
+        let digits = password.chars().filter(|c| c.is_ascii_digit()).count() as u32;
+        if digits < self.settings.min_digits {
+            return Err(PasswordPolicyError::MissingDigits(self.settings.min_digits));
+        }
+
+        let special = password.chars().filter(|c| !c.is_alphanumeric() && !c.is_whitespace()).count() as u32;
+        if special < self.settings.min_special {
+            return Err(PasswordPolicyError::MissingSpecial(self.settings.min_special));
+        }
+
+        if self.settings.deny_common {
+            let lowercase_password = password.to_lowercase();
+            if COMMON_PASSWORDS.contains(&lowercase_password.as_str()) || self.deny_list.contains(&lowercase_password) {
+                return Err(PasswordPolicyError::Common);
+            }
+        }
+
+        if let Some(min_strength) = self.settings.min_strength {
+            // zxcvbn only errors on a blank password, which is the weakest possible password.
+            let score = zxcvbn::zxcvbn(password, user_inputs).map(|entropy| entropy.score()).unwrap_or(0);
+            if score < min_strength {
+                return Err(PasswordPolicyError::TooWeak(min_strength));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Get a human readable description of the policy. This is used in the help popovers of the forms.
+    pub fn describe(&self) -> String {
+        let mut description = format!("The password must be at least {} characters and at most {} bytes long.",
+                                      self.settings.min_length, self.settings.max_length);
+        let requirements: Vec<String> = vec![
+            (self.settings.min_uppercase, "uppercase letter(s)"),
+            (self.settings.min_lowercase, "lowercase letter(s)"),
+            (self.settings.min_digits, "digit(s)"),
+            (self.settings.min_special, "special character(s)"),
+        ].into_iter()
+            .filter(|(count, _)| *count > 0)
+            .map(|(count, name)| format!("{} {}", count, name))
+            .collect();
+
+        if !requirements.is_empty() {
+            description.push_str(&format!(" It needs at least {}.", requirements.join(", ")));
+        }
+
+        if self.settings.deny_common {
+            description.push_str(" Common passwords are not allowed.");
+        }
+
+        description
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Settings without any character requirements, so a single rule can be tested at a time.
+    fn relaxed() -> PasswordSettings {
+        PasswordSettings {
+            min_length: 4,
+            max_length: 72,
+            min_uppercase: 0,
+            min_lowercase: 0,
+            min_digits: 0,
+            min_special: 0,
+            deny_common: false,
+            deny_list_file: None,
+            min_strength: None,
+        }
+    }
+
+    #[test]
+    fn default_policy() {
+        let policy = PasswordPolicy::new(&PasswordSettings::default());
+        assert_eq!(policy.validate("ABcde12!", &[]), Ok(()));
+        assert_eq!(policy.validate("ABcd12!", &[]), Err(PasswordPolicyError::TooShort(8)));
+        assert_eq!(policy.validate("Abcde12!", &[]), Err(PasswordPolicyError::MissingUppercase(2)));
+        assert_eq!(policy.validate("ABCde12!", &[]), Err(PasswordPolicyError::MissingLowercase(3)));
+        assert_eq!(policy.validate("ABcde1!x", &[]), Err(PasswordPolicyError::MissingDigits(2)));
+        assert_eq!(policy.validate("ABcde123", &[]), Err(PasswordPolicyError::MissingSpecial(1)));
+    }
+
+    #[test]
+    fn minimum_counts_characters() {
+        let policy = PasswordPolicy::new(&PasswordSettings { min_length: 5, ..relaxed() });
+        assert_eq!(policy.validate("äöüß", &[]), Err(PasswordPolicyError::TooShort(5)));
+        assert_eq!(policy.validate("äöüßé", &[]), Ok(()));
+    }
+
+    #[test]
+    fn maximum_counts_bytes() {
+        let policy = PasswordPolicy::new(&PasswordSettings::default());
+        let ascii = format!("ABcde12!{}", "x".repeat(64));
+        assert_eq!(policy.validate(&ascii, &[]), Ok(()));
+        // 36 characters, but 72 bytes of them would be cut off by bcrypt.
+        let accented = format!("ABcde12!{}", "é".repeat(32));
+        assert_eq!(policy.validate(&accented, &[]), Err(PasswordPolicyError::TooLong(72)));
+        assert_eq!(policy.validate(&format!("{}x", ascii), &[]), Err(PasswordPolicyError::TooLong(72)));
+    }
+
+    #[test]
+    fn common_passwords() {
+        let policy = PasswordPolicy::new(&PasswordSettings { deny_common: true, ..relaxed() });
+        assert_eq!(policy.validate("Password123", &[]), Err(PasswordPolicyError::Common));
+        assert_eq!(policy.validate("correct horse", &[]), Ok(()));
+
+        let policy = PasswordPolicy::new(&relaxed());
+        assert_eq!(policy.validate("password123", &[]), Ok(()));
+    }
+
+    #[test]
+    fn deny_list_file() {
+        let file = std::env::temp_dir().join(format!("forumrs-deny-list-{}.txt", std::process::id()));
+        fs::write(&file, "  Hunter2 \n\ncorrecthorse\n").unwrap();
+        let policy = PasswordPolicy::new(&PasswordSettings {
+            deny_common: true,
+            deny_list_file: Some(file.to_string_lossy().to_string()),
+            ..relaxed()
+        });
+        fs::remove_file(&file).unwrap();
+
+        assert_eq!(policy.validate("hunter2", &[]), Err(PasswordPolicyError::Common));
+        assert_eq!(policy.validate("CorrectHorse", &[]), Err(PasswordPolicyError::Common));
+        assert_eq!(policy.validate("hunter3", &[]), Ok(()));
+    }
+
+    #[test]
+    fn missing_deny_list_file() {
+        let policy = PasswordPolicy::new(&PasswordSettings {
+            deny_common: true,
+            deny_list_file: Some("/nonexistent/deny-list.txt".to_string()),
+            ..relaxed()
+        });
+        assert_eq!(policy.validate("letmein", &[]), Err(PasswordPolicyError::Common));
+        assert_eq!(policy.validate("hunter2", &[]), Ok(()));
+    }
+
+    #[test]
+    fn strength_uses_user_inputs() {
+        let policy = PasswordPolicy::new(&PasswordSettings { min_strength: Some(3), ..relaxed() });
+        assert_eq!(policy.validate("aaaa", &[]), Err(PasswordPolicyError::TooWeak(3)));
+        assert_eq!(policy.validate("wombat-kettle-29-lantern", &[]), Ok(()));
+        assert_eq!(policy.validate("jsmith", &["jsmith"]), Err(PasswordPolicyError::TooWeak(3)));
+    }
+
+    #[test]
+    fn error_codes_are_unique() {
+        let errors = [
+            PasswordPolicyError::TooShort(0), PasswordPolicyError::TooLong(0),
+            PasswordPolicyError::MissingUppercase(0), PasswordPolicyError::MissingLowercase(0),
+            PasswordPolicyError::MissingDigits(0), PasswordPolicyError::MissingSpecial(0),
+            PasswordPolicyError::Common, PasswordPolicyError::TooWeak(0),
+        ];
+        let mut codes: Vec<u32> = errors.iter().map(|err| err.error_code()).collect();
+        codes.sort_unstable();
+        codes.dedup();
+        assert_eq!(codes.len(), errors.len());
+    }
+
+    #[test]
+    fn describe() {
+        assert_eq!(PasswordPolicy::new(&PasswordSettings::default()).describe(),
+                   "The password must be at least 8 characters and at most 72 bytes long. It needs at least 2 uppercase letter(s), \
+                    3 lowercase letter(s), 2 digit(s), 1 special character(s). Common passwords are not allowed.");
+        assert_eq!(PasswordPolicy::new(&relaxed()).describe(), "The password must be at least 4 characters and at most 72 bytes long.");
+    }
+}
@@ -0,0 +1,49 @@
+use std::str::FromStr;
+
+use actix_web::{http, HttpMessage, HttpRequest};
+use uuid::Uuid;
+
+use crate::schema::database::Database;
+use crate::schema::tables::{Sessions, User, Users};
+
+/// The name of the cookie that stores the session id.
+pub const SESSION_COOKIE: &str = "session";
+
+/// Build the cookie used to store the session id of a user.
+pub fn session_cookie(session_id: Uuid) -> http::Cookie<'static> {
+    http::Cookie::build(SESSION_COOKIE, session_id.to_string())
+        .path("/")
+        .secure(true)
+        .http_only(true)
+        .finish()
+}
+
+/// Get the session id from the session cookie of the request.
+pub fn session_id(req: &HttpRequest) -> Option<Uuid> {
+    req.cookie(SESSION_COOKIE).and_then(|cookie| Uuid::from_str(cookie.value()).ok())
+}
+
+/**
+   Get the user that is logged in for a request.
+
+   ## Returns
+   The user if the session cookie belongs to a valid session of a user that is not banned.
+*/
+pub async fn current_user(db: &mut Database, req: &HttpRequest) -> Option<User> {
+    let session_id = session_id(req)?;
+    let user_uuid = Sessions::get_user_uuid(db, session_id).await.ok()??;
+    let user = Users::get_by_uuid(db, user_uuid).await.ok()??;
+
+    if user.is_banned {
+        return None;
+    }
+
+    Some(user)
+}
+
+/// Create a new session for a user and return the session id.
+pub async fn create_session(db: &mut Database, user: &User) -> Result<Uuid, sqlx::Error> {
+    let session_id = Uuid::new_v4();
+    Sessions::insert(db, session_id, user.uuid).await?;
+    Ok(session_id)
+}
@@ -0,0 +1,182 @@
+use hmac::{Hmac, Mac, NewMac};
+use qrcode::QrCode;
+use qrcode::render::svg;
+use rand::Rng;
+use rand::distributions::Alphanumeric;
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+
+use crate::schema::tables::{current_time, User};
+use crate::settings::TwoFactorSettings;
+
+/// The amount of seconds a TOTP code is valid for.
+const TIME_STEP: i64 = 30;
+/// The amount of digits in a TOTP code.
+const CODE_DIGITS: u32 = 6;
+/// The amount of recovery codes generated for a user.
+pub const RECOVERY_CODE_AMOUNT: usize = 10;
+
+/// Generate a new random TOTP secret. The secret is base32 encoded so it can be entered by hand.
+pub fn generate_secret() -> String {
+    let bytes: [u8; 20] = rand::thread_rng().gen();
+    base32::encode(base32::Alphabet::RFC4648 { padding: false }, &bytes)
+}
+
+/// Get the `otpauth://` uri that authenticator apps use to add an account.
+pub fn provisioning_uri(secret: &str, site_name: &str, username: &str) -> String {
+    format!("otpauth://totp/{}:{}?secret={}&issuer={}&digits={}&period={}",
+            urlencoding::encode(site_name), urlencoding::encode(username), secret,
+            urlencoding::encode(site_name), CODE_DIGITS, TIME_STEP)
+}
+
+/// Render the provisioning uri as an SVG QR code.
+pub fn provisioning_qr_svg(uri: &str) -> String {
+    match QrCode::new(uri.as_bytes()) {
+        Ok(code) => code.render::<svg::Color>().min_dimensions(200, 200).build(),
+        Err(_) => String::new()
+    }
+}
+
+/// Generate the TOTP code for a time step (RFC 6238).
+fn code_for_step(secret: &[u8], step: i64) -> u32 {
+    let mut mac = Hmac::<Sha1>::new_from_slice(secret).unwrap();
+    mac.update(&(step as u64).to_be_bytes());
+    let hash = mac.finalize().into_bytes();
+
+    let offset = (hash[hash.len() - 1] & 0x0f) as usize;
+    let binary = ((hash[offset] as u32 & 0x7f) << 24)
+        | ((hash[offset + 1] as u32) << 16)
+        | ((hash[offset + 2] as u32) << 8)
+        | (hash[offset + 3] as u32);
+
+    binary % 10_u32.pow(CODE_DIGITS)
+}
+
+/**
+   Verify a TOTP code. Codes from one step before and after the current one are accepted
+   to allow for clock drift.
+
+   ## Params
+   secret - The base32 encoded secret.
+   code - The code entered by the user.
+   last_step - The last step that was used by the user. Codes at or before this step are rejected.
+
+   ## Returns
+   The time step of the code if it is valid.
+*/
+pub fn verify_code(secret: &str, code: &str, last_step: i64) -> Option<i64> {
+    let secret = base32::decode(base32::Alphabet::RFC4648 { padding: false }, secret)?;
+    let code = code.trim().replace(' ', "");
+    if code.len() != CODE_DIGITS as usize {
+        return None;
+    }
+    let code = code.parse::<u32>().ok()?;
+
+    let current_step = current_time() / TIME_STEP;
+    (current_step - 1..=current_step + 1)
+        .filter(|step| *step > last_step)
+        .find(|step| code_for_step(&secret, *step) == code)
+}
+
+/// Generate a new set of recovery codes. These are shown to the user once and only the hashes are stored.
+pub fn generate_recovery_codes() -> Vec<String> {
+    (0..RECOVERY_CODE_AMOUNT).map(|_| {
+        let code: String = rand::thread_rng()
+            .sample_iter(&Alphanumeric)
+            .take(10)
+            .map(char::from)
+            .collect::<String>()
+            .to_lowercase();
+        format!("{}-{}", &code[..5], &code[5..])
+    }).collect()
+}
+
+/// Hash a recovery code for storage. The codes are random, so a fast hash is enough.
+pub fn hash_recovery_code(code: &str) -> String {
+    let normalized = code.trim().to_lowercase().replace(' ', "").replace('-', "");
+    hex::encode(Sha256::digest(normalized.as_bytes()))
+}
+
+/// Check if a user is required to have two factor authentication enabled.
+pub fn is_required(user: &User, settings: &TwoFactorSettings) -> bool {
+    (user.is_admin && settings.require_for_admins) || (user.is_moderator && settings.require_for_moderators)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The SHA1 secret of the RFC 6238 test vectors.
+    const RFC_SECRET: &[u8] = b"12345678901234567890";
+
+    fn encoded(secret: &[u8]) -> String {
+        base32::encode(base32::Alphabet::RFC4648 { padding: false }, secret)
+    }
+
+    #[test]
+    fn rfc_6238_vectors() {
+        // The RFC lists 8 digit codes, these are their last 6 digits.
+        let vectors = [
+            (59, 287082),
+            (1111111109, 81804),
+            (1111111111, 50471),
+            (1234567890, 5924),
+            (2000000000, 279037),
+            (20000000000, 353130),
+        ];
+        for (time, code) in vectors.iter() {
+            assert_eq!(code_for_step(RFC_SECRET, time / TIME_STEP), *code, "time {}", time);
+        }
+    }
+
+    #[test]
+    fn verify_current_code() {
+        let secret = encoded(RFC_SECRET);
+        let step = current_time() / TIME_STEP;
+        let code = format!("{:06}", code_for_step(RFC_SECRET, step));
+
+        let used = verify_code(&secret, &code, 0).expect("The current code was rejected.");
+        assert!((step..=step + 1).contains(&used));
+        assert_eq!(verify_code(&secret, &format!(" {} {} ", &code[..3], &code[3..]), 0), Some(used));
+    }
+
+    #[test]
+    fn reject_reused_code() {
+        let secret = encoded(RFC_SECRET);
+        let step = current_time() / TIME_STEP;
+        let code = format!("{:06}", code_for_step(RFC_SECRET, step));
+        assert_eq!(verify_code(&secret, &code, step + 1), None);
+    }
+
+    #[test]
+    fn reject_malformed_code() {
+        let secret = encoded(RFC_SECRET);
+        assert_eq!(verify_code(&secret, "12345", 0), None);
+        assert_eq!(verify_code(&secret, "1234567", 0), None);
+        assert_eq!(verify_code(&secret, "abcdef", 0), None);
+        assert_eq!(verify_code("not base32!", "123456", 0), None);
+    }
+
+    #[test]
+    fn generated_secret_decodes() {
+        let secret = generate_secret();
+        let decoded = base32::decode(base32::Alphabet::RFC4648 { padding: false }, &secret).unwrap();
+        assert_eq!(decoded.len(), 20);
+    }
+
+    #[test]
+    fn recovery_codes() {
+        let codes = generate_recovery_codes();
+        assert_eq!(codes.len(), RECOVERY_CODE_AMOUNT);
+        for code in &codes {
+            assert_eq!(code.len(), 11);
+            assert_eq!(&code[5..6], "-");
+        }
+    }
+
+    #[test]
+    fn recovery_code_hash_is_normalized() {
+        assert_eq!(hash_recovery_code("abcde-fghij"), hash_recovery_code(" ABCDE FGHIJ "));
+        assert_ne!(hash_recovery_code("abcde-fghij"), hash_recovery_code("abcde-fghik"));
+    }
+}
@@ -0,0 +1,479 @@
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::{BufRead, BufReader, BufWriter, Read, Write};
+use std::path::{Component, Path, PathBuf};
+
+use chrono::Utc;
+use flate2::Compression;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+use crate::schema::database::Database;
+use crate::schema::migrations::SCHEMA_VERSION;
+use crate::schema::tables::{current_time, SchemaVersion};
+use crate::schema::transfer;
+use crate::schema::transfer::TABLES;
+use crate::settings::{AttachmentStorageType, BaseSettings};
+
+pub mod schedule;
+
+/// The version of the archive format. Archives with a newer format cannot be restored.
+const FORMAT_VERSION: u32 = 1;
+/// The name of backup archives starts with this.
+const PREFIX: &str = "forumrs-backup-";
+/// The file extension of backup archives.
+const EXTENSION: &str = ".tar.gz";
+/// The name of the manifest in the archive.
+const MANIFEST: &str = "manifest.json";
+/// How many rows are read or written at a time.
+const BATCH_SIZE: i64 = 500;
+
+/**
+   The description of a backup, stored as `manifest.json` in the archive.
+
+   Every table is stored as a JSON Lines file with one object per row, and every uploaded file is stored under
+   `files/`. Timestamps are unix time, so a backup can be restored to any type of database.
+*/
+#[derive(Serialize, Deserialize)]
+pub struct Manifest {
+    /// The version of the archive format.
+    pub format: u32,
+    /// The version of ForumRS that made the backup.
+    pub forumrs_version: String,
+    /// The version of the schema the tables were read from.
+    pub schema_version: i32,
+    /// The unix time the backup was made.
+    pub created: i64,
+    /// The type of database the backup was made from.
+    pub database_type: String,
+    pub tables: Vec<ManifestTable>,
+    pub files: Vec<ManifestFile>,
+}
+
+/// A table in a backup.
+#[derive(Serialize, Deserialize)]
+pub struct ManifestTable {
+    pub name: String,
+    pub columns: Vec<String>,
+    pub rows: i64,
+    /// The location of the JSON Lines file in the archive.
+    pub path: String,
+    pub sha256: String,
+}
+
+/// An uploaded file in a backup.
+#[derive(Serialize, Deserialize)]
+pub struct ManifestFile {
+    /// The location of the file in the archive. (ex: files/attachments/...)
+    pub path: String,
+    pub size: u64,
+    pub sha256: String,
+}
+
+/// The directories of uploaded files that are included in backups, by the name of their directory in the archive.
+/// Attachments kept in an object storage are not included, the storage has to be backed up on its own.
+pub fn file_directories(settings: &BaseSettings) -> Vec<(&'static str, PathBuf)> {
+    let mut directories = Vec::new();
+    if settings.attachment_settings.storage == AttachmentStorageType::Local {
+        directories.push(("attachments", PathBuf::from(&settings.attachment_settings.directory)));
+    }
+    directories
+}
+
+/// Check if a file name is the name of a backup archive.
+fn is_backup_name(name: &str) -> bool {
+    name.starts_with(PREFIX) && name.ends_with(EXTENSION)
+}
+
+/// Get the hash of a file.
+fn sha256_file(path: &Path) -> io::Result<String> {
+    let mut file = File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buffer = [0_u8; 8192];
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+    Ok(hex::encode(hasher.finalize()))
+}
+
+/// Get a directory to build or unpack an archive in.
+fn staging_directory() -> PathBuf {
+    std::env::temp_dir().join(format!("forumrs-backup-{}", Uuid::new_v4()))
+}
+
+/// Get the location of a path from the manifest in the unpacked archive. Paths that leave the archive are refused.
+fn unpacked_path(staging: &Path, path: &str) -> Result<PathBuf, String> {
+    let relative = Path::new(path);
+    if path.is_empty() || !relative.components().all(|component| matches!(component, Component::Normal(_))) {
+        return Err(format!("The backup has an invalid path: {}", path));
+    }
+    Ok(staging.join(relative))
+}
+
+/// Get every file in a directory and its sub directories, relative to the directory.
+fn list_files(directory: &Path, relative: &Path, files: &mut Vec<PathBuf>) -> io::Result<()> {
+    for entry in fs::read_dir(directory.join(relative))? {
+        let entry = entry?;
+        let path = relative.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            list_files(directory, &path, files)?;
+        } else {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Convert a relative path to the form used in the archive, which always uses `/`.
+fn archive_path(path: &Path) -> String {
+    path.components()
+        .map(|component| component.as_os_str().to_string_lossy().to_string())
+        .collect::<Vec<String>>()
+        .join("/")
+}
+
+/**
+   Make a backup of the forum in the backup directory.
+
+   The archive is written under a temporary name and only renamed once it is complete, so an unfinished backup is
+   never listed, restored or counted as one of the backups to keep.
+
+   ## Returns
+   The location of the backup.
+*/
+pub async fn create_backup(db: &mut Database, settings: &BaseSettings) -> Result<PathBuf, String> {
+    let directory = Path::new(&settings.backup_settings.directory);
+    fs::create_dir_all(directory).map_err(|err| format!("Unable to create the backup directory {}: {}", directory.display(), err))?;
+
+    let path = directory.join(format!("{}{}{}", PREFIX, Utc::now().format("%Y%m%d-%H%M%S"), EXTENSION));
+    let staging = staging_directory();
+    let result = write_archive(db, settings, &staging, &path).await;
+    let _ = fs::remove_dir_all(&staging);
+
+    result.map(|_| path)
+}
+
+async fn write_archive(db: &mut Database, settings: &BaseSettings, staging: &Path, path: &Path) -> Result<(), String> {
+    let write_error = |err: io::Error| format!("Unable to write the backup: {}", err);
+
+    let schema_version = SchemaVersion::get(db).await.map_err(|err| format!("Unable to read the version of the database: {:?}", err))?;
+    if schema_version != Some(SCHEMA_VERSION) {
+        return Err(String::from("The database is not up to date. Run migrate before making a backup."));
+    }
+
+    fs::create_dir_all(staging.join("tables")).map_err(write_error)?;
+    let mut tables = Vec::new();
+    for table in TABLES.iter() {
+        let relative = format!("tables/{}.jsonl", table.name);
+        let file_path = staging.join(&relative);
+        let mut writer = BufWriter::new(File::create(&file_path).map_err(write_error)?);
+
+        let mut rows = 0;
+        loop {
+            let batch = transfer::export_rows(db, table, BATCH_SIZE, rows).await
+                .map_err(|err| format!("Unable to read the table {}: {:?}", table.name, err))?;
+            for row in batch.iter() {
+                serde_json::to_writer(&mut writer, row).map_err(|err| format!("Unable to write the backup: {}", err))?;
+                writer.write_all(b"\n").map_err(write_error)?;
+            }
+            rows += batch.len() as i64;
+            if (batch.len() as i64) < BATCH_SIZE {
+                break;
+            }
+        }
+        writer.flush().map_err(write_error)?;
+
+        tables.push(ManifestTable {
+            name: table.name.to_string(),
+            columns: table.columns.iter().map(|(column, _)| column.to_string()).collect(),
+            rows,
+            sha256: sha256_file(&file_path).map_err(write_error)?,
+            path: relative,
+        });
+    }
+
+    let mut files = Vec::new();
+    let mut sources = Vec::new();
+    for (name, directory) in file_directories(settings) {
+        if !directory.is_dir() {
+            continue;
+        }
+
+        let mut relative_files = Vec::new();
+        list_files(&directory, Path::new(""), &mut relative_files).map_err(write_error)?;
+        for relative in relative_files {
+            let source = directory.join(&relative);
+            files.push(ManifestFile {
+                path: format!("files/{}/{}", name, archive_path(&relative)),
+                size: fs::metadata(&source).map_err(write_error)?.len(),
+                sha256: sha256_file(&source).map_err(write_error)?,
+            });
+            sources.push(source);
+        }
+    }
+
+    let manifest = Manifest {
+        format: FORMAT_VERSION,
+        forumrs_version: env!("CARGO_PKG_VERSION").to_string(),
+        schema_version: SCHEMA_VERSION,
+        created: current_time(),
+        database_type: db.get_type().to_string(),
+        tables,
+        files,
+    };
+    fs::write(staging.join(MANIFEST), serde_json::to_vec_pretty(&manifest).unwrap()).map_err(write_error)?;
+
+    let partial = path.with_extension("partial");
+    let mut builder = tar::Builder::new(GzEncoder::new(File::create(&partial).map_err(write_error)?, Compression::default()));
+    builder.append_path_with_name(staging.join(MANIFEST), MANIFEST).map_err(write_error)?;
+    for table in manifest.tables.iter() {
+        builder.append_path_with_name(staging.join(&table.path), &table.path).map_err(write_error)?;
+    }
+    for (file, source) in manifest.files.iter().zip(sources.iter()) {
+        builder.append_path_with_name(source, &file.path).map_err(write_error)?;
+    }
+    builder.into_inner().and_then(|encoder| encoder.finish()).map_err(write_error)?;
+
+    fs::rename(&partial, path).map_err(write_error)
+}
+
+/**
+   Restore a backup into a database that uses the latest schema and has no data.
+
+   Backups from an older version of the schema can be restored, the columns they do not have keep their defaults.
+   Backups from a newer version are refused. Every table and file is checked against its checksum before anything
+   is written.
+
+   ## Returns
+   The manifest of the backup that was restored.
+*/
+pub async fn restore_backup(db: &mut Database, settings: &BaseSettings, archive: &Path) -> Result<Manifest, String> {
+    let staging = staging_directory();
+    let result = restore_archive(db, settings, archive, &staging).await;
+    let _ = fs::remove_dir_all(&staging);
+    result
+}
+
+async fn restore_archive(db: &mut Database, settings: &BaseSettings, archive: &Path, staging: &Path) -> Result<Manifest, String> {
+    let read_error = |err: io::Error| format!("Unable to read the backup: {}", err);
+
+    let file = File::open(archive).map_err(|err| format!("Unable to open the backup {}: {}", archive.display(), err))?;
+    tar::Archive::new(GzDecoder::new(file)).unpack(staging).map_err(read_error)?;
+
+    let manifest: Manifest = serde_json::from_slice(&fs::read(staging.join(MANIFEST)).map_err(read_error)?)
+        .map_err(|err| format!("The backup has an invalid manifest: {}", err))?;
+    if manifest.format > FORMAT_VERSION {
+        return Err(String::from("The backup was made by a newer version of ForumRS. Update ForumRS to restore it."));
+    }
+    if manifest.schema_version > SCHEMA_VERSION {
+        return Err(format!("The backup uses version {} of the schema, which is newer than this version of ForumRS supports ({}). Update ForumRS to restore it.",
+                           manifest.schema_version, SCHEMA_VERSION));
+    }
+
+    let paths = manifest.tables.iter().map(|table| (&table.path, &table.sha256))
+        .chain(manifest.files.iter().map(|file| (&file.path, &file.sha256)));
+    for (path, sha256) in paths {
+        if &sha256_file(&unpacked_path(staging, path)?).map_err(read_error)? != sha256 {
+            return Err(format!("The checksum of {} does not match. The backup is damaged.", path));
+        }
+    }
+
+    transfer::check_empty(db).await?;
+
+    // The database was empty, so a restore that fails part way is undone by emptying it again. Otherwise every
+    // later attempt would be refused because of the rows that were restored.
+    if let Err(err) = restore_contents(db, settings, &manifest, staging).await {
+        return match transfer::clear_tables(db).await {
+            Ok(()) => Err(err),
+            Err(clear_err) => Err(format!("{} The rows that were already restored could not be removed: {}", err, clear_err))
+        };
+    }
+    Ok(manifest)
+}
+
+/// Restore the tables and files of an unpacked backup.
+async fn restore_contents(db: &mut Database, settings: &BaseSettings, manifest: &Manifest, staging: &Path) -> Result<(), String> {
+    let read_error = |err: io::Error| format!("Unable to read the backup: {}", err);
+
+    for table in manifest.tables.iter() {
+        let spec = match TABLES.iter().find(|spec| spec.name == table.name) {
+            Some(spec) => spec,
+            None => {
+                println!("[WARN] The backup has the unknown table {}, it is not restored.", table.name);
+                continue;
+            }
+        };
+
+        let reader = BufReader::new(File::open(unpacked_path(staging, &table.path)?).map_err(read_error)?);
+        let mut batch = Vec::with_capacity(BATCH_SIZE as usize);
+        let mut rows = 0;
+        for line in reader.lines() {
+            let line = line.map_err(read_error)?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            batch.push(serde_json::from_str(&line).map_err(|err| format!("The table {} has an invalid row: {}", table.name, err))?);
+
+            if batch.len() as i64 >= BATCH_SIZE {
+                transfer::import_rows(db, spec, &table.columns, &batch).await?;
+                rows += batch.len() as i64;
+                batch.clear();
+            }
+        }
+        transfer::import_rows(db, spec, &table.columns, &batch).await?;
+        rows += batch.len() as i64;
+        transfer::finish_table(db, spec).await?;
+
+        let restored = transfer::count_rows(db, spec.name).await.map_err(|err| format!("Unable to count the rows of {}: {:?}", spec.name, err))?;
+        if restored != table.rows || rows != table.rows {
+            return Err(format!("The table {} has {} rows in the backup but {} were restored.", table.name, table.rows, restored));
+        }
+        println!("[INFO] {}: restored {} rows", table.name, restored);
+    }
+
+    let directories = file_directories(settings);
+    for file in manifest.files.iter() {
+        let mut parts = file.path.splitn(3, '/').skip(1);
+        let (name, relative) = match (parts.next(), parts.next()) {
+            (Some(name), Some(relative)) => (name, relative),
+            _ => continue
+        };
+        let directory = match directories.iter().find(|(directory_name, _)| *directory_name == name) {
+            Some((_, directory)) => directory,
+            None => {
+                println!("[WARN] The backup has files for the unknown directory {}, {} is not restored.", name, file.path);
+                continue;
+            }
+        };
+
+        let target = directory.join(relative);
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent).map_err(|err| format!("Unable to restore {}: {}", file.path, err))?;
+        }
+        fs::copy(unpacked_path(staging, &file.path)?, &target).map_err(|err| format!("Unable to restore {}: {}", file.path, err))?;
+    }
+    if !manifest.files.is_empty() {
+        println!("[INFO] Restored {} files.", manifest.files.len());
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unpacked_path_inside_archive() {
+        let staging = Path::new("/tmp/staging");
+        assert_eq!(unpacked_path(staging, "tables/users.jsonl"), Ok(staging.join("tables/users.jsonl")));
+        assert_eq!(unpacked_path(staging, "files/attachments/ab/cd"), Ok(staging.join("files/attachments/ab/cd")));
+    }
+
+    #[test]
+    fn unpacked_path_outside_archive() {
+        let staging = Path::new("/tmp/staging");
+        for path in ["", "../etc/passwd", "files/../../etc/passwd", "/etc/passwd", "./manifest.json", ".."].iter() {
+            assert_eq!(unpacked_path(staging, path), Err(format!("The backup has an invalid path: {}", path)), "{}", path);
+        }
+    }
+
+    #[test]
+    fn backup_names() {
+        assert!(is_backup_name("forumrs-backup-20240101-000000.tar.gz"));
+        assert!(!is_backup_name("forumrs-backup-20240101-000000.tar.partial"));
+        assert!(!is_backup_name("notes.tar.gz"));
+    }
+
+    #[test]
+    fn list_and_hash_files() {
+        let directory = staging_directory();
+        fs::create_dir_all(directory.join("ab/cd")).unwrap();
+        fs::write(directory.join("top"), b"abc").unwrap();
+        fs::write(directory.join("ab/cd/nested"), b"").unwrap();
+
+        let mut files = Vec::new();
+        list_files(&directory, Path::new(""), &mut files).unwrap();
+        let mut names: Vec<String> = files.iter().map(|file| archive_path(file)).collect();
+        names.sort();
+        assert_eq!(names, vec!["ab/cd/nested".to_string(), "top".to_string()]);
+
+        assert_eq!(sha256_file(&directory.join("top")).unwrap(), "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad");
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    /// Write an archive with a users table that has the rows, but claims to have `claimed_rows`.
+    fn user_archive(rows: &[serde_json::Value], claimed_rows: i64) -> PathBuf {
+        let directory = staging_directory();
+        fs::create_dir_all(directory.join("tables")).unwrap();
+        let lines: Vec<String> = rows.iter().map(|row| row.to_string()).collect();
+        fs::write(directory.join("tables/users.jsonl"), lines.join("\n")).unwrap();
+
+        let manifest = Manifest {
+            format: FORMAT_VERSION,
+            forumrs_version: String::from("test"),
+            schema_version: SCHEMA_VERSION,
+            created: 0,
+            database_type: String::from("SQLite"),
+            tables: vec![ManifestTable {
+                name: String::from("users"),
+                columns: vec![String::from("id"), String::from("uuid"), String::from("username"), String::from("email"), String::from("password"),
+                              String::from("is_banned"), String::from("is_admin")],
+                rows: claimed_rows,
+                path: String::from("tables/users.jsonl"),
+                sha256: sha256_file(&directory.join("tables/users.jsonl")).unwrap(),
+            }],
+            files: vec![],
+        };
+        fs::write(directory.join(MANIFEST), serde_json::to_vec(&manifest).unwrap()).unwrap();
+
+        let archive = std::env::temp_dir().join(format!("{}{}{}", PREFIX, Uuid::new_v4(), EXTENSION));
+        let mut builder = tar::Builder::new(GzEncoder::new(File::create(&archive).unwrap(), Compression::default()));
+        builder.append_path_with_name(directory.join(MANIFEST), MANIFEST).unwrap();
+        builder.append_path_with_name(directory.join("tables/users.jsonl"), "tables/users.jsonl").unwrap();
+        builder.into_inner().unwrap().finish().unwrap();
+        fs::remove_dir_all(&directory).unwrap();
+        archive
+    }
+
+    fn user_row(id: i32, username: &str) -> serde_json::Value {
+        serde_json::json!({"id": id, "uuid": Uuid::new_v4().to_string(), "username": username, "email": format!("{}@example.com", username),
+            "password": "hash", "is_banned": false, "is_admin": false})
+    }
+
+    async fn empty_db() -> Database {
+        let mut db = Database::new_sqlite(&crate::settings::SqlSettings { file_location: ":memory:".to_string() }).await.unwrap();
+        crate::schema::dbsetup::setup_database(&mut db).await.unwrap();
+        db
+    }
+
+    #[actix_rt::test]
+    async fn restore_users() {
+        let mut db = empty_db().await;
+        let archive = user_archive(&[user_row(1, "alice"), user_row(2, "bob")], 2);
+        let result = restore_backup(&mut db, &BaseSettings::create_default(), &archive).await;
+        fs::remove_file(&archive).unwrap();
+
+        assert_eq!(result.map(|manifest| manifest.tables[0].rows), Ok(2));
+        assert_eq!(transfer::count_rows(&mut db, "users").await.unwrap(), 2);
+    }
+
+    #[actix_rt::test]
+    async fn failed_restore_can_be_retried() {
+        let mut db = empty_db().await;
+        let archive = user_archive(&[user_row(1, "alice"), user_row(2, "bob")], 3);
+        let result = restore_backup(&mut db, &BaseSettings::create_default(), &archive).await;
+        fs::remove_file(&archive).unwrap();
+
+        assert_eq!(result.err(), Some(String::from("The table users has 3 rows in the backup but 2 were restored.")));
+        assert_eq!(transfer::count_rows(&mut db, "users").await.unwrap(), 0);
+        assert_eq!(transfer::check_empty(&mut db).await, Ok(()));
+    }
+}
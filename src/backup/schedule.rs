@@ -0,0 +1,106 @@
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, UNIX_EPOCH};
+
+use actix_web::rt::time::delay_for;
+
+use crate::backup::{create_backup, is_backup_name};
+use crate::schema::database::Database;
+use crate::schema::tables::current_time;
+use crate::settings::{BaseSettings, SettingsManager};
+
+/// How often the scheduler checks if a backup is due, in seconds.
+const CHECK_INTERVAL: u64 = 60;
+
+/// A backup archive in the backup directory.
+#[derive(Serialize)]
+pub struct BackupFile {
+    pub name: String,
+    pub size: u64,
+    /// The unix time the archive was last modified.
+    pub created: i64,
+}
+
+/// Get the backups in the backup directory, newest first.
+pub fn list_backups(settings: &BaseSettings) -> Vec<BackupFile> {
+    let entries = match fs::read_dir(Path::new(&settings.backup_settings.directory)) {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new()
+    };
+
+    let mut backups: Vec<BackupFile> = entries.filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let name = entry.file_name().to_string_lossy().to_string();
+            let metadata = entry.metadata().ok()?;
+            if !metadata.is_file() || !is_backup_name(&name) {
+                return None;
+            }
+            let created = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?.as_secs() as i64;
+            Some(BackupFile { name, size: metadata.len(), created })
+        })
+        .collect();
+    backups.sort_by(|a, b| b.created.cmp(&a.created).then_with(|| b.name.cmp(&a.name)));
+    backups
+}
+
+/**
+   Delete the oldest backups so only the amount set in the settings is kept. A limit of 0 keeps every backup.
+
+   ## Returns
+   The amount of backups that were deleted.
+*/
+pub fn prune(settings: &BaseSettings) -> usize {
+    let keep = settings.backup_settings.keep;
+    if keep == 0 {
+        return 0;
+    }
+
+    let directory = Path::new(&settings.backup_settings.directory);
+    let mut deleted = 0;
+    for backup in list_backups(settings).iter().skip(keep) {
+        match fs::remove_file(directory.join(&backup.name)) {
+            Ok(_) => deleted += 1,
+            Err(err) => println!("[WARN] Unable to delete the old backup {}: {}", backup.name, err)
+        }
+    }
+    deleted
+}
+
+/// Run the backup scheduler. This checks if a backup is due forever and should be spawned once when the forum starts.
+pub async fn run_schedule() {
+    loop {
+        delay_for(Duration::from_secs(CHECK_INTERVAL)).await;
+
+        // The settings are read every time so changes apply without a restart.
+        let settings = SettingsManager::get_settings();
+        if !settings.backup_settings.scheduled {
+            continue;
+        }
+        let interval = settings.backup_settings.interval_hours as i64 * 3600;
+        if let Some(latest) = list_backups(&settings).first() {
+            if current_time() - latest.created < interval {
+                continue;
+            }
+        }
+
+        let mut db = match Database::connect(&settings).await {
+            Ok(db) => db,
+            Err(err) => {
+                println!("[WARN] The backup scheduler is unable to connect to the database: {:?}", err);
+                continue;
+            }
+        };
+
+        match create_backup(&mut db, &settings).await {
+            Ok(path) => {
+                println!("[INFO] Made a scheduled backup at {}.", path.display());
+                let deleted = prune(&settings);
+                if deleted > 0 {
+                    println!("[INFO] Deleted {} old backups.", deleted);
+                }
+            }
+            Err(err) => println!("[ERROR] Unable to make a scheduled backup: {}", err)
+        }
+        db.close().await;
+    }
+}
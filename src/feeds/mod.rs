@@ -0,0 +1,275 @@
+use std::str::FromStr;
+
+use actix_web::{HttpRequest, HttpResponse};
+use actix_web::http::header::{IF_MODIFIED_SINCE, IF_NONE_MATCH};
+use chrono::{DateTime, TimeZone, Utc};
+use sha2::{Digest, Sha256};
+
+/// The amount of entries in a feed.
+pub const FEED_SIZE: i64 = 20;
+/// How long feed readers and proxies may cache a feed.
+const CACHE_SECONDS: u32 = 300;
+
+/// The formats feeds are available in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FeedFormat {
+    Rss,
+    Atom,
+}
+
+impl FeedFormat {
+    /// The content type of the format.
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            FeedFormat::Rss => "application/rss+xml; charset=utf-8",
+            FeedFormat::Atom => "application/atom+xml; charset=utf-8"
+        }
+    }
+}
+
+impl FromStr for FeedFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "rss" => Ok(FeedFormat::Rss),
+            "atom" => Ok(FeedFormat::Atom),
+            _ => Err(format!("Unknown feed format: {}", s))
+        }
+    }
+}
+
+/// An entry of a feed.
+pub struct FeedEntry {
+    pub title: String,
+    /// The absolute url of the page of the entry.
+    pub link: String,
+    /// A permanent unique id for the entry.
+    pub id: String,
+    pub content: String,
+    pub author: String,
+    /// The unix time the entry was published.
+    pub published: i64,
+}
+
+/// A feed of threads or replies. Links must be absolute.
+pub struct Feed {
+    pub title: String,
+    pub description: String,
+    /// The absolute url of the page the feed is for.
+    pub link: String,
+    /// The absolute url of the feed itself, without the extension.
+    pub self_link: String,
+    /// The entries, newest first.
+    pub entries: Vec<FeedEntry>,
+}
+
+impl Feed {
+    /// The unix time the feed was last updated. This is the time of the newest entry.
+    pub fn updated(&self) -> i64 {
+        self.entries.iter().map(|entry| entry.published).max().unwrap_or(0)
+    }
+
+    /// Render the feed as RSS 2.0.
+    pub fn to_rss(&self) -> String {
+        let mut xml = String::from("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n");
+        xml.push_str("<rss version=\"2.0\" xmlns:atom=\"http://www.w3.org/2005/Atom\" xmlns:dc=\"http://purl.org/dc/elements/1.1/\">\n<channel>\n");
+        xml.push_str(&format!("<title>{}</title>\n", escape_xml(&self.title)));
+        xml.push_str(&format!("<link>{}</link>\n", escape_xml(&self.link)));
+        xml.push_str(&format!("<description>{}</description>\n", escape_xml(&self.description)));
+        xml.push_str(&format!("<atom:link href=\"{}.rss\" rel=\"self\" type=\"application/rss+xml\" />\n", escape_xml(&self.self_link)));
+        xml.push_str(&format!("<lastBuildDate>{}</lastBuildDate>\n", timestamp(self.updated()).to_rfc2822()));
+
+        for entry in &self.entries {
+            xml.push_str("<item>\n");
+            xml.push_str(&format!("<title>{}</title>\n", escape_xml(&entry.title)));
+            xml.push_str(&format!("<link>{}</link>\n", escape_xml(&entry.link)));
+            xml.push_str(&format!("<guid isPermaLink=\"false\">{}</guid>\n", escape_xml(&entry.id)));
+            xml.push_str(&format!("<description>{}</description>\n", escape_xml(&entry.content)));
+            // The RSS author element must be an email address, so the username goes in dc:creator.
+            xml.push_str(&format!("<dc:creator>{}</dc:creator>\n", escape_xml(&entry.author)));
+            xml.push_str(&format!("<pubDate>{}</pubDate>\n", timestamp(entry.published).to_rfc2822()));
+            xml.push_str("</item>\n");
+        }
+
+        xml.push_str("</channel>\n</rss>\n");
+        xml
+    }
+
+    /// Render the feed as Atom.
+    pub fn to_atom(&self) -> String {
+        let mut xml = String::from("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n");
+        xml.push_str("<feed xmlns=\"http://www.w3.org/2005/Atom\">\n");
+        xml.push_str(&format!("<title>{}</title>\n", escape_xml(&self.title)));
+        xml.push_str(&format!("<subtitle>{}</subtitle>\n", escape_xml(&self.description)));
+        xml.push_str(&format!("<id>{}.atom</id>\n", escape_xml(&self.self_link)));
+        xml.push_str(&format!("<link href=\"{}\" rel=\"alternate\" type=\"text/html\" />\n", escape_xml(&self.link)));
+        xml.push_str(&format!("<link href=\"{}.atom\" rel=\"self\" type=\"application/atom+xml\" />\n", escape_xml(&self.self_link)));
+        xml.push_str(&format!("<updated>{}</updated>\n", timestamp(self.updated()).to_rfc3339()));
+
+        for entry in &self.entries {
+            xml.push_str("<entry>\n");
+            xml.push_str(&format!("<title>{}</title>\n", escape_xml(&entry.title)));
+            xml.push_str(&format!("<link href=\"{}\" rel=\"alternate\" type=\"text/html\" />\n", escape_xml(&entry.link)));
+            xml.push_str(&format!("<id>urn:uuid:{}</id>\n", escape_xml(&entry.id)));
+            xml.push_str(&format!("<author><name>{}</name></author>\n", escape_xml(&entry.author)));
+            xml.push_str(&format!("<published>{}</published>\n", timestamp(entry.published).to_rfc3339()));
+            xml.push_str(&format!("<updated>{}</updated>\n", timestamp(entry.published).to_rfc3339()));
+            xml.push_str(&format!("<content type=\"text\">{}</content>\n", escape_xml(&entry.content)));
+            xml.push_str("</entry>\n");
+        }
+
+        xml.push_str("</feed>\n");
+        xml
+    }
+}
+
+/// Convert a unix time to a date.
+fn timestamp(time: i64) -> DateTime<Utc> {
+    Utc.timestamp_opt(time, 0).single().unwrap_or_else(|| Utc.timestamp(0, 0))
+}
+
+/// Escape text so it can be placed in an XML element or attribute.
+pub fn escape_xml(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            // Control characters are not allowed in XML 1.0.
+            c if (c as u32) < 0x20 && c != '\n' && c != '\r' && c != '\t' => {}
+            c => escaped.push(c)
+        }
+    }
+    escaped
+}
+
+/**
+   Build the response for a feed with caching headers.
+
+   The ETag is a hash of the rendered feed and Last-Modified is the time of the newest entry. If the request
+   has a matching If-None-Match (or an If-Modified-Since that is not older than the feed), a 304 is returned.
+*/
+pub fn respond(req: &HttpRequest, feed: &Feed, format: FeedFormat) -> HttpResponse {
+    let body = match format {
+        FeedFormat::Rss => feed.to_rss(),
+        FeedFormat::Atom => feed.to_atom()
+    };
+
+    let etag = format!("\"{}\"", &hex::encode(Sha256::digest(body.as_bytes()))[..32]);
+    let last_modified = timestamp(feed.updated());
+    let last_modified_header = last_modified.format("%a, %d %b %Y %H:%M:%S GMT").to_string();
+
+    let headers = req.headers();
+    let not_modified = match headers.get(IF_NONE_MATCH).and_then(|value| value.to_str().ok()) {
+        Some(if_none_match) => if_none_match.split(',').any(|tag| tag.trim() == etag || tag.trim() == "*"),
+        None => headers.get(IF_MODIFIED_SINCE)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| DateTime::parse_from_rfc2822(value).ok())
+            .map(|since| since.timestamp() >= last_modified.timestamp())
+            .unwrap_or(false)
+    };
+
+    let mut builder = if not_modified { HttpResponse::NotModified() } else { HttpResponse::Ok() };
+    builder.header("ETag", etag)
+        .header("Last-Modified", last_modified_header)
+        .header("Cache-Control", format!("public, max-age={}", CACHE_SECONDS));
+
+    if not_modified {
+        return builder.finish();
+    }
+    builder.content_type(format.content_type()).body(body)
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::http::StatusCode;
+    use actix_web::test::TestRequest;
+
+    use super::*;
+
+    fn feed() -> Feed {
+        Feed {
+            title: "Rust & <friends>".to_string(),
+            description: "News".to_string(),
+            link: "https://forum.example/forums/1".to_string(),
+            self_link: "https://forum.example/forums/1/feed".to_string(),
+            entries: vec![FeedEntry {
+                title: "\"Quoted\" title".to_string(),
+                link: "https://forum.example/threads/1?page=1&sort=new".to_string(),
+                id: "d9428888-122b-11e1-b85c-61cd3cbb3210".to_string(),
+                content: "It's <b>bold</b>".to_string(),
+                author: "ferris".to_string(),
+                published: 1_600_000_000,
+            }],
+        }
+    }
+
+    #[test]
+    fn escape_xml_special_characters() {
+        assert_eq!(escape_xml("<a href=\"x\">Tom & Jerry's</a>"),
+                   "&lt;a href=&quot;x&quot;&gt;Tom &amp; Jerry&apos;s&lt;/a&gt;");
+    }
+
+    #[test]
+    fn escape_xml_control_characters() {
+        assert_eq!(escape_xml("a\u{0}b\u{8}c\u{1f}d"), "abcd");
+        assert_eq!(escape_xml("line\nbreak\r\n\ttab"), "line\nbreak\r\n\ttab");
+        assert_eq!(escape_xml("ünïcödé ✓"), "ünïcödé ✓");
+    }
+
+    #[test]
+    fn parse_format() {
+        assert_eq!("rss".parse::<FeedFormat>(), Ok(FeedFormat::Rss));
+        assert_eq!("atom".parse::<FeedFormat>(), Ok(FeedFormat::Atom));
+        assert!("json".parse::<FeedFormat>().is_err());
+    }
+
+    #[test]
+    fn rss_is_escaped() {
+        let rss = feed().to_rss();
+        assert!(rss.contains("<title>Rust &amp; &lt;friends&gt;</title>"));
+        assert!(rss.contains("<link>https://forum.example/threads/1?page=1&amp;sort=new</link>"));
+        assert!(rss.contains("<description>It&apos;s &lt;b&gt;bold&lt;/b&gt;</description>"));
+        assert!(rss.contains("<atom:link href=\"https://forum.example/forums/1/feed.rss\""));
+        assert!(rss.contains("<pubDate>Sun, 13 Sep 2020 12:26:40 +0000</pubDate>"));
+    }
+
+    #[test]
+    fn atom_is_escaped() {
+        let atom = feed().to_atom();
+        assert!(atom.contains("<title>&quot;Quoted&quot; title</title>"));
+        assert!(atom.contains("<id>urn:uuid:d9428888-122b-11e1-b85c-61cd3cbb3210</id>"));
+        assert!(atom.contains("<updated>2020-09-13T12:26:40+00:00</updated>"));
+    }
+
+    #[test]
+    fn empty_feed_updated() {
+        let mut feed = feed();
+        feed.entries.clear();
+        assert_eq!(feed.updated(), 0);
+    }
+
+    #[test]
+    fn respond_not_modified() {
+        let feed = feed();
+        let response = respond(&TestRequest::default().to_http_request(), &feed, FeedFormat::Rss);
+        assert_eq!(response.status(), StatusCode::OK);
+        let etag = response.headers().get("ETag").unwrap().to_str().unwrap().to_string();
+
+        let req = TestRequest::default().header(IF_NONE_MATCH, etag.as_str()).to_http_request();
+        assert_eq!(respond(&req, &feed, FeedFormat::Rss).status(), StatusCode::NOT_MODIFIED);
+
+        let req = TestRequest::default().header(IF_NONE_MATCH, "\"other\"").to_http_request();
+        assert_eq!(respond(&req, &feed, FeedFormat::Rss).status(), StatusCode::OK);
+
+        let req = TestRequest::default().header(IF_MODIFIED_SINCE, "Sun, 13 Sep 2020 12:26:40 GMT").to_http_request();
+        assert_eq!(respond(&req, &feed, FeedFormat::Atom).status(), StatusCode::NOT_MODIFIED);
+
+        let req = TestRequest::default().header(IF_MODIFIED_SINCE, "Sun, 13 Sep 2020 12:26:39 GMT").to_http_request();
+        assert_eq!(respond(&req, &feed, FeedFormat::Atom).status(), StatusCode::OK);
+    }
+}
@@ -0,0 +1,497 @@
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use actix_web::{get, http, HttpMessage, HttpRequest, HttpResponse, post, Responder, web};
+use serde::Deserialize;
+use serde_json::json;
+use uuid::Uuid;
+
+use crate::auth::login_protection;
+use crate::auth::login_protection::ThrottleError;
+use crate::auth::session;
+use crate::auth::two_factor;
+use crate::forum::open_database;
+use crate::schema::database::Database;
+use crate::schema::tables::{LoginAttempts, RecoveryCodes, Sessions, TwoFactor, User, Users};
+use crate::settings::{LoginProtectionSettings, SettingsManager};
+use crate::state::{ForumRSState, PendingLogin};
+
+/// The name of the cookie that stores the id of a pending two factor login.
+const PENDING_LOGIN_COOKIE: &str = "pending_login";
+/// How long a user has to enter their two factor code after entering their password.
+const PENDING_LOGIN_TIMEOUT: Duration = Duration::from_secs(300);
+/// A bcrypt hash with the default cost. It is verified (and the result ignored) when there is no password hash to
+/// check, so logins to accounts that do not exist take as long as any other login.
+const DUMMY_PASSWORD_HASH: &str = "$2a$12$R9h/cIPz0gi.URNNX3kh2OPST9/PgBkqquzi.Ss7KIUgO2t0jWMUW";
+
+/// The login page for the forum.
+#[get("/login")]
+pub async fn login(data: web::Data<ForumRSState>, req: HttpRequest) -> impl Responder {
+    let mut db = match open_database().await {
+        Ok(db) => db,
+        Err(resp) => return resp
+    };
+
+    if session::current_user(&mut db, &req).await.is_some() {
+        db.close().await;
+        return HttpResponse::Found().header("Location", "/").finish();
+    }
+    db.close().await;
+
+    let result: String = (&data.hbs).render("forum/login", &json!({"name": SettingsManager::get_settings().name})).unwrap();
+    HttpResponse::Ok().body(result)
+}
+
+/// The form data for the login authorization.
+#[derive(Deserialize)]
+pub struct AuthLoginForm {
+    username: String,
+    password: String,
+}
+
+/// Get the location a user is sent to once they are logged in.
+fn after_login_location(user: &User, has_two_factor: bool) -> &'static str {
+    if !has_two_factor && two_factor::is_required(user, &SettingsManager::get_settings().two_factor_settings) {
+        return "/account/twofactor?required=1";
+    }
+    "/"
+}
+
+/// Create a session for the user and build the response that logs them in.
+async fn finish_login(db: &mut Database, user: &User, has_two_factor: bool) -> HttpResponse {
+    let session_id = match session::create_session(db, user).await {
+        Ok(session_id) => session_id,
+        Err(err) => {
+            println!("[ERROR] Unable to create a session: {:?}", err);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+
+    HttpResponse::Found()
+        .cookie(session::session_cookie(session_id))
+        .header("Location", after_login_location(user, has_two_factor))
+        .finish()
+}
+
+/// Check if a login attempt for the account is allowed from the client.
+///
+/// If not, a response is compiled that returns the user to the login page with the amount of seconds to wait.
+fn check_throttle(data: &web::Data<ForumRSState>, username: &str, ip: &str, settings: &LoginProtectionSettings) -> Result<(), HttpResponse> {
+    let account_key = login_protection::account_key(username);
+    let ip_key = login_protection::ip_key(ip);
+    match data.login_throttle.lock().unwrap().check(&[account_key.as_str(), ip_key.as_str()], settings) {
+        Ok(()) => Ok(()),
+        Err(err) => {
+            let code = match err {
+                ThrottleError::Backoff(_) => 4,
+                ThrottleError::Locked(_) => 5
+            };
+            Err(HttpResponse::Found().header("Location", format!("/login?err={}&wait={}", code, err.retry_after())).finish())
+        }
+    }
+}
+
+/// Record a failed login in the throttle and the login attempts table.
+async fn record_failed_login(data: &web::Data<ForumRSState>, db: &mut Database, username: &str, ip: &str, settings: &LoginProtectionSettings) {
+    {
+        let mut throttle = data.login_throttle.lock().unwrap();
+        if throttle.record_failure(login_protection::account_key(username).as_str(), settings.account_lockout_threshold, settings) {
+            println!("[WARN] The account {} has been locked for {} minutes after too many failed logins.", username, settings.lockout_minutes);
+        }
+        if throttle.record_failure(login_protection::ip_key(ip).as_str(), settings.ip_lockout_threshold, settings) {
+            println!("[WARN] The IP {} has been locked for {} minutes after too many failed logins.", ip, settings.lockout_minutes);
+        }
+    }
+
+    if let Err(err) = LoginAttempts::insert(db, username, ip, false).await {
+        println!("[WARN] Unable to record a login attempt: {:?}", err);
+    }
+}
+
+/// Clear the failed logins of the account and client, and record the successful login.
+async fn record_successful_login(data: &web::Data<ForumRSState>, db: &mut Database, username: &str, ip: &str) {
+    {
+        let mut throttle = data.login_throttle.lock().unwrap();
+        throttle.record_success(login_protection::account_key(username).as_str());
+        throttle.record_success(login_protection::ip_key(ip).as_str());
+    }
+
+    if let Err(err) = LoginAttempts::insert(db, username, ip, true).await {
+        println!("[WARN] Unable to record a login attempt: {:?}", err);
+    }
+}
+
+/// The post request used to log into the forum.
+/// If the user has two factor authentication enabled, they are sent to enter their code.
+#[post("/auth/login")]
+pub async fn auth_login(data: web::Data<ForumRSState>, form: web::Form<AuthLoginForm>, req: HttpRequest) -> impl Responder {
+    let protection_settings = SettingsManager::get_settings().login_protection_settings;
+    let ip = login_protection::client_ip(&req, &protection_settings);
+
+    let throttled = check_throttle(&data, form.username.as_str(), ip.as_str(), &protection_settings);
+    if throttled.is_err() {
+        return throttled.unwrap_err();
+    }
+
+    let mut db = match open_database().await {
+        Ok(db) => db,
+        Err(resp) => return resp
+    };
+
+    let user = match Users::get_by_username(&mut db, form.username.as_str()).await {
+        Ok(Some(user)) => user,
+        _ => {
+            let _ = bcrypt::verify(form.password.as_str(), DUMMY_PASSWORD_HASH);
+            record_failed_login(&data, &mut db, form.username.as_str(), ip.as_str(), &protection_settings).await;
+            db.close().await;
+            return HttpResponse::Found().header("Location", "/login?err=1").finish();
+        }
+    };
+
+    // Imported users keep the password hash of the forum they came from until an admin sets a new password. Most of
+    // those hashes are not bcrypt and can never be verified, so a dummy hash is verified instead to take as long as
+    // any other login, and the notice is shown whatever password was entered.
+    if user.password_reset {
+        let _ = bcrypt::verify(form.password.as_str(), DUMMY_PASSWORD_HASH);
+        record_failed_login(&data, &mut db, user.username.as_str(), ip.as_str(), &protection_settings).await;
+        db.close().await;
+        return HttpResponse::Found().header("Location", "/login?err=6").finish();
+    }
+
+    if !bcrypt::verify(form.password.as_str(), user.password.as_str()).unwrap_or(false) {
+        record_failed_login(&data, &mut db, user.username.as_str(), ip.as_str(), &protection_settings).await;
+        db.close().await;
+        return HttpResponse::Found().header("Location", "/login?err=1").finish();
+    }
+
+    if user.is_banned {
+        db.close().await;
+        return HttpResponse::Found().header("Location", "/login?err=2").finish();
+    }
+
+    let two_factor_entry = TwoFactor::get(&mut db, user.uuid).await.unwrap_or(None);
+    if two_factor_entry.map(|entry| entry.enabled).unwrap_or(false) {
+        db.close().await;
+        let pending_id = Uuid::new_v4();
+        data.pending_logins.lock().unwrap().insert(pending_id, PendingLogin {
+            user_uuid: user.uuid,
+            created: Instant::now(),
+        });
+
+        return HttpResponse::Found()
+            .cookie(
+                http::Cookie::build(PENDING_LOGIN_COOKIE, pending_id.to_string())
+                    .path("/")
+                    .secure(true)
+                    .http_only(true)
+                    .finish()
+            )
+            .header("Location", "/login/twofactor")
+            .finish();
+    }
+
+    record_successful_login(&data, &mut db, user.username.as_str(), ip.as_str()).await;
+    let resp = finish_login(&mut db, &user, false).await;
+    db.close().await;
+    resp
+}
+
+/// Get the pending login of the request if it exists and has not expired.
+fn get_pending_login(data: &web::Data<ForumRSState>, req: &HttpRequest) -> Option<(Uuid, PendingLogin)> {
+    let pending_id = Uuid::from_str(req.cookie(PENDING_LOGIN_COOKIE)?.value()).ok()?;
+    let mut pending_logins = data.pending_logins.lock().unwrap();
+
+    // Clear out any logins that have expired.
+    pending_logins.retain(|_, pending| pending.created.elapsed() < PENDING_LOGIN_TIMEOUT);
+
+    pending_logins.get(&pending_id).map(|pending| (pending_id, pending.clone()))
+}
+
+/// The page where a user enters their two factor code after entering their password.
+#[get("/login/twofactor")]
+pub async fn login_two_factor(data: web::Data<ForumRSState>, req: HttpRequest) -> impl Responder {
+    if get_pending_login(&data, &req).is_none() {
+        return HttpResponse::Found().header("Location", "/login?err=3").finish();
+    }
+
+    let result: String = (&data.hbs).render("forum/login_twofactor", &json!({"name": SettingsManager::get_settings().name})).unwrap();
+    HttpResponse::Ok().body(result)
+}
+
+/// The form data for a two factor code.
+#[derive(Deserialize)]
+pub struct AuthTwoFactorForm {
+    code: String,
+}
+
+/// The post request used to verify the two factor code (or a recovery code) of a pending login.
+#[post("/auth/login/twofactor")]
+pub async fn auth_login_two_factor(data: web::Data<ForumRSState>, form: web::Form<AuthTwoFactorForm>, req: HttpRequest) -> impl Responder {
+    let (pending_id, pending) = match get_pending_login(&data, &req) {
+        Some(pending) => pending,
+        None => return HttpResponse::Found().header("Location", "/login?err=3").finish()
+    };
+
+    let mut db = match open_database().await {
+        Ok(db) => db,
+        Err(resp) => return resp
+    };
+
+    let user = match Users::get_by_uuid(&mut db, pending.user_uuid).await {
+        Ok(Some(user)) => user,
+        _ => {
+            db.close().await;
+            return HttpResponse::Found().header("Location", "/login?err=3").finish();
+        }
+    };
+
+    // Two factor codes are throttled the same way as passwords.
+    let protection_settings = SettingsManager::get_settings().login_protection_settings;
+    let ip = login_protection::client_ip(&req, &protection_settings);
+    let throttled = check_throttle(&data, user.username.as_str(), ip.as_str(), &protection_settings);
+    if throttled.is_err() {
+        db.close().await;
+        data.pending_logins.lock().unwrap().remove(&pending_id);
+        return throttled.unwrap_err();
+    }
+
+    let entry = match TwoFactor::get(&mut db, user.uuid).await {
+        Ok(Some(entry)) if entry.enabled => entry,
+        _ => {
+            db.close().await;
+            return HttpResponse::Found().header("Location", "/login?err=3").finish();
+        }
+    };
+
+    let valid = match two_factor::verify_code(entry.secret.as_str(), form.code.as_str(), entry.last_step) {
+        Some(step) => {
+            TwoFactor::set_last_step(&mut db, user.uuid, step).await.unwrap();
+            true
+        }
+        None => RecoveryCodes::consume(&mut db, user.uuid, two_factor::hash_recovery_code(form.code.as_str())).await.unwrap_or(false)
+    };
+
+    if !valid {
+        record_failed_login(&data, &mut db, user.username.as_str(), ip.as_str(), &protection_settings).await;
+        db.close().await;
+        return HttpResponse::Found().header("Location", "/login/twofactor?err=1").finish();
+    }
+
+    data.pending_logins.lock().unwrap().remove(&pending_id);
+    record_successful_login(&data, &mut db, user.username.as_str(), ip.as_str()).await;
+
+    let mut resp = finish_login(&mut db, &user, true).await;
+    db.close().await;
+
+    // Remove the pending login cookie now that the login is complete.
+    let mut removal = http::Cookie::named(PENDING_LOGIN_COOKIE);
+    removal.set_path("/");
+    removal.make_removal();
+    resp.add_cookie(&removal).unwrap();
+    resp
+}
+
+/// Log the user out of the forum.
+#[get("/logout")]
+pub async fn logout(req: HttpRequest) -> impl Responder {
+    if let Some(session_id) = session::session_id(&req) {
+        let mut db = match open_database().await {
+            Ok(db) => db,
+            Err(resp) => return resp
+        };
+        Sessions::delete(&mut db, session_id).await.unwrap();
+        db.close().await;
+    }
+
+    let mut builder = HttpResponse::Found();
+    if let Some(cookie) = req.cookie(session::SESSION_COOKIE) {
+        builder.del_cookie(&cookie);
+    }
+    builder.header("Location", "/").finish()
+}
+
+/// The query for the two factor settings page.
+#[derive(Deserialize)]
+pub struct TwoFactorQuery {
+    required: Option<String>,
+}
+
+/// The two factor settings page of an account.
+/// If two factor authentication is not enabled, a new secret is generated and shown as a QR code.
+#[get("/account/twofactor")]
+pub async fn two_factor(data: web::Data<ForumRSState>, query: web::Query<TwoFactorQuery>, req: HttpRequest) -> impl Responder {
+    let mut db = match open_database().await {
+        Ok(db) => db,
+        Err(resp) => return resp
+    };
+
+    let user = match session::current_user(&mut db, &req).await {
+        Some(user) => user,
+        None => {
+            db.close().await;
+            return HttpResponse::Found().header("Location", "/login").finish();
+        }
+    };
+
+    let settings = SettingsManager::get_settings();
+    let entry = TwoFactor::get(&mut db, user.uuid).await.unwrap();
+
+    let result: String = match entry {
+        Some(entry) if entry.enabled => {
+            let recovery_codes = RecoveryCodes::count(&mut db, user.uuid).await.unwrap();
+            (&data.hbs).render("forum/twofactor", &json!({
+                "name": settings.name,
+                "enabled": true,
+                "recovery_codes": recovery_codes,
+                "required": two_factor::is_required(&user, &settings.two_factor_settings),
+            })).unwrap()
+        }
+        entry => {
+            // Reuse the secret that has not been verified yet so refreshing the page does not invalidate the QR code.
+            let secret = match entry {
+                Some(entry) => entry.secret,
+                None => {
+                    let secret = two_factor::generate_secret();
+                    TwoFactor::insert(&mut db, user.uuid, secret.clone()).await.unwrap();
+                    secret
+                }
+            };
+            let uri = two_factor::provisioning_uri(secret.as_str(), settings.name.as_str(), user.username.as_str());
+            (&data.hbs).render("forum/twofactor", &json!({
+                "name": settings.name,
+                "enabled": false,
+                "secret": secret,
+                "qr_code": two_factor::provisioning_qr_svg(uri.as_str()),
+                "required": query.required.is_some(),
+            })).unwrap()
+        }
+    };
+
+    db.close().await;
+    HttpResponse::Ok().body(result)
+}
+
+/// Generate new recovery codes for a user, store the hashes and render the page showing them.
+async fn issue_recovery_codes(data: &web::Data<ForumRSState>, db: &mut Database, user: &User) -> HttpResponse {
+    let codes = two_factor::generate_recovery_codes();
+    let hashed_codes = codes.iter().map(|code| two_factor::hash_recovery_code(code)).collect();
+    RecoveryCodes::replace(db, user.uuid, hashed_codes).await.unwrap();
+
+    let result: String = (&data.hbs).render("forum/recovery_codes", &json!({
+        "name": SettingsManager::get_settings().name,
+        "codes": codes
+    })).unwrap();
+    HttpResponse::Ok().body(result)
+}
+
+/// Enable two factor authentication once the user has proven their authenticator app works.
+#[post("/auth/account/twofactor/enable")]
+pub async fn auth_two_factor_enable(data: web::Data<ForumRSState>, form: web::Form<AuthTwoFactorForm>, req: HttpRequest) -> impl Responder {
+    let mut db = match open_database().await {
+        Ok(db) => db,
+        Err(resp) => return resp
+    };
+
+    let user = match session::current_user(&mut db, &req).await {
+        Some(user) => user,
+        None => {
+            db.close().await;
+            return HttpResponse::Found().header("Location", "/login").finish();
+        }
+    };
+
+    let entry = match TwoFactor::get(&mut db, user.uuid).await.unwrap() {
+        Some(entry) if !entry.enabled => entry,
+        _ => {
+            db.close().await;
+            return HttpResponse::Found().header("Location", "/account/twofactor").finish();
+        }
+    };
+
+    let step = match two_factor::verify_code(entry.secret.as_str(), form.code.as_str(), entry.last_step) {
+        Some(step) => step,
+        None => {
+            db.close().await;
+            return HttpResponse::Found().header("Location", "/account/twofactor?err=1").finish();
+        }
+    };
+
+    TwoFactor::enable(&mut db, user.uuid).await.unwrap();
+    TwoFactor::set_last_step(&mut db, user.uuid, step).await.unwrap();
+
+    let resp = issue_recovery_codes(&data, &mut db, &user).await;
+    db.close().await;
+    resp
+}
+
+/// The form data for actions that require the password of the user.
+#[derive(Deserialize)]
+pub struct AuthPasswordForm {
+    password: String,
+}
+
+/// Disable two factor authentication for the logged in user.
+/// This is not allowed if the role of the user requires two factor authentication.
+#[post("/auth/account/twofactor/disable")]
+pub async fn auth_two_factor_disable(form: web::Form<AuthPasswordForm>, req: HttpRequest) -> impl Responder {
+    let mut db = match open_database().await {
+        Ok(db) => db,
+        Err(resp) => return resp
+    };
+
+    let user = match session::current_user(&mut db, &req).await {
+        Some(user) => user,
+        None => {
+            db.close().await;
+            return HttpResponse::Found().header("Location", "/login").finish();
+        }
+    };
+
+    if !bcrypt::verify(form.password.as_str(), user.password.as_str()).unwrap_or(false) {
+        db.close().await;
+        return HttpResponse::Found().header("Location", "/account/twofactor?err=2").finish();
+    }
+
+    if two_factor::is_required(&user, &SettingsManager::get_settings().two_factor_settings) {
+        db.close().await;
+        return HttpResponse::Found().header("Location", "/account/twofactor?err=3").finish();
+    }
+
+    TwoFactor::delete(&mut db, user.uuid).await.unwrap();
+    RecoveryCodes::delete_all(&mut db, user.uuid).await.unwrap();
+    db.close().await;
+
+    HttpResponse::Found().header("Location", "/account/twofactor").finish()
+}
+
+/// Replace the recovery codes of the logged in user with a new set.
+#[post("/auth/account/twofactor/recovery")]
+pub async fn auth_two_factor_recovery(data: web::Data<ForumRSState>, form: web::Form<AuthPasswordForm>, req: HttpRequest) -> impl Responder {
+    let mut db = match open_database().await {
+        Ok(db) => db,
+        Err(resp) => return resp
+    };
+
+    let user = match session::current_user(&mut db, &req).await {
+        Some(user) => user,
+        None => {
+            db.close().await;
+            return HttpResponse::Found().header("Location", "/login").finish();
+        }
+    };
+
+    if !bcrypt::verify(form.password.as_str(), user.password.as_str()).unwrap_or(false) {
+        db.close().await;
+        return HttpResponse::Found().header("Location", "/account/twofactor?err=2").finish();
+    }
+
+    let enabled = TwoFactor::get(&mut db, user.uuid).await.unwrap().map(|entry| entry.enabled).unwrap_or(false);
+    if !enabled {
+        db.close().await;
+        return HttpResponse::Found().header("Location", "/account/twofactor").finish();
+    }
+
+    let resp = issue_recovery_codes(&data, &mut db, &user).await;
+    db.close().await;
+    resp
+}
@@ -0,0 +1,433 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use actix_files::NamedFile;
+use actix_web::{get, HttpRequest, HttpResponse, post, Responder, web};
+use chrono::{NaiveDate, TimeZone, Utc};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+use crate::audit;
+use crate::audit::AuditAction;
+use crate::auth::session;
+use crate::auth::two_factor;
+use crate::backup;
+use crate::backup::schedule;
+use crate::forum::open_database;
+use crate::schema::database::Database;
+use crate::schema::tables::{AuditLog, LoginAttempts, RecoveryCodes, TwoFactor, User, Users};
+use crate::reload;
+use crate::settings::SettingsManager;
+use crate::state::ForumRSState;
+
+/// Check to see if the user accessing an admin page is an admin.
+///
+/// If the admin role requires two factor authentication and the user has not enabled it, they are sent to
+/// enable it first.
+pub async fn check_admin(db: &mut Database, req: &HttpRequest) -> Result<User, HttpResponse> {
+    check_role(db, req, |user| user.is_admin).await
+}
+
+/// Check to see if the user accessing a moderation page is a moderator or an admin.
+///
+/// Like [check_admin], the user is sent to enable two factor authentication first if their role requires it.
+pub async fn check_moderator(db: &mut Database, req: &HttpRequest) -> Result<User, HttpResponse> {
+    check_role(db, req, |user| user.is_admin || user.is_moderator).await
+}
+
+/// Check that the logged in user has a role, and has two factor authentication enabled if the role requires it.
+async fn check_role(db: &mut Database, req: &HttpRequest, has_role: fn(&User) -> bool) -> Result<User, HttpResponse> {
+    let user = match session::current_user(db, req).await {
+        Some(user) => user,
+        None => return Err(HttpResponse::Found().header("Location", "/login").finish())
+    };
+
+    if !has_role(&user) {
+        return Err(HttpResponse::Forbidden().body("You do not have permission to access this page."));
+    }
+
+    if two_factor::is_required(&user, &SettingsManager::get_settings().two_factor_settings) {
+        let enabled = TwoFactor::get(db, user.uuid).await.unwrap_or(None).map(|entry| entry.enabled).unwrap_or(false);
+        if !enabled {
+            return Err(HttpResponse::Found().header("Location", "/account/twofactor?required=1").finish());
+        }
+    }
+
+    Ok(user)
+}
+
+/// The security page of the admin panel.
+#[get("/admin/security")]
+pub async fn security(data: web::Data<ForumRSState>, req: HttpRequest) -> impl Responder {
+    let mut db = match open_database().await {
+        Ok(db) => db,
+        Err(resp) => return resp
+    };
+
+    let admin = check_admin(&mut db, &req).await;
+    db.close().await;
+    if admin.is_err() {
+        return admin.unwrap_err();
+    }
+
+    let settings = SettingsManager::get_settings();
+    let result: String = (&data.hbs).render("admin/security", &json!({
+        "name": settings.name,
+        "require_for_admins": settings.two_factor_settings.require_for_admins,
+        "require_for_moderators": settings.two_factor_settings.require_for_moderators,
+    })).unwrap();
+
+    HttpResponse::Ok().body(result)
+}
+
+/// The form data for the security settings.
+#[derive(Deserialize)]
+#[allow(non_snake_case)]
+pub struct AuthSecurityForm {
+    requireAdmins: Option<String>,
+    requireModerators: Option<String>,
+}
+
+/// Save the two factor requirements of the admin and moderator roles.
+#[post("/auth/admin/security")]
+pub async fn auth_security(form: web::Form<AuthSecurityForm>, req: HttpRequest) -> impl Responder {
+    let mut db = match open_database().await {
+        Ok(db) => db,
+        Err(resp) => return resp
+    };
+
+    let admin = match check_admin(&mut db, &req).await {
+        Ok(admin) => admin,
+        Err(resp) => {
+            db.close().await;
+            return resp;
+        }
+    };
+
+    // The file is edited instead of the running settings, so changes waiting on a restart are not undone.
+    let mut settings = SettingsManager::read_effective().unwrap_or_else(|_| SettingsManager::get_settings());
+    let old_settings = SettingsManager::redacted_json(&settings);
+    settings.two_factor_settings.require_for_admins = form.requireAdmins.as_deref() == Some("on");
+    settings.two_factor_settings.require_for_moderators = form.requireModerators.as_deref() == Some("on");
+    SettingsManager::save_settings(&settings);
+
+    let (old_value, new_value) = audit::changes(&old_settings, &SettingsManager::redacted_json(&settings));
+    audit::record(&mut db, admin.uuid, audit::request_ip(&req).as_str(), AuditAction::SettingsChange, "two_factor_settings", old_value, new_value).await.unwrap();
+    db.close().await;
+
+    HttpResponse::Found().header("Location", "/admin/security?saved=1").finish()
+}
+
+/// The form data to reset the two factor authentication of a user.
+#[derive(Deserialize)]
+pub struct AuthResetTwoFactorForm {
+    username: String,
+}
+
+/// Reset the two factor authentication of a user that has lost access to their authenticator and recovery codes.
+#[post("/auth/admin/users/twofactor/reset")]
+pub async fn auth_reset_two_factor(form: web::Form<AuthResetTwoFactorForm>, req: HttpRequest) -> impl Responder {
+    let mut db = match open_database().await {
+        Ok(db) => db,
+        Err(resp) => return resp
+    };
+
+    let admin = check_admin(&mut db, &req).await;
+    if admin.is_err() {
+        db.close().await;
+        return admin.unwrap_err();
+    }
+
+    let user = match Users::get_by_username(&mut db, form.username.as_str()).await.unwrap() {
+        Some(user) => user,
+        None => {
+            db.close().await;
+            return HttpResponse::Found().header("Location", "/admin/security?err=1").finish();
+        }
+    };
+
+    let admin = admin.unwrap();
+    TwoFactor::delete(&mut db, user.uuid).await.unwrap();
+    RecoveryCodes::delete_all(&mut db, user.uuid).await.unwrap();
+    audit::record(&mut db, admin.uuid, audit::request_ip(&req).as_str(), AuditAction::TwoFactorReset, user.uuid.to_string().as_str(),
+                  json!({"username": user.username}), Value::Null).await.unwrap();
+    db.close().await;
+
+    println!("[INFO] {} reset the two factor authentication of {}.", admin.username, user.username);
+
+    HttpResponse::Found().header("Location", "/admin/security?reset=1").finish()
+}
+
+/// The query for the login attempts page.
+#[derive(Deserialize)]
+pub struct LoginAttemptsQuery {
+    username: Option<String>,
+}
+
+/// The page where admins can review recent login attempts.
+#[get("/admin/loginattempts")]
+pub async fn login_attempts(data: web::Data<ForumRSState>, query: web::Query<LoginAttemptsQuery>, req: HttpRequest) -> impl Responder {
+    let mut db = match open_database().await {
+        Ok(db) => db,
+        Err(resp) => return resp
+    };
+
+    let admin = check_admin(&mut db, &req).await;
+    if admin.is_err() {
+        db.close().await;
+        return admin.unwrap_err();
+    }
+
+    let username = query.username.as_deref().filter(|username| !username.is_empty());
+    let attempts = LoginAttempts::get_recent(&mut db, username, 200).await.unwrap();
+    db.close().await;
+
+    let result: String = (&data.hbs).render("admin/loginattempts", &json!({
+        "name": SettingsManager::get_settings().name,
+        "username": username,
+        "attempts": attempts,
+    })).unwrap();
+
+    HttpResponse::Ok().body(result)
+}
+
+/// The query for the audit log page. Dates are formatted as YYYY-MM-DD in UTC.
+#[derive(Deserialize)]
+pub struct AuditLogQuery {
+    actor: Option<String>,
+    action: Option<String>,
+    from: Option<String>,
+    until: Option<String>,
+}
+
+/// Parse a date of the audit log filters as the unix time it starts at.
+fn parse_date(date: Option<&str>) -> Option<i64> {
+    date.and_then(|date| NaiveDate::parse_from_str(date, "%Y-%m-%d").ok())
+        .and_then(|date| date.and_hms_opt(0, 0, 0))
+        .map(|start| Utc.from_utc_datetime(&start).timestamp())
+}
+
+/// The page where admins can review the actions of admins and moderators.
+#[get("/admin/audit")]
+pub async fn audit_log(data: web::Data<ForumRSState>, query: web::Query<AuditLogQuery>, req: HttpRequest) -> impl Responder {
+    let mut db = match open_database().await {
+        Ok(db) => db,
+        Err(resp) => return resp
+    };
+
+    let admin = check_admin(&mut db, &req).await;
+    if admin.is_err() {
+        db.close().await;
+        return admin.unwrap_err();
+    }
+
+    let actor_name = query.actor.as_deref().map(str::trim).filter(|actor| !actor.is_empty());
+    let action = query.action.as_deref().and_then(AuditAction::parse);
+    let from = parse_date(query.from.as_deref());
+    // The until date is included in the results.
+    let until = parse_date(query.until.as_deref()).map(|until| until + 86400);
+
+    let actor = match actor_name {
+        Some(name) => Users::get_by_username(&mut db, name).await.unwrap().map(|user| Some(user.uuid)),
+        None => Some(None)
+    };
+    // An actor that does not exist has no entries.
+    let entries = match actor {
+        Some(actor) => AuditLog::search(&mut db, actor, action.map(|action| action.as_str()), from, until, 200).await.unwrap(),
+        None => Vec::new()
+    };
+
+    let mut usernames: HashMap<Uuid, String> = HashMap::new();
+    let mut rows = Vec::with_capacity(entries.len());
+    for entry in entries {
+        if !usernames.contains_key(&entry.actor) {
+            let username = if entry.actor.is_nil() {
+                "Console".to_string()
+            } else {
+                Users::get_by_uuid(&mut db, entry.actor).await.unwrap().map(|user| user.username).unwrap_or_else(|| "Deleted User".to_string())
+            };
+            usernames.insert(entry.actor, username);
+        }
+        rows.push(json!({
+            "entry": entry,
+            "actor": usernames[&entry.actor],
+            "description": AuditAction::parse(entry.action.as_str()).map(|action| action.description()),
+        }));
+    }
+    db.close().await;
+
+    let actions: Vec<Value> = AuditAction::ALL.iter()
+        .map(|option| json!({"name": option.as_str(), "description": option.description(), "selected": Some(*option) == action}))
+        .collect();
+    let result: String = (&data.hbs).render("admin/audit", &json!({
+        "name": SettingsManager::get_settings().name,
+        "actor": actor_name,
+        "actions": actions,
+        "from": from.and(query.from.as_deref()),
+        "until": query.until.as_deref().filter(|_| until.is_some()),
+        "entries": rows,
+    })).unwrap();
+
+    HttpResponse::Ok().body(result)
+}
+
+/// Render the settings page. The settings are shown with every secret redacted.
+fn render_settings(data: &ForumRSState, reload: serde_json::Value) -> HttpResponse {
+    let settings = SettingsManager::current();
+    let result: String = (&data.hbs).render("admin/settings", &json!({
+        "name": settings.name,
+        "path": SettingsManager::settings_path(),
+        "settings": SettingsManager::redacted(&settings),
+        "reload": reload,
+    })).unwrap();
+
+    HttpResponse::Ok().body(result)
+}
+
+/// The page with the settings the forum is running with.
+#[get("/admin/settings")]
+pub async fn settings(data: web::Data<ForumRSState>, req: HttpRequest) -> impl Responder {
+    let mut db = match open_database().await {
+        Ok(db) => db,
+        Err(resp) => return resp
+    };
+
+    let admin = check_admin(&mut db, &req).await;
+    db.close().await;
+    if admin.is_err() {
+        return admin.unwrap_err();
+    }
+
+    render_settings(&data, serde_json::Value::Null)
+}
+
+/// Reload the settings file without restarting the server.
+#[post("/auth/admin/settings/reload")]
+pub async fn auth_reload_settings(data: web::Data<ForumRSState>, req: HttpRequest) -> impl Responder {
+    let mut db = match open_database().await {
+        Ok(db) => db,
+        Err(resp) => return resp
+    };
+
+    let admin = match check_admin(&mut db, &req).await {
+        Ok(admin) => admin,
+        Err(resp) => {
+            db.close().await;
+            return resp;
+        }
+    };
+
+    let old_settings = SettingsManager::redacted_json(&SettingsManager::current());
+    let reload = match reload::reload_settings(format!("requested by {}", admin.username).as_str()) {
+        Ok(restart_required) => {
+            let (old_value, new_value) = audit::changes(&old_settings, &SettingsManager::redacted_json(&SettingsManager::current()));
+            audit::record(&mut db, admin.uuid, audit::request_ip(&req).as_str(), AuditAction::SettingsReload, SettingsManager::settings_path().as_str(),
+                          old_value, new_value).await.unwrap();
+            json!({"success": true, "restart_required": restart_required})
+        }
+        Err(err) => json!({"success": false, "error": err})
+    };
+    db.close().await;
+
+    render_settings(&data, reload)
+}
+
+/// The query for the backups page.
+#[derive(Deserialize)]
+pub struct BackupsQuery {
+    created: Option<String>,
+}
+
+/// The page where admins can make and download backups.
+#[get("/admin/backups")]
+pub async fn backups(data: web::Data<ForumRSState>, query: web::Query<BackupsQuery>, req: HttpRequest) -> impl Responder {
+    let mut db = match open_database().await {
+        Ok(db) => db,
+        Err(resp) => return resp
+    };
+
+    let admin = check_admin(&mut db, &req).await;
+    db.close().await;
+    if admin.is_err() {
+        return admin.unwrap_err();
+    }
+
+    let settings = SettingsManager::get_settings();
+    let result: String = (&data.hbs).render("admin/backups", &json!({
+        "name": settings.name,
+        "directory": settings.backup_settings.directory,
+        "scheduled": settings.backup_settings.scheduled,
+        "interval_hours": settings.backup_settings.interval_hours,
+        "keep": settings.backup_settings.keep,
+        "created": query.created,
+        "backups": schedule::list_backups(&settings),
+    })).unwrap();
+
+    HttpResponse::Ok().body(result)
+}
+
+/// Make a backup now. Old backups are pruned the same way as after a scheduled backup.
+#[post("/auth/admin/backups/create")]
+pub async fn auth_create_backup(req: HttpRequest) -> impl Responder {
+    let mut db = match open_database().await {
+        Ok(db) => db,
+        Err(resp) => return resp
+    };
+
+    let admin = check_admin(&mut db, &req).await;
+    if admin.is_err() {
+        db.close().await;
+        return admin.unwrap_err();
+    }
+
+    let admin = admin.unwrap();
+    let settings = SettingsManager::get_settings();
+    match backup::create_backup(&mut db, &settings).await {
+        Ok(path) => {
+            println!("[INFO] {} made the backup {}.", admin.username, path.display());
+            schedule::prune(&settings);
+            let name = path.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default();
+            audit::record(&mut db, admin.uuid, audit::request_ip(&req).as_str(), AuditAction::BackupCreate, name.as_str(), Value::Null, Value::Null).await.unwrap();
+            db.close().await;
+            HttpResponse::Found().header("Location", format!("/admin/backups?created={}", name)).finish()
+        }
+        Err(err) => {
+            db.close().await;
+            println!("[ERROR] Unable to make a backup: {}", err);
+            HttpResponse::Found().header("Location", "/admin/backups?err=1").finish()
+        }
+    }
+}
+
+/// Download a backup. Only the archives listed in the backup directory can be downloaded.
+#[get("/admin/backups/{name}")]
+pub async fn download_backup(name: web::Path<String>, req: HttpRequest) -> impl Responder {
+    let mut db = match open_database().await {
+        Ok(db) => db,
+        Err(resp) => return resp
+    };
+
+    let admin = match check_admin(&mut db, &req).await {
+        Ok(admin) => admin,
+        Err(resp) => {
+            db.close().await;
+            return resp;
+        }
+    };
+
+    let settings = SettingsManager::get_settings();
+    if !schedule::list_backups(&settings).iter().any(|backup| backup.name == *name) {
+        db.close().await;
+        return HttpResponse::NotFound().body("No backup exists with that name.");
+    }
+    // Backups hold every account, so downloads are recorded like changes.
+    audit::record(&mut db, admin.uuid, audit::request_ip(&req).as_str(), AuditAction::BackupDownload, name.as_str(), Value::Null, Value::Null).await.unwrap();
+    db.close().await;
+
+    let path = Path::new(&settings.backup_settings.directory).join(name.as_str());
+    match NamedFile::open(path) {
+        Ok(file) => file.into_response(&req).unwrap_or_else(|_| HttpResponse::InternalServerError().finish()),
+        Err(_) => HttpResponse::NotFound().body("No backup exists with that name.")
+    }
+}
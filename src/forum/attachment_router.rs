@@ -0,0 +1,108 @@
+use actix_web::{get, HttpRequest, HttpResponse, Responder, web};
+use uuid::Uuid;
+
+use crate::attachments::storage;
+use crate::auth::session;
+use crate::forum::open_database;
+use crate::schema::tables::{Attachment, Attachments, Forums, Threads};
+use crate::settings::SettingsManager;
+
+/**
+   Get an attachment if the user making the request can see the thread it belongs to.
+
+   ## Returns
+   The attachment, or the response to send if it cannot be shown.
+*/
+async fn find_attachment(uuid: Uuid, req: &HttpRequest) -> Result<Attachment, HttpResponse> {
+    let mut db = open_database().await?;
+
+    let user = session::current_user(&mut db, req).await;
+    let attachment = Attachments::get_by_uuid(&mut db, uuid).await.unwrap();
+    let thread = match &attachment {
+        Some(attachment) => Threads::get_by_uuid(&mut db, attachment.thread_uuid).await.unwrap(),
+        None => None
+    };
+    let forum = match &thread {
+        Some(thread) => Forums::get_by_uuid(&mut db, thread.forum_uuid).await.unwrap(),
+        None => None
+    };
+    db.close().await;
+
+    match (attachment, forum) {
+        (Some(attachment), Some(forum)) if forum.visible_to(user.as_ref()) => Ok(attachment),
+        _ => Err(HttpResponse::NotFound().body("The attachment does not exist."))
+    }
+}
+
+/// Read a file of an attachment from the storage.
+async fn read_file(key: &str) -> Result<Vec<u8>, HttpResponse> {
+    let storage = storage::from_settings(&SettingsManager::get_settings().attachment_settings);
+    match storage.get(key).await {
+        Ok(Some(data)) => Ok(data),
+        Ok(None) => {
+            println!("[WARN] The attachment file {} is missing from the storage.", key);
+            Err(HttpResponse::NotFound().body("The attachment does not exist."))
+        }
+        Err(err) => {
+            println!("[ERROR] {}", err);
+            Err(HttpResponse::InternalServerError().body("Unable to read the attachment."))
+        }
+    }
+}
+
+/**
+   Download an attachment.
+
+   Images are shown in the browser, every other file is downloaded. `nosniff` stops browsers from treating
+   an uploaded file as a page or script.
+*/
+#[get("/attachments/{uuid}")]
+pub async fn download(path: web::Path<Uuid>, req: HttpRequest) -> impl Responder {
+    let attachment = match find_attachment(path.into_inner(), &req).await {
+        Ok(attachment) => attachment,
+        Err(resp) => return resp
+    };
+    let data = match read_file(attachment.storage_key.as_str()).await {
+        Ok(data) => data,
+        Err(resp) => return resp
+    };
+
+    if let Ok(mut db) = open_database().await {
+        if let Err(err) = Attachments::add_download(&mut db, attachment.uuid).await {
+            println!("[WARN] Unable to count a download of an attachment: {:?}", err);
+        }
+        db.close().await;
+    }
+
+    let disposition = if attachment.thumbnail_key.is_some() { "inline" } else { "attachment" };
+    HttpResponse::Ok()
+        .content_type(attachment.content_type.as_str())
+        .header("Content-Disposition", format!("{}; filename=\"{}\"; filename*=UTF-8''{}", disposition,
+                                               attachment.file_name.replace(|c: char| !c.is_ascii(), "_"),
+                                               urlencoding::encode(attachment.file_name.as_str())))
+        .header("X-Content-Type-Options", "nosniff")
+        .body(data)
+}
+
+/// Get the thumbnail of an image attachment.
+#[get("/attachments/{uuid}/thumbnail")]
+pub async fn thumbnail(path: web::Path<Uuid>, req: HttpRequest) -> impl Responder {
+    let attachment = match find_attachment(path.into_inner(), &req).await {
+        Ok(attachment) => attachment,
+        Err(resp) => return resp
+    };
+    let key = match &attachment.thumbnail_key {
+        Some(key) => key,
+        None => return HttpResponse::NotFound().body("The attachment does not have a thumbnail.")
+    };
+    let data = match read_file(key.as_str()).await {
+        Ok(data) => data,
+        Err(resp) => return resp
+    };
+
+    HttpResponse::Ok()
+        .content_type("image/png")
+        .header("X-Content-Type-Options", "nosniff")
+        .header("Cache-Control", "private, max-age=86400")
+        .body(data)
+}
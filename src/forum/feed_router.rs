@@ -0,0 +1,176 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use actix_web::{get, HttpRequest, HttpResponse, Responder, web};
+use uuid::Uuid;
+
+use crate::feeds;
+use crate::feeds::{Feed, FeedEntry, FeedFormat, FEED_SIZE};
+use crate::forum::open_database;
+use crate::schema::database::Database;
+use crate::schema::tables::{Forums, Posts, Thread, Threads, Users};
+use crate::settings::SettingsManager;
+
+/// Get the usernames of a list of users. Unknown users are shown as "Deleted User".
+async fn usernames(db: &mut Database, uuids: impl Iterator<Item=Uuid>) -> HashMap<Uuid, String> {
+    let mut names = HashMap::new();
+    for uuid in uuids {
+        if names.contains_key(&uuid) {
+            continue;
+        }
+        let name = match Users::get_by_uuid(db, uuid).await {
+            Ok(Some(user)) => user.username,
+            _ => "Deleted User".to_string()
+        };
+        names.insert(uuid, name);
+    }
+    names
+}
+
+/// Convert threads to feed entries.
+async fn thread_entries(db: &mut Database, threads: Vec<Thread>, base_url: &str) -> Vec<FeedEntry> {
+    let names = usernames(db, threads.iter().map(|thread| thread.creator)).await;
+    threads.into_iter().map(|thread| FeedEntry {
+        link: format!("{}/threads/{}", base_url, thread.uuid),
+        id: thread.uuid.to_string(),
+        author: names.get(&thread.creator).cloned().unwrap_or_default(),
+        title: thread.name,
+        content: thread.content,
+        published: thread.date,
+    }).collect()
+}
+
+/// Parse the format of a feed url.
+fn parse_format(format: &str) -> Result<FeedFormat, HttpResponse> {
+    FeedFormat::from_str(format).map_err(|_| HttpResponse::NotFound().body("Unknown feed format. Use .rss or .atom."))
+}
+
+/// The feed of the newest threads in every forum guests can see.
+#[get("/feeds/latest.{format}")]
+pub async fn latest(path: web::Path<String>, req: HttpRequest) -> impl Responder {
+    let format = match parse_format(path.as_str()) {
+        Ok(format) => format,
+        Err(resp) => return resp
+    };
+
+    let mut db = match open_database().await {
+        Ok(db) => db,
+        Err(resp) => return resp
+    };
+
+    let settings = SettingsManager::get_settings();
+    let base_url = settings.base_url();
+
+    let visible_forums: Vec<Uuid> = Forums::get_all(&mut db).await.unwrap().into_iter()
+        .filter(|forum| forum.visible_to(None))
+        .map(|forum| forum.uuid)
+        .collect();
+    let threads = Threads::get_latest(&mut db, FEED_SIZE).await.unwrap().into_iter()
+        .filter(|thread| visible_forums.contains(&thread.forum_uuid))
+        .collect();
+    let entries = thread_entries(&mut db, threads, base_url.as_str()).await;
+    db.close().await;
+
+    let feed = Feed {
+        title: format!("{} | Latest Threads", settings.name),
+        description: format!("The newest threads on {}.", settings.name),
+        link: format!("{}/", base_url),
+        self_link: format!("{}/feeds/latest", base_url),
+        entries,
+    };
+    feeds::respond(&req, &feed, format)
+}
+
+/// The feed of the newest threads in a forum.
+#[get("/feeds/forums/{uuid}.{format}")]
+pub async fn forum(path: web::Path<(Uuid, String)>, req: HttpRequest) -> impl Responder {
+    let (forum_uuid, format) = path.into_inner();
+    let format = match parse_format(format.as_str()) {
+        Ok(format) => format,
+        Err(resp) => return resp
+    };
+
+    let mut db = match open_database().await {
+        Ok(db) => db,
+        Err(resp) => return resp
+    };
+
+    // Feed readers do not send a session, so only forums guests can see have a feed.
+    let forum = match Forums::get_by_uuid(&mut db, forum_uuid).await.unwrap() {
+        Some(forum) if forum.visible_to(None) => forum,
+        _ => {
+            db.close().await;
+            return HttpResponse::NotFound().body("The forum does not exist.");
+        }
+    };
+
+    let settings = SettingsManager::get_settings();
+    let base_url = settings.base_url();
+
+    let threads = Threads::get_by_forum(&mut db, forum.uuid, None, FEED_SIZE).await.unwrap();
+    let entries = thread_entries(&mut db, threads, base_url.as_str()).await;
+    db.close().await;
+
+    let feed = Feed {
+        title: format!("{} | {}", settings.name, forum.name),
+        description: forum.caption,
+        link: format!("{}/forums/{}", base_url, forum.uuid),
+        self_link: format!("{}/feeds/forums/{}", base_url, forum.uuid),
+        entries,
+    };
+    feeds::respond(&req, &feed, format)
+}
+
+/// The feed of the newest replies to a thread.
+#[get("/feeds/threads/{uuid}.{format}")]
+pub async fn thread(path: web::Path<(Uuid, String)>, req: HttpRequest) -> impl Responder {
+    let (thread_uuid, format) = path.into_inner();
+    let format = match parse_format(format.as_str()) {
+        Ok(format) => format,
+        Err(resp) => return resp
+    };
+
+    let mut db = match open_database().await {
+        Ok(db) => db,
+        Err(resp) => return resp
+    };
+
+    let thread = Threads::get_by_uuid(&mut db, thread_uuid).await.unwrap();
+    let forum = match &thread {
+        Some(thread) => Forums::get_by_uuid(&mut db, thread.forum_uuid).await.unwrap(),
+        None => None
+    };
+    let thread = match (thread, forum) {
+        (Some(thread), Some(forum)) if forum.visible_to(None) => thread,
+        _ => {
+            db.close().await;
+            return HttpResponse::NotFound().body("The thread does not exist.");
+        }
+    };
+
+    let settings = SettingsManager::get_settings();
+    let base_url = settings.base_url();
+    let thread_link = format!("{}/threads/{}", base_url, thread.uuid);
+
+    let posts = Posts::get_latest_by_thread(&mut db, thread.uuid, FEED_SIZE).await.unwrap();
+    let names = usernames(&mut db, posts.iter().map(|post| post.creator)).await;
+    db.close().await;
+
+    let entries = posts.into_iter().map(|post| FeedEntry {
+        title: format!("Re: {}", thread.name),
+        link: format!("{}#post-{}", thread_link, post.uuid),
+        id: post.uuid.to_string(),
+        author: names.get(&post.creator).cloned().unwrap_or_default(),
+        content: post.content,
+        published: post.date,
+    }).collect();
+
+    let feed = Feed {
+        title: format!("{} | {}", settings.name, thread.name),
+        description: format!("The newest replies to {}.", thread.name),
+        link: thread_link,
+        self_link: format!("{}/feeds/threads/{}", base_url, thread.uuid),
+        entries,
+    };
+    feeds::respond(&req, &feed, format)
+}
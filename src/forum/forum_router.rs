@@ -0,0 +1,172 @@
+use actix_web::{get, HttpRequest, HttpResponse, Responder, web};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+use crate::attachments;
+use crate::auth::session;
+use crate::forum::open_database;
+use crate::profiles;
+use crate::schema::database::Database;
+use crate::schema::tables::{Attachment, Attachments, Forums, Posts, Profile, Profiles, Thread, Threads, User, Users};
+use crate::settings::{ProfileSettings, SettingsManager};
+use crate::state::ForumRSState;
+
+/// The amount of threads or replies shown on a page.
+const PAGE_SIZE: i64 = 25;
+
+/// Get the attachments of a thread or post that are shown on the thread page.
+fn attachment_views(attachments: &[Attachment], post_uuid: Option<Uuid>) -> Vec<Value> {
+    attachments.iter().filter(|attachment| attachment.post_uuid == post_uuid).map(|attachment| json!({
+        "uuid": attachment.uuid,
+        "file_name": attachment.file_name,
+        "size": attachments::format_size(attachment.size),
+        "downloads": attachment.downloads,
+        "image": attachment.thumbnail_key.is_some(),
+    })).collect()
+}
+
+/// Get the avatar and signature shown with the threads and posts of an author. Deleted users have neither.
+async fn author_profile(db: &mut Database, author: Option<&User>, settings: &ProfileSettings) -> Value {
+    let author = match author {
+        Some(author) => author,
+        None => return json!({})
+    };
+    let profile = Profiles::get(db, author.uuid).await.unwrap().unwrap_or_else(|| Profile::empty(author.uuid));
+    json!({
+        "avatar_url": profiles::avatar_url(author, &profile, settings),
+        "signature": profile.signature,
+    })
+}
+
+/// The index page of the forum. This lists all of the forums.
+#[get("/")]
+pub async fn index(data: web::Data<ForumRSState>, req: HttpRequest) -> impl Responder {
+    let mut db = match open_database().await {
+        Ok(db) => db,
+        Err(resp) => return resp
+    };
+
+    let user = session::current_user(&mut db, &req).await;
+    let forums: Vec<_> = Forums::get_all(&mut db).await.unwrap().into_iter()
+        .filter(|forum| forum.visible_to(user.as_ref()))
+        .collect();
+    db.close().await;
+
+    let result: String = (&data.hbs).render("forum/index", &json!({
+        "name": SettingsManager::get_settings().name,
+        "user": user,
+        "forums": forums,
+        "feed": "/feeds/latest",
+    })).unwrap();
+
+    HttpResponse::Ok().body(result)
+}
+
+/// The query for paginated pages.
+#[derive(Deserialize)]
+pub struct PageQuery {
+    /// The id of the last item of the previous page.
+    cursor: Option<i32>,
+}
+
+/// The page of a forum. This lists the threads of the forum, newest first.
+#[get("/forums/{uuid}")]
+pub async fn forum(data: web::Data<ForumRSState>, path: web::Path<Uuid>, query: web::Query<PageQuery>, req: HttpRequest) -> impl Responder {
+    let mut db = match open_database().await {
+        Ok(db) => db,
+        Err(resp) => return resp
+    };
+
+    let user = session::current_user(&mut db, &req).await;
+    let forum = match Forums::get_by_uuid(&mut db, path.into_inner()).await.unwrap() {
+        Some(forum) if forum.visible_to(user.as_ref()) => forum,
+        _ => {
+            db.close().await;
+            return HttpResponse::NotFound().body("The forum does not exist.");
+        }
+    };
+
+    // Pinned threads are shown first on the first page instead of in their place.
+    let pinned = match query.cursor {
+        Some(_) => Vec::new(),
+        None => Threads::get_pinned(&mut db, forum.uuid).await.unwrap()
+    };
+    let threads = Threads::get_by_forum(&mut db, forum.uuid, query.cursor, PAGE_SIZE).await.unwrap();
+    db.close().await;
+
+    let next_cursor = if threads.len() as i64 >= PAGE_SIZE { threads.last().map(|thread| thread.id) } else { None };
+    let threads: Vec<Thread> = pinned.into_iter().chain(threads.into_iter().filter(|thread| !thread.pinned)).collect();
+    let result: String = (&data.hbs).render("forum/forum", &json!({
+        "name": SettingsManager::get_settings().name,
+        "user": user,
+        "forum": forum,
+        "threads": threads,
+        "next_cursor": next_cursor,
+        "feed": format!("/feeds/forums/{}", forum.uuid),
+    })).unwrap();
+
+    HttpResponse::Ok().body(result)
+}
+
+/// The page of a thread. This shows the thread and its replies, oldest first.
+#[get("/threads/{uuid}")]
+pub async fn thread(data: web::Data<ForumRSState>, path: web::Path<Uuid>, query: web::Query<PageQuery>, req: HttpRequest) -> impl Responder {
+    let mut db = match open_database().await {
+        Ok(db) => db,
+        Err(resp) => return resp
+    };
+
+    let user = session::current_user(&mut db, &req).await;
+    let thread = Threads::get_by_uuid(&mut db, path.into_inner()).await.unwrap();
+    let forum = match &thread {
+        Some(thread) => Forums::get_by_uuid(&mut db, thread.forum_uuid).await.unwrap(),
+        None => None
+    };
+    let (thread, forum) = match (thread, forum) {
+        (Some(thread), Some(forum)) if forum.visible_to(user.as_ref()) => (thread, forum),
+        _ => {
+            db.close().await;
+            return HttpResponse::NotFound().body("The thread does not exist.");
+        }
+    };
+
+    let settings = SettingsManager::get_settings();
+    let creator = Users::get_by_uuid(&mut db, thread.creator).await.unwrap();
+    let creator_profile = author_profile(&mut db, creator.as_ref(), &settings.profile_settings).await;
+    let posts = Posts::get_by_thread(&mut db, thread.uuid, query.cursor, PAGE_SIZE).await.unwrap();
+    let thread_attachments = Attachments::get_by_thread(&mut db, thread.uuid).await.unwrap();
+    let mut replies = Vec::with_capacity(posts.len());
+    for post in &posts {
+        let author = Users::get_by_uuid(&mut db, post.creator).await.unwrap();
+        let profile = author_profile(&mut db, author.as_ref(), &settings.profile_settings).await;
+        replies.push(json!({
+            "post": post,
+            "author": author,
+            "profile": profile,
+            "attachments": attachment_views(&thread_attachments, Some(post.uuid)),
+        }));
+    }
+    let moderator = user.as_ref().map(|user| user.is_admin || user.is_moderator).unwrap_or(false);
+    // The forums a moderator can move the thread to.
+    let forums = if moderator { Forums::get_all(&mut db).await.unwrap() } else { Vec::new() };
+    db.close().await;
+
+    let next_cursor = if posts.len() as i64 >= PAGE_SIZE { posts.last().map(|post| post.id) } else { None };
+    let result: String = (&data.hbs).render("forum/thread", &json!({
+        "name": settings.name,
+        "user": user,
+        "forum": forum,
+        "thread": thread,
+        "creator": creator,
+        "creator_profile": creator_profile,
+        "attachments": attachment_views(&thread_attachments, None),
+        "replies": replies,
+        "next_cursor": next_cursor,
+        "moderator": moderator,
+        "forums": forums,
+        "feed": format!("/feeds/threads/{}", thread.uuid),
+    })).unwrap();
+
+    HttpResponse::Ok().body(result)
+}
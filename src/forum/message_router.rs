@@ -0,0 +1,570 @@
+use std::collections::HashMap;
+
+use actix_web::{get, HttpRequest, HttpResponse, post, Responder, web};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+use crate::auth::session;
+use crate::forum::open_database;
+use crate::schema::database::Database;
+use crate::schema::tables::{ConversationMembers, Conversations, Message, Messages, User, UserBlocks, Users};
+use crate::settings::SettingsManager;
+use crate::state::ForumRSState;
+
+/// The maximum length of the subject of a conversation.
+const MAX_SUBJECT_LENGTH: usize = 100;
+/// The amount of conversations shown in the inbox.
+const INBOX_SIZE: i64 = 50;
+
+/// Get the usernames of a list of users. Unknown users are shown as "Deleted User".
+async fn usernames(db: &mut Database, uuids: impl Iterator<Item=Uuid>) -> HashMap<Uuid, String> {
+    let mut names = HashMap::new();
+    for uuid in uuids {
+        if names.contains_key(&uuid) {
+            continue;
+        }
+        let name = match Users::get_by_uuid(db, uuid).await {
+            Ok(Some(user)) => user.username,
+            _ => "Deleted User".to_string()
+        };
+        names.insert(uuid, name);
+    }
+    names
+}
+
+/// Get the logged in user for a private message page. Private messages must be enabled.
+async fn message_user(db: &mut Database, req: &HttpRequest) -> Result<User, HttpResponse> {
+    if !SettingsManager::get_settings().message_settings.enabled {
+        return Err(HttpResponse::NotFound().body("Private messages are disabled."));
+    }
+    match session::current_user(db, req).await {
+        Some(user) => Ok(user),
+        None => Err(HttpResponse::Found().header("Location", "/login").finish())
+    }
+}
+
+/// Check the content of a message. Returns the trimmed content.
+fn clean_message(content: &str) -> Option<String> {
+    let content = content.trim();
+    let length = content.chars().count();
+    if length == 0 || length > SettingsManager::get_settings().message_settings.max_message_length {
+        return None;
+    }
+    Some(content.to_string())
+}
+
+/// The inbox of the logged in user. This lists their conversations, most recently updated first.
+#[get("/messages")]
+pub async fn inbox(data: web::Data<ForumRSState>, req: HttpRequest) -> impl Responder {
+    let mut db = match open_database().await {
+        Ok(db) => db,
+        Err(resp) => return resp
+    };
+    let user = match message_user(&mut db, &req).await {
+        Ok(user) => user,
+        Err(resp) => {
+            db.close().await;
+            return resp;
+        }
+    };
+
+    let entries = Conversations::get_inbox(&mut db, user.uuid, INBOX_SIZE).await.unwrap();
+    let count = ConversationMembers::count_by_user(&mut db, user.uuid).await.unwrap();
+    let mut conversations = Vec::with_capacity(entries.len());
+    for entry in entries {
+        let members: Vec<Uuid> = ConversationMembers::get_members(&mut db, entry.conversation.uuid).await.unwrap().into_iter()
+            .filter(|member| *member != user.uuid)
+            .collect();
+        let names = usernames(&mut db, members.iter().cloned()).await;
+        conversations.push(json!({
+            "conversation": entry.conversation,
+            "unread": entry.unread,
+            "members": members.iter().filter_map(|member| names.get(member).cloned()).collect::<Vec<String>>().join(", "),
+        }));
+    }
+    db.close().await;
+
+    let settings = SettingsManager::get_settings();
+    let result: String = (&data.hbs).render("forum/messages", &json!({
+        "name": settings.name,
+        "user": user,
+        "conversations": conversations,
+        "count": count,
+        "quota": settings.message_settings.max_conversations,
+    })).unwrap();
+
+    HttpResponse::Ok().body(result)
+}
+
+/// The query parameters of the page to start a conversation.
+#[derive(Deserialize)]
+pub struct NewMessageQuery {
+    /// The usernames to fill in as the recipients.
+    to: Option<String>,
+}
+
+/// The page to start a conversation.
+#[get("/messages/new")]
+pub async fn new_message(data: web::Data<ForumRSState>, query: web::Query<NewMessageQuery>, req: HttpRequest) -> impl Responder {
+    let mut db = match open_database().await {
+        Ok(db) => db,
+        Err(resp) => return resp
+    };
+    let user = message_user(&mut db, &req).await;
+    db.close().await;
+    let user = match user {
+        Ok(user) => user,
+        Err(resp) => return resp
+    };
+
+    let settings = SettingsManager::get_settings();
+    let result: String = (&data.hbs).render("forum/new_message", &json!({
+        "name": settings.name,
+        "user": user,
+        "to": query.to,
+        "max_recipients": settings.message_settings.max_recipients,
+        "max_subject_length": MAX_SUBJECT_LENGTH,
+        "max_message_length": settings.message_settings.max_message_length,
+    })).unwrap();
+
+    HttpResponse::Ok().body(result)
+}
+
+/// The form data to start a conversation.
+#[derive(Deserialize)]
+pub struct AuthNewMessageForm {
+    /// The usernames of the recipients, separated by commas.
+    to: String,
+    subject: String,
+    content: String,
+}
+
+/**
+   Check if a conversation can be started with the recipients.
+
+   ## Returns
+   The error shown on the new message page if it cannot: 5 if a block stops it, 6 if the inbox of the sender is
+   full and 7 if the inbox of a recipient is full.
+*/
+async fn check_recipients(db: &mut Database, sender: Uuid, recipients: &[User], max_conversations: i64) -> Result<(), i32> {
+    for recipient in recipients {
+        if UserBlocks::is_blocked(db, recipient.uuid, sender).await.unwrap()
+            || UserBlocks::is_blocked(db, sender, recipient.uuid).await.unwrap() {
+            return Err(5);
+        }
+    }
+    if ConversationMembers::count_by_user(db, sender).await.unwrap() >= max_conversations {
+        return Err(6);
+    }
+    for recipient in recipients {
+        if ConversationMembers::count_by_user(db, recipient.uuid).await.unwrap() >= max_conversations {
+            return Err(7);
+        }
+    }
+    Ok(())
+}
+
+/**
+   Start a conversation.
+
+   Users cannot message users that have blocked them or that they have blocked, and the inbox of the sender
+   and of every recipient must have room for the conversation.
+*/
+#[post("/auth/messages/new")]
+pub async fn auth_new_message(form: web::Form<AuthNewMessageForm>, req: HttpRequest) -> impl Responder {
+    let mut db = match open_database().await {
+        Ok(db) => db,
+        Err(resp) => return resp
+    };
+    let user = match message_user(&mut db, &req).await {
+        Ok(user) => user,
+        Err(resp) => {
+            db.close().await;
+            return resp;
+        }
+    };
+    let settings = SettingsManager::get_settings().message_settings;
+    let error = |err: i32| HttpResponse::Found().header("Location", format!("/messages/new?err={}", err)).finish();
+
+    let mut recipients: Vec<User> = Vec::new();
+    for username in form.to.split(',').map(|username| username.trim()).filter(|username| !username.is_empty()) {
+        match Users::get_by_username(&mut db, username).await.unwrap() {
+            Some(recipient) if recipient.uuid != user.uuid => {
+                if !recipients.iter().any(|other| other.uuid == recipient.uuid) {
+                    recipients.push(recipient);
+                }
+            }
+            _ => {
+                db.close().await;
+                return error(1);
+            }
+        }
+    }
+    if recipients.is_empty() {
+        db.close().await;
+        return error(1);
+    }
+    if recipients.len() > settings.max_recipients {
+        db.close().await;
+        return error(2);
+    }
+
+    let subject = form.subject.trim().replace(|c: char| c.is_control(), "");
+    if subject.is_empty() || subject.chars().count() > MAX_SUBJECT_LENGTH {
+        db.close().await;
+        return error(3);
+    }
+    let content = match clean_message(form.content.as_str()) {
+        Some(content) => content,
+        None => {
+            db.close().await;
+            return error(4);
+        }
+    };
+
+    if let Err(err) = check_recipients(&mut db, user.uuid, &recipients, settings.max_conversations).await {
+        db.close().await;
+        return error(err);
+    }
+
+    let conversation_uuid = Uuid::new_v4();
+    Conversations::insert(&mut db, conversation_uuid, subject, user.uuid).await.unwrap();
+    ConversationMembers::insert(&mut db, conversation_uuid, user.uuid).await.unwrap();
+    for recipient in &recipients {
+        ConversationMembers::insert(&mut db, conversation_uuid, recipient.uuid).await.unwrap();
+    }
+    Messages::insert(&mut db, Uuid::new_v4(), conversation_uuid, user.uuid, content).await.unwrap();
+    ConversationMembers::mark_read(&mut db, conversation_uuid, user.uuid).await.unwrap();
+    db.close().await;
+
+    HttpResponse::Found().header("Location", format!("/messages/{}", conversation_uuid)).finish()
+}
+
+/// The number of conversations with unread messages, used by the notification in the navigation bar.
+#[get("/messages/unread")]
+pub async fn unread(req: HttpRequest) -> impl Responder {
+    let mut db = match open_database().await {
+        Ok(db) => db,
+        Err(resp) => return resp
+    };
+
+    let unread = match session::current_user(&mut db, &req).await {
+        Some(user) if SettingsManager::get_settings().message_settings.enabled => Conversations::count_unread(&mut db, user.uuid).await.unwrap(),
+        _ => 0
+    };
+    db.close().await;
+
+    HttpResponse::Ok().json(json!({ "unread": unread }))
+}
+
+/// Build the messages shown in a conversation. Messages of blocked users are flagged so the page can hide them.
+fn message_entries(messages: &[Message], names: &HashMap<Uuid, String>, blocked: &[Uuid]) -> Vec<Value> {
+    messages.iter().map(|message| json!({
+        "message": message,
+        "sender": names.get(&message.sender),
+        "blocked": blocked.contains(&message.sender),
+    })).collect()
+}
+
+/// A conversation. Only its members can see it, and opening it marks its messages as read.
+#[get("/messages/{uuid}")]
+pub async fn conversation(data: web::Data<ForumRSState>, path: web::Path<Uuid>, req: HttpRequest) -> impl Responder {
+    let mut db = match open_database().await {
+        Ok(db) => db,
+        Err(resp) => return resp
+    };
+    let user = match message_user(&mut db, &req).await {
+        Ok(user) => user,
+        Err(resp) => {
+            db.close().await;
+            return resp;
+        }
+    };
+
+    let conversation_uuid = path.into_inner();
+    let is_member = ConversationMembers::is_member(&mut db, conversation_uuid, user.uuid).await.unwrap();
+    let conversation = match Conversations::get_by_uuid(&mut db, conversation_uuid).await.unwrap() {
+        Some(conversation) if is_member => conversation,
+        _ => {
+            db.close().await;
+            return HttpResponse::NotFound().body("The conversation does not exist.");
+        }
+    };
+
+    let members = ConversationMembers::get_members(&mut db, conversation_uuid).await.unwrap();
+    let blocked = UserBlocks::get_blocked(&mut db, user.uuid).await.unwrap();
+    let messages = Messages::get_by_conversation(&mut db, conversation_uuid).await.unwrap();
+    let names = usernames(&mut db, members.iter().cloned().chain(messages.iter().map(|message| message.sender))).await;
+    ConversationMembers::mark_read(&mut db, conversation_uuid, user.uuid).await.unwrap();
+    db.close().await;
+
+    let messages = message_entries(&messages, &names, &blocked);
+    let members: Vec<_> = members.iter().filter_map(|member| names.get(member)).collect();
+
+    let result: String = (&data.hbs).render("forum/conversation", &json!({
+        "name": SettingsManager::get_settings().name,
+        "user": user,
+        "conversation": conversation,
+        "members": members,
+        "messages": messages,
+    })).unwrap();
+
+    HttpResponse::Ok().body(result)
+}
+
+/// The form data to reply to a conversation.
+#[derive(Deserialize)]
+pub struct AuthReplyForm {
+    content: String,
+}
+
+/// Reply to a conversation.
+#[post("/auth/messages/{uuid}/reply")]
+pub async fn auth_reply(path: web::Path<Uuid>, form: web::Form<AuthReplyForm>, req: HttpRequest) -> impl Responder {
+    let mut db = match open_database().await {
+        Ok(db) => db,
+        Err(resp) => return resp
+    };
+    let user = match message_user(&mut db, &req).await {
+        Ok(user) => user,
+        Err(resp) => {
+            db.close().await;
+            return resp;
+        }
+    };
+
+    let conversation_uuid = path.into_inner();
+    if !ConversationMembers::is_member(&mut db, conversation_uuid, user.uuid).await.unwrap() {
+        db.close().await;
+        return HttpResponse::NotFound().body("The conversation does not exist.");
+    }
+    let content = match clean_message(form.content.as_str()) {
+        Some(content) => content,
+        None => {
+            db.close().await;
+            return HttpResponse::Found().header("Location", format!("/messages/{}?err=1", conversation_uuid)).finish();
+        }
+    };
+
+    Messages::insert(&mut db, Uuid::new_v4(), conversation_uuid, user.uuid, content).await.unwrap();
+    Conversations::touch(&mut db, conversation_uuid).await.unwrap();
+    ConversationMembers::mark_read(&mut db, conversation_uuid, user.uuid).await.unwrap();
+    db.close().await;
+
+    HttpResponse::Found().header("Location", format!("/messages/{}", conversation_uuid)).finish()
+}
+
+/// Leave a conversation. The conversation is deleted once every member has left.
+#[post("/auth/messages/{uuid}/leave")]
+pub async fn auth_leave(path: web::Path<Uuid>, req: HttpRequest) -> impl Responder {
+    let mut db = match open_database().await {
+        Ok(db) => db,
+        Err(resp) => return resp
+    };
+    let user = match message_user(&mut db, &req).await {
+        Ok(user) => user,
+        Err(resp) => {
+            db.close().await;
+            return resp;
+        }
+    };
+
+    let conversation_uuid = path.into_inner();
+    ConversationMembers::delete(&mut db, conversation_uuid, user.uuid).await.unwrap();
+    if ConversationMembers::get_members(&mut db, conversation_uuid).await.unwrap().is_empty() {
+        Conversations::delete(&mut db, conversation_uuid).await.unwrap();
+    }
+    db.close().await;
+
+    HttpResponse::Found().header("Location", "/messages").finish()
+}
+
+/// The page listing the users the logged in user has blocked.
+#[get("/account/blocked")]
+pub async fn blocked(data: web::Data<ForumRSState>, req: HttpRequest) -> impl Responder {
+    let mut db = match open_database().await {
+        Ok(db) => db,
+        Err(resp) => return resp
+    };
+    let user = match session::current_user(&mut db, &req).await {
+        Some(user) => user,
+        None => {
+            db.close().await;
+            return HttpResponse::Found().header("Location", "/login").finish();
+        }
+    };
+
+    let blocked = UserBlocks::get_blocked(&mut db, user.uuid).await.unwrap();
+    let names = usernames(&mut db, blocked.iter().cloned()).await;
+    db.close().await;
+    let blocked: Vec<_> = blocked.iter().filter_map(|uuid| names.get(uuid)).collect();
+
+    let result: String = (&data.hbs).render("forum/blocked", &json!({
+        "name": SettingsManager::get_settings().name,
+        "user": user,
+        "blocked": blocked,
+    })).unwrap();
+
+    HttpResponse::Ok().body(result)
+}
+
+/// The form data to block or unblock a user.
+#[derive(Deserialize)]
+pub struct AuthBlockForm {
+    username: String,
+}
+
+/// Block a user. They can no longer message the logged in user, and their messages are hidden.
+#[post("/auth/account/block")]
+pub async fn auth_block(form: web::Form<AuthBlockForm>, req: HttpRequest) -> impl Responder {
+    let mut db = match open_database().await {
+        Ok(db) => db,
+        Err(resp) => return resp
+    };
+    let user = match session::current_user(&mut db, &req).await {
+        Some(user) => user,
+        None => {
+            db.close().await;
+            return HttpResponse::Found().header("Location", "/login").finish();
+        }
+    };
+
+    let result = match Users::get_by_username(&mut db, form.username.trim()).await.unwrap() {
+        Some(blocked) if blocked.uuid != user.uuid => {
+            UserBlocks::insert(&mut db, user.uuid, blocked.uuid).await.unwrap();
+            "/account/blocked"
+        }
+        _ => "/account/blocked?err=1"
+    };
+    db.close().await;
+
+    HttpResponse::Found().header("Location", result).finish()
+}
+
+/// Unblock a user.
+#[post("/auth/account/unblock")]
+pub async fn auth_unblock(form: web::Form<AuthBlockForm>, req: HttpRequest) -> impl Responder {
+    let mut db = match open_database().await {
+        Ok(db) => db,
+        Err(resp) => return resp
+    };
+    let user = match session::current_user(&mut db, &req).await {
+        Some(user) => user,
+        None => {
+            db.close().await;
+            return HttpResponse::Found().header("Location", "/login").finish();
+        }
+    };
+
+    if let Some(blocked) = Users::get_by_username(&mut db, form.username.trim()).await.unwrap() {
+        UserBlocks::delete(&mut db, user.uuid, blocked.uuid).await.unwrap();
+    }
+    db.close().await;
+
+    HttpResponse::Found().header("Location", "/account/blocked").finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::schema::tables::Table;
+    use crate::settings::SqlSettings;
+
+    use super::*;
+
+    async fn open_test_database() -> Database {
+        let mut db = Database::new_sqlite(&SqlSettings { file_location: ":memory:".to_string() }).await.unwrap();
+        ConversationMembers::create(&mut db).await.unwrap();
+        Messages::create(&mut db).await.unwrap();
+        UserBlocks::create(&mut db).await.unwrap();
+        db
+    }
+
+    fn user(username: &str) -> User {
+        User {
+            id: 1,
+            uuid: Uuid::new_v4(),
+            username: username.to_string(),
+            email: format!("{}@example.com", username),
+            password: String::new(),
+            is_banned: false,
+            is_admin: false,
+            is_moderator: false,
+            password_reset: false,
+            date: 0,
+        }
+    }
+
+    /// Add a user to the amount of conversations.
+    async fn join_conversations(db: &mut Database, user_uuid: Uuid, amount: usize) {
+        for _ in 0..amount {
+            ConversationMembers::insert(db, Uuid::new_v4(), user_uuid).await.unwrap();
+        }
+    }
+
+    #[actix_rt::test]
+    async fn blocks_stop_conversations_both_ways() {
+        let mut db = open_test_database().await;
+        let (sender, recipient) = (user("sender"), user("recipient"));
+        assert_eq!(check_recipients(&mut db, sender.uuid, &[recipient.clone()], 10).await, Ok(()));
+
+        UserBlocks::insert(&mut db, recipient.uuid, sender.uuid).await.unwrap();
+        assert_eq!(check_recipients(&mut db, sender.uuid, &[recipient.clone()], 10).await, Err(5));
+
+        UserBlocks::delete(&mut db, recipient.uuid, sender.uuid).await.unwrap();
+        UserBlocks::insert(&mut db, sender.uuid, recipient.uuid).await.unwrap();
+        assert_eq!(check_recipients(&mut db, sender.uuid, &[recipient.clone()], 10).await, Err(5));
+
+        db.close().await;
+    }
+
+    #[actix_rt::test]
+    async fn full_inboxes_are_rejected() {
+        let mut db = open_test_database().await;
+        let (sender, first, second) = (user("sender"), user("first"), user("second"));
+        let recipients = vec![first.clone(), second.clone()];
+
+        join_conversations(&mut db, sender.uuid, 2).await;
+        join_conversations(&mut db, second.uuid, 3).await;
+        assert_eq!(check_recipients(&mut db, sender.uuid, &recipients, 2).await, Err(6));
+        assert_eq!(check_recipients(&mut db, sender.uuid, &recipients, 3).await, Err(7));
+        assert_eq!(check_recipients(&mut db, sender.uuid, &[first], 3).await, Ok(()));
+        assert_eq!(check_recipients(&mut db, sender.uuid, &recipients, 4).await, Ok(()));
+
+        db.close().await;
+    }
+
+    #[actix_rt::test]
+    async fn blocking_twice_keeps_one_block() {
+        let mut db = open_test_database().await;
+        let (blocker, blocked) = (Uuid::new_v4(), Uuid::new_v4());
+        UserBlocks::insert(&mut db, blocker, blocked).await.unwrap();
+        UserBlocks::insert(&mut db, blocker, blocked).await.unwrap();
+
+        assert_eq!(UserBlocks::get_blocked(&mut db, blocker).await.unwrap(), vec![blocked]);
+        assert!(UserBlocks::get_blocked(&mut db, blocked).await.unwrap().is_empty());
+
+        db.close().await;
+    }
+
+    #[test]
+    fn messages_of_blocked_users_are_flagged() {
+        let (friend, blocked) = (Uuid::new_v4(), Uuid::new_v4());
+        let message = |sender: Uuid| Message {
+            id: 1,
+            uuid: Uuid::new_v4(),
+            conversation_uuid: Uuid::new_v4(),
+            sender,
+            content: "Hello".to_string(),
+            created: 0,
+        };
+        let mut names = HashMap::new();
+        names.insert(friend, "friend".to_string());
+
+        let entries = message_entries(&[message(friend), message(blocked)], &names, &[blocked]);
+        assert_eq!(entries[0]["blocked"], json!(false));
+        assert_eq!(entries[0]["sender"], json!("friend"));
+        assert_eq!(entries[1]["blocked"], json!(true));
+        assert_eq!(entries[1]["sender"], Value::Null);
+    }
+}
@@ -0,0 +1,30 @@
+use actix_web::HttpResponse;
+
+use crate::schema::database::Database;
+use crate::settings::SettingsManager;
+
+pub mod account_router;
+pub mod admin_router;
+pub mod attachment_router;
+pub mod feed_router;
+pub mod forum_router;
+pub mod message_router;
+pub mod moderation_router;
+pub mod oauth_router;
+pub mod privacy_router;
+pub mod profile_router;
+pub mod token_router;
+pub mod webhook_router;
+
+/// Open a connection to the database of the forum.
+///
+/// If the connection fails, the error is logged and an internal server error response is returned.
+pub async fn open_database() -> Result<Database, HttpResponse> {
+    match Database::connect(&SettingsManager::get_settings()).await {
+        Ok(db) => Ok(db),
+        Err(err) => {
+            println!("[ERROR] Unable to connect to the database: {:?}", err);
+            Err(HttpResponse::InternalServerError().body("Unable to connect to the database."))
+        }
+    }
+}
@@ -0,0 +1,511 @@
+use std::collections::HashMap;
+
+use actix_web::{get, HttpRequest, HttpResponse, post, Responder, web};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+use crate::api::v1::MAX_THREAD_NAME_LENGTH;
+use crate::audit;
+use crate::auth::session;
+use crate::forum::admin_router::check_moderator;
+use crate::forum::open_database;
+use crate::moderation;
+use crate::moderation::{ActionError, ReportAction, ReportedContent};
+use crate::schema::database::Database;
+use crate::schema::tables::{ConversationMembers, Conversations, Forums, Posts, Reports, ReportTarget, Thread, Threads, User, Users};
+use crate::settings::SettingsManager;
+use crate::state::ForumRSState;
+
+/// The maximum length of the reason of a report.
+const MAX_REASON_LENGTH: usize = 1000;
+/// The amount of closed reports shown under the moderation queue.
+const RESOLVED_HISTORY: i64 = 50;
+
+/// Get the username of a user. Unknown users are shown as "Deleted User".
+async fn username(db: &mut Database, uuid: Uuid) -> String {
+    match Users::get_by_uuid(db, uuid).await {
+        Ok(Some(user)) => user.username,
+        _ => "Deleted User".to_string()
+    }
+}
+
+/// Check if a user can see the target they want to report.
+async fn can_report(db: &mut Database, user: &User, content: &ReportedContent) -> bool {
+    let thread_uuid = match content {
+        ReportedContent::Thread(thread) => thread.uuid,
+        ReportedContent::Post(post) => post.thread_uuid,
+        ReportedContent::User(reported) => return reported.uuid != user.uuid,
+        ReportedContent::Message(message) => {
+            return message.sender != user.uuid
+                && ConversationMembers::is_member(db, message.conversation_uuid, user.uuid).await.unwrap();
+        }
+        ReportedContent::Missing => return false
+    };
+    let forum = match Threads::get_by_uuid(db, thread_uuid).await.unwrap() {
+        Some(thread) => Forums::get_by_uuid(db, thread.forum_uuid).await.unwrap(),
+        None => None
+    };
+    forum.map(|forum| forum.visible_to(Some(user))).unwrap_or(false)
+}
+
+/// Describe the target of a report for the report page and the moderation queue.
+async fn describe(db: &mut Database, content: &ReportedContent) -> Value {
+    let author = match content.author() {
+        Some(author) => Some(username(db, author).await),
+        None => None
+    };
+    match content {
+        ReportedContent::Thread(thread) => json!({
+            "title": format!("Thread: {}", thread.name),
+            "content": thread.content,
+            "link": format!("/threads/{}", thread.uuid),
+            "author": author,
+            "locked": thread.locked,
+        }),
+        ReportedContent::Post(post) => {
+            let thread = Threads::get_by_uuid(db, post.thread_uuid).await.unwrap();
+            json!({
+                "title": format!("Reply in {}", thread.as_ref().map(|thread| thread.name.as_str()).unwrap_or("a deleted thread")),
+                "content": post.content,
+                "link": format!("/threads/{}#post-{}", post.thread_uuid, post.uuid),
+                "author": author,
+                "locked": thread.map(|thread| thread.locked).unwrap_or(false),
+            })
+        }
+        ReportedContent::User(user) => json!({
+            "title": format!("Profile of {}", user.username),
+            "link": format!("/users/{}", user.username),
+            "author": author,
+            "banned": user.is_banned,
+        }),
+        ReportedContent::Message(message) => {
+            // Moderators are not members of the conversation, so only the reported message is shown.
+            let subject = Conversations::get_by_uuid(db, message.conversation_uuid).await.unwrap().map(|conversation| conversation.subject);
+            json!({
+                "title": format!("Private message in {}", subject.unwrap_or_else(|| "a deleted conversation".to_string())),
+                "content": message.content,
+                "author": author,
+            })
+        }
+        ReportedContent::Missing => json!({
+            "title": "Deleted content",
+            "missing": true,
+        })
+    }
+}
+
+/// The query parameters of the report page.
+#[derive(Deserialize)]
+pub struct ReportQuery {
+    #[serde(rename = "type")]
+    target_type: String,
+    uuid: Uuid,
+    /// If the report was just sent.
+    sent: Option<bool>,
+}
+
+/// The page to report a thread, post, user or private message.
+#[get("/report")]
+pub async fn report(data: web::Data<ForumRSState>, query: web::Query<ReportQuery>, req: HttpRequest) -> impl Responder {
+    let mut db = match open_database().await {
+        Ok(db) => db,
+        Err(resp) => return resp
+    };
+    let user = match session::current_user(&mut db, &req).await {
+        Some(user) => user,
+        None => {
+            db.close().await;
+            return HttpResponse::Found().header("Location", "/login").finish();
+        }
+    };
+
+    let target_type = ReportTarget::parse(query.target_type.as_str());
+    let content = match target_type {
+        Some(target_type) => ReportedContent::load(&mut db, target_type, query.uuid).await.unwrap(),
+        None => ReportedContent::Missing
+    };
+    if !can_report(&mut db, &user, &content).await {
+        db.close().await;
+        return HttpResponse::NotFound().body("There is nothing to report.");
+    }
+    let target = describe(&mut db, &content).await;
+    db.close().await;
+
+    let result: String = (&data.hbs).render("forum/report", &json!({
+        "name": SettingsManager::get_settings().name,
+        "user": user,
+        "target_type": query.target_type,
+        "target_uuid": query.uuid,
+        "target": target,
+        "sent": query.sent.unwrap_or(false),
+        "max_reason_length": MAX_REASON_LENGTH,
+    })).unwrap();
+
+    HttpResponse::Ok().body(result)
+}
+
+/// The form data to report something.
+#[derive(Deserialize)]
+pub struct AuthReportForm {
+    target_type: String,
+    target_uuid: Uuid,
+    reason: String,
+}
+
+/// Report a thread, post, user or private message. A user can only have one open report of the same target.
+#[post("/auth/report")]
+pub async fn auth_report(form: web::Form<AuthReportForm>, req: HttpRequest) -> impl Responder {
+    let mut db = match open_database().await {
+        Ok(db) => db,
+        Err(resp) => return resp
+    };
+    let user = match session::current_user(&mut db, &req).await {
+        Some(user) => user,
+        None => {
+            db.close().await;
+            return HttpResponse::Found().header("Location", "/login").finish();
+        }
+    };
+
+    let target_type = match ReportTarget::parse(form.target_type.as_str()) {
+        Some(target_type) => target_type,
+        None => {
+            db.close().await;
+            return HttpResponse::BadRequest().body("Unknown report type.");
+        }
+    };
+    let content = ReportedContent::load(&mut db, target_type, form.target_uuid).await.unwrap();
+    if !can_report(&mut db, &user, &content).await {
+        db.close().await;
+        return HttpResponse::NotFound().body("There is nothing to report.");
+    }
+
+    let page = format!("/report?type={}&uuid={}", target_type.as_str(), form.target_uuid);
+    let reason = form.reason.trim();
+    if reason.is_empty() || reason.chars().count() > MAX_REASON_LENGTH {
+        db.close().await;
+        return HttpResponse::Found().header("Location", format!("{}&err=1", page)).finish();
+    }
+
+    if !Reports::has_open_report(&mut db, target_type, form.target_uuid, user.uuid).await.unwrap() {
+        Reports::insert(&mut db, Uuid::new_v4(), target_type, form.target_uuid, user.uuid, reason.to_string()).await.unwrap();
+    }
+    db.close().await;
+
+    HttpResponse::Found().header("Location", format!("{}&sent=true", page)).finish()
+}
+
+/**
+   The moderation queue. Open reports are grouped by what they report, oldest first, so a target reported by
+   many users is handled once.
+*/
+#[get("/moderation/reports")]
+pub async fn reports(data: web::Data<ForumRSState>, req: HttpRequest) -> impl Responder {
+    let mut db = match open_database().await {
+        Ok(db) => db,
+        Err(resp) => return resp
+    };
+    let moderator = match check_moderator(&mut db, &req).await {
+        Ok(moderator) => moderator,
+        Err(resp) => {
+            db.close().await;
+            return resp;
+        }
+    };
+
+    let mut groups: Vec<(ReportTarget, Uuid, Vec<Value>)> = Vec::new();
+    let mut indexes: HashMap<(&'static str, Uuid), usize> = HashMap::new();
+    for report in Reports::get_open(&mut db).await.unwrap() {
+        let key = (report.target_type.as_str(), report.target_uuid);
+        let index = match indexes.get(&key) {
+            Some(index) => *index,
+            None => {
+                groups.push((report.target_type, report.target_uuid, Vec::new()));
+                indexes.insert(key, groups.len() - 1);
+                groups.len() - 1
+            }
+        };
+        let reporter = username(&mut db, report.reporter).await;
+        groups[index].2.push(json!({
+            "report": report,
+            "reporter": reporter,
+        }));
+    }
+
+    let mut queue = Vec::with_capacity(groups.len());
+    for (target_type, target_uuid, reports) in groups {
+        let content = ReportedContent::load(&mut db, target_type, target_uuid).await.unwrap();
+        let actions: Vec<&str> = content.actions().iter().map(|action| action.as_str()).collect();
+        queue.push(json!({
+            "target_type": target_type,
+            "target_uuid": target_uuid,
+            "target": describe(&mut db, &content).await,
+            "actions": actions,
+            "reports": reports,
+        }));
+    }
+
+    let mut history = Vec::new();
+    for report in Reports::get_resolved(&mut db, RESOLVED_HISTORY).await.unwrap() {
+        let moderator = match report.resolved_by {
+            Some(uuid) => username(&mut db, uuid).await,
+            None => String::new()
+        };
+        history.push(json!({
+            "report": report,
+            "moderator": moderator,
+        }));
+    }
+    db.close().await;
+
+    let result: String = (&data.hbs).render("forum/moderation_reports", &json!({
+        "name": SettingsManager::get_settings().name,
+        "user": moderator,
+        "queue": queue,
+        "history": history,
+    })).unwrap();
+
+    HttpResponse::Ok().body(result)
+}
+
+/// The form data for a moderator action on a reported target.
+#[derive(Deserialize)]
+pub struct AuthReportActionForm {
+    target_type: String,
+    target_uuid: Uuid,
+    action: String,
+}
+
+/// Take an action on a reported target from the moderation queue. This closes every open report of the target.
+#[post("/auth/moderation/reports")]
+pub async fn auth_report_action(form: web::Form<AuthReportActionForm>, req: HttpRequest) -> impl Responder {
+    let mut db = match open_database().await {
+        Ok(db) => db,
+        Err(resp) => return resp
+    };
+    let moderator = match check_moderator(&mut db, &req).await {
+        Ok(moderator) => moderator,
+        Err(resp) => {
+            db.close().await;
+            return resp;
+        }
+    };
+
+    let (target_type, action) = match (ReportTarget::parse(form.target_type.as_str()), ReportAction::parse(form.action.as_str())) {
+        (Some(target_type), Some(action)) => (target_type, action),
+        _ => {
+            db.close().await;
+            return HttpResponse::Found().header("Location", "/moderation/reports?err=1").finish();
+        }
+    };
+
+    let ip = audit::request_ip(&req);
+    let result = moderation::resolve(&mut db, &moderator, ip.as_str(), target_type, form.target_uuid, action).await;
+    db.close().await;
+
+    match result {
+        Ok(()) => HttpResponse::Found().header("Location", "/moderation/reports").finish(),
+        Err(ActionError::NotAllowed) => HttpResponse::Found().header("Location", "/moderation/reports?err=1").finish(),
+        Err(ActionError::Protected) => HttpResponse::Found().header("Location", "/moderation/reports?err=2").finish(),
+        Err(ActionError::Database(err)) => {
+            println!("[ERROR] Unable to {} a reported {}: {:?}", action.as_str(), target_type.as_str(), err);
+            HttpResponse::InternalServerError().body("Unable to take the action.")
+        }
+    }
+}
+
+/// Get the moderator and the thread for a thread moderation tool.
+async fn moderate_thread(db: &mut Database, req: &HttpRequest, thread_uuid: Uuid) -> Result<(User, Thread), HttpResponse> {
+    let moderator = check_moderator(db, req).await?;
+    match Threads::get_by_uuid(db, thread_uuid).await.unwrap() {
+        Some(thread) => Ok((moderator, thread)),
+        None => Err(HttpResponse::NotFound().body("The thread does not exist."))
+    }
+}
+
+/// The form data to lock, unlock, pin or unpin a thread.
+#[derive(Deserialize)]
+pub struct AuthThreadStateForm {
+    thread: Uuid,
+    enabled: bool,
+}
+
+/// Lock or unlock a thread.
+#[post("/auth/moderation/threads/lock")]
+pub async fn auth_lock_thread(form: web::Form<AuthThreadStateForm>, req: HttpRequest) -> impl Responder {
+    let mut db = match open_database().await {
+        Ok(db) => db,
+        Err(resp) => return resp
+    };
+    let (moderator, thread) = match moderate_thread(&mut db, &req, form.thread).await {
+        Ok(result) => result,
+        Err(resp) => {
+            db.close().await;
+            return resp;
+        }
+    };
+
+    moderation::set_locked(&mut db, &moderator, audit::request_ip(&req).as_str(), &thread, form.enabled).await.unwrap();
+    db.close().await;
+
+    HttpResponse::Found().header("Location", format!("/threads/{}", thread.uuid)).finish()
+}
+
+/// Pin or unpin a thread.
+#[post("/auth/moderation/threads/pin")]
+pub async fn auth_pin_thread(form: web::Form<AuthThreadStateForm>, req: HttpRequest) -> impl Responder {
+    let mut db = match open_database().await {
+        Ok(db) => db,
+        Err(resp) => return resp
+    };
+    let (moderator, thread) = match moderate_thread(&mut db, &req, form.thread).await {
+        Ok(result) => result,
+        Err(resp) => {
+            db.close().await;
+            return resp;
+        }
+    };
+
+    moderation::set_pinned(&mut db, &moderator, audit::request_ip(&req).as_str(), &thread, form.enabled).await.unwrap();
+    db.close().await;
+
+    HttpResponse::Found().header("Location", format!("/threads/{}", thread.uuid)).finish()
+}
+
+/// The form data to move a thread to another forum.
+#[derive(Deserialize)]
+pub struct AuthMoveThreadForm {
+    thread: Uuid,
+    forum: Uuid,
+}
+
+/// Move a thread to another forum.
+#[post("/auth/moderation/threads/move")]
+pub async fn auth_move_thread(form: web::Form<AuthMoveThreadForm>, req: HttpRequest) -> impl Responder {
+    let mut db = match open_database().await {
+        Ok(db) => db,
+        Err(resp) => return resp
+    };
+    let (moderator, thread) = match moderate_thread(&mut db, &req, form.thread).await {
+        Ok(result) => result,
+        Err(resp) => {
+            db.close().await;
+            return resp;
+        }
+    };
+
+    let forum = match Forums::get_by_uuid(&mut db, form.forum).await.unwrap() {
+        Some(forum) => forum,
+        None => {
+            db.close().await;
+            return HttpResponse::Found().header("Location", format!("/threads/{}?err=1", thread.uuid)).finish();
+        }
+    };
+    if forum.uuid != thread.forum_uuid {
+        moderation::move_thread(&mut db, &moderator, audit::request_ip(&req).as_str(), &thread, &forum).await.unwrap();
+    }
+    db.close().await;
+
+    HttpResponse::Found().header("Location", format!("/threads/{}", thread.uuid)).finish()
+}
+
+/// The form data to merge a thread into another thread.
+#[derive(Deserialize)]
+pub struct AuthMergeThreadForm {
+    thread: Uuid,
+    /// The uuid or the link of the thread to merge into.
+    target: String,
+}
+
+/// Merge a thread into another thread. The merged thread is deleted.
+#[post("/auth/moderation/threads/merge")]
+pub async fn auth_merge_thread(form: web::Form<AuthMergeThreadForm>, req: HttpRequest) -> impl Responder {
+    let mut db = match open_database().await {
+        Ok(db) => db,
+        Err(resp) => return resp
+    };
+    let (moderator, thread) = match moderate_thread(&mut db, &req, form.thread).await {
+        Ok(result) => result,
+        Err(resp) => {
+            db.close().await;
+            return resp;
+        }
+    };
+
+    // Accept a link to the thread as well as its uuid.
+    let target = form.target.trim().split(|c| c == '?' || c == '#').next().unwrap_or_default();
+    let target_uuid = target.trim_end_matches('/').rsplit('/').next().and_then(|uuid| Uuid::parse_str(uuid).ok());
+    let target = match target_uuid {
+        Some(target_uuid) => Threads::get_by_uuid(&mut db, target_uuid).await.unwrap(),
+        None => None
+    };
+    let target = match target {
+        Some(target) => target,
+        None => {
+            db.close().await;
+            return HttpResponse::Found().header("Location", format!("/threads/{}?err=2", thread.uuid)).finish();
+        }
+    };
+
+    let result = moderation::merge_threads(&mut db, &moderator, audit::request_ip(&req).as_str(), &thread, &target).await;
+    db.close().await;
+
+    match result {
+        Ok(()) => HttpResponse::Found().header("Location", format!("/threads/{}", target.uuid)).finish(),
+        Err(ActionError::Database(err)) => {
+            println!("[ERROR] Unable to merge the thread {} into {}: {:?}", thread.uuid, target.uuid, err);
+            HttpResponse::InternalServerError().body("Unable to merge the threads.")
+        }
+        Err(_) => HttpResponse::Found().header("Location", format!("/threads/{}?err=2", thread.uuid)).finish()
+    }
+}
+
+/**
+   Split replies of a thread into a new thread.
+
+   The form has the uuid of the thread in `thread`, the name of the new thread in `name`, and a `post-<uuid>` field
+   for every selected reply.
+*/
+#[post("/auth/moderation/threads/split")]
+pub async fn auth_split_thread(form: web::Form<HashMap<String, String>>, req: HttpRequest) -> impl Responder {
+    let thread_uuid = match form.get("thread").and_then(|thread| Uuid::parse_str(thread).ok()) {
+        Some(thread_uuid) => thread_uuid,
+        None => return HttpResponse::BadRequest().body("The thread is missing.")
+    };
+    let mut db = match open_database().await {
+        Ok(db) => db,
+        Err(resp) => return resp
+    };
+    let (moderator, thread) = match moderate_thread(&mut db, &req, thread_uuid).await {
+        Ok(result) => result,
+        Err(resp) => {
+            db.close().await;
+            return resp;
+        }
+    };
+
+    let name = form.get("name").map(|name| name.trim().to_string()).unwrap_or_default();
+    let mut posts = Vec::new();
+    for post_uuid in form.keys().filter_map(|key| key.strip_prefix("post-")).filter_map(|uuid| Uuid::parse_str(uuid).ok()) {
+        if let Some(post) = Posts::get_by_uuid(&mut db, post_uuid).await.unwrap() {
+            posts.push(post);
+        }
+    }
+    if name.is_empty() || name.chars().count() > MAX_THREAD_NAME_LENGTH || posts.is_empty() {
+        db.close().await;
+        return HttpResponse::Found().header("Location", format!("/threads/{}?err=3", thread.uuid)).finish();
+    }
+
+    let result = moderation::split_thread(&mut db, &moderator, audit::request_ip(&req).as_str(), &thread, posts, name).await;
+    db.close().await;
+
+    match result {
+        Ok(new_uuid) => HttpResponse::Found().header("Location", format!("/threads/{}", new_uuid)).finish(),
+        Err(ActionError::Database(err)) => {
+            println!("[ERROR] Unable to split the thread {}: {:?}", thread.uuid, err);
+            HttpResponse::InternalServerError().body("Unable to split the thread.")
+        }
+        Err(_) => HttpResponse::Found().header("Location", format!("/threads/{}?err=3", thread.uuid)).finish()
+    }
+}
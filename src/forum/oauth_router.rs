@@ -0,0 +1,419 @@
+use std::time::{Duration, Instant};
+
+use actix_web::{get, HttpRequest, HttpResponse, post, Responder, web};
+use actix_web::http::StatusCode;
+use rand::Rng;
+use rand::distributions::Alphanumeric;
+use serde::Deserialize;
+use serde_json::json;
+use uuid::Uuid;
+
+use crate::auth::api_token;
+use crate::auth::api_token::{MAX_TOKEN_NAME_LENGTH, Scope};
+use crate::auth::session;
+use crate::forum::open_database;
+use crate::schema::tables::{ApiTokens, OAuthClient, OAuthClients};
+use crate::settings::SettingsManager;
+use crate::state::{AuthorizationCode, ForumRSState, OAuthConsent};
+
+/// How long a user has to approve or deny an application.
+const CONSENT_TIMEOUT: Duration = Duration::from_secs(600);
+/// The amount of random characters in an authorization code.
+const CODE_LENGTH: usize = 32;
+
+/// Generate a random string for authorization codes and client secrets.
+fn random_string(length: usize) -> String {
+    rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(length)
+        .map(char::from)
+        .collect()
+}
+
+/// Check if a redirect uri can be registered.
+/// Only https uris are allowed, except for apps running on the same machine as the user.
+fn valid_redirect_uri(uri: &str) -> bool {
+    if uri.len() > 255 || uri.contains('#') {
+        return false;
+    }
+    uri.starts_with("https://") || uri.starts_with("http://localhost") || uri.starts_with("http://127.0.0.1")
+}
+
+/// Append query parameters to a redirect uri.
+fn redirect_with(uri: &str, params: &[(&str, &str)]) -> String {
+    let query = params.iter()
+        .map(|(key, value)| format!("{}={}", key, urlencoding::encode(value)))
+        .collect::<Vec<String>>()
+        .join("&");
+    let separator = if uri.contains('?') { '&' } else { '?' };
+    format!("{}{}{}", uri, separator, query)
+}
+
+/// The response for OAuth pages when OAuth is disabled.
+fn disabled() -> HttpResponse {
+    HttpResponse::NotFound().body("OAuth applications are not enabled on this forum.")
+}
+
+/// The applications page of an account, where users register apps that can use the OAuth flow.
+#[get("/account/applications")]
+pub async fn applications(data: web::Data<ForumRSState>, req: HttpRequest) -> impl Responder {
+    if !SettingsManager::get_settings().oauth_settings.enabled {
+        return disabled();
+    }
+
+    let mut db = match open_database().await {
+        Ok(db) => db,
+        Err(resp) => return resp
+    };
+
+    let user = match session::current_user(&mut db, &req).await {
+        Some(user) => user,
+        None => {
+            db.close().await;
+            return HttpResponse::Found().header("Location", "/login").finish();
+        }
+    };
+
+    let clients = OAuthClients::get_by_owner(&mut db, user.uuid).await.unwrap();
+    db.close().await;
+
+    let result: String = (&data.hbs).render("forum/applications", &json!({
+        "name": SettingsManager::get_settings().name,
+        "applications": clients,
+    })).unwrap();
+
+    HttpResponse::Ok().body(result)
+}
+
+/// The form data to register an application.
+#[derive(Deserialize)]
+pub struct AuthCreateApplicationForm {
+    name: String,
+    redirect_uri: String,
+}
+
+/// Register an application. The client secret is shown once and only its hash is stored.
+#[post("/auth/account/applications/create")]
+pub async fn auth_create_application(data: web::Data<ForumRSState>, form: web::Form<AuthCreateApplicationForm>, req: HttpRequest) -> impl Responder {
+    if !SettingsManager::get_settings().oauth_settings.enabled {
+        return disabled();
+    }
+
+    let name = form.name.trim();
+    if name.is_empty() || name.len() > MAX_TOKEN_NAME_LENGTH {
+        return HttpResponse::Found().header("Location", "/account/applications?err=1").finish();
+    }
+    let redirect_uri = form.redirect_uri.trim();
+    if !valid_redirect_uri(redirect_uri) {
+        return HttpResponse::Found().header("Location", "/account/applications?err=2").finish();
+    }
+
+    let mut db = match open_database().await {
+        Ok(db) => db,
+        Err(resp) => return resp
+    };
+
+    let user = match session::current_user(&mut db, &req).await {
+        Some(user) => user,
+        None => {
+            db.close().await;
+            return HttpResponse::Found().header("Location", "/login").finish();
+        }
+    };
+
+    let client_uuid = Uuid::new_v4();
+    let secret = random_string(48);
+    OAuthClients::insert(&mut db, client_uuid, user.uuid, name.to_string(), redirect_uri.to_string(),
+                         api_token::hash_token(secret.as_str())).await.unwrap();
+    db.close().await;
+
+    let result: String = (&data.hbs).render("forum/application_created", &json!({
+        "name": SettingsManager::get_settings().name,
+        "application_name": name,
+        "client_id": client_uuid,
+        "client_secret": secret,
+    })).unwrap();
+
+    HttpResponse::Ok().body(result)
+}
+
+/// The form data to delete an application.
+#[derive(Deserialize)]
+pub struct AuthDeleteApplicationForm {
+    client_id: Uuid,
+}
+
+/// Delete an application and revoke every token that was issued to it.
+#[post("/auth/account/applications/delete")]
+pub async fn auth_delete_application(form: web::Form<AuthDeleteApplicationForm>, req: HttpRequest) -> impl Responder {
+    let mut db = match open_database().await {
+        Ok(db) => db,
+        Err(resp) => return resp
+    };
+
+    let user = match session::current_user(&mut db, &req).await {
+        Some(user) => user,
+        None => {
+            db.close().await;
+            return HttpResponse::Found().header("Location", "/login").finish();
+        }
+    };
+
+    let owned = OAuthClients::get_by_uuid(&mut db, form.client_id).await.unwrap()
+        .map(|client| client.owner_uuid == user.uuid)
+        .unwrap_or(false);
+    if owned {
+        OAuthClients::delete(&mut db, form.client_id, user.uuid).await.unwrap();
+        ApiTokens::delete_by_client(&mut db, form.client_id).await.unwrap();
+    }
+    db.close().await;
+
+    HttpResponse::Found().header("Location", "/account/applications").finish()
+}
+
+/// The query of an OAuth authorization request.
+#[derive(Deserialize)]
+pub struct AuthorizeQuery {
+    response_type: Option<String>,
+    client_id: Option<String>,
+    redirect_uri: Option<String>,
+    scope: Option<String>,
+    state: Option<String>,
+}
+
+/**
+   The start of the OAuth authorization code flow.
+
+   The application and redirect uri are checked before anything else, since errors can only be sent back to
+   a redirect uri that belongs to the application. The user is then asked to approve the scopes that their
+   role allows.
+*/
+#[get("/oauth/authorize")]
+pub async fn authorize(data: web::Data<ForumRSState>, query: web::Query<AuthorizeQuery>, req: HttpRequest) -> impl Responder {
+    if !SettingsManager::get_settings().oauth_settings.enabled {
+        return disabled();
+    }
+
+    let client_uuid = match query.client_id.as_deref().and_then(|id| Uuid::parse_str(id).ok()) {
+        Some(client_uuid) => client_uuid,
+        None => return HttpResponse::BadRequest().body("The client_id is missing or invalid.")
+    };
+
+    let mut db = match open_database().await {
+        Ok(db) => db,
+        Err(resp) => return resp
+    };
+
+    let client: OAuthClient = match OAuthClients::get_by_uuid(&mut db, client_uuid).await.unwrap() {
+        Some(client) => client,
+        None => {
+            db.close().await;
+            return HttpResponse::BadRequest().body("The application does not exist.");
+        }
+    };
+
+    if query.redirect_uri.as_deref().map(|uri| uri != client.redirect_uri).unwrap_or(false) {
+        db.close().await;
+        return HttpResponse::BadRequest().body("The redirect_uri does not match the one registered for the application.");
+    }
+
+    let state = query.state.as_deref();
+    let error_redirect = |error: &str| {
+        let mut params = vec![("error", error)];
+        if let Some(state) = state {
+            params.push(("state", state));
+        }
+        HttpResponse::Found().header("Location", redirect_with(client.redirect_uri.as_str(), &params)).finish()
+    };
+
+    if query.response_type.as_deref() != Some("code") {
+        db.close().await;
+        return error_redirect("unsupported_response_type");
+    }
+
+    let requested = match api_token::parse_scopes(query.scope.as_deref().unwrap_or("read")) {
+        Ok(scopes) if !scopes.is_empty() => scopes,
+        _ => {
+            db.close().await;
+            return error_redirect("invalid_scope");
+        }
+    };
+
+    let user = match session::current_user(&mut db, &req).await {
+        Some(user) => user,
+        None => {
+            db.close().await;
+            return HttpResponse::Found().header("Location", "/login").finish();
+        }
+    };
+    db.close().await;
+
+    // Only the scopes the role of the user allows can be granted.
+    let scopes: Vec<Scope> = requested.into_iter().filter(|scope| scope.available_to(&user)).collect();
+    if scopes.is_empty() {
+        return error_redirect("invalid_scope");
+    }
+
+    let consent_id = Uuid::new_v4();
+    {
+        let mut consents = data.oauth_consents.lock().unwrap();
+        consents.retain(|_, consent| consent.created.elapsed() < CONSENT_TIMEOUT);
+        consents.insert(consent_id, OAuthConsent {
+            client_uuid: client.uuid,
+            user_uuid: user.uuid,
+            redirect_uri: client.redirect_uri.clone(),
+            scopes: scopes.clone(),
+            state: query.state.clone(),
+            created: Instant::now(),
+        });
+    }
+
+    let scopes: Vec<_> = scopes.iter().map(|scope| json!({
+        "name": scope.as_str(),
+        "description": scope.description(),
+    })).collect();
+
+    let result: String = (&data.hbs).render("forum/oauth_authorize", &json!({
+        "name": SettingsManager::get_settings().name,
+        "application_name": client.name,
+        "consent": consent_id,
+        "scopes": scopes,
+    })).unwrap();
+
+    HttpResponse::Ok().body(result)
+}
+
+/// The form data of the consent page.
+#[derive(Deserialize)]
+pub struct AuthAuthorizeForm {
+    consent: Uuid,
+    decision: String,
+}
+
+/// Approve or deny an application. If approved, the user is sent back to the application with an authorization code.
+#[post("/auth/oauth/authorize")]
+pub async fn auth_authorize(data: web::Data<ForumRSState>, form: web::Form<AuthAuthorizeForm>, req: HttpRequest) -> impl Responder {
+    let settings = SettingsManager::get_settings();
+    if !settings.oauth_settings.enabled {
+        return disabled();
+    }
+
+    let consent = match data.oauth_consents.lock().unwrap().remove(&form.consent) {
+        Some(consent) if consent.created.elapsed() < CONSENT_TIMEOUT => consent,
+        _ => return HttpResponse::BadRequest().body("The authorization request has expired. Please try again from the application.")
+    };
+
+    let mut db = match open_database().await {
+        Ok(db) => db,
+        Err(resp) => return resp
+    };
+    let user = session::current_user(&mut db, &req).await;
+    db.close().await;
+
+    // The consent id is only valid for the user it was shown to.
+    if user.map(|user| user.uuid) != Some(consent.user_uuid) {
+        return HttpResponse::Forbidden().body("This authorization request belongs to a different user.");
+    }
+
+    let mut params = vec![];
+    let code = random_string(CODE_LENGTH);
+    if form.decision == "approve" {
+        let mut codes = data.authorization_codes.lock().unwrap();
+        let lifetime = Duration::from_secs(settings.oauth_settings.code_lifetime_seconds);
+        codes.retain(|_, code| code.created.elapsed() < lifetime);
+        codes.insert(api_token::hash_token(code.as_str()), AuthorizationCode {
+            client_uuid: consent.client_uuid,
+            user_uuid: consent.user_uuid,
+            redirect_uri: consent.redirect_uri.clone(),
+            scopes: consent.scopes.clone(),
+            created: Instant::now(),
+        });
+        params.push(("code", code.as_str()));
+    } else {
+        params.push(("error", "access_denied"));
+    }
+    if let Some(state) = consent.state.as_deref() {
+        params.push(("state", state));
+    }
+
+    HttpResponse::Found().header("Location", redirect_with(consent.redirect_uri.as_str(), &params)).finish()
+}
+
+/// The form data of a token request. Clients send their credentials in the body.
+#[derive(Deserialize)]
+pub struct TokenForm {
+    grant_type: Option<String>,
+    code: Option<String>,
+    redirect_uri: Option<String>,
+    client_id: Option<String>,
+    client_secret: Option<String>,
+}
+
+/// Build an OAuth error response for the token endpoint.
+fn token_error(status: StatusCode, error: &str, description: &str) -> HttpResponse {
+    HttpResponse::build(status)
+        .header("Cache-Control", "no-store")
+        .json(json!({
+            "error": error,
+            "error_description": description
+        }))
+}
+
+/// Exchange an authorization code for an API token.
+#[post("/oauth/token")]
+pub async fn token(data: web::Data<ForumRSState>, form: web::Form<TokenForm>) -> impl Responder {
+    let settings = SettingsManager::get_settings();
+    if !settings.oauth_settings.enabled {
+        return disabled();
+    }
+
+    if form.grant_type.as_deref() != Some("authorization_code") {
+        return token_error(StatusCode::BAD_REQUEST, "unsupported_grant_type", "Only the authorization_code grant is supported.");
+    }
+
+    let client_uuid = match form.client_id.as_deref().and_then(|id| Uuid::parse_str(id).ok()) {
+        Some(client_uuid) => client_uuid,
+        None => return token_error(StatusCode::UNAUTHORIZED, "invalid_client", "The client_id is missing or invalid.")
+    };
+
+    let mut db = match open_database().await {
+        Ok(db) => db,
+        Err(resp) => return resp
+    };
+
+    let client = match OAuthClients::get_by_uuid(&mut db, client_uuid).await.unwrap() {
+        Some(client) if form.client_secret.as_deref().map(api_token::hash_token) == Some(client.secret_hash.clone()) => client,
+        _ => {
+            db.close().await;
+            return token_error(StatusCode::UNAUTHORIZED, "invalid_client", "The client credentials are invalid.");
+        }
+    };
+
+    // Codes can only be used once, so the code is removed even if the request turns out to be invalid.
+    let code = form.code.as_deref()
+        .and_then(|code| data.authorization_codes.lock().unwrap().remove(&api_token::hash_token(code)));
+    let lifetime = Duration::from_secs(settings.oauth_settings.code_lifetime_seconds);
+    let code = match code {
+        Some(code) if code.client_uuid == client.uuid
+            && code.created.elapsed() < lifetime
+            && form.redirect_uri.as_deref().map(|uri| uri == code.redirect_uri).unwrap_or(true) => code,
+        _ => {
+            db.close().await;
+            return token_error(StatusCode::BAD_REQUEST, "invalid_grant", "The authorization code is invalid or has expired.");
+        }
+    };
+
+    let token = api_token::generate_token();
+    let scopes = api_token::format_scopes(&code.scopes);
+    ApiTokens::insert(&mut db, Uuid::new_v4(), code.user_uuid, client.name.clone(), api_token::hash_token(token.as_str()),
+                      scopes.clone(), Some(client.uuid)).await.unwrap();
+    db.close().await;
+
+    HttpResponse::Ok()
+        .header("Cache-Control", "no-store")
+        .json(json!({
+            "access_token": token,
+            "token_type": "bearer",
+            "scope": scopes
+        }))
+}
@@ -0,0 +1,122 @@
+use actix_web::{get, HttpMessage, HttpRequest, HttpResponse, post, Responder, web};
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::auth::account_data;
+use crate::auth::session;
+use crate::forum::open_database;
+use crate::settings::{DeletedContent, SettingsManager};
+use crate::state::ForumRSState;
+
+/// The privacy page of an account, where users can download their data and delete their account.
+#[get("/account/privacy")]
+pub async fn privacy(data: web::Data<ForumRSState>, req: HttpRequest) -> impl Responder {
+    let mut db = match open_database().await {
+        Ok(db) => db,
+        Err(resp) => return resp
+    };
+
+    let user = session::current_user(&mut db, &req).await;
+    db.close().await;
+    let user = match user {
+        Some(user) => user,
+        None => return HttpResponse::Found().header("Location", "/login").finish()
+    };
+
+    let settings = SettingsManager::get_settings();
+    let result: String = (&data.hbs).render("forum/privacy", &json!({
+        "name": settings.name,
+        "user": user,
+        "remove_content": settings.privacy_settings.deleted_content == DeletedContent::Remove,
+    })).unwrap();
+
+    HttpResponse::Ok().body(result)
+}
+
+/// Download the personal data of the account as a JSON file.
+#[get("/account/privacy/export")]
+pub async fn export(req: HttpRequest) -> impl Responder {
+    let mut db = match open_database().await {
+        Ok(db) => db,
+        Err(resp) => return resp
+    };
+
+    let user = match session::current_user(&mut db, &req).await {
+        Some(user) => user,
+        None => {
+            db.close().await;
+            return HttpResponse::Found().header("Location", "/login").finish();
+        }
+    };
+
+    let export = account_data::export(&mut db, &user).await;
+    db.close().await;
+
+    match export {
+        Ok(export) => HttpResponse::Ok()
+            .content_type("application/json")
+            .header("Content-Disposition", format!("attachment; filename=\"forumrs-data-{}.json\"", user.uuid))
+            .body(serde_json::to_string_pretty(&export).unwrap()),
+        Err(err) => {
+            println!("[ERROR] Unable to export the data of {}: {:?}", user.username, err);
+            HttpResponse::InternalServerError().body("Unable to export your data.")
+        }
+    }
+}
+
+/// The form data to delete an account.
+#[derive(Deserialize)]
+pub struct AuthDeleteAccountForm {
+    password: String,
+    /// The username of the account, typed again to confirm the deletion.
+    confirm: String,
+}
+
+/// Delete the account of the user. This cannot be undone.
+#[post("/auth/account/delete")]
+pub async fn auth_delete_account(form: web::Form<AuthDeleteAccountForm>, req: HttpRequest) -> impl Responder {
+    let mut db = match open_database().await {
+        Ok(db) => db,
+        Err(resp) => return resp
+    };
+
+    let user = match session::current_user(&mut db, &req).await {
+        Some(user) => user,
+        None => {
+            db.close().await;
+            return HttpResponse::Found().header("Location", "/login").finish();
+        }
+    };
+
+    if form.confirm != user.username {
+        db.close().await;
+        return HttpResponse::Found().header("Location", "/account/privacy?err=1").finish();
+    }
+
+    if !bcrypt::verify(form.password.as_str(), user.password.as_str()).unwrap_or(false) {
+        db.close().await;
+        return HttpResponse::Found().header("Location", "/account/privacy?err=2").finish();
+    }
+
+    // An admin deleting their account could leave the forum without one.
+    if user.is_admin {
+        db.close().await;
+        return HttpResponse::Found().header("Location", "/account/privacy?err=3").finish();
+    }
+
+    let content = SettingsManager::get_settings().privacy_settings.deleted_content;
+    let result = account_data::delete_account(&mut db, &user, content).await;
+    db.close().await;
+
+    if let Err(err) = result {
+        println!("[ERROR] Unable to delete the account of {}: {:?}", user.username, err);
+        return HttpResponse::InternalServerError().body("Unable to delete your account.");
+    }
+    println!("[INFO] {} deleted their account.", user.username);
+
+    let mut builder = HttpResponse::Found();
+    if let Some(cookie) = req.cookie(session::SESSION_COOKIE) {
+        builder.del_cookie(&cookie);
+    }
+    builder.header("Location", "/").finish()
+}
@@ -0,0 +1,238 @@
+use actix_web::{get, HttpRequest, HttpResponse, post, Responder, web};
+use futures::StreamExt;
+use serde::Deserialize;
+use serde_json::json;
+use uuid::Uuid;
+
+use crate::attachments::storage;
+use crate::auth::session;
+use crate::forum::open_database;
+use crate::profiles;
+use crate::schema::tables::{Avatar, Profile, Profiles, Posts, Threads, Users};
+use crate::settings::SettingsManager;
+use crate::state::ForumRSState;
+
+/// The amount of recent threads and posts shown on a profile.
+const RECENT_CONTENT: i64 = 5;
+/// The most characters of a post shown in the recent posts of a profile.
+const POST_PREVIEW_LENGTH: usize = 200;
+
+/// The public profile page of a user.
+#[get("/users/{username}")]
+pub async fn profile(data: web::Data<ForumRSState>, path: web::Path<String>, req: HttpRequest) -> impl Responder {
+    let mut db = match open_database().await {
+        Ok(db) => db,
+        Err(resp) => return resp
+    };
+
+    let user = session::current_user(&mut db, &req).await;
+    let member = match Users::get_by_username(&mut db, path.as_str()).await.unwrap() {
+        Some(member) => member,
+        None => {
+            db.close().await;
+            return HttpResponse::NotFound().body("The user does not exist.");
+        }
+    };
+
+    let profile = Profiles::get(&mut db, member.uuid).await.unwrap().unwrap_or_else(|| Profile::empty(member.uuid));
+    let post_count = Users::count_content(&mut db, member.uuid).await.unwrap();
+    let threads = Threads::get_latest_by_creator(&mut db, member.uuid, RECENT_CONTENT).await.unwrap();
+    let posts: Vec<_> = Posts::get_latest_by_creator(&mut db, member.uuid, RECENT_CONTENT).await.unwrap().into_iter()
+        .map(|post| json!({
+            "post": post,
+            "preview": post.content.chars().take(POST_PREVIEW_LENGTH).collect::<String>(),
+        }))
+        .collect();
+    db.close().await;
+
+    let settings = SettingsManager::get_settings();
+    let result: String = (&data.hbs).render("forum/profile", &json!({
+        "name": settings.name,
+        "user": user,
+        "own_profile": user.as_ref().map(|user| user.uuid == member.uuid).unwrap_or(false),
+        "member": member,
+        "profile": profile,
+        "avatar_url": profiles::avatar_url(&member, &profile, &settings.profile_settings),
+        "post_count": post_count,
+        "threads": threads,
+        "posts": posts,
+    })).unwrap();
+
+    HttpResponse::Ok().body(result)
+}
+
+/// Get the uploaded avatar of a user.
+#[get("/avatars/{uuid}")]
+pub async fn avatar(path: web::Path<Uuid>) -> impl Responder {
+    let storage = storage::from_settings(&SettingsManager::get_settings().attachment_settings);
+    match storage.get(profiles::avatar_key(path.into_inner()).as_str()).await {
+        Ok(Some(data)) => HttpResponse::Ok()
+            .content_type("image/png")
+            .header("X-Content-Type-Options", "nosniff")
+            .header("Cache-Control", "public, max-age=604800")
+            .body(data),
+        Ok(None) => HttpResponse::NotFound().body("The user does not have an avatar."),
+        Err(err) => {
+            println!("[ERROR] {}", err);
+            HttpResponse::InternalServerError().body("Unable to read the avatar.")
+        }
+    }
+}
+
+/// The page where users edit their profile.
+#[get("/account/profile")]
+pub async fn edit_profile(data: web::Data<ForumRSState>, req: HttpRequest) -> impl Responder {
+    let mut db = match open_database().await {
+        Ok(db) => db,
+        Err(resp) => return resp
+    };
+
+    let user = match session::current_user(&mut db, &req).await {
+        Some(user) => user,
+        None => {
+            db.close().await;
+            return HttpResponse::Found().header("Location", "/login").finish();
+        }
+    };
+    let profile = Profiles::get(&mut db, user.uuid).await.unwrap().unwrap_or_else(|| Profile::empty(user.uuid));
+    db.close().await;
+
+    let settings = SettingsManager::get_settings();
+    let result: String = (&data.hbs).render("forum/edit_profile", &json!({
+        "name": settings.name,
+        "avatar_url": profiles::avatar_url(&user, &profile, &settings.profile_settings),
+        "user": user,
+        "profile": profile,
+        "uploaded": profile.avatar == Avatar::Uploaded,
+        "gravatar": settings.profile_settings.gravatar,
+        "using_gravatar": profile.avatar == Avatar::Gravatar,
+        "limits": {
+            "bio": settings.profile_settings.max_bio_length,
+            "signature": settings.profile_settings.max_signature_length,
+            "signature_lines": settings.profile_settings.max_signature_lines,
+            "location": profiles::MAX_LOCATION_LENGTH,
+            "website": profiles::MAX_WEBSITE_LENGTH,
+        },
+    })).unwrap();
+
+    HttpResponse::Ok().body(result)
+}
+
+/// The form data to edit a profile.
+#[derive(Deserialize)]
+pub struct AuthProfileForm {
+    bio: String,
+    signature: String,
+    location: String,
+    website: String,
+    /// The avatar to use: `keep`, `none` or `gravatar`. Avatars are uploaded separately.
+    avatar: String,
+}
+
+/// Save the profile of the logged in user.
+#[post("/auth/account/profile")]
+pub async fn auth_edit_profile(form: web::Form<AuthProfileForm>, req: HttpRequest) -> impl Responder {
+    let mut db = match open_database().await {
+        Ok(db) => db,
+        Err(resp) => return resp
+    };
+
+    let user = match session::current_user(&mut db, &req).await {
+        Some(user) => user,
+        None => {
+            db.close().await;
+            return HttpResponse::Found().header("Location", "/login").finish();
+        }
+    };
+    let old_profile = Profiles::get(&mut db, user.uuid).await.unwrap().unwrap_or_else(|| Profile::empty(user.uuid));
+
+    let settings = SettingsManager::get_settings().profile_settings;
+    let fields = (
+        profiles::clean_text(form.bio.as_str(), "bio", settings.max_bio_length, None),
+        profiles::clean_text(form.signature.as_str(), "signature", settings.max_signature_length, Some(settings.max_signature_lines)),
+        profiles::clean_text(form.location.as_str(), "location", profiles::MAX_LOCATION_LENGTH, Some(1)),
+        profiles::clean_website(form.website.as_str()),
+    );
+    let (bio, signature, location, website) = match fields {
+        (Ok(bio), Ok(signature), Ok(location), Ok(website)) => (bio, signature, location, website),
+        (bio, signature, location, _) => {
+            db.close().await;
+            let err = if bio.is_err() { 1 } else if signature.is_err() { 2 } else if location.is_err() { 3 } else { 4 };
+            return HttpResponse::Found().header("Location", format!("/account/profile?err={}", err)).finish();
+        }
+    };
+
+    let avatar = match form.avatar.as_str() {
+        "none" => Avatar::None,
+        "gravatar" if settings.gravatar => Avatar::Gravatar,
+        "gravatar" => {
+            db.close().await;
+            return HttpResponse::Found().header("Location", "/account/profile?err=5").finish();
+        }
+        _ => old_profile.avatar
+    };
+
+    Profiles::save(&mut db, &Profile { user_uuid: user.uuid, avatar, bio, signature, location, website, updated: 0 }).await.unwrap();
+    db.close().await;
+
+    if old_profile.avatar == Avatar::Uploaded && avatar != Avatar::Uploaded {
+        let storage = storage::from_settings(&SettingsManager::get_settings().attachment_settings);
+        if let Err(err) = storage.delete(profiles::avatar_key(user.uuid).as_str()).await {
+            println!("[WARN] Unable to delete the avatar of {}: {}", user.username, err);
+        }
+    }
+
+    HttpResponse::Found().header("Location", "/account/profile").finish()
+}
+
+/// Upload an avatar for the logged in user. The body of the request is the image.
+#[post("/auth/account/profile/avatar")]
+pub async fn auth_upload_avatar(mut payload: web::Payload, req: HttpRequest) -> impl Responder {
+    let mut db = match open_database().await {
+        Ok(db) => db,
+        Err(resp) => return resp
+    };
+
+    let user = match session::current_user(&mut db, &req).await {
+        Some(user) => user,
+        None => {
+            db.close().await;
+            return HttpResponse::Found().header("Location", "/login").finish();
+        }
+    };
+
+    // The body is read here instead of with an extractor so the limit follows the profile settings.
+    let settings = SettingsManager::get_settings();
+    let mut body = Vec::new();
+    while let Some(chunk) = payload.next().await {
+        match chunk {
+            Ok(chunk) if body.len() + chunk.len() <= settings.profile_settings.max_avatar_size => body.extend_from_slice(&chunk),
+            _ => {
+                db.close().await;
+                return HttpResponse::Found().header("Location", "/account/profile?err=6").finish();
+            }
+        }
+    }
+
+    let profile_settings = settings.profile_settings.clone();
+    let avatar = match web::block(move || profiles::process_avatar(&body, &profile_settings)).await {
+        Ok(avatar) => avatar,
+        Err(_) => {
+            db.close().await;
+            return HttpResponse::Found().header("Location", "/account/profile?err=6").finish();
+        }
+    };
+
+    let storage = storage::from_settings(&settings.attachment_settings);
+    if let Err(err) = storage.put(profiles::avatar_key(user.uuid).as_str(), "image/png", avatar).await {
+        println!("[ERROR] Unable to store the avatar of {}: {}", user.username, err);
+        db.close().await;
+        return HttpResponse::Found().header("Location", "/account/profile?err=7").finish();
+    }
+
+    let profile = Profiles::get(&mut db, user.uuid).await.unwrap().unwrap_or_else(|| Profile::empty(user.uuid));
+    Profiles::save(&mut db, &Profile { avatar: Avatar::Uploaded, ..profile }).await.unwrap();
+    db.close().await;
+
+    HttpResponse::Found().header("Location", "/account/profile").finish()
+}
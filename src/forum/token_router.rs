@@ -0,0 +1,143 @@
+use actix_web::{get, HttpRequest, HttpResponse, post, Responder, web};
+use serde::Deserialize;
+use serde_json::json;
+use uuid::Uuid;
+
+use crate::auth::api_token;
+use crate::auth::api_token::{MAX_TOKEN_NAME_LENGTH, Scope};
+use crate::auth::session;
+use crate::forum::open_database;
+use crate::schema::tables::ApiTokens;
+use crate::settings::SettingsManager;
+use crate::state::ForumRSState;
+
+/// The API token settings page of an account.
+/// This lists the personal tokens and authorized applications of the user.
+#[get("/account/tokens")]
+pub async fn tokens(data: web::Data<ForumRSState>, req: HttpRequest) -> impl Responder {
+    let mut db = match open_database().await {
+        Ok(db) => db,
+        Err(resp) => return resp
+    };
+
+    let user = match session::current_user(&mut db, &req).await {
+        Some(user) => user,
+        None => {
+            db.close().await;
+            return HttpResponse::Found().header("Location", "/login").finish();
+        }
+    };
+
+    let tokens = ApiTokens::get_by_user(&mut db, user.uuid).await.unwrap();
+    db.close().await;
+
+    let scopes: Vec<_> = api_token::scopes_for(&user).iter().map(|scope| json!({
+        "name": scope.as_str(),
+        "description": scope.description(),
+    })).collect();
+
+    let result: String = (&data.hbs).render("forum/tokens", &json!({
+        "name": SettingsManager::get_settings().name,
+        "personal_tokens": tokens.iter().filter(|token| token.client_uuid.is_none()).collect::<Vec<_>>(),
+        "application_tokens": tokens.iter().filter(|token| token.client_uuid.is_some()).collect::<Vec<_>>(),
+        "scopes": scopes,
+    })).unwrap();
+
+    HttpResponse::Ok().body(result)
+}
+
+/// The form data to create a personal API token.
+/// Every scope is its own checkbox.
+#[derive(Deserialize)]
+pub struct AuthCreateTokenForm {
+    name: String,
+    read: Option<String>,
+    post: Option<String>,
+    moderate: Option<String>,
+    admin: Option<String>,
+}
+
+impl AuthCreateTokenForm {
+    /// Get the scopes that were checked.
+    fn scopes(&self) -> Vec<Scope> {
+        let checked = [&self.read, &self.post, &self.moderate, &self.admin];
+        Scope::ALL.iter().zip(checked.iter())
+            .filter(|(_, checked)| checked.as_deref() == Some("on"))
+            .map(|(scope, _)| *scope)
+            .collect()
+    }
+}
+
+/// Create a personal API token. The token is shown once and only its hash is stored.
+#[post("/auth/account/tokens/create")]
+pub async fn auth_create_token(data: web::Data<ForumRSState>, form: web::Form<AuthCreateTokenForm>, req: HttpRequest) -> impl Responder {
+    let name = form.name.trim();
+    if name.is_empty() || name.len() > MAX_TOKEN_NAME_LENGTH {
+        return HttpResponse::Found().header("Location", "/account/tokens?err=1").finish();
+    }
+
+    let mut db = match open_database().await {
+        Ok(db) => db,
+        Err(resp) => return resp
+    };
+
+    let user = match session::current_user(&mut db, &req).await {
+        Some(user) => user,
+        None => {
+            db.close().await;
+            return HttpResponse::Found().header("Location", "/login").finish();
+        }
+    };
+
+    let scopes = form.scopes();
+    if scopes.is_empty() {
+        db.close().await;
+        return HttpResponse::Found().header("Location", "/account/tokens?err=2").finish();
+    }
+    if scopes.iter().any(|scope| !scope.available_to(&user)) {
+        db.close().await;
+        return HttpResponse::Found().header("Location", "/account/tokens?err=3").finish();
+    }
+
+    let token = api_token::generate_token();
+    ApiTokens::insert(&mut db, Uuid::new_v4(), user.uuid, name.to_string(), api_token::hash_token(token.as_str()),
+                      api_token::format_scopes(&scopes), None).await.unwrap();
+    db.close().await;
+
+    let result: String = (&data.hbs).render("forum/token_created", &json!({
+        "name": SettingsManager::get_settings().name,
+        "token_name": name,
+        "token": token,
+        "scopes": api_token::format_scopes(&scopes),
+    })).unwrap();
+
+    HttpResponse::Ok().body(result)
+}
+
+/// The form data to revoke an API token.
+#[derive(Deserialize)]
+pub struct AuthRevokeTokenForm {
+    token: Uuid,
+}
+
+/// Revoke a personal token or the access of an application.
+#[post("/auth/account/tokens/revoke")]
+pub async fn auth_revoke_token(form: web::Form<AuthRevokeTokenForm>, req: HttpRequest) -> impl Responder {
+    let mut db = match open_database().await {
+        Ok(db) => db,
+        Err(resp) => return resp
+    };
+
+    let user = match session::current_user(&mut db, &req).await {
+        Some(user) => user,
+        None => {
+            db.close().await;
+            return HttpResponse::Found().header("Location", "/login").finish();
+        }
+    };
+
+    ApiTokens::delete(&mut db, form.token, user.uuid).await.unwrap();
+    db.close().await;
+
+    HttpResponse::Found().header("Location", "/account/tokens").finish()
+}
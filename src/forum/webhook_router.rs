@@ -0,0 +1,232 @@
+use actix_web::{get, HttpRequest, HttpResponse, post, Responder, web};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+use crate::audit;
+use crate::audit::AuditAction;
+use crate::forum::admin_router::check_admin;
+use crate::forum::open_database;
+use crate::schema::tables::{WebhookDeliveries, Webhooks};
+use crate::settings::SettingsManager;
+use crate::state::ForumRSState;
+use crate::webhooks;
+use crate::webhooks::WebhookEvent;
+
+/// The amount of deliveries shown in the delivery log of a webhook.
+const DELIVERY_LOG_SIZE: i64 = 100;
+
+/// The webhooks page of the admin panel.
+#[get("/admin/webhooks")]
+pub async fn webhooks(data: web::Data<ForumRSState>, req: HttpRequest) -> impl Responder {
+    let mut db = match open_database().await {
+        Ok(db) => db,
+        Err(resp) => return resp
+    };
+
+    let admin = check_admin(&mut db, &req).await;
+    if admin.is_err() {
+        db.close().await;
+        return admin.unwrap_err();
+    }
+
+    let webhooks = Webhooks::get_all(&mut db).await.unwrap();
+    db.close().await;
+
+    let events: Vec<_> = WebhookEvent::ALL.iter().map(|event| json!({
+        "name": event.as_str(),
+        "field": event.as_str().replace('.', "_"),
+        "description": event.description(),
+    })).collect();
+
+    let result: String = (&data.hbs).render("admin/webhooks", &json!({
+        "name": SettingsManager::get_settings().name,
+        "webhooks": webhooks,
+        "events": events,
+    })).unwrap();
+
+    HttpResponse::Ok().body(result)
+}
+
+/// The form data to register a webhook.
+/// Every event is its own checkbox.
+#[derive(Deserialize)]
+pub struct AuthCreateWebhookForm {
+    url: String,
+    thread_created: Option<String>,
+    post_created: Option<String>,
+    user_registered: Option<String>,
+    user_banned: Option<String>,
+}
+
+impl AuthCreateWebhookForm {
+    /// Get the events that were checked.
+    fn events(&self) -> Vec<WebhookEvent> {
+        let checked = [&self.thread_created, &self.post_created, &self.user_registered, &self.user_banned];
+        WebhookEvent::ALL.iter().zip(checked.iter())
+            .filter(|(_, checked)| checked.as_deref() == Some("on"))
+            .map(|(event, _)| *event)
+            .collect()
+    }
+}
+
+/// Register a webhook. The signing secret is shown once.
+#[post("/auth/admin/webhooks/create")]
+pub async fn auth_create_webhook(data: web::Data<ForumRSState>, form: web::Form<AuthCreateWebhookForm>, req: HttpRequest) -> impl Responder {
+    let mut db = match open_database().await {
+        Ok(db) => db,
+        Err(resp) => return resp
+    };
+
+    let admin = check_admin(&mut db, &req).await;
+    if admin.is_err() {
+        db.close().await;
+        return admin.unwrap_err();
+    }
+
+    let url = form.url.trim();
+    if url.len() > 255 || !(url.starts_with("http://") || url.starts_with("https://")) {
+        db.close().await;
+        return HttpResponse::Found().header("Location", "/admin/webhooks?err=1").finish();
+    }
+
+    let events = form.events();
+    if events.is_empty() {
+        db.close().await;
+        return HttpResponse::Found().header("Location", "/admin/webhooks?err=2").finish();
+    }
+
+    let admin = admin.unwrap();
+    let secret = webhooks::generate_secret();
+    let event_names = events.iter().map(WebhookEvent::as_str).collect::<Vec<&str>>().join(" ");
+    let webhook_uuid = Uuid::new_v4();
+    Webhooks::insert(&mut db, webhook_uuid, url.to_string(), secret.clone(), event_names.clone()).await.unwrap();
+    audit::record(&mut db, admin.uuid, audit::request_ip(&req).as_str(), AuditAction::WebhookCreate, webhook_uuid.to_string().as_str(),
+                  Value::Null, json!({"url": url, "events": event_names})).await.unwrap();
+    db.close().await;
+
+    println!("[INFO] {} registered a webhook for {}.", admin.username, url);
+
+    let result: String = (&data.hbs).render("admin/webhook_created", &json!({
+        "name": SettingsManager::get_settings().name,
+        "url": url,
+        "events": event_names,
+        "secret": secret,
+    })).unwrap();
+
+    HttpResponse::Ok().body(result)
+}
+
+/// The form data for actions on a webhook.
+#[derive(Deserialize)]
+pub struct AuthWebhookForm {
+    webhook: Uuid,
+}
+
+/// Enable or disable a webhook.
+#[post("/auth/admin/webhooks/toggle")]
+pub async fn auth_toggle_webhook(form: web::Form<AuthWebhookForm>, req: HttpRequest) -> impl Responder {
+    let mut db = match open_database().await {
+        Ok(db) => db,
+        Err(resp) => return resp
+    };
+
+    let admin = check_admin(&mut db, &req).await;
+    if admin.is_err() {
+        db.close().await;
+        return admin.unwrap_err();
+    }
+
+    if let Some(webhook) = Webhooks::get_by_uuid(&mut db, form.webhook).await.unwrap() {
+        Webhooks::set_enabled(&mut db, webhook.uuid, !webhook.enabled).await.unwrap();
+        audit::record(&mut db, admin.unwrap().uuid, audit::request_ip(&req).as_str(), AuditAction::WebhookToggle, webhook.uuid.to_string().as_str(),
+                      json!({"enabled": webhook.enabled}), json!({"enabled": !webhook.enabled})).await.unwrap();
+    }
+    db.close().await;
+
+    HttpResponse::Found().header("Location", "/admin/webhooks").finish()
+}
+
+/// Delete a webhook and its delivery log.
+#[post("/auth/admin/webhooks/delete")]
+pub async fn auth_delete_webhook(form: web::Form<AuthWebhookForm>, req: HttpRequest) -> impl Responder {
+    let mut db = match open_database().await {
+        Ok(db) => db,
+        Err(resp) => return resp
+    };
+
+    let admin = check_admin(&mut db, &req).await;
+    if admin.is_err() {
+        db.close().await;
+        return admin.unwrap_err();
+    }
+
+    if let Some(webhook) = Webhooks::get_by_uuid(&mut db, form.webhook).await.unwrap() {
+        Webhooks::delete(&mut db, webhook.uuid).await.unwrap();
+        audit::record(&mut db, admin.unwrap().uuid, audit::request_ip(&req).as_str(), AuditAction::WebhookDelete, webhook.uuid.to_string().as_str(),
+                      json!({"url": webhook.url, "events": webhook.events}), Value::Null).await.unwrap();
+    }
+    db.close().await;
+
+    HttpResponse::Found().header("Location", "/admin/webhooks").finish()
+}
+
+/// The delivery log of a webhook.
+#[get("/admin/webhooks/{uuid}")]
+pub async fn deliveries(data: web::Data<ForumRSState>, path: web::Path<Uuid>, req: HttpRequest) -> impl Responder {
+    let mut db = match open_database().await {
+        Ok(db) => db,
+        Err(resp) => return resp
+    };
+
+    let admin = check_admin(&mut db, &req).await;
+    if admin.is_err() {
+        db.close().await;
+        return admin.unwrap_err();
+    }
+
+    let webhook = match Webhooks::get_by_uuid(&mut db, path.into_inner()).await.unwrap() {
+        Some(webhook) => webhook,
+        None => {
+            db.close().await;
+            return HttpResponse::NotFound().body("The webhook does not exist.");
+        }
+    };
+    let deliveries = WebhookDeliveries::get_by_webhook(&mut db, webhook.uuid, DELIVERY_LOG_SIZE).await.unwrap();
+    db.close().await;
+
+    let result: String = (&data.hbs).render("admin/webhook_deliveries", &json!({
+        "name": SettingsManager::get_settings().name,
+        "webhook": webhook,
+        "deliveries": deliveries,
+    })).unwrap();
+
+    HttpResponse::Ok().body(result)
+}
+
+/// The form data to send a delivery again.
+#[derive(Deserialize)]
+pub struct AuthRedeliverForm {
+    webhook: Uuid,
+    delivery: Uuid,
+}
+
+/// Queue a delivery to be sent again, resetting its attempts.
+#[post("/auth/admin/webhooks/redeliver")]
+pub async fn auth_redeliver(form: web::Form<AuthRedeliverForm>, req: HttpRequest) -> impl Responder {
+    let mut db = match open_database().await {
+        Ok(db) => db,
+        Err(resp) => return resp
+    };
+
+    let admin = check_admin(&mut db, &req).await;
+    if admin.is_err() {
+        db.close().await;
+        return admin.unwrap_err();
+    }
+
+    WebhookDeliveries::retry(&mut db, form.delivery, form.webhook).await.unwrap();
+    db.close().await;
+
+    HttpResponse::Found().header("Location", format!("/admin/webhooks/{}", form.webhook)).finish()
+}
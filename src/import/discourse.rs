@@ -0,0 +1,203 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+
+use chrono::DateTime;
+
+use crate::import::{ImportData, ImportedForum, ImportedPost, ImportedThread, ImportedUser};
+
+/// A Discourse backup exported as JSON. Each list has the columns of the Discourse table of the same name.
+#[derive(Deserialize)]
+struct Backup {
+    #[serde(default)]
+    categories: Vec<Category>,
+    #[serde(default)]
+    users: Vec<User>,
+    #[serde(default)]
+    topics: Vec<Topic>,
+    #[serde(default)]
+    posts: Vec<Post>,
+}
+
+#[derive(Deserialize)]
+struct Category {
+    id: i64,
+    name: String,
+    #[serde(default)]
+    description: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct User {
+    id: i64,
+    username: String,
+    #[serde(default)]
+    email: Option<String>,
+    created_at: String,
+}
+
+#[derive(Deserialize)]
+struct Topic {
+    id: i64,
+    #[serde(default)]
+    category_id: Option<i64>,
+    title: String,
+    #[serde(default)]
+    user_id: Option<i64>,
+    #[serde(default)]
+    closed: bool,
+    #[serde(default)]
+    archetype: Option<String>,
+    created_at: String,
+    #[serde(default)]
+    deleted_at: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Post {
+    id: i64,
+    topic_id: i64,
+    #[serde(default)]
+    user_id: Option<i64>,
+    raw: String,
+    post_number: i64,
+    created_at: String,
+    #[serde(default)]
+    deleted_at: Option<String>,
+}
+
+/// Convert a Discourse timestamp to unix time.
+fn unix_time(timestamp: &str) -> Result<i64, String> {
+    DateTime::parse_from_rfc3339(timestamp)
+        .map(|date| date.timestamp())
+        .map_err(|err| format!("Invalid timestamp {}: {}", timestamp, err))
+}
+
+/**
+   Read a Discourse backup that was exported as JSON.
+
+   The backup is an object with a `categories`, `users`, `topics` and `posts` list. Categories are imported as forums
+   and the raw Markdown of the posts is kept. Private messages, deleted topics and posts, and the system users
+   (the users with an id below 1) are not imported.
+*/
+pub fn read(location: &str) -> Result<ImportData, String> {
+    let contents = fs::read_to_string(location).map_err(|err| format!("Unable to read {}: {}", location, err))?;
+    let backup: Backup = serde_json::from_str(&contents).map_err(|err| format!("Unable to read the Discourse backup: {}", err))?;
+    let mut data = ImportData::default();
+
+    for category in backup.categories {
+        data.forums.push(ImportedForum {
+            id: category.id.to_string(),
+            name: category.name,
+            caption: category.description.unwrap_or_default(),
+        });
+    }
+
+    for user in backup.users.into_iter().filter(|user| user.id > 0) {
+        data.users.push(ImportedUser {
+            id: user.id.to_string(),
+            username: user.username,
+            email: user.email.unwrap_or_default(),
+            // Discourse backups do not include passwords.
+            password: String::new(),
+            date: unix_time(&user.created_at)?,
+        });
+    }
+
+    let topics: Vec<Topic> = backup.topics.into_iter()
+        .filter(|topic| topic.deleted_at.is_none() && topic.archetype.as_deref() != Some("private_message"))
+        .collect();
+    let topic_ids: HashSet<i64> = topics.iter().map(|topic| topic.id).collect();
+
+    // The first post of a topic is the content of the thread.
+    let mut first_posts: HashMap<i64, String> = HashMap::new();
+    for post in backup.posts.into_iter().filter(|post| post.deleted_at.is_none() && topic_ids.contains(&post.topic_id)) {
+        if post.post_number == 1 {
+            first_posts.insert(post.topic_id, post.raw);
+            continue;
+        }
+
+        data.posts.push(ImportedPost {
+            id: post.id.to_string(),
+            thread_id: post.topic_id.to_string(),
+            creator_id: post.user_id.map(|id| id.to_string()).unwrap_or_default(),
+            content: post.raw,
+            date: unix_time(&post.created_at)?,
+        });
+    }
+
+    for topic in topics {
+        data.threads.push(ImportedThread {
+            id: topic.id.to_string(),
+            forum_id: topic.category_id.map(|id| id.to_string()).unwrap_or_default(),
+            name: topic.title,
+            content: first_posts.remove(&topic.id).unwrap_or_default(),
+            creator_id: topic.user_id.map(|id| id.to_string()).unwrap_or_default(),
+            locked: topic.closed,
+            date: unix_time(&topic.created_at)?,
+        });
+    }
+
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    /// Write a backup to a temporary file and read it.
+    fn read_backup(backup: serde_json::Value) -> Result<ImportData, String> {
+        let path = std::env::temp_dir().join(format!("forumrs-discourse-{}.json", uuid::Uuid::new_v4()));
+        fs::write(&path, backup.to_string()).unwrap();
+        let data = read(path.to_str().unwrap());
+        fs::remove_file(&path).unwrap();
+        data
+    }
+
+    #[test]
+    fn read_backup_skips_private_and_deleted_content() {
+        let data = read_backup(json!({
+            "categories": [{"id": 5, "name": "General", "description": null}],
+            "users": [
+                {"id": -1, "username": "system", "created_at": "2020-01-01T00:00:00Z"},
+                {"id": 1, "username": "alice", "email": "alice@example.com", "created_at": "2020-09-13T12:26:40.000Z"}
+            ],
+            "topics": [
+                {"id": 1, "category_id": 5, "title": "Welcome", "user_id": 1, "closed": true, "archetype": "regular", "created_at": "2020-09-13T12:26:40Z"},
+                {"id": 2, "title": "Private", "user_id": 1, "archetype": "private_message", "created_at": "2020-09-13T12:26:40Z"},
+                {"id": 3, "category_id": 5, "title": "Deleted", "user_id": 1, "created_at": "2020-09-13T12:26:40Z", "deleted_at": "2020-09-14T00:00:00Z"}
+            ],
+            "posts": [
+                {"id": 11, "topic_id": 1, "user_id": 1, "raw": "**Opening**", "post_number": 1, "created_at": "2020-09-13T12:26:40Z"},
+                {"id": 12, "topic_id": 1, "raw": "Reply by a deleted user", "post_number": 2, "created_at": "2020-09-13T12:30:00Z"},
+                {"id": 13, "topic_id": 1, "user_id": 1, "raw": "Removed", "post_number": 3, "created_at": "2020-09-13T12:31:00Z", "deleted_at": "2020-09-13T13:00:00Z"},
+                {"id": 21, "topic_id": 2, "user_id": 1, "raw": "Secret", "post_number": 1, "created_at": "2020-09-13T12:26:40Z"}
+            ]
+        })).unwrap();
+
+        assert_eq!(data.forums.len(), 1);
+        assert_eq!(data.forums[0].caption, "");
+
+        assert_eq!(data.users.len(), 1);
+        assert_eq!((data.users[0].username.as_str(), data.users[0].password.as_str(), data.users[0].date), ("alice", "", 1600000000));
+
+        assert_eq!(data.threads.len(), 1);
+        assert_eq!((data.threads[0].forum_id.as_str(), data.threads[0].content.as_str()), ("5", "**Opening**"));
+        assert!(data.threads[0].locked);
+
+        assert_eq!(data.posts.len(), 1);
+        assert_eq!((data.posts[0].id.as_str(), data.posts[0].creator_id.as_str()), ("12", ""));
+    }
+
+    #[test]
+    fn read_backup_rejects_invalid_timestamps() {
+        let err = read_backup(json!({"users": [{"id": 1, "username": "alice", "created_at": "yesterday"}]})).err().unwrap();
+        assert!(err.starts_with("Invalid timestamp yesterday"));
+    }
+
+    #[test]
+    fn read_missing_backup() {
+        assert!(read("/nonexistent/discourse.json").err().unwrap().starts_with("Unable to read /nonexistent/discourse.json"));
+    }
+}
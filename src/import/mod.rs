@@ -0,0 +1,498 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use sqlx::{AnyConnection, Connection};
+use uuid::Uuid;
+
+use crate::schema::database::Database;
+use crate::schema::tables::{Forums, ImportMap, Posts, Threads, Users};
+
+pub mod discourse;
+pub mod mybb;
+pub mod phpbb;
+
+/// How many rows are imported between progress messages.
+const PROGRESS_INTERVAL: usize = 500;
+
+/// The forum software data is imported from.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ImportSource {
+    PhpBB,
+    MyBB,
+    Discourse,
+}
+
+impl ImportSource {
+    /// The name of the source. This is stored in the import map, so it must never change.
+    pub fn name(&self) -> &'static str {
+        match self {
+            ImportSource::PhpBB => "phpbb",
+            ImportSource::MyBB => "mybb",
+            ImportSource::Discourse => "discourse"
+        }
+    }
+}
+
+impl FromStr for ImportSource {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "phpbb" => Ok(ImportSource::PhpBB),
+            "mybb" => Ok(ImportSource::MyBB),
+            "discourse" => Ok(ImportSource::Discourse),
+            other => Err(format!("Unknown import source {}. Use phpbb, mybb or discourse.", other))
+        }
+    }
+}
+
+/// A forum (or category) of the other forum.
+pub struct ImportedForum {
+    pub id: String,
+    pub name: String,
+    pub caption: String,
+}
+
+/// A user of the other forum.
+pub struct ImportedUser {
+    pub id: String,
+    pub username: String,
+    pub email: String,
+    /// The password hash of the other forum. It is kept for reference, the user has to be given a new password.
+    pub password: String,
+    /// The unix time the user joined.
+    pub date: i64,
+}
+
+/// A thread (or topic) of the other forum. The content is the text of its first post.
+pub struct ImportedThread {
+    pub id: String,
+    pub forum_id: String,
+    pub name: String,
+    pub content: String,
+    pub creator_id: String,
+    pub locked: bool,
+    /// The unix time the thread was created.
+    pub date: i64,
+}
+
+/// A reply to a thread of the other forum.
+pub struct ImportedPost {
+    pub id: String,
+    pub thread_id: String,
+    pub creator_id: String,
+    pub content: String,
+    /// The unix time the post was created.
+    pub date: i64,
+}
+
+/// Everything read from the other forum.
+#[derive(Default)]
+pub struct ImportData {
+    pub forums: Vec<ImportedForum>,
+    pub users: Vec<ImportedUser>,
+    pub threads: Vec<ImportedThread>,
+    pub posts: Vec<ImportedPost>,
+}
+
+/// The amount of rows of one kind that were found and what happened to them.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct KindReport {
+    pub found: usize,
+    /// Rows that were imported, or would be imported on a dry run.
+    pub imported: usize,
+    /// Rows that were imported by an earlier run.
+    pub already_imported: usize,
+    /// Rows that cannot be imported, such as threads in a forum that was not imported.
+    pub skipped: usize,
+}
+
+/// What an import did, or would do on a dry run.
+#[derive(Debug, Default)]
+pub struct ImportReport {
+    pub forums: KindReport,
+    pub users: KindReport,
+    pub threads: KindReport,
+    pub posts: KindReport,
+    /// Users whose username was taken, with the username they were imported as.
+    pub renamed_users: Vec<(String, String)>,
+    /// Users that were linked to an existing account with the same username and email.
+    pub merged_users: Vec<String>,
+    pub warnings: Vec<String>,
+}
+
+/**
+   Read everything that can be imported from another forum.
+
+   ## Params
+   location - The database of the other forum (phpBB and MyBB) or the path to the JSON backup (Discourse).
+   table_prefix - The prefix of the tables of the other forum. The default prefix of the software is used when none is given.
+*/
+pub async fn read(source: ImportSource, location: &str, table_prefix: Option<&str>) -> Result<ImportData, String> {
+    match source {
+        ImportSource::PhpBB => phpbb::read(location, table_prefix.unwrap_or("phpbb_")).await,
+        ImportSource::MyBB => mybb::read(location, table_prefix.unwrap_or("mybb_")).await,
+        ImportSource::Discourse => discourse::read(location)
+    }
+}
+
+/// Connect to the database of the other forum. A location without a scheme is opened as an SQLite file.
+async fn connect_source(location: &str) -> Result<AnyConnection, String> {
+    let url = if location.contains("://") {
+        location.to_string()
+    } else {
+        format!("sqlite://{}", location)
+    };
+
+    AnyConnection::connect(&url).await.map_err(|err| format!("Unable to connect to the database of the other forum: {:?}", err))
+}
+
+/// Select a text column so it reads the same from SQLite and MySQL.
+fn text_column(column: &str) -> String {
+    format!("CAST({0} AS CHAR) AS {0}", column)
+}
+
+/// Select an integer column as a signed 64 bit integer, as the other forums use unsigned columns.
+fn int_column(column: &str) -> String {
+    format!("CAST({0} AS SIGNED) AS {0}", column)
+}
+
+/// Decode the HTML entities that phpBB and MyBB escape text with.
+fn decode_entities(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#039;", "'")
+        .replace("&#39;", "'")
+        .replace("&amp;", "&")
+}
+
+/// Cut text to the length of the column it is stored in.
+fn truncate(text: &str, max: usize) -> String {
+    text.chars().take(max).collect()
+}
+
+/**
+   Move the first post of every thread into the thread, as ForumRS stores the opening post as the content of the thread.
+
+   ## Params
+   threads - The threads and the id of their first post.
+*/
+fn attach_first_posts(threads: Vec<(ImportedThread, String)>, posts: Vec<ImportedPost>) -> (Vec<ImportedThread>, Vec<ImportedPost>) {
+    let first_posts: HashMap<String, usize> = threads.iter().enumerate()
+        .map(|(index, (_, first_post))| (first_post.clone(), index))
+        .collect();
+    let mut threads: Vec<ImportedThread> = threads.into_iter().map(|(thread, _)| thread).collect();
+
+    let mut replies = Vec::with_capacity(posts.len());
+    for post in posts {
+        match first_posts.get(&post.id) {
+            Some(index) if threads[*index].id == post.thread_id => threads[*index].content = post.content,
+            _ => replies.push(post)
+        }
+    }
+
+    (threads, replies)
+}
+
+/// Print the progress of a kind of row every so often.
+fn print_progress(kind: &str, done: usize, total: usize) {
+    if done % PROGRESS_INTERVAL == 0 || done == total {
+        println!("[INFO] {}: {} of {}", kind, done, total);
+    }
+}
+
+/// Get the uuid a row was imported as by an earlier run.
+async fn previous_import(db: &mut Database, source: ImportSource, kind: &str, id: &str) -> Result<Option<Uuid>, String> {
+    ImportMap::get(db, source.name(), kind, id).await
+        .map_err(|err| format!("Unable to read the import map: {:?}", err))
+}
+
+/// Record the uuid a row was imported as, so it is not imported again.
+async fn record_import(db: &mut Database, source: ImportSource, kind: &str, id: &str, uuid: Uuid) -> Result<(), String> {
+    ImportMap::insert(db, source.name(), kind, id, uuid).await
+        .map(|_| ())
+        .map_err(|err| format!("Unable to write the import map: {:?}", err))
+}
+
+/**
+   Import the data of another forum.
+
+   Every imported row is recorded in the import map, so running the import again continues where it stopped and
+   never imports a row twice. Users are imported without a usable password and have to be given a new one.
+   Posts by users that were not imported are shown as posts by a deleted user.
+
+   ## Params
+   dry_run - Only report what would be imported. Nothing is written to the database.
+*/
+pub async fn import(db: &mut Database, source: ImportSource, data: &ImportData, dry_run: bool) -> Result<ImportReport, String> {
+    let mut report = ImportReport::default();
+
+    let mut forums: HashMap<&str, Uuid> = HashMap::new();
+    report.forums.found = data.forums.len();
+    for (done, forum) in data.forums.iter().enumerate() {
+        if let Some(uuid) = previous_import(db, source, "forum", &forum.id).await? {
+            forums.insert(forum.id.as_str(), uuid);
+            report.forums.already_imported += 1;
+            continue;
+        }
+
+        let uuid = Uuid::new_v4();
+        if !dry_run {
+            Forums::insert_imported(db, uuid, truncate(&forum.name, 100), truncate(&forum.caption, 255)).await
+                .map_err(|err| format!("Unable to import the forum {}: {:?}", forum.name, err))?;
+            record_import(db, source, "forum", &forum.id, uuid).await?;
+            print_progress("Forums", done + 1, data.forums.len());
+        }
+        forums.insert(forum.id.as_str(), uuid);
+        report.forums.imported += 1;
+    }
+
+    let mut users: HashMap<&str, Uuid> = HashMap::new();
+    report.users.found = data.users.len();
+    for (done, user) in data.users.iter().enumerate() {
+        if let Some(uuid) = previous_import(db, source, "user", &user.id).await? {
+            users.insert(user.id.as_str(), uuid);
+            report.users.already_imported += 1;
+            continue;
+        }
+
+        if user.username.trim().is_empty() {
+            report.users.skipped += 1;
+            report.warnings.push(format!("The user {} has no username and was not imported.", user.id));
+            continue;
+        }
+
+        let mut username = truncate(&user.username, 40);
+        let existing = Users::get_by_username(db, &username).await.map_err(|err| format!("{:?}", err))?;
+        if let Some(existing) = existing {
+            // The same person on both forums keeps a single account.
+            if !user.email.is_empty() && existing.email.to_lowercase() == user.email.to_lowercase() {
+                if !dry_run {
+                    record_import(db, source, "user", &user.id, existing.uuid).await?;
+                }
+                users.insert(user.id.as_str(), existing.uuid);
+                report.merged_users.push(username);
+                report.users.imported += 1;
+                continue;
+            }
+
+            let suffix = format!("_{}", user.id);
+            let renamed = format!("{}{}", truncate(&username, 40 - suffix.chars().count().min(40)), suffix);
+            if Users::get_by_username(db, &renamed).await.map_err(|err| format!("{:?}", err))?.is_some() {
+                report.users.skipped += 1;
+                report.warnings.push(format!("The username {} is taken and the user was not imported.", username));
+                continue;
+            }
+            report.renamed_users.push((username, renamed.clone()));
+            username = renamed;
+        }
+
+        let uuid = Uuid::new_v4();
+        if !dry_run {
+            Users::insert_imported(db, uuid, username.clone(), user.email.clone(), user.password.clone(), user.date).await
+                .map_err(|err| format!("Unable to import the user {}: {:?}", username, err))?;
+            record_import(db, source, "user", &user.id, uuid).await?;
+            print_progress("Users", done + 1, data.users.len());
+        }
+        users.insert(user.id.as_str(), uuid);
+        report.users.imported += 1;
+    }
+
+    let mut threads: HashMap<&str, Uuid> = HashMap::new();
+    report.threads.found = data.threads.len();
+    for (done, thread) in data.threads.iter().enumerate() {
+        if let Some(uuid) = previous_import(db, source, "thread", &thread.id).await? {
+            threads.insert(thread.id.as_str(), uuid);
+            report.threads.already_imported += 1;
+            continue;
+        }
+
+        let forum_uuid = match forums.get(thread.forum_id.as_str()) {
+            Some(forum_uuid) => *forum_uuid,
+            None => {
+                report.threads.skipped += 1;
+                continue;
+            }
+        };
+        let creator = users.get(thread.creator_id.as_str()).cloned().unwrap_or_default();
+
+        let uuid = Uuid::new_v4();
+        if !dry_run {
+            Threads::insert_imported(db, uuid, forum_uuid, truncate(&thread.name, 100), thread.content.clone(), creator, thread.locked, thread.date).await
+                .map_err(|err| format!("Unable to import the thread {}: {:?}", thread.name, err))?;
+            record_import(db, source, "thread", &thread.id, uuid).await?;
+            print_progress("Threads", done + 1, data.threads.len());
+        }
+        threads.insert(thread.id.as_str(), uuid);
+        report.threads.imported += 1;
+    }
+
+    report.posts.found = data.posts.len();
+    for (done, post) in data.posts.iter().enumerate() {
+        if previous_import(db, source, "post", &post.id).await?.is_some() {
+            report.posts.already_imported += 1;
+            continue;
+        }
+
+        let thread_uuid = match threads.get(post.thread_id.as_str()) {
+            Some(thread_uuid) => *thread_uuid,
+            None => {
+                report.posts.skipped += 1;
+                continue;
+            }
+        };
+        let creator = users.get(post.creator_id.as_str()).cloned().unwrap_or_default();
+
+        if !dry_run {
+            let uuid = Uuid::new_v4();
+            Posts::insert_imported(db, uuid, thread_uuid, creator, post.content.clone(), post.date).await
+                .map_err(|err| format!("Unable to import the post {}: {:?}", post.id, err))?;
+            record_import(db, source, "post", &post.id, uuid).await?;
+            print_progress("Posts", done + 1, data.posts.len());
+        }
+        report.posts.imported += 1;
+    }
+
+    if report.threads.skipped > 0 {
+        report.warnings.push(format!("{} threads are in a forum that was not imported and were skipped.", report.threads.skipped));
+    }
+    if report.posts.skipped > 0 {
+        report.warnings.push(format!("{} posts are in a thread that was not imported and were skipped.", report.posts.skipped));
+    }
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::schema::dbsetup::setup_database;
+    use crate::settings::SqlSettings;
+
+    use super::*;
+
+    fn user(id: &str, username: &str, email: &str) -> ImportedUser {
+        ImportedUser { id: id.to_string(), username: username.to_string(), email: email.to_string(), password: "legacy".to_string(), date: 1600000000 }
+    }
+
+    fn thread(id: &str, forum_id: &str, creator_id: &str) -> ImportedThread {
+        ImportedThread { id: id.to_string(), forum_id: forum_id.to_string(), name: format!("Thread {}", id), content: String::new(),
+            creator_id: creator_id.to_string(), locked: false, date: 1600000000 }
+    }
+
+    fn post(id: &str, thread_id: &str, creator_id: &str) -> ImportedPost {
+        ImportedPost { id: id.to_string(), thread_id: thread_id.to_string(), creator_id: creator_id.to_string(),
+            content: format!("Post {}", id), date: 1600000000 }
+    }
+
+    /// A forum with a user that has the same account already, one whose username is taken by someone else, one
+    /// without a username, and content in a forum and thread that are not part of the import.
+    fn data() -> ImportData {
+        ImportData {
+            forums: vec![ImportedForum { id: "1".to_string(), name: "General".to_string(), caption: "Talk".to_string() }],
+            users: vec![user("1", "alice", "Alice@Example.com"), user("2", "bob", "bob@other.example"), user("3", " ", ""), user("4", "carol", "carol@example.com")],
+            threads: vec![thread("10", "1", "4"), thread("11", "9", "4")],
+            posts: vec![post("100", "10", "2"), post("101", "11", "4"), post("102", "10", "7")],
+        }
+    }
+
+    async fn forum_db() -> Database {
+        let mut db = Database::new_sqlite(&SqlSettings { file_location: ":memory:".to_string() }).await.unwrap();
+        setup_database(&mut db).await.unwrap();
+        Users::insert(&mut db, Uuid::new_v4(), "alice".to_string(), "alice@example.com".to_string(), "hash".to_string(), false, false).await;
+        Users::insert(&mut db, Uuid::new_v4(), "bob".to_string(), "bob@example.com".to_string(), "hash".to_string(), false, false).await;
+        db
+    }
+
+    #[test]
+    fn parse_source() {
+        assert_eq!("phpBB".parse::<ImportSource>(), Ok(ImportSource::PhpBB));
+        assert_eq!("MYBB".parse::<ImportSource>(), Ok(ImportSource::MyBB));
+        assert_eq!("discourse".parse::<ImportSource>(), Ok(ImportSource::Discourse));
+        assert!("vbulletin".parse::<ImportSource>().is_err());
+    }
+
+    #[test]
+    fn decode_entities_once() {
+        assert_eq!(decode_entities("&lt;b&gt; &quot;Tom&quot; &amp; Jerry&#039;s &#39;"), "<b> \"Tom\" & Jerry's '");
+        assert_eq!(decode_entities("&amp;lt;"), "&lt;");
+    }
+
+    #[test]
+    fn truncate_characters() {
+        assert_eq!(truncate("äöü", 2), "äö");
+        assert_eq!(truncate("abc", 10), "abc");
+    }
+
+    #[test]
+    fn first_posts_become_thread_content() {
+        let threads = vec![(thread("10", "1", "4"), "100".to_string()), (thread("11", "1", "4"), "101".to_string())];
+        // Post 101 is the first post of thread 11 but was moved to thread 10, so it stays a reply.
+        let posts = vec![post("100", "10", "4"), post("101", "10", "4"), post("102", "10", "4")];
+        let (threads, posts) = attach_first_posts(threads, posts);
+
+        assert_eq!(threads[0].content, "Post 100");
+        assert_eq!(threads[1].content, "");
+        assert_eq!(posts.iter().map(|post| post.id.as_str()).collect::<Vec<&str>>(), vec!["101", "102"]);
+    }
+
+    #[actix_rt::test]
+    async fn dry_run_writes_nothing() {
+        let mut db = forum_db().await;
+        let report = import(&mut db, ImportSource::PhpBB, &data(), true).await.unwrap();
+
+        assert_eq!((report.users.imported, report.users.skipped), (3, 1));
+        assert_eq!(report.renamed_users, vec![("bob".to_string(), "bob_2".to_string())]);
+        assert_eq!((report.threads.imported, report.threads.skipped), (1, 1));
+        assert_eq!((report.posts.imported, report.posts.skipped), (2, 1));
+        assert!(Users::get_by_username(&mut db, "carol").await.unwrap().is_none());
+        assert!(Forums::get_all(&mut db).await.unwrap().is_empty());
+        assert_eq!(ImportMap::get(&mut db, "phpbb", "user", "1").await.unwrap(), None);
+    }
+
+    #[actix_rt::test]
+    async fn import_maps_renames_and_merges_users() {
+        let mut db = forum_db().await;
+        let report = import(&mut db, ImportSource::PhpBB, &data(), false).await.unwrap();
+
+        assert_eq!(report.merged_users, vec!["alice".to_string()]);
+        assert_eq!(report.renamed_users, vec![("bob".to_string(), "bob_2".to_string())]);
+        assert_eq!(report.warnings.len(), 3);
+
+        let alice = Users::get_by_username(&mut db, "alice").await.unwrap().unwrap();
+        assert_eq!(ImportMap::get(&mut db, "phpbb", "user", "1").await.unwrap(), Some(alice.uuid));
+        assert!(!alice.password_reset);
+        let bob = Users::get_by_username(&mut db, "bob_2").await.unwrap().unwrap();
+        assert!(bob.password_reset);
+        let carol = Users::get_by_username(&mut db, "carol").await.unwrap().unwrap();
+        assert_eq!(carol.password, "legacy");
+
+        let forums = Forums::get_all(&mut db).await.unwrap();
+        assert_eq!(forums.len(), 1);
+        let threads = Threads::get_by_forum(&mut db, forums[0].uuid, None, 10).await.unwrap();
+        assert_eq!(threads.len(), 1);
+        assert_eq!(threads[0].creator, carol.uuid);
+
+        let posts = Posts::get_by_thread(&mut db, threads[0].uuid, None, 10).await.unwrap();
+        let creators: Vec<Uuid> = posts.iter().map(|post| post.creator).collect();
+        // Post 102 is by a user that was not imported, so it is shown as a post by a deleted user.
+        assert_eq!(creators, vec![bob.uuid, Uuid::nil()]);
+    }
+
+    #[actix_rt::test]
+    async fn import_again_continues() {
+        let mut db = forum_db().await;
+        import(&mut db, ImportSource::PhpBB, &data(), false).await.unwrap();
+        let report = import(&mut db, ImportSource::PhpBB, &data(), false).await.unwrap();
+
+        assert_eq!((report.forums.imported, report.forums.already_imported), (0, 1));
+        assert_eq!((report.users.imported, report.users.already_imported), (0, 3));
+        assert_eq!((report.threads.imported, report.threads.already_imported), (0, 1));
+        assert_eq!((report.posts.imported, report.posts.already_imported), (0, 2));
+        assert!(report.renamed_users.is_empty());
+
+        // The same ids from another source are a different forum.
+        let report = import(&mut db, ImportSource::MyBB, &data(), true).await.unwrap();
+        assert_eq!(report.forums.imported, 1);
+    }
+}
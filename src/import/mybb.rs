@@ -0,0 +1,129 @@
+use sqlx::{AnyConnection, Connection, Row};
+
+use crate::import::{attach_first_posts, connect_source, decode_entities, int_column, text_column, ImportData, ImportedForum, ImportedPost, ImportedThread, ImportedUser};
+
+/// The visibility of approved threads and posts. Unapproved and deleted ones are not imported.
+const VISIBLE: i64 = 1;
+
+/**
+   Read a MyBB 1.8 board from a copy of its database.
+
+   Forums are imported; categories are not, as ForumRS forums cannot be nested. The redirects MyBB leaves behind
+   when a thread is moved are not imported either.
+*/
+pub async fn read(location: &str, prefix: &str) -> Result<ImportData, String> {
+    let mut con = connect_source(location).await?;
+    let result = read_tables(&mut con, prefix).await
+        .map_err(|err| format!("Unable to read the MyBB database: {:?}", err));
+    let _ = con.close().await;
+    result
+}
+
+async fn read_tables(con: &mut AnyConnection, prefix: &str) -> Result<ImportData, sqlx::Error> {
+    let mut data = ImportData::default();
+
+    let rows = sqlx::query(&format!("SELECT {}, {}, {} FROM {}forums WHERE type = 'f' ORDER BY fid",
+                                    int_column("fid"), text_column("name"), text_column("description"), prefix))
+        .fetch_all(&mut *con).await?;
+    for row in rows.iter() {
+        data.forums.push(ImportedForum {
+            id: row.try_get::<i64, _>("fid")?.to_string(),
+            name: decode_entities(&row.try_get::<Option<String>, _>("name")?.unwrap_or_default()),
+            caption: decode_entities(&row.try_get::<Option<String>, _>("description")?.unwrap_or_default()),
+        });
+    }
+
+    let rows = sqlx::query(&format!("SELECT {}, {}, {}, {}, {}, {} FROM {}users ORDER BY uid",
+                                    int_column("uid"), text_column("username"), text_column("email"), text_column("password"),
+                                    text_column("salt"), int_column("regdate"), prefix))
+        .fetch_all(&mut *con).await?;
+    for row in rows.iter() {
+        // MyBB hashes passwords with a salt stored next to them, so both are kept.
+        let password: String = row.try_get::<Option<String>, _>("password")?.unwrap_or_default();
+        let salt: String = row.try_get::<Option<String>, _>("salt")?.unwrap_or_default();
+        data.users.push(ImportedUser {
+            id: row.try_get::<i64, _>("uid")?.to_string(),
+            username: row.try_get::<Option<String>, _>("username")?.unwrap_or_default(),
+            email: row.try_get::<Option<String>, _>("email")?.unwrap_or_default(),
+            password: format!("mybb:{}:{}", salt, password),
+            date: row.try_get("regdate")?,
+        });
+    }
+
+    let rows = sqlx::query(&format!("SELECT {}, {}, {}, {}, {}, {}, {} FROM {}threads WHERE visible = {} AND closed NOT LIKE 'moved|%' ORDER BY tid",
+                                    int_column("tid"), int_column("fid"), text_column("subject"), int_column("uid"), text_column("closed"),
+                                    int_column("dateline"), int_column("firstpost"), prefix, VISIBLE))
+        .fetch_all(&mut *con).await?;
+    let mut threads = Vec::with_capacity(rows.len());
+    for row in rows.iter() {
+        threads.push((ImportedThread {
+            id: row.try_get::<i64, _>("tid")?.to_string(),
+            forum_id: row.try_get::<i64, _>("fid")?.to_string(),
+            name: row.try_get::<Option<String>, _>("subject")?.unwrap_or_default(),
+            content: String::new(),
+            creator_id: row.try_get::<i64, _>("uid")?.to_string(),
+            locked: row.try_get::<Option<String>, _>("closed")?.map(|closed| closed == "1").unwrap_or(false),
+            date: row.try_get("dateline")?,
+        }, row.try_get::<i64, _>("firstpost")?.to_string()));
+    }
+
+    let rows = sqlx::query(&format!("SELECT {}, {}, {}, {}, {} FROM {}posts WHERE visible = {} ORDER BY pid",
+                                    int_column("pid"), int_column("tid"), int_column("uid"), text_column("message"),
+                                    int_column("dateline"), prefix, VISIBLE))
+        .fetch_all(&mut *con).await?;
+    let mut posts = Vec::with_capacity(rows.len());
+    for row in rows.iter() {
+        posts.push(ImportedPost {
+            id: row.try_get::<i64, _>("pid")?.to_string(),
+            thread_id: row.try_get::<i64, _>("tid")?.to_string(),
+            creator_id: row.try_get::<i64, _>("uid")?.to_string(),
+            content: row.try_get::<Option<String>, _>("message")?.unwrap_or_default(),
+            date: row.try_get("dateline")?,
+        });
+    }
+
+    let (threads, posts) = attach_first_posts(threads, posts);
+    data.threads = threads;
+    data.posts = posts;
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[actix_rt::test]
+    async fn read_board() {
+        let mut con = AnyConnection::connect("sqlite::memory:").await.unwrap();
+        for query in [
+            "CREATE TABLE mybb_forums (fid INTEGER, name TEXT, description TEXT, type TEXT)",
+            "INSERT INTO mybb_forums VALUES (1, 'A category', '', 'c'), (2, 'General', 'Talk &amp; chat', 'f')",
+            "CREATE TABLE mybb_users (uid INTEGER, username TEXT, email TEXT, password TEXT, salt TEXT, regdate INTEGER)",
+            "INSERT INTO mybb_users VALUES (1, 'bob', 'bob@example.com', '5f4dcc3b5aa765d61d8327deb882cf99', 'a1b2c3d4', 1600000000)",
+            "CREATE TABLE mybb_threads (tid INTEGER, fid INTEGER, subject TEXT, uid INTEGER, closed TEXT, dateline INTEGER, firstpost INTEGER, visible INTEGER)",
+            "INSERT INTO mybb_threads VALUES (10, 2, 'Welcome', 1, '1', 1600000001, 100, 1), (11, 2, 'Welcome', 1, 'moved|10', 1600000001, 0, 1), \
+             (12, 2, 'Open', 1, '', 1600000002, 102, 1), (13, 2, 'Unapproved', 1, '', 1600000003, 104, 0)",
+            "CREATE TABLE mybb_posts (pid INTEGER, tid INTEGER, uid INTEGER, message TEXT, dateline INTEGER, visible INTEGER)",
+            "INSERT INTO mybb_posts VALUES (100, 10, 1, 'First', 1600000001, 1), (101, 10, 1, 'Reply', 1600000004, 1), \
+             (102, 12, 1, 'Second', 1600000002, 1), (103, 12, 1, 'Deleted', 1600000005, -1), (104, 13, 1, 'Unapproved', 1600000003, 0)",
+        ].iter() {
+            sqlx::query(query).execute(&mut con).await.unwrap();
+        }
+
+        let data = read_tables(&mut con, "mybb_").await.unwrap();
+        assert_eq!(data.forums.len(), 1);
+        assert_eq!((data.forums[0].id.as_str(), data.forums[0].caption.as_str()), ("2", "Talk & chat"));
+
+        assert_eq!(data.users.len(), 1);
+        assert_eq!(data.users[0].password, "mybb:a1b2c3d4:5f4dcc3b5aa765d61d8327deb882cf99");
+
+        let threads: Vec<(&str, &str, bool)> = data.threads.iter()
+            .map(|thread| (thread.id.as_str(), thread.content.as_str(), thread.locked))
+            .collect();
+        assert_eq!(threads, vec![("10", "First", true), ("12", "Second", false)]);
+
+        let posts: Vec<(&str, &str)> = data.posts.iter().map(|post| (post.id.as_str(), post.thread_id.as_str())).collect();
+        assert_eq!(posts, vec![("101", "10")]);
+        con.close().await.unwrap();
+    }
+}
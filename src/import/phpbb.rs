@@ -0,0 +1,184 @@
+use regex::Regex;
+use sqlx::{AnyConnection, Connection, Row};
+
+use crate::import::{attach_first_posts, connect_source, decode_entities, int_column, text_column, ImportData, ImportedForum, ImportedPost, ImportedThread, ImportedUser};
+
+/// The forum type of phpBB forums that hold topics. Categories and links cannot hold topics.
+const FORUM_POST: i64 = 1;
+/// The user type of the anonymous user and the search engine bots.
+const USER_IGNORE: i64 = 2;
+/// The status of a locked topic.
+const ITEM_LOCKED: i64 = 1;
+/// The visibility of approved topics and posts. Unapproved and soft deleted ones are not imported.
+const ITEM_APPROVED: i64 = 1;
+
+/// Converts the text phpBB stores back to the text that was written.
+struct TextCleaner {
+    smilies: Regex,
+    tags: Regex,
+    bbcode_uid: Regex,
+}
+
+impl TextCleaner {
+    fn new() -> TextCleaner {
+        TextCleaner {
+            smilies: Regex::new(r"<!-- s(\S+) --><img[^>]*><!-- s\S+ -->").unwrap(),
+            tags: Regex::new(r"<[^>]*>").unwrap(),
+            bbcode_uid: Regex::new(r"\[(/?[^\[\]:]+):[a-z0-9]+\]").unwrap(),
+        }
+    }
+
+    /// Clean the text of a post. The BBCode is kept as it was written.
+    fn clean(&self, text: &str) -> String {
+        // phpBB 3.2 and newer store posts as XML, where the tags wrap the BBCode that was written.
+        if text.starts_with("<r>") || text.starts_with("<t>") {
+            return decode_entities(&self.tags.replace_all(text, ""));
+        }
+
+        // Older versions add a unique id to every BBCode tag and store smilies and links as HTML.
+        let text = self.smilies.replace_all(text, "$1");
+        let text = self.tags.replace_all(&text, "");
+        decode_entities(&self.bbcode_uid.replace_all(&text, "[$1]"))
+    }
+}
+
+/**
+   Read a phpBB 3.1 (or newer) board from a copy of its database.
+
+   Forums that hold topics are imported; categories and links are not, as ForumRS forums cannot be nested.
+   The anonymous user and the bots are not imported.
+*/
+pub async fn read(location: &str, prefix: &str) -> Result<ImportData, String> {
+    let mut con = connect_source(location).await?;
+    let result = read_tables(&mut con, prefix).await
+        .map_err(|err| format!("Unable to read the phpBB database: {:?}", err));
+    let _ = con.close().await;
+    result
+}
+
+async fn read_tables(con: &mut AnyConnection, prefix: &str) -> Result<ImportData, sqlx::Error> {
+    let cleaner = TextCleaner::new();
+    let mut data = ImportData::default();
+
+    let rows = sqlx::query(&format!("SELECT {}, {}, {} FROM {}forums WHERE forum_type = {} ORDER BY forum_id",
+                                    int_column("forum_id"), text_column("forum_name"), text_column("forum_desc"), prefix, FORUM_POST))
+        .fetch_all(&mut *con).await?;
+    for row in rows.iter() {
+        data.forums.push(ImportedForum {
+            id: row.try_get::<i64, _>("forum_id")?.to_string(),
+            name: decode_entities(&row.try_get::<Option<String>, _>("forum_name")?.unwrap_or_default()),
+            caption: cleaner.clean(&row.try_get::<Option<String>, _>("forum_desc")?.unwrap_or_default()),
+        });
+    }
+
+    let rows = sqlx::query(&format!("SELECT {}, {}, {}, {}, {} FROM {}users WHERE user_type <> {} ORDER BY user_id",
+                                    int_column("user_id"), text_column("username"), text_column("user_email"), text_column("user_password"),
+                                    int_column("user_regdate"), prefix, USER_IGNORE))
+        .fetch_all(&mut *con).await?;
+    for row in rows.iter() {
+        data.users.push(ImportedUser {
+            id: row.try_get::<i64, _>("user_id")?.to_string(),
+            username: decode_entities(&row.try_get::<Option<String>, _>("username")?.unwrap_or_default()),
+            email: row.try_get::<Option<String>, _>("user_email")?.unwrap_or_default(),
+            password: row.try_get::<Option<String>, _>("user_password")?.unwrap_or_default(),
+            date: row.try_get("user_regdate")?,
+        });
+    }
+
+    // Moved topics leave a shadow topic behind that points to the real one.
+    let rows = sqlx::query(&format!("SELECT {}, {}, {}, {}, {}, {}, {} FROM {}topics WHERE topic_moved_id = 0 AND topic_visibility = {} ORDER BY topic_id",
+                                    int_column("topic_id"), int_column("forum_id"), text_column("topic_title"), int_column("topic_poster"),
+                                    int_column("topic_status"), int_column("topic_time"), int_column("topic_first_post_id"), prefix, ITEM_APPROVED))
+        .fetch_all(&mut *con).await?;
+    let mut threads = Vec::with_capacity(rows.len());
+    for row in rows.iter() {
+        threads.push((ImportedThread {
+            id: row.try_get::<i64, _>("topic_id")?.to_string(),
+            forum_id: row.try_get::<i64, _>("forum_id")?.to_string(),
+            name: decode_entities(&row.try_get::<Option<String>, _>("topic_title")?.unwrap_or_default()),
+            content: String::new(),
+            creator_id: row.try_get::<i64, _>("topic_poster")?.to_string(),
+            locked: row.try_get::<i64, _>("topic_status")? == ITEM_LOCKED,
+            date: row.try_get("topic_time")?,
+        }, row.try_get::<i64, _>("topic_first_post_id")?.to_string()));
+    }
+
+    let rows = sqlx::query(&format!("SELECT {}, {}, {}, {}, {} FROM {}posts WHERE post_visibility = {} ORDER BY post_id",
+                                    int_column("post_id"), int_column("topic_id"), int_column("poster_id"), text_column("post_text"),
+                                    int_column("post_time"), prefix, ITEM_APPROVED))
+        .fetch_all(&mut *con).await?;
+    let mut posts = Vec::with_capacity(rows.len());
+    for row in rows.iter() {
+        posts.push(ImportedPost {
+            id: row.try_get::<i64, _>("post_id")?.to_string(),
+            thread_id: row.try_get::<i64, _>("topic_id")?.to_string(),
+            creator_id: row.try_get::<i64, _>("poster_id")?.to_string(),
+            content: cleaner.clean(&row.try_get::<Option<String>, _>("post_text")?.unwrap_or_default()),
+            date: row.try_get("post_time")?,
+        });
+    }
+
+    let (threads, posts) = attach_first_posts(threads, posts);
+    data.threads = threads;
+    data.posts = posts;
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clean_xml_posts() {
+        let cleaner = TextCleaner::new();
+        assert_eq!(cleaner.clean("<r><B><s>[b]</s>Hi<e>[/b]</e></B> &amp; welcome</r>"), "[b]Hi[/b] & welcome");
+        assert_eq!(cleaner.clean("<t>Plain &lt;text&gt;</t>"), "Plain <text>");
+    }
+
+    #[test]
+    fn clean_legacy_posts() {
+        let cleaner = TextCleaner::new();
+        let text = "[b:1a2b3c]bold[/b:1a2b3c] <!-- s:) --><img src=\"smile.gif\" alt=\":)\" /><!-- s:) --> \
+                    <!-- m --><a href=\"https://example.com\">https://example.com</a><!-- m -->";
+        assert_eq!(cleaner.clean(text), "[b]bold[/b] :) https://example.com");
+        assert_eq!(cleaner.clean("[quote=&quot;alice&quot;:9z]Hi[/quote:9z]"), "[quote=\"alice\"]Hi[/quote]");
+    }
+
+    #[actix_rt::test]
+    async fn read_board() {
+        let mut con = AnyConnection::connect("sqlite::memory:").await.unwrap();
+        for query in [
+            "CREATE TABLE phpbb_forums (forum_id INTEGER, forum_name TEXT, forum_desc TEXT, forum_type INTEGER)",
+            "INSERT INTO phpbb_forums VALUES (1, 'Rust &amp; Friends', 'All about <!-- m --><a href=\"https://rust-lang.org\">Rust</a><!-- m -->', 1), \
+             (2, 'A category', '', 0)",
+            "CREATE TABLE phpbb_users (user_id INTEGER, username TEXT, user_email TEXT, user_password TEXT, user_regdate INTEGER, user_type INTEGER)",
+            "INSERT INTO phpbb_users VALUES (1, 'Anonymous', '', '', 0, 2), (2, 'alice', 'alice@example.com', '$2y$10$hash', 1600000000, 0)",
+            "CREATE TABLE phpbb_topics (topic_id INTEGER, forum_id INTEGER, topic_title TEXT, topic_poster INTEGER, topic_status INTEGER, \
+             topic_time INTEGER, topic_first_post_id INTEGER, topic_moved_id INTEGER, topic_visibility INTEGER)",
+            "INSERT INTO phpbb_topics VALUES (10, 1, 'Hello &quot;world&quot;', 2, 1, 1600000001, 100, 0, 1), \
+             (11, 1, 'Hello &quot;world&quot;', 2, 2, 1600000001, 100, 10, 1), (12, 1, 'Unapproved', 2, 0, 1600000002, 102, 0, 0)",
+            "CREATE TABLE phpbb_posts (post_id INTEGER, topic_id INTEGER, poster_id INTEGER, post_text TEXT, post_time INTEGER, post_visibility INTEGER)",
+            "INSERT INTO phpbb_posts VALUES (100, 10, 2, '<t>Opening</t>', 1600000001, 1), (101, 10, 2, '<t>Reply</t>', 1600000005, 1), \
+             (102, 12, 2, '<t>Unapproved</t>', 1600000002, 0)",
+        ].iter() {
+            sqlx::query(query).execute(&mut con).await.unwrap();
+        }
+
+        let data = read_tables(&mut con, "phpbb_").await.unwrap();
+        assert_eq!(data.forums.len(), 1);
+        assert_eq!(data.forums[0].name, "Rust & Friends");
+        assert_eq!(data.forums[0].caption, "All about Rust");
+
+        assert_eq!(data.users.len(), 1);
+        assert_eq!((data.users[0].id.as_str(), data.users[0].password.as_str(), data.users[0].date), ("2", "$2y$10$hash", 1600000000));
+
+        assert_eq!(data.threads.len(), 1);
+        assert_eq!(data.threads[0].name, "Hello \"world\"");
+        assert_eq!(data.threads[0].content, "Opening");
+        assert!(data.threads[0].locked);
+
+        assert_eq!(data.posts.len(), 1);
+        assert_eq!((data.posts[0].id.as_str(), data.posts[0].content.as_str()), ("101", "Reply"));
+        con.close().await.unwrap();
+    }
+}
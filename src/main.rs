@@ -6,26 +6,61 @@ extern crate serde;
 extern crate serde_json;
 extern crate bcrypt;
 
+use std::collections::HashMap;
 use std::sync::{Arc, Mutex};
 
 use actix_files as actixfs;
+use chrono::{TimeZone, Utc};
 use actix_web::{App, get, HttpResponse, HttpServer, post, Responder, web};
+use futures::channel::oneshot;
+use futures::future;
+use futures::future::Either;
+use clap::Parser;
 use handlebars::{Context, Handlebars, Helper, Output, Renderable, RenderContext, RenderError};
 use uuid::Uuid;
 
+use crate::auth::login_protection::LoginThrottle;
+use crate::rate_limit::RateLimiter;
+use crate::schema::database::Database;
+use crate::schema::migrations;
 use crate::settings::{BaseSettings, SettingsManager, SqlSettings};
-use crate::state::SetupForumRSState;
+use crate::setup::headless::HeadlessSetup;
+use crate::state::{ForumRSState, SetupForumRSState};
 
 
+pub mod api;
+pub mod attachments;
+pub mod audit;
+pub mod auth;
+pub mod backup;
+pub mod cli;
+pub mod feeds;
+pub mod forum;
+pub mod import;
+pub mod moderation;
+pub mod profiles;
+pub mod rate_limit;
+pub mod reload;
 pub mod settings;
 pub mod setup;
 pub mod state;
+pub mod webhooks;
 pub mod schema;
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
+    // Administration commands run and exit without starting the server.
+    let cli = cli::Cli::parse();
+    if let Some(settings) = &cli.settings {
+        std::env::set_var("FORUMRS_SETTINGS", settings);
+    }
+    match cli.command {
+        None | Some(cli::Command::Serve) => {}
+        Some(command) => std::process::exit(cli::run(command).await)
+    }
+
     let new_setup: bool;
-    let base_settings: BaseSettings;
+    let mut base_settings: BaseSettings;
 
     // Check if the settings exist.
     if SettingsManager::settings_exist() {
@@ -34,7 +69,7 @@ async fn main() -> std::io::Result<()> {
         if valid_settings.is_err() {
             println!("[ERROR] An error has occurred when trying to start ForumRS!");
             println!("[ERROR] {}", valid_settings.unwrap_err());
-            println!("[ERROR] If this is your first time using ForumRS, then delete the settings.toml file.");
+            println!("[ERROR] If this is your first time using ForumRS, then delete the {} file.", SettingsManager::settings_path());
             panic!("An unexpected error has occurred! Please check the above logs.");
         }
         base_settings = SettingsManager::get_settings();
@@ -45,62 +80,231 @@ async fn main() -> std::io::Result<()> {
         settings::SettingsManager::save_settings(&base_settings);
     }
 
+    // Set up ForumRS from the setup file or the environment when they are given, so the setup wizard is never started.
+    if base_settings.new_setup {
+        let headless = match HeadlessSetup::load() {
+            Ok(headless) => headless,
+            Err(err) => {
+                println!("[ERROR] {}", err);
+                std::process::exit(1);
+            }
+        };
+
+        if let Some(headless) = headless {
+            println!("[INFO] Running the headless setup.");
+            match headless.run().await {
+                Ok(settings) => base_settings = settings,
+                Err(err) => {
+                    println!("[ERROR] The headless setup failed: {}", err);
+                    std::process::exit(1);
+                }
+            }
+            println!("[INFO] Configuration complete.");
+            if std::path::Path::new(HeadlessSetup::file_location().as_str()).exists() {
+                println!("[WARN] The setup file {} contains the admin password. Delete it now that setup is finished.", HeadlessSetup::file_location());
+            }
+        }
+    }
+
     println!("Starting ForumRS on port {}.", base_settings.port);
 
     let mut handlebars = Handlebars::new();
     handlebars.set_dev_mode(true);
     handlebars.register_helper("get_lang", Box::new(get_lang));
     handlebars.register_helper("ifEq", Box::new(if_eq));
+    handlebars.register_helper("formatTime", Box::new(format_time));
 
     handlebars.register_templates_directory(".hbs", "./views")
         .unwrap();
 
+    // The setup wizard runs until it is finished, then the forum starts in the same process.
     if base_settings.new_setup {
-        let console_session_login = Uuid::new_v4();
-        let setup_form_state = SetupForumRSState {
-            hbs: handlebars.clone(),
-            setup_code: console_session_login,
-            setup_session: Arc::new(Mutex::new(None)),
-        };
-        println!("The Configuration Login code is: {}", console_session_login);
-        HttpServer::new(move || {
-            App::new()
-                .app_data(web::Data::new(setup_form_state.clone()))
-                .service(actixfs::Files::new("/public", "./public"))
-                .service(setup::setup_router::welcome)
-                .service(setup::setup_router::login)
-                .service(setup::setup_router::auth_login)
-                .service(setup::setup_router::general)
-                .service(setup::setup_router::auth_general)
-                .service(setup::setup_router::security)
-                .service(setup::setup_router::auth_security)
-                .service(setup::setup_router::storage)
-                .service(setup::setup_router::auth_storage)
-                .service(setup::setup_router::finished)
-                .service(setup::setup_router::auth_finished)
-                .service(setup::setup_router::existing_storage)
-                .service(setup::setup_router::auth_existing_storage_migrate)
-                .service(setup::setup_router::auth_existing_storage_reset)
-                .service(setup::setup_router::account_creation)
-                .service(setup::setup_router::auth_account_creation)
-        }).bind(format!("{}:{}", base_settings.ip, base_settings.port))?
-            .run()
-            .await
-    } else {
-        HttpServer::new(move || {
-            App::new()
-                .app_data(web::Data::new(SetupForumRSState {
-                    hbs: handlebars.clone(),
-                    setup_code: Uuid::new_v4(),
-                    setup_session: Arc::new(Mutex::new(None)),
-                }))
-                .service(actixfs::Files::new(".", "./public").show_files_listing())
-                .service(setup::setup_router::welcome)
-                .service(setup::setup_router::login)
-        }).bind(format!("{}:{}", base_settings.ip, base_settings.port))?
-            .run()
-            .await
+        if !run_setup_server(&base_settings, handlebars.clone()).await? {
+            return Ok(());
+        }
+        base_settings = SettingsManager::get_settings();
+        println!("[INFO] Setup is finished. Starting ForumRS on port {}.", base_settings.port);
+    }
+
+    run_forum_server(&base_settings, handlebars).await
+}
+
+/**
+   Run the setup wizard.
+
+   ## Returns
+   True once setup is finished and the setup server has shut down, false if the server stopped for another reason.
+*/
+async fn run_setup_server(base_settings: &BaseSettings, handlebars: Handlebars<'static>) -> std::io::Result<bool> {
+    let console_session_login = Uuid::new_v4();
+    let (finished_sender, finished_receiver) = oneshot::channel();
+    let setup_form_state = SetupForumRSState {
+        hbs: handlebars,
+        setup_code: console_session_login,
+        setup_session: Arc::new(Mutex::new(None)),
+        login_throttle: Arc::new(Mutex::new(LoginThrottle::new())),
+        setup_account: Arc::new(Mutex::new(None)),
+        admin_account: Arc::new(Mutex::new(None)),
+        setup_address: (base_settings.ip.clone(), base_settings.port),
+        setup_finished: Arc::new(Mutex::new(Some(finished_sender))),
+    };
+    println!("The Configuration Login code is: {}", console_session_login);
+    let server = HttpServer::new(move || {
+        App::new()
+            .app_data(web::Data::new(setup_form_state.clone()))
+            .service(actixfs::Files::new("/public", "./public"))
+            .service(setup::setup_router::welcome)
+            .service(setup::setup_router::login)
+            .service(setup::setup_router::auth_login)
+            .service(setup::setup_router::general)
+            .service(setup::setup_router::auth_general)
+            .service(setup::setup_router::security)
+            .service(setup::setup_router::auth_security)
+            .service(setup::setup_router::storage)
+            .service(setup::setup_router::auth_storage)
+            .service(setup::setup_router::finished)
+            .service(setup::setup_router::auth_finished)
+            .service(setup::setup_router::existing_storage)
+            .service(setup::setup_router::auth_existing_storage_migrate)
+            .service(setup::setup_router::auth_existing_storage_reset)
+            .service(setup::setup_router::account_creation)
+            .service(setup::setup_router::auth_account_creation)
+            .service(setup::setup_router::review)
+            .service(setup::setup_router::auth_review)
+    }).bind(format!("{}:{}", base_settings.ip, base_settings.port))?
+        .shutdown_timeout(5)
+        .run();
+
+    // Stop the setup server gracefully once the finished page has been sent.
+    let handle = server.clone();
+    match future::select(server, finished_receiver).await {
+        Either::Left((result, _)) => result?,
+        Either::Right((_, server)) => {
+            handle.stop(true).await;
+            server.await?;
+        }
     }
+
+    Ok(!SettingsManager::get_settings().new_setup)
+}
+
+/**
+   Apply the schema upgrades the database is missing, such as after ForumRS is updated.
+
+   ## Returns
+   An error if the database cannot be upgraded. Every query expects the latest schema, so the forum cannot start.
+*/
+async fn upgrade_schema(settings: &BaseSettings) -> Result<(), String> {
+    let mut db = Database::connect(settings).await.map_err(|err| format!("Unable to connect to the database: {:?}", err))?;
+    let applied = migrations::upgrade(&mut db).await;
+    db.close().await;
+
+    if !applied?.is_empty() {
+        println!("[INFO] The database has been upgraded to version {} of the schema.", migrations::SCHEMA_VERSION);
+    }
+    Ok(())
+}
+
+/// Run the forum.
+async fn run_forum_server(base_settings: &BaseSettings, handlebars: Handlebars<'static>) -> std::io::Result<()> {
+    if let Err(err) = upgrade_schema(base_settings).await {
+        println!("[ERROR] {}", err);
+        println!("[ERROR] Fix the problem and run `forumrs migrate`, or start the version of ForumRS the database was made with.");
+        std::process::exit(1);
+    }
+
+    let rate_limiter = RateLimiter::new(base_settings, handlebars.clone());
+    let forum_state = ForumRSState {
+        hbs: handlebars.clone(),
+        pending_logins: Arc::new(Mutex::new(HashMap::new())),
+        login_throttle: Arc::new(Mutex::new(LoginThrottle::new())),
+        oauth_consents: Arc::new(Mutex::new(HashMap::new())),
+        authorization_codes: Arc::new(Mutex::new(HashMap::new())),
+    };
+    SettingsManager::start_live(SettingsManager::get_settings());
+    actix_web::rt::spawn(reload::watch_settings());
+    actix_web::rt::spawn(reload::listen_for_hangup());
+    actix_web::rt::spawn(webhooks::delivery::run_worker());
+    actix_web::rt::spawn(backup::schedule::run_schedule());
+    actix_web::rt::spawn(audit::run_retention());
+    HttpServer::new(move || {
+        App::new()
+            .wrap(rate_limiter.clone())
+            .app_data(web::Data::new(forum_state.clone()))
+            .service(actixfs::Files::new("/public", "./public"))
+            .service(api::v1_scope())
+            .service(forum::forum_router::index)
+            .service(forum::forum_router::forum)
+            .service(forum::forum_router::thread)
+            .service(forum::attachment_router::download)
+            .service(forum::attachment_router::thumbnail)
+            .service(forum::feed_router::latest)
+            .service(forum::feed_router::forum)
+            .service(forum::feed_router::thread)
+            .service(forum::account_router::login)
+            .service(forum::account_router::auth_login)
+            .service(forum::account_router::login_two_factor)
+            .service(forum::account_router::auth_login_two_factor)
+            .service(forum::account_router::logout)
+            .service(forum::account_router::two_factor)
+            .service(forum::account_router::auth_two_factor_enable)
+            .service(forum::account_router::auth_two_factor_disable)
+            .service(forum::account_router::auth_two_factor_recovery)
+            .service(forum::token_router::tokens)
+            .service(forum::token_router::auth_create_token)
+            .service(forum::token_router::auth_revoke_token)
+            .service(forum::oauth_router::applications)
+            .service(forum::oauth_router::auth_create_application)
+            .service(forum::oauth_router::auth_delete_application)
+            .service(forum::oauth_router::authorize)
+            .service(forum::oauth_router::auth_authorize)
+            .service(forum::oauth_router::token)
+            .service(forum::privacy_router::privacy)
+            .service(forum::privacy_router::export)
+            .service(forum::privacy_router::auth_delete_account)
+            .service(forum::profile_router::profile)
+            .service(forum::profile_router::avatar)
+            .service(forum::profile_router::edit_profile)
+            .service(forum::profile_router::auth_edit_profile)
+            .service(forum::profile_router::auth_upload_avatar)
+            .service(forum::message_router::inbox)
+            .service(forum::message_router::new_message)
+            .service(forum::message_router::auth_new_message)
+            .service(forum::message_router::unread)
+            .service(forum::message_router::conversation)
+            .service(forum::message_router::auth_reply)
+            .service(forum::message_router::auth_leave)
+            .service(forum::message_router::blocked)
+            .service(forum::message_router::auth_block)
+            .service(forum::message_router::auth_unblock)
+            .service(forum::moderation_router::report)
+            .service(forum::moderation_router::auth_report)
+            .service(forum::moderation_router::reports)
+            .service(forum::moderation_router::auth_report_action)
+            .service(forum::moderation_router::auth_lock_thread)
+            .service(forum::moderation_router::auth_pin_thread)
+            .service(forum::moderation_router::auth_move_thread)
+            .service(forum::moderation_router::auth_merge_thread)
+            .service(forum::moderation_router::auth_split_thread)
+            .service(forum::admin_router::security)
+            .service(forum::admin_router::auth_security)
+            .service(forum::admin_router::auth_reset_two_factor)
+            .service(forum::admin_router::login_attempts)
+            .service(forum::admin_router::audit_log)
+            .service(forum::admin_router::settings)
+            .service(forum::admin_router::auth_reload_settings)
+            .service(forum::admin_router::backups)
+            .service(forum::admin_router::auth_create_backup)
+            .service(forum::admin_router::download_backup)
+            .service(forum::webhook_router::webhooks)
+            .service(forum::webhook_router::auth_create_webhook)
+            .service(forum::webhook_router::auth_toggle_webhook)
+            .service(forum::webhook_router::auth_delete_webhook)
+            .service(forum::webhook_router::deliveries)
+            .service(forum::webhook_router::auth_redeliver)
+    }).bind(format!("{}:{}", base_settings.ip, base_settings.port))?
+        .run()
+        .await
 }
 
 fn get_lang(h: &Helper, _: &Handlebars, _: &Context, _: &mut RenderContext, out: &mut dyn Output) -> Result<(), RenderError> {
@@ -136,3 +340,18 @@ fn if_eq<'reg, 'rc>(h: &Helper<'reg, 'rc>, r: &'reg Handlebars, ctx: &'rc Contex
         None => Ok(()),
     }
 }
+
+/**
+   Handlebars helper method to format a unix timestamp (in seconds) as a UTC date and time.
+
+   ```
+    <span>{{formatTime attempt_time}}</span>
+   ```
+*/
+fn format_time(h: &Helper, _: &Handlebars, _: &Context, _: &mut RenderContext, out: &mut dyn Output) -> Result<(), RenderError> {
+    let timestamp = h.param(0).ok_or_else(|| RenderError::new("Param 0 not found for formatTime."))?.value().as_i64();
+    if let Some(time) = timestamp.and_then(|timestamp| Utc.timestamp_opt(timestamp, 0).single()) {
+        out.write(&time.format("%Y-%m-%d %H:%M UTC").to_string())?;
+    }
+    Ok(())
+}
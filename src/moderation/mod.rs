@@ -0,0 +1,490 @@
+use serde_json::{json, Value};
+use sqlx::Error;
+use uuid::Uuid;
+
+use crate::attachments;
+use crate::audit;
+use crate::audit::AuditAction;
+use crate::attachments::storage;
+use crate::schema::database::Database;
+use crate::schema::tables::{Attachment, Attachments, Forum, Message, Messages, Post, Posts, Reports, ReportTarget, Thread, Threads, User, Users};
+use crate::settings::SettingsManager;
+use crate::webhooks;
+use crate::webhooks::WebhookEvent;
+
+/// An action a moderator can take on a reported target. Every action closes the open reports of the target.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReportAction {
+    /// Close the reports without changing anything.
+    Dismiss,
+    /// Delete the reported thread, post or message.
+    Delete,
+    /// Lock the reported thread, or the thread of the reported post.
+    Lock,
+    /// Ban the author of the reported content, or the reported user.
+    Ban,
+}
+
+impl ReportAction {
+    /// The name of the action used in forms and stored as the resolution of reports.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReportAction::Dismiss => "dismiss",
+            ReportAction::Delete => "delete",
+            ReportAction::Lock => "lock",
+            ReportAction::Ban => "ban"
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<ReportAction> {
+        match value {
+            "dismiss" => Some(ReportAction::Dismiss),
+            "delete" => Some(ReportAction::Delete),
+            "lock" => Some(ReportAction::Lock),
+            "ban" => Some(ReportAction::Ban),
+            _ => None
+        }
+    }
+}
+
+/// Why a moderator action could not be taken.
+#[derive(Debug)]
+pub enum ActionError {
+    /// The action cannot be taken on this kind of target, or the target no longer exists.
+    NotAllowed,
+    /// The user cannot be banned by this moderator.
+    Protected,
+    Database(Error),
+}
+
+impl From<Error> for ActionError {
+    fn from(err: Error) -> Self {
+        ActionError::Database(err)
+    }
+}
+
+/// The thread, post, user or private message a report is about.
+pub enum ReportedContent {
+    Thread(Thread),
+    Post(Post),
+    User(User),
+    Message(Message),
+    /// The target was deleted after it was reported.
+    Missing,
+}
+
+impl ReportedContent {
+    /// Load the target of a report.
+    pub async fn load(db: &mut Database, target_type: ReportTarget, target_uuid: Uuid) -> Result<ReportedContent, Error> {
+        let content = match target_type {
+            ReportTarget::Thread => Threads::get_by_uuid(db, target_uuid).await?.map(ReportedContent::Thread),
+            ReportTarget::Post => Posts::get_by_uuid(db, target_uuid).await?.map(ReportedContent::Post),
+            ReportTarget::User => Users::get_by_uuid(db, target_uuid).await?.map(ReportedContent::User),
+            ReportTarget::Message => Messages::get_by_uuid(db, target_uuid).await?.map(ReportedContent::Message)
+        };
+        Ok(content.unwrap_or(ReportedContent::Missing))
+    }
+
+    /// The user responsible for the target: the author of content, or the reported user.
+    pub fn author(&self) -> Option<Uuid> {
+        match self {
+            ReportedContent::Thread(thread) => Some(thread.creator),
+            ReportedContent::Post(post) => Some(post.creator),
+            ReportedContent::User(user) => Some(user.uuid),
+            ReportedContent::Message(message) => Some(message.sender),
+            ReportedContent::Missing => None
+        }
+    }
+
+    /// The actions that can be taken on the target.
+    pub fn actions(&self) -> Vec<ReportAction> {
+        match self {
+            ReportedContent::Thread(_) | ReportedContent::Post(_) => vec![ReportAction::Dismiss, ReportAction::Delete, ReportAction::Lock, ReportAction::Ban],
+            ReportedContent::Message(_) => vec![ReportAction::Dismiss, ReportAction::Delete, ReportAction::Ban],
+            ReportedContent::User(_) => vec![ReportAction::Dismiss, ReportAction::Ban],
+            ReportedContent::Missing => vec![ReportAction::Dismiss]
+        }
+    }
+}
+
+/// Delete attachments along with their files.
+async fn delete_attachments(db: &mut Database, attachments: Vec<Attachment>) -> Result<(), Error> {
+    let storage = storage::from_settings(&SettingsManager::get_settings().attachment_settings);
+    for attachment in attachments {
+        Attachments::delete(db, attachment.uuid).await?;
+        attachments::delete_files(storage.as_ref(), &attachment).await;
+    }
+    Ok(())
+}
+
+/// Delete a thread with its replies and every file attached to them.
+pub async fn delete_thread(db: &mut Database, thread: &Thread) -> Result<(), Error> {
+    let attachments = Attachments::get_by_thread(db, thread.uuid).await?;
+    delete_attachments(db, attachments).await?;
+    Posts::delete_by_thread(db, thread.uuid).await?;
+    Threads::delete(db, thread.uuid).await?;
+    Ok(())
+}
+
+/// Delete a post and the files attached to it.
+pub async fn delete_post(db: &mut Database, post: &Post) -> Result<(), Error> {
+    let attachments = Attachments::get_by_thread(db, post.thread_uuid).await?.into_iter()
+        .filter(|attachment| attachment.post_uuid == Some(post.uuid))
+        .collect();
+    delete_attachments(db, attachments).await?;
+    Posts::delete(db, post.uuid).await?;
+    Ok(())
+}
+
+/**
+   Ban a user. Admins cannot be banned, and only admins can ban moderators.
+
+   ## Params
+   ip - Where the moderator took the action from, for the audit log.
+*/
+pub async fn ban(db: &mut Database, moderator: &User, ip: &str, user: &User) -> Result<(), ActionError> {
+    if user.uuid == moderator.uuid || user.is_admin || (user.is_moderator && !moderator.is_admin) {
+        return Err(ActionError::Protected);
+    }
+    if user.is_banned {
+        return Ok(());
+    }
+
+    Users::set_banned(db, user.uuid, true).await?;
+    audit::record(db, moderator.uuid, ip, AuditAction::UserBan, user.uuid.to_string().as_str(),
+                  json!({"username": user.username, "is_banned": false}), json!({"is_banned": true})).await?;
+    let mut user = user.clone();
+    user.is_banned = true;
+    webhooks::trigger(db, WebhookEvent::UserBanned, webhooks::user_data(&user)).await;
+    Ok(())
+}
+
+/**
+   Take an action on a reported target and close its open reports. The action is recorded in the audit log.
+
+   The reports are closed even if the target was already deleted, so stale reports can be cleared.
+
+   ## Params
+   ip - Where the moderator took the action from, for the audit log.
+*/
+pub async fn resolve(db: &mut Database, moderator: &User, ip: &str, target_type: ReportTarget, target_uuid: Uuid, action: ReportAction) -> Result<(), ActionError> {
+    let content = ReportedContent::load(db, target_type, target_uuid).await?;
+    if !content.actions().contains(&action) {
+        return Err(ActionError::NotAllowed);
+    }
+
+    // Deleted content is kept in the audit log so the deletion can be reviewed.
+    let entry = match (action, &content) {
+        (ReportAction::Delete, ReportedContent::Thread(thread)) => {
+            delete_thread(db, thread).await?;
+            Some((AuditAction::ThreadDelete, thread.uuid, json!(thread), Value::Null))
+        }
+        (ReportAction::Delete, ReportedContent::Post(post)) => {
+            delete_post(db, post).await?;
+            Some((AuditAction::PostDelete, post.uuid, json!(post), Value::Null))
+        }
+        (ReportAction::Delete, ReportedContent::Message(message)) => {
+            Messages::delete(db, message.uuid).await?;
+            Some((AuditAction::MessageDelete, message.uuid, json!(message), Value::Null))
+        }
+        (ReportAction::Lock, ReportedContent::Thread(thread)) => {
+            Threads::set_locked(db, thread.uuid, true).await?;
+            Some((AuditAction::ThreadLock, thread.uuid, json!({"locked": thread.locked}), json!({"locked": true})))
+        }
+        (ReportAction::Lock, ReportedContent::Post(post)) => {
+            Threads::set_locked(db, post.thread_uuid, true).await?;
+            Some((AuditAction::ThreadLock, post.thread_uuid, Value::Null, json!({"locked": true})))
+        }
+        (ReportAction::Ban, content) => {
+            let author = match content.author() {
+                Some(author) => Users::get_by_uuid(db, author).await?,
+                None => None
+            };
+            // The author may have deleted their account.
+            let author = author.ok_or(ActionError::NotAllowed)?;
+            // The ban is recorded by ban.
+            ban(db, moderator, ip, &author).await?;
+            None
+        }
+        _ => Some((AuditAction::ReportDismiss, target_uuid, json!({"type": target_type}), Value::Null))
+    };
+    if let Some((audit_action, target, old_value, new_value)) = entry {
+        audit::record(db, moderator.uuid, ip, audit_action, target.to_string().as_str(), old_value, new_value).await?;
+    }
+
+    Reports::resolve(db, target_type, target_uuid, action.as_str(), moderator.uuid).await?;
+    Ok(())
+}
+
+/// Lock or unlock a thread.
+pub async fn set_locked(db: &mut Database, moderator: &User, ip: &str, thread: &Thread, locked: bool) -> Result<(), Error> {
+    Threads::set_locked(db, thread.uuid, locked).await?;
+    let action = if locked { AuditAction::ThreadLock } else { AuditAction::ThreadUnlock };
+    audit::record(db, moderator.uuid, ip, action, thread.uuid.to_string().as_str(),
+                  json!({"locked": thread.locked}), json!({"locked": locked})).await
+}
+
+/// Pin or unpin a thread.
+pub async fn set_pinned(db: &mut Database, moderator: &User, ip: &str, thread: &Thread, pinned: bool) -> Result<(), Error> {
+    Threads::set_pinned(db, thread.uuid, pinned).await?;
+    let action = if pinned { AuditAction::ThreadPin } else { AuditAction::ThreadUnpin };
+    audit::record(db, moderator.uuid, ip, action, thread.uuid.to_string().as_str(),
+                  json!({"pinned": thread.pinned}), json!({"pinned": pinned})).await
+}
+
+/// Move a thread to another forum.
+pub async fn move_thread(db: &mut Database, moderator: &User, ip: &str, thread: &Thread, forum: &Forum) -> Result<(), Error> {
+    Threads::set_forum(db, thread.uuid, forum.uuid).await?;
+    audit::record(db, moderator.uuid, ip, AuditAction::ThreadMove, thread.uuid.to_string().as_str(),
+                  json!({"forum_uuid": thread.forum_uuid}), json!({"forum_uuid": forum.uuid})).await
+}
+
+/**
+   Merge a thread into another thread.
+
+   The opening post of the merged thread becomes a reply, and it is added to the end of the other thread with every
+   reply of the merged thread, in order. Posts keep their uuid, author and date, and attachments follow their posts.
+*/
+pub async fn merge_threads(db: &mut Database, moderator: &User, ip: &str, source: &Thread, target: &Thread) -> Result<(), ActionError> {
+    if source.uuid == target.uuid {
+        return Err(ActionError::NotAllowed);
+    }
+
+    // The replies are deleted and inserted again, so a failure part way through must not lose any of them.
+    db.begin().await?;
+    match merge_posts(db, moderator, ip, source, target).await {
+        Ok(()) => {
+            db.commit().await?;
+            Ok(())
+        }
+        Err(err) => {
+            db.rollback().await?;
+            Err(err.into())
+        }
+    }
+}
+
+/// The writes of [merge_threads], which are run in a single transaction.
+async fn merge_posts(db: &mut Database, moderator: &User, ip: &str, source: &Thread, target: &Thread) -> Result<(), Error> {
+    // The replies are inserted again after the opening post so the thread stays in order.
+    let opening_uuid = Uuid::new_v4();
+    Posts::insert_imported(db, opening_uuid, target.uuid, source.creator, source.content.clone(), source.date).await?;
+    for post in Posts::get_by_thread(db, source.uuid, None, i64::MAX).await? {
+        Posts::delete(db, post.uuid).await?;
+        Posts::insert_imported(db, post.uuid, target.uuid, post.creator, post.content, post.date).await?;
+    }
+    for attachment in Attachments::get_by_thread(db, source.uuid).await? {
+        Attachments::move_to(db, attachment.uuid, target.uuid, Some(attachment.post_uuid.unwrap_or(opening_uuid))).await?;
+    }
+    Threads::delete(db, source.uuid).await?;
+
+    audit::record(db, moderator.uuid, ip, AuditAction::ThreadMerge, source.uuid.to_string().as_str(),
+                  json!({"name": source.name, "forum_uuid": source.forum_uuid}), json!({"merged_into": target.uuid, "opening_post": opening_uuid})).await?;
+    Ok(())
+}
+
+/**
+   Split replies of a thread into a new thread in the same forum.
+
+   The oldest of the replies becomes the opening post of the new thread, keeping its author and date. The other
+   replies keep their uuid, and attachments follow their posts.
+
+   ## Returns
+   The uuid of the new thread.
+*/
+pub async fn split_thread(db: &mut Database, moderator: &User, ip: &str, thread: &Thread, mut posts: Vec<Post>, name: String) -> Result<Uuid, ActionError> {
+    if posts.is_empty() || posts.iter().any(|post| post.thread_uuid != thread.uuid) {
+        return Err(ActionError::NotAllowed);
+    }
+    posts.sort_by_key(|post| post.id);
+
+    db.begin().await?;
+    match split_posts(db, moderator, ip, thread, posts, name).await {
+        Ok(new_uuid) => {
+            db.commit().await?;
+            Ok(new_uuid)
+        }
+        Err(err) => {
+            db.rollback().await?;
+            Err(err.into())
+        }
+    }
+}
+
+/// The writes of [split_thread], which are run in a single transaction.
+async fn split_posts(db: &mut Database, moderator: &User, ip: &str, thread: &Thread, mut posts: Vec<Post>, name: String) -> Result<Uuid, Error> {
+    let opening = posts.remove(0);
+    let new_uuid = Uuid::new_v4();
+    Threads::insert_imported(db, new_uuid, thread.forum_uuid, name.clone(), opening.content.clone(), opening.creator, false, opening.date).await?;
+    for attachment in Attachments::get_by_thread(db, thread.uuid).await? {
+        match attachment.post_uuid {
+            Some(post_uuid) if post_uuid == opening.uuid => { Attachments::move_to(db, attachment.uuid, new_uuid, None).await?; }
+            Some(post_uuid) if posts.iter().any(|post| post.uuid == post_uuid) => { Attachments::move_to(db, attachment.uuid, new_uuid, Some(post_uuid)).await?; }
+            _ => {}
+        }
+    }
+    Posts::delete(db, opening.uuid).await?;
+    for post in &posts {
+        Posts::set_thread(db, post.uuid, new_uuid).await?;
+    }
+
+    let moved: Vec<Uuid> = posts.iter().map(|post| post.uuid).collect();
+    audit::record(db, moderator.uuid, ip, AuditAction::ThreadSplit, thread.uuid.to_string().as_str(),
+                  json!({"opening_post": opening.uuid, "posts": moved}), json!({"thread": new_uuid, "name": name})).await?;
+    Ok(new_uuid)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::schema::dbsetup::setup_database;
+    use crate::schema::tables::AuditLog;
+    use crate::settings::SqlSettings;
+
+    use super::*;
+
+    async fn open_test_database() -> Database {
+        let mut db = Database::new_sqlite(&SqlSettings { file_location: ":memory:".to_string() }).await.unwrap();
+        setup_database(&mut db).await.unwrap();
+        db
+    }
+
+    fn moderator() -> User {
+        User {
+            id: 1,
+            uuid: Uuid::new_v4(),
+            username: "moderator".to_string(),
+            email: "moderator@example.com".to_string(),
+            password: String::new(),
+            is_banned: false,
+            is_admin: false,
+            is_moderator: true,
+            password_reset: false,
+            date: 0,
+        }
+    }
+
+    /// Insert a thread with replies made at the dates, and return the thread and its replies in order.
+    async fn thread_with_replies(db: &mut Database, forum_uuid: Uuid, name: &str, date: i64, reply_dates: &[i64]) -> (Thread, Vec<Post>) {
+        let uuid = Uuid::new_v4();
+        Threads::insert_imported(db, uuid, forum_uuid, name.to_string(), format!("{} opening", name), Uuid::new_v4(), false, date).await.unwrap();
+        for (i, reply_date) in reply_dates.iter().enumerate() {
+            Posts::insert_imported(db, Uuid::new_v4(), uuid, Uuid::new_v4(), format!("{} reply {}", name, i), *reply_date).await.unwrap();
+        }
+        let thread = Threads::get_by_uuid(db, uuid).await.unwrap().unwrap();
+        let replies = Posts::get_by_thread(db, uuid, None, i64::MAX).await.unwrap();
+        (thread, replies)
+    }
+
+    /// Insert an attachment of a thread or post, and return its uuid.
+    async fn attach(db: &mut Database, thread_uuid: Uuid, post_uuid: Option<Uuid>) -> Uuid {
+        let uuid = Uuid::new_v4();
+        Attachments::insert(db, &Attachment {
+            id: 0,
+            uuid,
+            thread_uuid,
+            post_uuid,
+            uploader: Uuid::new_v4(),
+            file_name: "file.txt".to_string(),
+            content_type: "text/plain".to_string(),
+            size: 4,
+            storage_key: uuid.to_string(),
+            thumbnail_key: None,
+            downloads: 0,
+            created: 0,
+        }).await.unwrap();
+        uuid
+    }
+
+    #[actix_rt::test]
+    async fn merge_appends_the_thread_as_replies() {
+        let mut db = open_test_database().await;
+        let forum_uuid = Uuid::new_v4();
+        let (target, target_replies) = thread_with_replies(&mut db, forum_uuid, "Target", 10, &[20]).await;
+        let (source, source_replies) = thread_with_replies(&mut db, forum_uuid, "Source", 30, &[40, 50]).await;
+        let thread_file = attach(&mut db, source.uuid, None).await;
+        let reply_file = attach(&mut db, source.uuid, Some(source_replies[0].uuid)).await;
+
+        merge_threads(&mut db, &moderator(), "127.0.0.1", &source, &target).await.unwrap();
+
+        assert!(Threads::get_by_uuid(&mut db, source.uuid).await.unwrap().is_none());
+        let posts = Posts::get_by_thread(&mut db, target.uuid, None, i64::MAX).await.unwrap();
+        assert_eq!(posts.len(), 4);
+        assert_eq!(posts[0].uuid, target_replies[0].uuid);
+        // The opening post of the source comes first, keeping its author and date.
+        let opening = &posts[1];
+        assert_eq!((opening.content.as_str(), opening.creator, opening.date), ("Source opening", source.creator, 30));
+        let moved: Vec<(Uuid, Uuid, i64)> = posts[2..].iter().map(|post| (post.uuid, post.creator, post.date)).collect();
+        assert_eq!(moved, source_replies.iter().map(|post| (post.uuid, post.creator, post.date)).collect::<Vec<_>>());
+
+        let thread_file = Attachments::get_by_uuid(&mut db, thread_file).await.unwrap().unwrap();
+        assert_eq!((thread_file.thread_uuid, thread_file.post_uuid), (target.uuid, Some(opening.uuid)));
+        let reply_file = Attachments::get_by_uuid(&mut db, reply_file).await.unwrap().unwrap();
+        assert_eq!((reply_file.thread_uuid, reply_file.post_uuid), (target.uuid, Some(source_replies[0].uuid)));
+
+        let entries = AuditLog::search(&mut db, None, Some(AuditAction::ThreadMerge.as_str()), None, None, 10).await.unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].target, source.uuid.to_string());
+
+        db.close().await;
+    }
+
+    #[actix_rt::test]
+    async fn threads_cannot_be_merged_into_themselves() {
+        let mut db = open_test_database().await;
+        let (thread, _) = thread_with_replies(&mut db, Uuid::new_v4(), "Thread", 10, &[20]).await;
+
+        assert!(matches!(merge_threads(&mut db, &moderator(), "127.0.0.1", &thread, &thread).await, Err(ActionError::NotAllowed)));
+        assert_eq!(Posts::get_by_thread(&mut db, thread.uuid, None, i64::MAX).await.unwrap().len(), 1);
+
+        db.close().await;
+    }
+
+    #[actix_rt::test]
+    async fn split_moves_replies_to_a_new_thread() {
+        let mut db = open_test_database().await;
+        let (thread, replies) = thread_with_replies(&mut db, Uuid::new_v4(), "Thread", 10, &[20, 30, 40]).await;
+        let kept_file = attach(&mut db, thread.uuid, Some(replies[0].uuid)).await;
+        let opening_file = attach(&mut db, thread.uuid, Some(replies[1].uuid)).await;
+        let reply_file = attach(&mut db, thread.uuid, Some(replies[2].uuid)).await;
+
+        // The posts are sorted, so the oldest one opens the new thread whatever the order they are passed in.
+        let split = vec![replies[2].clone(), replies[1].clone()];
+        let new_uuid = split_thread(&mut db, &moderator(), "127.0.0.1", &thread, split, "Split".to_string()).await.unwrap();
+
+        let new_thread = Threads::get_by_uuid(&mut db, new_uuid).await.unwrap().unwrap();
+        assert_eq!(new_thread.name, "Split");
+        assert_eq!(new_thread.forum_uuid, thread.forum_uuid);
+        assert_eq!((new_thread.content.as_str(), new_thread.creator, new_thread.date), ("Thread reply 1", replies[1].creator, 30));
+        assert!(Posts::get_by_uuid(&mut db, replies[1].uuid).await.unwrap().is_none());
+
+        let new_posts: Vec<Uuid> = Posts::get_by_thread(&mut db, new_uuid, None, i64::MAX).await.unwrap().iter().map(|post| post.uuid).collect();
+        assert_eq!(new_posts, vec![replies[2].uuid]);
+        let old_posts: Vec<Uuid> = Posts::get_by_thread(&mut db, thread.uuid, None, i64::MAX).await.unwrap().iter().map(|post| post.uuid).collect();
+        assert_eq!(old_posts, vec![replies[0].uuid]);
+
+        let kept_file = Attachments::get_by_uuid(&mut db, kept_file).await.unwrap().unwrap();
+        assert_eq!((kept_file.thread_uuid, kept_file.post_uuid), (thread.uuid, Some(replies[0].uuid)));
+        let opening_file = Attachments::get_by_uuid(&mut db, opening_file).await.unwrap().unwrap();
+        assert_eq!((opening_file.thread_uuid, opening_file.post_uuid), (new_uuid, None));
+        let reply_file = Attachments::get_by_uuid(&mut db, reply_file).await.unwrap().unwrap();
+        assert_eq!((reply_file.thread_uuid, reply_file.post_uuid), (new_uuid, Some(replies[2].uuid)));
+
+        db.close().await;
+    }
+
+    #[actix_rt::test]
+    async fn split_needs_posts_of_the_thread() {
+        let mut db = open_test_database().await;
+        let forum_uuid = Uuid::new_v4();
+        let (thread, _) = thread_with_replies(&mut db, forum_uuid, "Thread", 10, &[20]).await;
+        let (_, other_replies) = thread_with_replies(&mut db, forum_uuid, "Other", 10, &[20]).await;
+
+        assert!(matches!(split_thread(&mut db, &moderator(), "127.0.0.1", &thread, vec![], "Split".to_string()).await,
+                         Err(ActionError::NotAllowed)));
+        assert!(matches!(split_thread(&mut db, &moderator(), "127.0.0.1", &thread, other_replies.clone(), "Split".to_string()).await,
+                         Err(ActionError::NotAllowed)));
+        assert_eq!(Posts::get_by_uuid(&mut db, other_replies[0].uuid).await.unwrap().unwrap().thread_uuid, other_replies[0].thread_uuid);
+
+        db.close().await;
+    }
+}
@@ -0,0 +1,170 @@
+use image::imageops::FilterType;
+use image::{ImageFormat, ImageOutputFormat};
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+use crate::attachments;
+use crate::schema::tables::{Avatar, Profile, User};
+use crate::settings::ProfileSettings;
+
+/// The most characters a location can have.
+pub const MAX_LOCATION_LENGTH: usize = 50;
+/// The most characters the url of a website can have.
+pub const MAX_WEBSITE_LENGTH: usize = 200;
+
+/// Get the key of the uploaded avatar of a user in the attachment storage.
+pub fn avatar_key(user_uuid: Uuid) -> String {
+    format!("avatars/{}.png", user_uuid.to_simple())
+}
+
+/**
+   Get the url of the avatar of a user.
+
+   Uploaded avatars include the time the profile was changed, so browsers do not show a cached old avatar.
+
+   ## Returns
+   The url, or None if the user has no avatar.
+*/
+pub fn avatar_url(user: &User, profile: &Profile, settings: &ProfileSettings) -> Option<String> {
+    match profile.avatar {
+        Avatar::Uploaded => Some(format!("/avatars/{}?v={}", user.uuid, profile.updated)),
+        Avatar::Gravatar if settings.gravatar => {
+            let hash = hex::encode(Sha256::digest(user.email.trim().to_lowercase().as_bytes()));
+            Some(format!("https://www.gravatar.com/avatar/{}?s={}&d=identicon", hash, settings.avatar_size))
+        }
+        _ => None
+    }
+}
+
+/**
+   Clean text written by a user, such as a bio or signature.
+
+   Line endings are normalized and control characters are removed. The text is escaped when it is shown, so
+   HTML does not need to be removed.
+
+   ## Params
+   max_lines - The most lines the text can have. None for no limit.
+*/
+pub fn clean_text(text: &str, name: &str, max_length: usize, max_lines: Option<usize>) -> Result<String, String> {
+    let text: String = text.replace("\r\n", "\n").chars()
+        .filter(|c| *c == '\n' || !c.is_control())
+        .collect();
+    let text = text.trim();
+
+    if text.chars().count() > max_length {
+        return Err(format!("The {} cannot be longer than {} characters.", name, max_length));
+    }
+    if let Some(max_lines) = max_lines {
+        if text.lines().count() > max_lines {
+            return Err(format!("The {} cannot have more than {} lines.", name, max_lines));
+        }
+    }
+    Ok(text.to_string())
+}
+
+/// Check if a url starts with a scheme without slashes, such as `javascript:` or `data:`. A host with a port
+/// (ex: example.com:8080) is not a scheme.
+fn has_scheme(url: &str) -> bool {
+    match url.split_once(':') {
+        Some((scheme, rest)) => {
+            scheme.chars().next().map(|c| c.is_ascii_alphabetic()).unwrap_or(false)
+                && scheme.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'))
+                && !rest.starts_with(|c: char| c.is_ascii_digit())
+        }
+        None => false
+    }
+}
+
+/**
+   Clean the url of a website. Only http and https urls are allowed, so a link cannot run a script.
+   Urls without a scheme are given https.
+
+   ## Returns
+   The url, or an empty string if there is no website.
+*/
+pub fn clean_website(website: &str) -> Result<String, String> {
+    let website = website.trim();
+    if website.is_empty() {
+        return Ok(String::new());
+    }
+
+    let lowercase = website.to_lowercase();
+    let website = if lowercase.starts_with("http://") || lowercase.starts_with("https://") {
+        website.to_string()
+    } else if website.contains("://") || has_scheme(lowercase.as_str()) {
+        return Err(String::from("The website must be an http or https url."));
+    } else {
+        format!("https://{}", website)
+    };
+
+    if website.chars().count() > MAX_WEBSITE_LENGTH {
+        return Err(format!("The website cannot be longer than {} characters.", MAX_WEBSITE_LENGTH));
+    }
+    if website.chars().any(|c| c.is_whitespace() || c.is_control() || matches!(c, '"' | '\'' | '<' | '>' | '`')) {
+        return Err(String::from("The website is not a valid url."));
+    }
+    Ok(website)
+}
+
+/**
+   Turn an uploaded image into an avatar.
+
+   The image is cropped to a square and resized to the avatar size. It is stored as a PNG, which removes the
+   metadata of the original image. Animated images keep only their first frame.
+*/
+pub fn process_avatar(data: &[u8], settings: &ProfileSettings) -> Result<Vec<u8>, String> {
+    if data.is_empty() {
+        return Err(String::from("The image is empty."));
+    }
+    if data.len() > settings.max_avatar_size {
+        return Err(format!("The avatar cannot be larger than {} bytes.", settings.max_avatar_size));
+    }
+
+    let format = image::guess_format(data).ok()
+        .filter(|format| matches!(format, ImageFormat::Png | ImageFormat::Jpeg | ImageFormat::Gif | ImageFormat::WebP))
+        .ok_or_else(|| String::from("The avatar must be a PNG, JPEG, GIF or WebP image."))?;
+    let image = attachments::decode_image(data, format)?;
+
+    let avatar = image.resize_to_fill(settings.avatar_size, settings.avatar_size, FilterType::Lanczos3);
+    attachments::encode(&avatar, ImageOutputFormat::Png)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn websites_must_be_http() {
+        assert_eq!(clean_website(" https://example.com/me "), Ok("https://example.com/me".to_string()));
+        assert_eq!(clean_website("HTTP://example.com"), Ok("HTTP://example.com".to_string()));
+        assert_eq!(clean_website("example.com"), Ok("https://example.com".to_string()));
+        assert_eq!(clean_website("example.com:8080/me"), Ok("https://example.com:8080/me".to_string()));
+        assert_eq!(clean_website(""), Ok(String::new()));
+
+        for website in &["javascript:alert(1)", "JavaScript:alert(1)", " javascript://%0aalert(1)", "data:text/html,<b>", "ftp://example.com"] {
+            assert!(clean_website(website).is_err(), "{} was allowed", website);
+        }
+    }
+
+    #[test]
+    fn websites_cannot_break_out_of_the_link() {
+        assert!(clean_website("https://example.com/\"onmouseover=\"alert(1)").is_err());
+        assert!(clean_website("https://example.com/<script>").is_err());
+        assert!(clean_website("https://example.com/a b").is_err());
+        assert!(clean_website(format!("https://{}", "a".repeat(MAX_WEBSITE_LENGTH)).as_str()).is_err());
+    }
+
+    #[test]
+    fn text_is_normalized() {
+        assert_eq!(clean_text("  Hello\r\nworld\u{0007}  ", "bio", 100, None), Ok("Hello\nworld".to_string()));
+        assert_eq!(clean_text("<b>bold</b>", "bio", 100, None), Ok("<b>bold</b>".to_string()));
+    }
+
+    #[test]
+    fn text_is_limited() {
+        assert_eq!(clean_text("äöü", "bio", 3, None), Ok("äöü".to_string()));
+        assert_eq!(clean_text("äöüß", "bio", 3, None), Err("The bio cannot be longer than 3 characters.".to_string()));
+        assert_eq!(clean_text("a\nb\nc", "signature", 100, Some(2)), Err("The signature cannot have more than 2 lines.".to_string()));
+        assert_eq!(clean_text("a\r\nb", "signature", 100, Some(2)), Ok("a\nb".to_string()));
+    }
+}
@@ -0,0 +1,208 @@
+use std::cell::RefCell;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use actix_web::{Error, HttpResponse};
+use actix_web::dev::{Service, ServiceRequest, ServiceResponse, Transform};
+use futures::future::{ok, Ready};
+use futures::Future;
+use handlebars::Handlebars;
+use serde_json::json;
+use uuid::Uuid;
+
+use crate::auth::api_token;
+use crate::auth::login_protection;
+use crate::auth::session;
+use crate::rate_limit::store::{DatabaseStore, MemoryStore, RateLimitStore};
+use crate::schema::database::Database;
+use crate::schema::tables::{ApiTokens, Sessions};
+use crate::settings::{BaseSettings, LoginProtectionSettings, RateLimitKey, RateLimitRule, RateLimitStoreType, SettingsManager};
+
+pub mod store;
+
+/// The shared state of the rate limiter.
+/// The rules are read from the live settings on every request, so they can be reloaded without a restart.
+struct RateLimiterInner {
+    store: Box<dyn RateLimitStore>,
+    hbs: Handlebars<'static>,
+}
+
+/**
+   Middleware that applies the token bucket rate limits from the rate limit settings.
+
+   When a bucket is empty, a 429 response with a Retry-After header is returned instead of calling the route.
+   Routes under `/api/` get a JSON error, everything else gets the themed error page.
+*/
+#[derive(Clone)]
+pub struct RateLimiter {
+    inner: Arc<RateLimiterInner>,
+}
+
+impl RateLimiter {
+    /// Construct the rate limiter from the base settings.
+    /// The same instance should be cloned into every worker so the in memory buckets are shared.
+    pub fn new(settings: &BaseSettings, hbs: Handlebars<'static>) -> RateLimiter {
+        let store: Box<dyn RateLimitStore> = match settings.rate_limit_settings.store {
+            RateLimitStoreType::Memory => Box::new(MemoryStore::new()),
+            RateLimitStoreType::Database => Box::new(DatabaseStore::new())
+        };
+
+        RateLimiter {
+            inner: Arc::new(RateLimiterInner {
+                store,
+                hbs,
+            })
+        }
+    }
+}
+
+impl RateLimiterInner {
+    /// Check if a rule applies to a request.
+    fn matches(rule: &RateLimitRule, req: &ServiceRequest) -> bool {
+        let method_matches = rule.methods.is_empty()
+            || rule.methods.iter().any(|method| method.eq_ignore_ascii_case(req.method().as_str()));
+        method_matches && rule.paths.iter().any(|path| req.path().starts_with(path.as_str()))
+    }
+
+    /**
+       Get the user a request is authenticated as, from its API token or session cookie.
+
+       Only credentials that belong to a user count, so sending a made up token or cookie
+       does not give the client a fresh bucket.
+    */
+    async fn user_uuid(req: &ServiceRequest, settings: &BaseSettings) -> Option<Uuid> {
+        let token = api_token::bearer_token(req.request());
+        let session_id = session::session_id(req.request());
+        if token.is_none() && session_id.is_none() {
+            return None;
+        }
+
+        let mut db = Database::connect(settings).await.ok()?;
+        let user_uuid = match (token, session_id) {
+            (Some(token), _) => ApiTokens::get_by_hash(&mut db, api_token::hash_token(token.as_str()).as_str()).await
+                .ok().flatten().map(|token| token.user_uuid),
+            (None, Some(session_id)) => Sessions::get_user_uuid(&mut db, session_id).await.ok().flatten(),
+            (None, None) => None
+        };
+        db.close().await;
+        user_uuid
+    }
+
+    /// Get who a request should be limited as. Requests that are not authenticated as a user are limited by IP.
+    fn identity(rule: &RateLimitRule, user_uuid: Option<Uuid>, req: &ServiceRequest, protection_settings: &LoginProtectionSettings) -> String {
+        match user_uuid {
+            Some(user_uuid) if rule.key == RateLimitKey::User => format!("user:{}", user_uuid),
+            _ => format!("ip:{}", login_protection::client_ip(req.request(), protection_settings))
+        }
+    }
+
+    /// Take a token from every group the request belongs to.
+    ///
+    /// # Returns
+    /// The 429 response if any of the buckets are empty.
+    async fn check(&self, req: &ServiceRequest) -> Option<HttpResponse> {
+        let settings = SettingsManager::current();
+        if !settings.rate_limit_settings.enabled {
+            return None;
+        }
+
+        // The user is looked up once, and only if a group keyed by user applies.
+        let mut user_uuid: Option<Option<Uuid>> = None;
+        for (name, rule) in &settings.rate_limit_settings.groups {
+            if !RateLimiterInner::matches(rule, req) {
+                continue;
+            }
+
+            if rule.key == RateLimitKey::User && user_uuid.is_none() {
+                user_uuid = Some(RateLimiterInner::user_uuid(req, &settings).await);
+            }
+            let identity = RateLimiterInner::identity(rule, user_uuid.flatten(), req, &settings.login_protection_settings);
+            let bucket_key = format!("{}:{}", name, identity);
+            if let Err(wait) = self.store.take(bucket_key.as_str(), rule).await {
+                return Some(self.limited_response(req, wait.as_secs().max(1)));
+            }
+        }
+
+        None
+    }
+
+    /// Build the response for a request that has been rate limited.
+    fn limited_response(&self, req: &ServiceRequest, retry_after: u64) -> HttpResponse {
+        let mut builder = HttpResponse::TooManyRequests();
+        builder.header("Retry-After", retry_after.to_string());
+
+        if req.path().starts_with("/api/") {
+            return builder.json(json!({
+                "error": {
+                    "code": "rate_limited",
+                    "message": format!("Too many requests. Try again in {} seconds.", retry_after)
+                }
+            }));
+        }
+
+        let result: String = self.hbs.render("forum/ratelimited", &json!({
+            "name": SettingsManager::current().name,
+            "retry_after": retry_after
+        })).unwrap();
+        builder.content_type("text/html; charset=utf-8").body(result)
+    }
+}
+
+impl<S, B> Transform<S> for RateLimiter
+    where
+        S: Service<Request=ServiceRequest, Response=ServiceResponse<B>, Error=Error> + 'static,
+        S::Future: 'static,
+        B: 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = RateLimiterMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(RateLimiterMiddleware {
+            service: Rc::new(RefCell::new(service)),
+            inner: self.inner.clone(),
+        })
+    }
+}
+
+/// The service created by the [RateLimiter] middleware.
+pub struct RateLimiterMiddleware<S> {
+    service: Rc<RefCell<S>>,
+    inner: Arc<RateLimiterInner>,
+}
+
+impl<S, B> Service for RateLimiterMiddleware<S>
+    where
+        S: Service<Request=ServiceRequest, Response=ServiceResponse<B>, Error=Error> + 'static,
+        S::Future: 'static,
+        B: 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output=Result<Self::Response, Self::Error>>>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.borrow_mut().poll_ready(cx)
+    }
+
+    fn call(&mut self, req: ServiceRequest) -> Self::Future {
+        let service = self.service.clone();
+        let inner = self.inner.clone();
+
+        Box::pin(async move {
+            if let Some(resp) = inner.check(&req).await {
+                return Ok(req.into_response(resp.into_body()));
+            }
+
+            let fut = service.borrow_mut().call(req);
+            fut.await
+        })
+    }
+}
@@ -0,0 +1,291 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use async_trait::async_trait;
+use sqlx::Error;
+
+use crate::schema::database::Database;
+use crate::schema::tables::RateLimitBuckets;
+use crate::settings::{RateLimitRule, SettingsManager};
+
+/// The amount of buckets kept in memory before full buckets are cleared out.
+const MAX_BUCKETS_BEFORE_PURGE: usize = 10000;
+/// The amount of tokens taken from the database store between clearing out old buckets.
+const TAKES_BETWEEN_PURGE: u64 = 1000;
+/// How long a bucket in the database can go without being updated before it is cleared out.
+const DATABASE_BUCKET_MAX_AGE_MILLIS: i64 = 24 * 60 * 60 * 1000;
+/// The most idle connections the database store keeps open.
+const MAX_IDLE_CONNECTIONS: usize = 8;
+
+/// Storage for the token buckets used by the rate limiter.
+#[async_trait]
+pub trait RateLimitStore: Send + Sync {
+    /**
+       Take a token from a bucket.
+
+       ## Returns
+       An empty result if a token was taken, how long until a token is available if not.
+    */
+    async fn take(&self, bucket_key: &str, rule: &RateLimitRule) -> Result<(), Duration>;
+}
+
+/**
+   Refill a bucket and try to take a token from it.
+
+   ## Returns
+   The new amount of tokens in the bucket, and the wait until a token is available if none could be taken.
+*/
+fn take_token(tokens: f64, elapsed_seconds: f64, rule: &RateLimitRule) -> (f64, Option<Duration>) {
+    let tokens = (tokens + elapsed_seconds / rule.refill_seconds).min(rule.capacity as f64);
+    if tokens >= 1.0 {
+        (tokens - 1.0, None)
+    } else {
+        (tokens, Some(Duration::from_secs_f64((1.0 - tokens) * rule.refill_seconds)))
+    }
+}
+
+/// A bucket stored in memory.
+struct MemoryBucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+/// Stores the buckets in memory. This is the default store.
+pub struct MemoryStore {
+    buckets: Mutex<HashMap<String, MemoryBucket>>,
+}
+
+impl MemoryStore {
+    pub fn new() -> MemoryStore {
+        MemoryStore {
+            buckets: Mutex::new(HashMap::new())
+        }
+    }
+}
+
+#[async_trait]
+impl RateLimitStore for MemoryStore {
+    async fn take(&self, bucket_key: &str, rule: &RateLimitRule) -> Result<(), Duration> {
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().unwrap();
+
+        if buckets.len() > MAX_BUCKETS_BEFORE_PURGE {
+            // A bucket that has been idle long enough to refill completely is the same as a missing bucket.
+            let max_idle = Duration::from_secs_f64(rule.refill_seconds * rule.capacity as f64);
+            buckets.retain(|_, bucket| now.duration_since(bucket.updated) < max_idle);
+        }
+
+        let bucket = buckets.entry(bucket_key.to_string()).or_insert(MemoryBucket {
+            tokens: rule.capacity as f64,
+            updated: now,
+        });
+
+        let (tokens, wait) = take_token(bucket.tokens, now.duration_since(bucket.updated).as_secs_f64(), rule);
+        bucket.tokens = tokens;
+        bucket.updated = now;
+
+        match wait {
+            Some(wait) => Err(wait),
+            None => Ok(())
+        }
+    }
+}
+
+/// Stores the buckets in the rate_limit_buckets table so multiple instances of ForumRS share the same limits.
+pub struct DatabaseStore {
+    takes: AtomicU64,
+    /// Open connections that are not in use, so a request does not have to connect to the database first.
+    idle: Mutex<Vec<Database>>,
+}
+
+impl DatabaseStore {
+    pub fn new() -> DatabaseStore {
+        DatabaseStore {
+            takes: AtomicU64::new(0),
+            idle: Mutex::new(Vec::new())
+        }
+    }
+
+    /// Get an idle connection, or open a new one if every connection is in use.
+    async fn connection(&self) -> Result<Database, Error> {
+        let idle = self.idle.lock().unwrap().pop();
+        match idle {
+            Some(db) => Ok(db),
+            None => Database::connect(&SettingsManager::current()).await
+        }
+    }
+
+    /// Give a connection back once the request is done with it.
+    async fn release(&self, db: Database) {
+        let db = {
+            let mut idle = self.idle.lock().unwrap();
+            if idle.len() < MAX_IDLE_CONNECTIONS {
+                idle.push(db);
+                return;
+            }
+            db
+        };
+        db.close().await;
+    }
+
+    /// Take a token from a bucket, creating the bucket if this is its first request.
+    async fn take_from_database(&self, db: &mut Database, bucket_key: &str, rule: &RateLimitRule) -> Result<Result<(), Duration>, Error> {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as i64;
+        let capacity = rule.capacity as f64;
+        let refill_rate = 1.0 / (rule.refill_seconds * 1000.0);
+
+        if self.takes.fetch_add(1, Ordering::Relaxed) % TAKES_BETWEEN_PURGE == 0 {
+            RateLimitBuckets::delete_older_than(db, now - DATABASE_BUCKET_MAX_AGE_MILLIS).await.ok();
+        }
+
+        if RateLimitBuckets::take(db, bucket_key, capacity, refill_rate, now).await? {
+            return Ok(Ok(()));
+        }
+        // The insert fails if another request created the bucket in the meantime, which then has to be taken from.
+        if RateLimitBuckets::insert(db, bucket_key, capacity - 1.0, now).await?
+            || RateLimitBuckets::take(db, bucket_key, capacity, refill_rate, now).await? {
+            return Ok(Ok(()));
+        }
+
+        // The bucket is empty. Its state is only read to tell the client how long to wait.
+        let wait = match RateLimitBuckets::get(db, bucket_key).await? {
+            Some((tokens, updated)) => take_token(tokens, (now - updated).max(0) as f64 / 1000.0, rule).1
+                .unwrap_or_else(|| Duration::from_secs_f64(rule.refill_seconds)),
+            None => Duration::from_secs_f64(rule.refill_seconds)
+        };
+        Ok(Err(wait))
+    }
+}
+
+#[async_trait]
+impl RateLimitStore for DatabaseStore {
+    async fn take(&self, bucket_key: &str, rule: &RateLimitRule) -> Result<(), Duration> {
+        // If the database cannot be reached the request is let through, since the rest of the
+        // site will not work either.
+        let mut db = match self.connection().await {
+            Ok(db) => db,
+            Err(err) => {
+                println!("[WARN] Unable to connect to the database for rate limiting: {:?}", err);
+                return Ok(());
+            }
+        };
+
+        match self.take_from_database(&mut db, bucket_key, rule).await {
+            Ok(result) => {
+                self.release(db).await;
+                result
+            }
+            Err(err) => {
+                // The connection is dropped instead of reused, in case it is the reason the query failed.
+                println!("[WARN] Unable to update a rate limit bucket: {:?}", err);
+                Ok(())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::executor::block_on;
+
+    use crate::schema::tables::Table;
+    use crate::settings::{RateLimitKey, SqlSettings};
+
+    use super::*;
+
+    fn rule(capacity: u32, refill_seconds: f64) -> RateLimitRule {
+        RateLimitRule {
+            paths: vec!["/".to_string()],
+            methods: vec![],
+            capacity,
+            refill_seconds,
+            key: RateLimitKey::Ip,
+        }
+    }
+
+    #[test]
+    fn take_token_from_a_full_bucket() {
+        let (tokens, wait) = take_token(3.0, 0.0, &rule(3, 10.0));
+        assert_eq!(tokens, 2.0);
+        assert_eq!(wait, None);
+    }
+
+    #[test]
+    fn empty_bucket_waits_for_the_refill() {
+        let (tokens, wait) = take_token(0.25, 0.0, &rule(3, 10.0));
+        assert_eq!(tokens, 0.25);
+        assert_eq!(wait, Some(Duration::from_secs_f64(7.5)));
+    }
+
+    #[test]
+    fn refill_is_capped_at_the_capacity() {
+        let (tokens, wait) = take_token(0.0, 1000.0, &rule(3, 10.0));
+        assert_eq!(tokens, 2.0);
+        assert_eq!(wait, None);
+    }
+
+    #[test]
+    fn memory_store_limits_each_bucket() {
+        let store = MemoryStore::new();
+        let rule = rule(2, 60.0);
+        assert!(block_on(store.take("login:ip:1", &rule)).is_ok());
+        assert!(block_on(store.take("login:ip:1", &rule)).is_ok());
+        assert!(block_on(store.take("login:ip:1", &rule)).is_err());
+        assert!(block_on(store.take("login:ip:2", &rule)).is_ok());
+    }
+
+    async fn buckets_db() -> Database {
+        let mut db = Database::new_sqlite(&SqlSettings { file_location: ":memory:".to_string() }).await.unwrap();
+        RateLimitBuckets::create(&mut db).await.unwrap();
+        db
+    }
+
+    #[actix_rt::test]
+    async fn database_take_is_conditional() {
+        let mut db = buckets_db().await;
+        // One token every 10 seconds.
+        let rate = 1.0 / 10_000.0;
+        assert!(!RateLimitBuckets::take(&mut db, "api:ip:1", 2.0, rate, 0).await.unwrap());
+        assert!(RateLimitBuckets::insert(&mut db, "api:ip:1", 1.0, 0).await.unwrap());
+        assert!(!RateLimitBuckets::insert(&mut db, "api:ip:1", 1.0, 0).await.unwrap());
+
+        assert!(RateLimitBuckets::take(&mut db, "api:ip:1", 2.0, rate, 0).await.unwrap());
+        assert!(!RateLimitBuckets::take(&mut db, "api:ip:1", 2.0, rate, 5_000).await.unwrap());
+        assert_eq!(RateLimitBuckets::get(&mut db, "api:ip:1").await.unwrap(), Some((0.0, 0)));
+
+        assert!(RateLimitBuckets::take(&mut db, "api:ip:1", 2.0, rate, 10_000).await.unwrap());
+        assert_eq!(RateLimitBuckets::get(&mut db, "api:ip:1").await.unwrap(), Some((0.0, 10_000)));
+    }
+
+    #[actix_rt::test]
+    async fn database_refill_is_capped_and_never_goes_back_in_time() {
+        let mut db = buckets_db().await;
+        let rate = 1.0 / 10_000.0;
+        RateLimitBuckets::insert(&mut db, "api:ip:1", 0.0, 50_000).await.unwrap();
+
+        assert!(!RateLimitBuckets::take(&mut db, "api:ip:1", 2.0, rate, 10_000).await.unwrap());
+        assert!(RateLimitBuckets::take(&mut db, "api:ip:1", 2.0, rate, 1_000_000).await.unwrap());
+        assert_eq!(RateLimitBuckets::get(&mut db, "api:ip:1").await.unwrap(), Some((1.0, 1_000_000)));
+
+        assert!(RateLimitBuckets::take(&mut db, "api:ip:1", 2.0, rate, 900_000).await.unwrap());
+        assert_eq!(RateLimitBuckets::get(&mut db, "api:ip:1").await.unwrap(), Some((0.0, 1_000_000)));
+    }
+
+    #[actix_rt::test]
+    async fn database_store_limits_each_bucket() {
+        let store = DatabaseStore::new();
+        store.idle.lock().unwrap().push(buckets_db().await);
+        let rule = rule(2, 60.0);
+
+        assert!(store.take("login:ip:1", &rule).await.is_ok());
+        assert!(store.take("login:ip:1", &rule).await.is_ok());
+        let wait = store.take("login:ip:1", &rule).await.unwrap_err();
+        assert!(wait > Duration::from_secs(59) && wait <= Duration::from_secs(60));
+        assert!(store.take("login:ip:2", &rule).await.is_ok());
+
+        // The in-memory database only exists on its connection, so every take used the same one.
+        assert_eq!(store.idle.lock().unwrap().len(), 1);
+    }
+}
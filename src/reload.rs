@@ -0,0 +1,75 @@
+use std::fs;
+use std::time::{Duration, SystemTime};
+
+use actix_web::rt::time::delay_for;
+
+use crate::settings::SettingsManager;
+
+/// How often the settings file is checked for changes.
+const WATCH_INTERVAL_SECONDS: u64 = 2;
+
+/**
+   Reload the settings of the running forum and log the result.
+
+   ## Params
+   reason - What caused the reload, for the logs.
+
+   ## Returns
+   The settings that changed but only apply after a restart, or the reason the settings were not reloaded.
+*/
+pub fn reload_settings(reason: &str) -> Result<Vec<&'static str>, String> {
+    match SettingsManager::reload() {
+        Ok(restart_required) => {
+            println!("[INFO] The settings were reloaded ({}).", reason);
+            if !restart_required.is_empty() {
+                println!("[WARN] These settings changed but only apply after a restart: {}", restart_required.join(", "));
+            }
+            Ok(restart_required)
+        }
+        Err(err) => {
+            println!("[ERROR] The settings were not reloaded ({}), the current settings are still in use: {}", reason, err);
+            Err(err)
+        }
+    }
+}
+
+/// Get the time the settings file was last changed.
+fn modified() -> Option<SystemTime> {
+    fs::metadata(SettingsManager::settings_path()).and_then(|metadata| metadata.modified()).ok()
+}
+
+/// Reload the settings every time the settings file changes. This should be spawned once when the forum starts.
+pub async fn watch_settings() {
+    let mut last_modified = modified();
+    loop {
+        delay_for(Duration::from_secs(WATCH_INTERVAL_SECONDS)).await;
+
+        let current = modified();
+        if current.is_some() && current != last_modified {
+            last_modified = current;
+            let _ = reload_settings("the settings file changed");
+        }
+    }
+}
+
+/// Reload the settings every time the process receives SIGHUP. This should be spawned once when the forum starts.
+#[cfg(unix)]
+pub async fn listen_for_hangup() {
+    use actix_web::rt::signal::unix::{signal, SignalKind};
+
+    let mut hangup = match signal(SignalKind::hangup()) {
+        Ok(hangup) => hangup,
+        Err(err) => {
+            println!("[WARN] Unable to listen for SIGHUP, the settings can still be reloaded from the admin panel: {}", err);
+            return;
+        }
+    };
+
+    while hangup.recv().await.is_some() {
+        let _ = reload_settings("SIGHUP");
+    }
+}
+
+/// SIGHUP only exists on unix, the settings file watcher and the admin panel still reload the settings.
+#[cfg(not(unix))]
+pub async fn listen_for_hangup() {}
@@ -1,7 +1,7 @@
 use crate::settings::{DatabaseType, SettingsManager, SqlSettings, MysqlSettings, PostgreSQLSettings};
 use crate::schema::database::Database;
 use sqlx::{Connection, AnyConnection};
-use crate::schema::tables::{Users, Table, Sessions, Threads, Posts, Forums, BannedUsers};
+use crate::schema::tables::{Users, Table, Sessions, ApiTokens, OAuthClients, Threads, Posts, Forums, BannedUsers, TwoFactor, RecoveryCodes, LoginAttempts, RateLimitBuckets, Webhooks, WebhookDeliveries, ImportMap, Attachments, Profiles, Conversations, ConversationMembers, Messages, UserBlocks, Reports, AuditLog};
 use uuid::Uuid;
 
 pub async fn create_schema_mysql(mysql_settings: &MysqlSettings) {
@@ -21,14 +21,30 @@ pub async fn create_schema_postgre(postgre_settings: &PostgreSQLSettings) {
 
 /// Setup the database.
 /// Note: The actual database/schema must be already created.
-pub async fn setup_database(db: &mut Database) {
-    let conn: &mut AnyConnection = db.connection();
-
+pub async fn setup_database(db: &mut Database) -> Result<(), sqlx::Error> {
     // Create the users table.
-    Users::create(db).await.unwrap();
-    Sessions::create(db).await.unwrap();
-    Threads::create(db).await.unwrap();
-    Posts::create(db).await.unwrap();
-    Forums::create(db).await.unwrap();
-    BannedUsers::create(db).await.unwrap();
+    Users::create(db).await?;
+    Sessions::create(db).await?;
+    ApiTokens::create(db).await?;
+    OAuthClients::create(db).await?;
+    Threads::create(db).await?;
+    Posts::create(db).await?;
+    Forums::create(db).await?;
+    BannedUsers::create(db).await?;
+    TwoFactor::create(db).await?;
+    RecoveryCodes::create(db).await?;
+    LoginAttempts::create(db).await?;
+    RateLimitBuckets::create(db).await?;
+    Webhooks::create(db).await?;
+    WebhookDeliveries::create(db).await?;
+    ImportMap::create(db).await?;
+    Attachments::create(db).await?;
+    Profiles::create(db).await?;
+    Conversations::create(db).await?;
+    ConversationMembers::create(db).await?;
+    Messages::create(db).await?;
+    UserBlocks::create(db).await?;
+    Reports::create(db).await?;
+    AuditLog::create(db).await?;
+    Ok(())
 }
\ No newline at end of file
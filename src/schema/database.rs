@@ -1,5 +1,5 @@
-use sqlx::{Connection, MySqlConnection, Error, PgConnection, SqliteConnection, AnyConnection};
-use crate::settings::{DatabaseType, MysqlSettings, PostgreSQLSettings, SqlSettings};
+use sqlx::{Connection, Executor, MySqlConnection, Error, PgConnection, SqliteConnection, AnyConnection};
+use crate::settings::{BaseSettings, DatabaseType, MysqlSettings, PostgreSQLSettings, SqlSettings};
 
 /// Manages the database connection for the website.
 pub struct Database {
@@ -23,11 +23,23 @@ impl Database {
 
         Ok(Database {
             connection: connection.unwrap(),
-            conn_type: DatabaseType::MySQL,
+            conn_type: DatabaseType::SQLite,
             in_db: true
         })
     }
 
+    /// Construct a new database connection using the database type from the base settings.
+    ///
+    /// # Returns
+    /// This returns either the Database struct or an SQLX error if one occurs.
+    pub async fn connect(settings: &BaseSettings) -> Result<Self, Error> {
+        match settings.database_type {
+            DatabaseType::SQLite => Database::new_sqlite(settings.sql_settings.as_ref().unwrap()).await,
+            DatabaseType::MySQL => Database::new_mysql(settings.mysql_settings.as_ref().unwrap()).await,
+            DatabaseType::PostgreSQL => Database::new_postgre(settings.postgre_settings.as_ref().unwrap()).await
+        }
+    }
+
     /// Construct a new MySQL database connection from the provided settings.
     /// This will use the database_name setting to connect to the database directly.
     ///
@@ -113,6 +125,87 @@ impl Database {
         self.conn_type.clone()
     }
 
+    /// Format a query written with `?` placeholders for the type of this database.
+    /// PostgreSQL uses numbered placeholders (`$1`, `$2`, ...), so they are converted here.
+    pub fn format_query(&self, query: &str) -> String {
+        match self.conn_type {
+            DatabaseType::PostgreSQL => {
+                let mut formatted = String::with_capacity(query.len() + 8);
+                let mut index = 0;
+                for c in query.chars() {
+                    if c == '?' {
+                        index += 1;
+                        formatted.push_str(&format!("${}", index));
+                    } else {
+                        formatted.push(c);
+                    }
+                }
+                formatted
+            }
+            DatabaseType::MySQL | DatabaseType::SQLite => query.to_string()
+        }
+    }
+
+    /// Get the SQL expression that converts a timestamp column to unix time in seconds.
+    pub fn unix_time(&self, column: &str) -> String {
+        match self.conn_type {
+            DatabaseType::SQLite => format!("CAST(strftime('%s', {}) AS INTEGER)", column),
+            DatabaseType::MySQL => format!("CAST(UNIX_TIMESTAMP({}) AS SIGNED)", column),
+            DatabaseType::PostgreSQL => format!("CAST(EXTRACT(EPOCH FROM {}) AS BIGINT)", column)
+        }
+    }
+
+    /// Get the SQL expression that converts unix time in seconds to a timestamp column, the opposite of [Database::unix_time].
+    pub fn from_unix_time(&self, value: &str) -> String {
+        match self.conn_type {
+            DatabaseType::SQLite => format!("datetime({}, 'unixepoch')", value),
+            DatabaseType::MySQL => format!("FROM_UNIXTIME({})", value),
+            DatabaseType::PostgreSQL => format!("(TO_TIMESTAMP({}) AT TIME ZONE 'UTC')", value)
+        }
+    }
+
+    /// Get the SQL expression for the smaller of two values.
+    pub fn least(&self, a: &str, b: &str) -> String {
+        match self.conn_type {
+            DatabaseType::SQLite => format!("MIN({}, {})", a, b),
+            DatabaseType::MySQL | DatabaseType::PostgreSQL => format!("LEAST({}, {})", a, b)
+        }
+    }
+
+    /// Get the SQL expression for the larger of two values.
+    pub fn greatest(&self, a: &str, b: &str) -> String {
+        match self.conn_type {
+            DatabaseType::SQLite => format!("MAX({}, {})", a, b),
+            DatabaseType::MySQL | DatabaseType::PostgreSQL => format!("GREATEST({}, {})", a, b)
+        }
+    }
+
+    /// Get the SQL expression that reads a small integer column as an INTEGER.
+    /// PostgreSQL stores some flags as SMALLINT, which cannot be read as a 32 bit integer directly.
+    pub fn int_column(&self, column: &str) -> String {
+        match self.conn_type {
+            DatabaseType::PostgreSQL => format!("CAST({} AS INTEGER)", column),
+            DatabaseType::MySQL | DatabaseType::SQLite => column.to_string()
+        }
+    }
+
+    /// Start a transaction. Every query until [Database::commit] or [Database::rollback] is part of it.
+    ///
+    /// The statement is sent unprepared since MySQL cannot prepare it.
+    pub async fn begin(&mut self) -> Result<(), Error> {
+        self.connection.execute("BEGIN").await.map(|_| ())
+    }
+
+    /// Commit the transaction started by [Database::begin].
+    pub async fn commit(&mut self) -> Result<(), Error> {
+        self.connection.execute("COMMIT").await.map(|_| ())
+    }
+
+    /// Undo every query since [Database::begin].
+    pub async fn rollback(&mut self) -> Result<(), Error> {
+        self.connection.execute("ROLLBACK").await.map(|_| ())
+    }
+
     pub fn connection(&mut self) -> &mut AnyConnection {
         &mut self.connection
     }
@@ -0,0 +1,190 @@
+use sqlx::{Error, Row};
+
+use crate::schema::database::Database;
+use crate::schema::dbsetup::setup_database;
+use crate::schema::tables::{Attachments, AuditLog, ConversationMembers, Conversations, ImportMap, Messages, Profiles, Reports, SchemaVersion, Table, UserBlocks};
+use crate::schema::transfer::TABLES;
+use crate::settings::DatabaseType;
+
+/// The upgrades to the schema in the order they are applied. The version of the schema is the amount of upgrades applied.
+/// Every upgrade must be safe to apply to a database from before the schema was versioned.
+const UPGRADES: [&str; 10] = [
+    "Create the tables that are missing",
+    "Add the moderator flag to users",
+    "Add the password reset flag to users",
+    "Create the import map table",
+    "Create the attachments table",
+    "Create the profiles table",
+    "Create the private message tables",
+    "Create the reports table",
+    "Create the audit log table",
+    "Add the pinned flag to threads",
+];
+
+/// The version of the schema created by this version of ForumRS.
+pub const SCHEMA_VERSION: i32 = UPGRADES.len() as i32;
+
+/// The amount of rows in a table.
+#[derive(Debug, Clone, Serialize)]
+pub struct TableReport {
+    pub name: &'static str,
+    /// If the table exists. A missing table is created by the upgrades.
+    pub exists: bool,
+    /// The amount of rows, none if the table does not exist.
+    pub rows: Option<i64>,
+}
+
+/// What was found in an existing database before it is migrated.
+#[derive(Debug, Clone, Serialize)]
+pub struct MigrationReport {
+    /// The version of the schema, none if the database is from before the schema was versioned.
+    pub version: Option<i32>,
+    /// The version of the schema after the upgrades are applied.
+    pub latest_version: i32,
+    /// If the database can be used by this version of ForumRS. Databases from a newer version cannot.
+    pub supported: bool,
+    /// The upgrades that will be applied.
+    pub pending: Vec<&'static str>,
+    /// The amount of rows in each table.
+    pub tables: Vec<TableReport>,
+    /// The amount of admin accounts.
+    pub admins: i64,
+}
+
+/// Check if a column exists.
+async fn has_column(db: &mut Database, table: &str, column: &str) -> Result<bool, Error> {
+    match sqlx::query(&format!("SELECT {} FROM {} LIMIT 1", column, table)).fetch_optional(db.connection()).await {
+        Ok(_) => Ok(true),
+        Err(Error::Database(_)) => Ok(false),
+        Err(err) => Err(err)
+    }
+}
+
+/// Count the rows of a table. None if the table does not exist.
+async fn count_rows(db: &mut Database, table: &str) -> Result<Option<i64>, Error> {
+    match sqlx::query(&format!("SELECT COUNT(*) AS amount FROM {}", table)).fetch_one(db.connection()).await {
+        Ok(row) => Ok(Some(row.try_get("amount")?)),
+        Err(Error::Database(_)) => Ok(None),
+        Err(err) => Err(err)
+    }
+}
+
+/// Count the admin accounts. A database without a users table has none.
+async fn count_admins(db: &mut Database) -> Result<i64, Error> {
+    let query = db.format_query("SELECT COUNT(*) AS amount FROM users WHERE is_admin = ?");
+    match sqlx::query(&query).bind(true).fetch_one(db.connection()).await {
+        Ok(row) => row.try_get("amount"),
+        Err(Error::Database(_)) => Ok(0),
+        Err(err) => Err(err)
+    }
+}
+
+/// Apply a single upgrade to the schema.
+async fn apply(db: &mut Database, version: i32) -> Result<(), Error> {
+    match version {
+        1 => setup_database(db).await,
+        2 => {
+            if has_column(db, "users", "is_moderator").await? {
+                return Ok(());
+            }
+            let query = match db.get_type() {
+                DatabaseType::SQLite => "ALTER TABLE users ADD COLUMN is_moderator TINYINT NOT NULL DEFAULT 0",
+                DatabaseType::MySQL | DatabaseType::PostgreSQL => "ALTER TABLE users ADD COLUMN is_moderator BOOL NOT NULL DEFAULT FALSE"
+            };
+            sqlx::query(query).execute(db.connection()).await?;
+            Ok(())
+        }
+        3 => {
+            if has_column(db, "users", "password_reset").await? {
+                return Ok(());
+            }
+            let query = match db.get_type() {
+                DatabaseType::SQLite => "ALTER TABLE users ADD COLUMN password_reset TINYINT NOT NULL DEFAULT 0",
+                DatabaseType::MySQL | DatabaseType::PostgreSQL => "ALTER TABLE users ADD COLUMN password_reset BOOL NOT NULL DEFAULT FALSE"
+            };
+            sqlx::query(query).execute(db.connection()).await?;
+            Ok(())
+        }
+        4 => ImportMap::create(db).await.map(|_| ()),
+        5 => Attachments::create(db).await.map(|_| ()),
+        6 => Profiles::create(db).await.map(|_| ()),
+        7 => {
+            Conversations::create(db).await?;
+            ConversationMembers::create(db).await?;
+            Messages::create(db).await?;
+            UserBlocks::create(db).await?;
+            Ok(())
+        }
+        8 => Reports::create(db).await.map(|_| ()),
+        9 => AuditLog::create(db).await.map(|_| ()),
+        10 => {
+            if has_column(db, "threads", "pinned").await? {
+                return Ok(());
+            }
+            let query = match db.get_type() {
+                DatabaseType::SQLite | DatabaseType::MySQL => "ALTER TABLE threads ADD COLUMN pinned TINYINT NOT NULL DEFAULT 0",
+                DatabaseType::PostgreSQL => "ALTER TABLE threads ADD COLUMN pinned SMALLINT NOT NULL DEFAULT 0"
+            };
+            sqlx::query(query).execute(db.connection()).await?;
+            Ok(())
+        }
+        _ => unreachable!("There is no schema upgrade {}.", version)
+    }
+}
+
+/// Look at an existing database without changing it.
+pub async fn inspect(db: &mut Database) -> Result<MigrationReport, Error> {
+    let version = SchemaVersion::get(db).await?;
+    let current = version.unwrap_or(0);
+
+    let mut tables = Vec::new();
+    for table in TABLES.iter() {
+        let name = table.name;
+        let rows = count_rows(db, name).await?;
+        tables.push(TableReport {
+            name,
+            exists: rows.is_some(),
+            rows
+        });
+    }
+
+    Ok(MigrationReport {
+        version,
+        latest_version: SCHEMA_VERSION,
+        supported: current <= SCHEMA_VERSION,
+        pending: UPGRADES.iter().skip(current.max(0) as usize).cloned().collect(),
+        tables,
+        admins: count_admins(db).await?,
+    })
+}
+
+/**
+   Apply the upgrades the database is missing and record the new version of the schema.
+
+   ## Returns
+   The upgrades that were applied.
+*/
+pub async fn upgrade(db: &mut Database) -> Result<Vec<&'static str>, String> {
+    let current = SchemaVersion::get(db).await
+        .map_err(|err| format!("Unable to read the version of the schema: {:?}", err))?
+        .unwrap_or(0);
+
+    if current > SCHEMA_VERSION {
+        return Err(format!("The database uses version {} of the schema, which is newer than this version of ForumRS supports ({}).",
+                           current, SCHEMA_VERSION));
+    }
+
+    let mut applied = Vec::new();
+    for version in (current + 1)..=SCHEMA_VERSION {
+        let description = UPGRADES[(version - 1) as usize];
+        apply(db, version).await.map_err(|err| format!("Unable to apply the upgrade '{}': {:?}", description, err))?;
+
+        // Record every upgrade so a failure later on does not apply it twice.
+        SchemaVersion::set(db, version).await
+            .map_err(|err| format!("Unable to record the version of the schema: {:?}", err))?;
+        println!("[INFO] Applied the schema upgrade: {}", description);
+        applied.push(description);
+    }
+
+    Ok(applied)
+}
@@ -1,3 +1,5 @@
 pub mod dbsetup;
 pub mod database;
-pub mod tables;
\ No newline at end of file
+pub mod migrations;
+pub mod tables;
+pub mod transfer;
\ No newline at end of file
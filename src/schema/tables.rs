@@ -1,12 +1,19 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
 use async_trait::async_trait;
 use sqlx;
-use sqlx::any::AnyDone;
-use sqlx::Error;
+use sqlx::any::{AnyDone, AnyRow};
+use sqlx::{Error, Row};
 use uuid::Uuid;
 
 use crate::schema::database::Database;
 use crate::settings::DatabaseType;
 
+/// Get the current unix time in seconds.
+pub fn current_time() -> i64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as i64
+}
+
 #[async_trait]
 pub trait Table {
     async fn create(db: &mut Database) -> Result<AnyDone, Error>;
@@ -14,10 +21,84 @@ pub trait Table {
     async fn exists(db: &mut Database) -> bool;
 }
 
+/// Check if a table exists by selecting from it. A database error means it is missing, any other error is
+/// logged and treated as missing too since the table cannot be used either way.
+async fn table_exists(db: &mut Database, table: &str) -> bool {
+    match sqlx::query(&format!("SELECT 1 FROM {} LIMIT 1", table)).fetch_optional(db.connection()).await {
+        Ok(_) => true,
+        Err(Error::Database(_)) => false,
+        Err(err) => {
+            println!("[ERROR] Could not check if the {} table exists: {}", table, err);
+            false
+        }
+    }
+}
+
+/// A user from the users table.
+#[derive(Debug, Clone, Serialize)]
+pub struct User {
+    pub id: i32,
+    pub uuid: Uuid,
+    pub username: String,
+    pub email: String,
+    #[serde(skip_serializing)]
+    pub password: String,
+    pub is_banned: bool,
+    pub is_admin: bool,
+    pub is_moderator: bool,
+    /// If the user has to be given a new password before they can log in, as their password could not be imported.
+    #[serde(skip_serializing)]
+    pub password_reset: bool,
+    /// The unix time the user joined.
+    pub date: i64,
+}
+
+impl User {
+    /// Construct a user from a row selected with [Users::columns].
+    pub fn from_row(row: &AnyRow) -> Result<User, Error> {
+        Ok(User {
+            id: row.try_get::<i32, _>("id")?,
+            uuid: Uuid::parse_str(row.try_get::<String, _>("uuid")?.as_str()).unwrap_or_default(),
+            username: row.try_get("username")?,
+            email: row.try_get("email")?,
+            password: row.try_get("password")?,
+            is_banned: row.try_get("is_banned")?,
+            is_admin: row.try_get("is_admin")?,
+            is_moderator: row.try_get("is_moderator")?,
+            password_reset: row.try_get("password_reset")?,
+            date: row.try_get("date")?,
+        })
+    }
+}
+
 /// The users table. This stores information about the user.
 pub struct Users {}
 
 impl Users {
+    /// Get the columns to select for a [User].
+    pub fn columns(db: &Database) -> String {
+        format!("id, uuid, username, email, password, is_banned, is_admin, is_moderator, password_reset, {} AS date", db.unix_time("date"))
+    }
+
+    /// Get a user by their username.
+    pub async fn get_by_username(db: &mut Database, username: &str) -> Result<Option<User>, Error> {
+        let query = db.format_query(&format!("SELECT {} FROM users WHERE username = ?", Users::columns(db)));
+        let row = sqlx::query(&query)
+            .bind(username.to_string())
+            .fetch_optional(db.connection()).await?;
+        row.map(|row| User::from_row(&row)).transpose()
+    }
+
+    /// Get a user by their uuid.
+    pub async fn get_by_uuid(db: &mut Database, uuid: Uuid) -> Result<Option<User>, Error> {
+        let query = db.format_query(&format!("SELECT {} FROM users WHERE uuid = ?", Users::columns(db)));
+        let row = sqlx::query(&query)
+            .bind(uuid.to_string())
+            .fetch_optional(db.connection()).await?;
+        row.map(|row| User::from_row(&row)).transpose()
+    }
+
+
     /// Insert a user into the table.
     pub async fn insert(db: &mut Database, uuid: Uuid, username: String, email: String, hashed_password: String, is_banned: bool, is_admin: bool) {
         let tp = db.get_type();
@@ -54,6 +135,72 @@ impl Users {
             }
         }
     }
+
+    /// Insert a user imported from another forum. The password is the hash of the other forum, so the user
+    /// has to be given a new password before they can log in.
+    pub async fn insert_imported(db: &mut Database, uuid: Uuid, username: String, email: String, legacy_password: String, date: i64) -> Result<AnyDone, Error> {
+        let query = db.format_query(&format!("INSERT INTO users (uuid, username, email, password, is_banned, is_admin, is_moderator, password_reset, date) \
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, {})", db.from_unix_time("?")));
+        sqlx::query(&query)
+            .bind(uuid.to_string())
+            .bind(username)
+            .bind(email)
+            .bind(legacy_password)
+            .bind(false)
+            .bind(false)
+            .bind(false)
+            .bind(true)
+            .bind(date)
+            .execute(db.connection()).await
+    }
+
+    /// Set the role of a user. A user that is neither an admin nor a moderator is a regular user.
+    pub async fn set_role(db: &mut Database, uuid: Uuid, is_admin: bool, is_moderator: bool) -> Result<AnyDone, Error> {
+        let query = db.format_query("UPDATE users SET is_admin = ?, is_moderator = ? WHERE uuid = ?");
+        sqlx::query(&query)
+            .bind(is_admin)
+            .bind(is_moderator)
+            .bind(uuid.to_string())
+            .execute(db.connection()).await
+    }
+
+    /// Ban or unban a user.
+    pub async fn set_banned(db: &mut Database, uuid: Uuid, is_banned: bool) -> Result<AnyDone, Error> {
+        let query = db.format_query("UPDATE users SET is_banned = ? WHERE uuid = ?");
+        sqlx::query(&query)
+            .bind(is_banned)
+            .bind(uuid.to_string())
+            .execute(db.connection()).await
+    }
+
+    /// Change the password of a user. The password must already be hashed.
+    /// This also clears the password reset flag of imported users.
+    pub async fn set_password(db: &mut Database, uuid: Uuid, hashed_password: String) -> Result<AnyDone, Error> {
+        let query = db.format_query("UPDATE users SET password = ?, password_reset = ? WHERE uuid = ?");
+        sqlx::query(&query)
+            .bind(hashed_password)
+            .bind(false)
+            .bind(uuid.to_string())
+            .execute(db.connection()).await
+    }
+
+    /// Delete a user. The rows that belong to the user in other tables must be deleted first.
+    pub async fn delete(db: &mut Database, uuid: Uuid) -> Result<AnyDone, Error> {
+        let query = db.format_query("DELETE FROM users WHERE uuid = ?");
+        sqlx::query(&query)
+            .bind(uuid.to_string())
+            .execute(db.connection()).await
+    }
+
+    /// Count the threads and posts created by a user.
+    pub async fn count_content(db: &mut Database, uuid: Uuid) -> Result<i64, Error> {
+        let query = db.format_query("SELECT (SELECT COUNT(*) FROM threads WHERE creator = ?) + (SELECT COUNT(*) FROM posts WHERE creator = ?) AS amount");
+        let row = sqlx::query(&query)
+            .bind(uuid.to_string())
+            .bind(uuid.to_string())
+            .fetch_one(db.connection()).await?;
+        row.try_get("amount")
+    }
 }
 
 #[async_trait]
@@ -70,18 +217,22 @@ impl Table for Users {
                 password VARCHAR(100) NOT NULL,\
                 is_banned TINYINT NOT NULL,\
                 is_admin TINYINT NOT NULL,\
+                is_moderator TINYINT NOT NULL DEFAULT 0,\
+                password_reset TINYINT NOT NULL DEFAULT 0,\
                 date INTEGER DEFAULT CURRENT_TIMESTAMP\
                 )").execute(db.connection()).await
             }
             DatabaseType::MySQL => {
                 sqlx::query("CREATE TABLE IF NOT EXISTS users (\
-                id INTEGER PRIMARY KEY,
+                id INTEGER PRIMARY KEY AUTO_INCREMENT,
                 uuid VARCHAR(40) NOT NULL,\
                 username VARCHAR(40) NOT NULL,\
                 email VARCHAR(255) NOT NULL,\
                 password VARCHAR(100) NOT NULL,\
                 is_banned BOOL NOT NULL,\
                 is_admin BOOL NOT NULL,\
+                is_moderator BOOL NOT NULL DEFAULT FALSE,\
+                password_reset BOOL NOT NULL DEFAULT FALSE,\
                 date TIMESTAMP DEFAULT CURRENT_TIMESTAMP\
                 )").execute(db.connection()).await
             }
@@ -94,6 +245,8 @@ impl Table for Users {
                 password VARCHAR(100) NOT NULL,\
                 is_banned BOOL NOT NULL,\
                 is_admin BOOL NOT NULL,\
+                is_moderator BOOL NOT NULL DEFAULT FALSE,\
+                password_reset BOOL NOT NULL DEFAULT FALSE,\
                 date TIMESTAMP DEFAULT CURRENT_TIMESTAMP\
                 )").execute(db.connection()).await
             }
@@ -113,6 +266,52 @@ impl Table for Users {
 /// This stores active login sessions.
 pub struct Sessions {}
 
+impl Sessions {
+    /// Insert a new session for a user.
+    pub async fn insert(db: &mut Database, session_uuid: Uuid, user_uuid: Uuid) -> Result<AnyDone, Error> {
+        let query = db.format_query("INSERT INTO sessions (session_uuid, user_uuid, session_creation) VALUES (?, ?, ?)");
+        sqlx::query(&query)
+            .bind(session_uuid.to_string())
+            .bind(user_uuid.to_string())
+            .bind(current_time())
+            .execute(db.connection()).await
+    }
+
+    /// Get the uuid of the user that owns a session.
+    pub async fn get_user_uuid(db: &mut Database, session_uuid: Uuid) -> Result<Option<Uuid>, Error> {
+        let query = db.format_query("SELECT user_uuid FROM sessions WHERE session_uuid = ?");
+        let row = sqlx::query(&query)
+            .bind(session_uuid.to_string())
+            .fetch_optional(db.connection()).await?;
+        Ok(row.and_then(|row| Uuid::parse_str(row.try_get::<String, _>("user_uuid").ok()?.as_str()).ok()))
+    }
+
+    /// Delete a session.
+    pub async fn delete(db: &mut Database, session_uuid: Uuid) -> Result<AnyDone, Error> {
+        let query = db.format_query("DELETE FROM sessions WHERE session_uuid = ?");
+        sqlx::query(&query)
+            .bind(session_uuid.to_string())
+            .execute(db.connection()).await
+    }
+
+    /// Get the unix times the sessions of a user were created, newest first.
+    pub async fn get_creation_times(db: &mut Database, user_uuid: Uuid) -> Result<Vec<i64>, Error> {
+        let query = db.format_query("SELECT session_creation FROM sessions WHERE user_uuid = ? ORDER BY session_creation DESC");
+        let rows = sqlx::query(&query)
+            .bind(user_uuid.to_string())
+            .fetch_all(db.connection()).await?;
+        rows.iter().map(|row| Ok(row.try_get::<Option<i64>, _>("session_creation")?.unwrap_or(0))).collect()
+    }
+
+    /// Delete every session of a user, logging them out everywhere.
+    pub async fn delete_by_user(db: &mut Database, user_uuid: Uuid) -> Result<AnyDone, Error> {
+        let query = db.format_query("DELETE FROM sessions WHERE user_uuid = ?");
+        sqlx::query(&query)
+            .bind(user_uuid.to_string())
+            .execute(db.connection()).await
+    }
+}
+
 #[async_trait]
 impl Table for Sessions {
     async fn create(db: &mut Database) -> Result<AnyDone, Error> {
@@ -128,7 +327,7 @@ impl Table for Sessions {
             }
             DatabaseType::MySQL => {
                 sqlx::query("CREATE TABLE IF NOT EXISTS sessions (\
-                id INTEGER PRIMARY KEY,
+                id INTEGER PRIMARY KEY AUTO_INCREMENT,
                 session_uuid VARCHAR(40) NOT NULL,\
                 user_uuid VARCHAR(40) NOT NULL,\
                 session_creation BIGINT\
@@ -154,9 +353,494 @@ impl Table for Sessions {
     }
 }
 
+/// An API token from the api_tokens table.
+#[derive(Debug, Clone, Serialize)]
+pub struct ApiToken {
+    pub uuid: Uuid,
+    pub user_uuid: Uuid,
+    pub name: String,
+    /// The granted scopes separated by spaces.
+    pub scopes: String,
+    /// The OAuth application the token was issued to. None for personal tokens.
+    pub client_uuid: Option<Uuid>,
+    /// The unix time the token was created.
+    pub created: i64,
+    /// The unix time the token was last used. (0 if it was never used)
+    pub last_used: i64,
+}
+
+impl ApiToken {
+    /// Construct a token from a row selected with [ApiTokens::COLUMNS].
+    pub fn from_row(row: &AnyRow) -> Result<ApiToken, Error> {
+        Ok(ApiToken {
+            uuid: Uuid::parse_str(row.try_get::<String, _>("token_uuid")?.as_str()).unwrap_or_default(),
+            user_uuid: Uuid::parse_str(row.try_get::<String, _>("user_uuid")?.as_str()).unwrap_or_default(),
+            name: row.try_get("name")?,
+            scopes: row.try_get("scopes")?,
+            client_uuid: row.try_get::<Option<String>, _>("client_uuid")?.and_then(|uuid| Uuid::parse_str(uuid.as_str()).ok()),
+            created: row.try_get("created")?,
+            last_used: row.try_get("last_used")?,
+        })
+    }
+}
+
+/// The table for API tokens.
+/// This stores the hashes of personal access tokens and tokens issued to OAuth applications.
+pub struct ApiTokens {}
+
+impl ApiTokens {
+    /// The columns to select for an [ApiToken].
+    pub const COLUMNS: &'static str = "token_uuid, user_uuid, name, scopes, client_uuid, created, last_used";
+
+    /// Insert a new token for a user.
+    pub async fn insert(db: &mut Database, token_uuid: Uuid, user_uuid: Uuid, name: String, token_hash: String, scopes: String, client_uuid: Option<Uuid>) -> Result<AnyDone, Error> {
+        let query = db.format_query("INSERT INTO api_tokens (token_uuid, user_uuid, name, token_hash, scopes, client_uuid, created, last_used) VALUES (?, ?, ?, ?, ?, ?, ?, ?)");
+        sqlx::query(&query)
+            .bind(token_uuid.to_string())
+            .bind(user_uuid.to_string())
+            .bind(name)
+            .bind(token_hash)
+            .bind(scopes)
+            .bind(client_uuid.map(|uuid| uuid.to_string()))
+            .bind(current_time())
+            .bind(0_i64)
+            .execute(db.connection()).await
+    }
+
+    /// Get a token by the hash of the token.
+    pub async fn get_by_hash(db: &mut Database, token_hash: &str) -> Result<Option<ApiToken>, Error> {
+        let query = db.format_query(&format!("SELECT {} FROM api_tokens WHERE token_hash = ?", ApiTokens::COLUMNS));
+        let row = sqlx::query(&query)
+            .bind(token_hash.to_string())
+            .fetch_optional(db.connection()).await?;
+        row.map(|row| ApiToken::from_row(&row)).transpose()
+    }
+
+    /// Get every token of a user, newest first.
+    pub async fn get_by_user(db: &mut Database, user_uuid: Uuid) -> Result<Vec<ApiToken>, Error> {
+        let query = db.format_query(&format!("SELECT {} FROM api_tokens WHERE user_uuid = ? ORDER BY created DESC", ApiTokens::COLUMNS));
+        let rows = sqlx::query(&query)
+            .bind(user_uuid.to_string())
+            .fetch_all(db.connection()).await?;
+        rows.iter().map(ApiToken::from_row).collect()
+    }
+
+    /// Update the last used time of a token.
+    pub async fn touch(db: &mut Database, token_uuid: Uuid) -> Result<AnyDone, Error> {
+        let query = db.format_query("UPDATE api_tokens SET last_used = ? WHERE token_uuid = ?");
+        sqlx::query(&query)
+            .bind(current_time())
+            .bind(token_uuid.to_string())
+            .execute(db.connection()).await
+    }
+
+    /// Revoke a token of a user.
+    pub async fn delete(db: &mut Database, token_uuid: Uuid, user_uuid: Uuid) -> Result<AnyDone, Error> {
+        let query = db.format_query("DELETE FROM api_tokens WHERE token_uuid = ? AND user_uuid = ?");
+        sqlx::query(&query)
+            .bind(token_uuid.to_string())
+            .bind(user_uuid.to_string())
+            .execute(db.connection()).await
+    }
+
+    /// Revoke every token issued to an OAuth application.
+    pub async fn delete_by_client(db: &mut Database, client_uuid: Uuid) -> Result<AnyDone, Error> {
+        let query = db.format_query("DELETE FROM api_tokens WHERE client_uuid = ?");
+        sqlx::query(&query)
+            .bind(client_uuid.to_string())
+            .execute(db.connection()).await
+    }
+
+    /// Revoke every token of a user.
+    pub async fn delete_by_user(db: &mut Database, user_uuid: Uuid) -> Result<AnyDone, Error> {
+        let query = db.format_query("DELETE FROM api_tokens WHERE user_uuid = ?");
+        sqlx::query(&query)
+            .bind(user_uuid.to_string())
+            .execute(db.connection()).await
+    }
+}
+
+#[async_trait]
+impl Table for ApiTokens {
+    async fn create(db: &mut Database) -> Result<AnyDone, Error> {
+        let tp = db.get_type();
+        match tp {
+            DatabaseType::SQLite => {
+                sqlx::query("CREATE TABLE IF NOT EXISTS api_tokens (\
+                id INTEGER PRIMARY KEY,
+                token_uuid VARCHAR(40) NOT NULL,\
+                user_uuid VARCHAR(40) NOT NULL,\
+                name VARCHAR(50) NOT NULL,\
+                token_hash VARCHAR(64) NOT NULL UNIQUE,\
+                scopes VARCHAR(100) NOT NULL,\
+                client_uuid VARCHAR(40),\
+                created BIGINT NOT NULL,\
+                last_used BIGINT NOT NULL\
+                )").execute(db.connection()).await
+            }
+            DatabaseType::MySQL => {
+                sqlx::query("CREATE TABLE IF NOT EXISTS api_tokens (\
+                id INTEGER PRIMARY KEY AUTO_INCREMENT,
+                token_uuid VARCHAR(40) NOT NULL,\
+                user_uuid VARCHAR(40) NOT NULL,\
+                name VARCHAR(50) NOT NULL,\
+                token_hash VARCHAR(64) NOT NULL UNIQUE,\
+                scopes VARCHAR(100) NOT NULL,\
+                client_uuid VARCHAR(40),\
+                created BIGINT NOT NULL,\
+                last_used BIGINT NOT NULL\
+                )").execute(db.connection()).await
+            }
+            DatabaseType::PostgreSQL => {
+                sqlx::query("CREATE TABLE IF NOT EXISTS api_tokens (\
+                id SERIAL PRIMARY KEY,
+                token_uuid VARCHAR(40) NOT NULL,\
+                user_uuid VARCHAR(40) NOT NULL,\
+                name VARCHAR(50) NOT NULL,\
+                token_hash VARCHAR(64) NOT NULL UNIQUE,\
+                scopes VARCHAR(100) NOT NULL,\
+                client_uuid VARCHAR(40),\
+                created BIGINT NOT NULL,\
+                last_used BIGINT NOT NULL\
+                )").execute(db.connection()).await
+            }
+        }
+    }
+
+    async fn drop(db: &mut Database) {
+        sqlx::query("DROP TABLE api_tokens").execute(db.connection()).await.unwrap();
+    }
+
+    async fn exists(db: &mut Database) -> bool {
+        table_exists(db, "api_tokens").await
+    }
+}
+
+/// An OAuth application from the oauth_clients table.
+#[derive(Debug, Clone, Serialize)]
+pub struct OAuthClient {
+    pub uuid: Uuid,
+    /// The user that registered the application.
+    pub owner_uuid: Uuid,
+    pub name: String,
+    /// The only uri users are sent back to after authorizing the application.
+    pub redirect_uri: String,
+    #[serde(skip_serializing)]
+    pub secret_hash: String,
+    /// The unix time the application was registered.
+    pub created: i64,
+}
+
+impl OAuthClient {
+    /// Construct an application from a row selected with [OAuthClients::COLUMNS].
+    pub fn from_row(row: &AnyRow) -> Result<OAuthClient, Error> {
+        Ok(OAuthClient {
+            uuid: Uuid::parse_str(row.try_get::<String, _>("client_uuid")?.as_str()).unwrap_or_default(),
+            owner_uuid: Uuid::parse_str(row.try_get::<String, _>("owner_uuid")?.as_str()).unwrap_or_default(),
+            name: row.try_get("name")?,
+            redirect_uri: row.try_get("redirect_uri")?,
+            secret_hash: row.try_get("secret_hash")?,
+            created: row.try_get("created")?,
+        })
+    }
+}
+
+/// The table for OAuth applications that can act on behalf of users.
+pub struct OAuthClients {}
+
+impl OAuthClients {
+    /// The columns to select for an [OAuthClient].
+    pub const COLUMNS: &'static str = "client_uuid, owner_uuid, name, redirect_uri, secret_hash, created";
+
+    /// Register a new application.
+    pub async fn insert(db: &mut Database, client_uuid: Uuid, owner_uuid: Uuid, name: String, redirect_uri: String, secret_hash: String) -> Result<AnyDone, Error> {
+        let query = db.format_query("INSERT INTO oauth_clients (client_uuid, owner_uuid, name, redirect_uri, secret_hash, created) VALUES (?, ?, ?, ?, ?, ?)");
+        sqlx::query(&query)
+            .bind(client_uuid.to_string())
+            .bind(owner_uuid.to_string())
+            .bind(name)
+            .bind(redirect_uri)
+            .bind(secret_hash)
+            .bind(current_time())
+            .execute(db.connection()).await
+    }
+
+    /// Get an application by its client id.
+    pub async fn get_by_uuid(db: &mut Database, client_uuid: Uuid) -> Result<Option<OAuthClient>, Error> {
+        let query = db.format_query(&format!("SELECT {} FROM oauth_clients WHERE client_uuid = ?", OAuthClients::COLUMNS));
+        let row = sqlx::query(&query)
+            .bind(client_uuid.to_string())
+            .fetch_optional(db.connection()).await?;
+        row.map(|row| OAuthClient::from_row(&row)).transpose()
+    }
+
+    /// Get the applications registered by a user.
+    pub async fn get_by_owner(db: &mut Database, owner_uuid: Uuid) -> Result<Vec<OAuthClient>, Error> {
+        let query = db.format_query(&format!("SELECT {} FROM oauth_clients WHERE owner_uuid = ? ORDER BY created DESC", OAuthClients::COLUMNS));
+        let rows = sqlx::query(&query)
+            .bind(owner_uuid.to_string())
+            .fetch_all(db.connection()).await?;
+        rows.iter().map(OAuthClient::from_row).collect()
+    }
+
+    /// Delete an application of a user.
+    pub async fn delete(db: &mut Database, client_uuid: Uuid, owner_uuid: Uuid) -> Result<AnyDone, Error> {
+        let query = db.format_query("DELETE FROM oauth_clients WHERE client_uuid = ? AND owner_uuid = ?");
+        sqlx::query(&query)
+            .bind(client_uuid.to_string())
+            .bind(owner_uuid.to_string())
+            .execute(db.connection()).await
+    }
+}
+
+#[async_trait]
+impl Table for OAuthClients {
+    async fn create(db: &mut Database) -> Result<AnyDone, Error> {
+        let tp = db.get_type();
+        match tp {
+            DatabaseType::SQLite => {
+                sqlx::query("CREATE TABLE IF NOT EXISTS oauth_clients (\
+                id INTEGER PRIMARY KEY,
+                client_uuid VARCHAR(40) NOT NULL,\
+                owner_uuid VARCHAR(40) NOT NULL,\
+                name VARCHAR(50) NOT NULL,\
+                redirect_uri VARCHAR(255) NOT NULL,\
+                secret_hash VARCHAR(64) NOT NULL,\
+                created BIGINT NOT NULL\
+                )").execute(db.connection()).await
+            }
+            DatabaseType::MySQL => {
+                sqlx::query("CREATE TABLE IF NOT EXISTS oauth_clients (\
+                id INTEGER PRIMARY KEY AUTO_INCREMENT,
+                client_uuid VARCHAR(40) NOT NULL,\
+                owner_uuid VARCHAR(40) NOT NULL,\
+                name VARCHAR(50) NOT NULL,\
+                redirect_uri VARCHAR(255) NOT NULL,\
+                secret_hash VARCHAR(64) NOT NULL,\
+                created BIGINT NOT NULL\
+                )").execute(db.connection()).await
+            }
+            DatabaseType::PostgreSQL => {
+                sqlx::query("CREATE TABLE IF NOT EXISTS oauth_clients (\
+                id SERIAL PRIMARY KEY,
+                client_uuid VARCHAR(40) NOT NULL,\
+                owner_uuid VARCHAR(40) NOT NULL,\
+                name VARCHAR(50) NOT NULL,\
+                redirect_uri VARCHAR(255) NOT NULL,\
+                secret_hash VARCHAR(64) NOT NULL,\
+                created BIGINT NOT NULL\
+                )").execute(db.connection()).await
+            }
+        }
+    }
+
+    async fn drop(db: &mut Database) {
+        sqlx::query("DROP TABLE oauth_clients").execute(db.connection()).await.unwrap();
+    }
+
+    async fn exists(db: &mut Database) -> bool {
+        table_exists(db, "oauth_clients").await
+    }
+}
+
+/// A thread from the threads table.
+#[derive(Debug, Clone, Serialize)]
+pub struct Thread {
+    pub id: i32,
+    pub uuid: Uuid,
+    pub forum_uuid: Uuid,
+    pub name: String,
+    pub content: String,
+    pub creator: Uuid,
+    pub locked: bool,
+    /// If the thread is shown before the other threads of its forum.
+    pub pinned: bool,
+    /// The unix time the thread was created.
+    pub date: i64,
+}
+
+impl Thread {
+    /// Construct a thread from a row selected with [Threads::columns].
+    pub fn from_row(row: &AnyRow) -> Result<Thread, Error> {
+        Ok(Thread {
+            id: row.try_get::<i32, _>("id")?,
+            uuid: Uuid::parse_str(row.try_get::<String, _>("uuid")?.as_str()).unwrap_or_default(),
+            forum_uuid: Uuid::parse_str(row.try_get::<String, _>("forum_uuid")?.as_str()).unwrap_or_default(),
+            name: row.try_get("name")?,
+            content: row.try_get("content")?,
+            creator: Uuid::parse_str(row.try_get::<String, _>("creator")?.as_str()).unwrap_or_default(),
+            locked: row.try_get::<i32, _>("locked")? != 0,
+            pinned: row.try_get::<i32, _>("pinned")? != 0,
+            date: row.try_get("date")?,
+        })
+    }
+}
+
 /// The table to store thread data.
 pub struct Threads {}
 
+impl Threads {
+    /// Get the columns to select for a [Thread].
+    pub fn columns(db: &Database) -> String {
+        format!("id, uuid, forum_uuid, name, content, creator, {} AS locked, {} AS pinned, {} AS date",
+                db.int_column("locked"), db.int_column("pinned"), db.unix_time("date"))
+    }
+
+    /// Insert a new thread.
+    pub async fn insert(db: &mut Database, uuid: Uuid, forum_uuid: Uuid, name: String, content: String, creator: Uuid) -> Result<AnyDone, Error> {
+        let query = db.format_query("INSERT INTO threads (uuid, forum_uuid, name, content, creator, locked) VALUES (?, ?, ?, ?, ?, ?)");
+        sqlx::query(&query)
+            .bind(uuid.to_string())
+            .bind(forum_uuid.to_string())
+            .bind(name)
+            .bind(content)
+            .bind(creator.to_string())
+            .bind(0_i32)
+            .execute(db.connection()).await
+    }
+
+    /// Insert a thread imported from another forum, keeping the time it was created.
+    pub async fn insert_imported(db: &mut Database, uuid: Uuid, forum_uuid: Uuid, name: String, content: String, creator: Uuid, locked: bool, date: i64) -> Result<AnyDone, Error> {
+        let query = db.format_query(&format!("INSERT INTO threads (uuid, forum_uuid, name, content, creator, locked, date) VALUES (?, ?, ?, ?, ?, ?, {})",
+                                             db.from_unix_time("?")));
+        sqlx::query(&query)
+            .bind(uuid.to_string())
+            .bind(forum_uuid.to_string())
+            .bind(name)
+            .bind(content)
+            .bind(creator.to_string())
+            .bind(if locked { 1_i32 } else { 0_i32 })
+            .bind(date)
+            .execute(db.connection()).await
+    }
+
+    /// Get a thread by its uuid.
+    pub async fn get_by_uuid(db: &mut Database, uuid: Uuid) -> Result<Option<Thread>, Error> {
+        let query = db.format_query(&format!("SELECT {} FROM threads WHERE uuid = ?", Threads::columns(db)));
+        let row = sqlx::query(&query)
+            .bind(uuid.to_string())
+            .fetch_optional(db.connection()).await?;
+        row.map(|row| Thread::from_row(&row)).transpose()
+    }
+
+    /// Get a page of the threads in a forum, newest first.
+    ///
+    /// # Params
+    /// before_id - Only threads with an id lower than this are returned. (Used as the pagination cursor)
+    pub async fn get_by_forum(db: &mut Database, forum_uuid: Uuid, before_id: Option<i32>, limit: i64) -> Result<Vec<Thread>, Error> {
+        let query = db.format_query(&format!("SELECT {} FROM threads WHERE forum_uuid = ? AND id < ? ORDER BY id DESC LIMIT ?", Threads::columns(db)));
+        let rows = sqlx::query(&query)
+            .bind(forum_uuid.to_string())
+            .bind(before_id.unwrap_or(i32::MAX))
+            .bind(limit)
+            .fetch_all(db.connection()).await?;
+        rows.iter().map(|row| Thread::from_row(row)).collect()
+    }
+
+    /// Get the pinned threads of a forum, newest first.
+    pub async fn get_pinned(db: &mut Database, forum_uuid: Uuid) -> Result<Vec<Thread>, Error> {
+        let query = db.format_query(&format!("SELECT {} FROM threads WHERE forum_uuid = ? AND pinned = ? ORDER BY id DESC", Threads::columns(db)));
+        let rows = sqlx::query(&query)
+            .bind(forum_uuid.to_string())
+            .bind(1_i32)
+            .fetch_all(db.connection()).await?;
+        rows.iter().map(|row| Thread::from_row(row)).collect()
+    }
+
+    /// Get the newest threads across every forum.
+    pub async fn get_latest(db: &mut Database, limit: i64) -> Result<Vec<Thread>, Error> {
+        let query = db.format_query(&format!("SELECT {} FROM threads ORDER BY id DESC LIMIT ?", Threads::columns(db)));
+        let rows = sqlx::query(&query)
+            .bind(limit)
+            .fetch_all(db.connection()).await?;
+        rows.iter().map(|row| Thread::from_row(row)).collect()
+    }
+
+    /// Get every thread created by a user, oldest first.
+    pub async fn get_by_creator(db: &mut Database, creator: Uuid) -> Result<Vec<Thread>, Error> {
+        let query = db.format_query(&format!("SELECT {} FROM threads WHERE creator = ? ORDER BY id ASC", Threads::columns(db)));
+        let rows = sqlx::query(&query)
+            .bind(creator.to_string())
+            .fetch_all(db.connection()).await?;
+        rows.iter().map(|row| Thread::from_row(row)).collect()
+    }
+
+    /// Get the latest threads created by a user, newest first.
+    pub async fn get_latest_by_creator(db: &mut Database, creator: Uuid, limit: i64) -> Result<Vec<Thread>, Error> {
+        let query = db.format_query(&format!("SELECT {} FROM threads WHERE creator = ? ORDER BY id DESC LIMIT ?", Threads::columns(db)));
+        let rows = sqlx::query(&query)
+            .bind(creator.to_string())
+            .bind(limit)
+            .fetch_all(db.connection()).await?;
+        rows.iter().map(|row| Thread::from_row(row)).collect()
+    }
+
+    /// Lock or unlock a thread. Locked threads cannot be replied to.
+    pub async fn set_locked(db: &mut Database, uuid: Uuid, locked: bool) -> Result<AnyDone, Error> {
+        let query = db.format_query("UPDATE threads SET locked = ? WHERE uuid = ?");
+        sqlx::query(&query)
+            .bind(if locked { 1_i32 } else { 0_i32 })
+            .bind(uuid.to_string())
+            .execute(db.connection()).await
+    }
+
+    /// Pin or unpin a thread. Pinned threads are shown before the other threads of their forum.
+    pub async fn set_pinned(db: &mut Database, uuid: Uuid, pinned: bool) -> Result<AnyDone, Error> {
+        let query = db.format_query("UPDATE threads SET pinned = ? WHERE uuid = ?");
+        sqlx::query(&query)
+            .bind(if pinned { 1_i32 } else { 0_i32 })
+            .bind(uuid.to_string())
+            .execute(db.connection()).await
+    }
+
+    /// Move a thread to another forum.
+    pub async fn set_forum(db: &mut Database, uuid: Uuid, forum_uuid: Uuid) -> Result<AnyDone, Error> {
+        let query = db.format_query("UPDATE threads SET forum_uuid = ? WHERE uuid = ?");
+        sqlx::query(&query)
+            .bind(forum_uuid.to_string())
+            .bind(uuid.to_string())
+            .execute(db.connection()).await
+    }
+
+    /// Delete a thread. The replies to the thread must be deleted first.
+    pub async fn delete(db: &mut Database, uuid: Uuid) -> Result<AnyDone, Error> {
+        let query = db.format_query("DELETE FROM threads WHERE uuid = ?");
+        sqlx::query(&query)
+            .bind(uuid.to_string())
+            .execute(db.connection()).await
+    }
+
+    /// Give every thread of a user to another creator. (ex: the nil uuid when the user deletes their account)
+    pub async fn replace_creator(db: &mut Database, creator: Uuid, new_creator: Uuid) -> Result<AnyDone, Error> {
+        let query = db.format_query("UPDATE threads SET creator = ? WHERE creator = ?");
+        sqlx::query(&query)
+            .bind(new_creator.to_string())
+            .bind(creator.to_string())
+            .execute(db.connection()).await
+    }
+
+    /// Delete every thread of a user. The replies to the threads must be deleted first.
+    pub async fn delete_by_creator(db: &mut Database, creator: Uuid) -> Result<AnyDone, Error> {
+        let query = db.format_query("DELETE FROM threads WHERE creator = ?");
+        sqlx::query(&query)
+            .bind(creator.to_string())
+            .execute(db.connection()).await
+    }
+
+    /// Search the names and content of threads, newest first.
+    pub async fn search(db: &mut Database, text: &str, before_id: Option<i32>, limit: i64) -> Result<Vec<Thread>, Error> {
+        let query = db.format_query(&format!("SELECT {} FROM threads WHERE (LOWER(name) LIKE ? OR LOWER(content) LIKE ?) AND id < ? ORDER BY id DESC LIMIT ?", Threads::columns(db)));
+        let pattern = format!("%{}%", text.to_lowercase());
+        let rows = sqlx::query(&query)
+            .bind(pattern.clone())
+            .bind(pattern)
+            .bind(before_id.unwrap_or(i32::MAX))
+            .bind(limit)
+            .fetch_all(db.connection()).await?;
+        rows.iter().map(|row| Thread::from_row(row)).collect()
+    }
+}
+
 #[async_trait]
 impl Table for Threads {
     async fn create(db: &mut Database) -> Result<AnyDone, Error> {
@@ -171,18 +855,20 @@ impl Table for Threads {
                 content TEXT NOT NULL,\
                 creator VARCHAR(40) NOT NULL,\
                 locked TINYINT NOT NULL,\
+                pinned TINYINT NOT NULL DEFAULT 0,\
                 date INTEGER DEFAULT CURRENT_TIMESTAMP\
                 )").execute(db.connection()).await
             }
             DatabaseType::MySQL => {
                 sqlx::query("CREATE TABLE IF NOT EXISTS threads (\
-                id INTEGER PRIMARY KEY,
+                id INTEGER PRIMARY KEY AUTO_INCREMENT,
                 uuid VARCHAR(40) NOT NULL,\
                 forum_uuid VARCHAR(40) NOT NULL,\
                 name VARCHAR(100) NOT NULL,\
                 content TEXT NOT NULL,\
                 creator VARCHAR(40) NOT NULL,\
                 locked TINYINT NOT NULL,\
+                pinned TINYINT NOT NULL DEFAULT 0,\
                 date TIMESTAMP DEFAULT CURRENT_TIMESTAMP\
                 )").execute(db.connection()).await
             }
@@ -195,6 +881,7 @@ impl Table for Threads {
                 content TEXT NOT NULL,\
                 creator VARCHAR(40) NOT NULL,\
                 locked SMALLINT NOT NULL,\
+                pinned SMALLINT NOT NULL DEFAULT 0,\
                 date TIMESTAMP DEFAULT CURRENT_TIMESTAMP\
                 )").execute(db.connection()).await
             }
@@ -210,8 +897,181 @@ impl Table for Threads {
     }
 }
 
+/// A post from the posts table.
+#[derive(Debug, Clone, Serialize)]
+pub struct Post {
+    pub id: i32,
+    pub uuid: Uuid,
+    pub thread_uuid: Uuid,
+    pub creator: Uuid,
+    pub content: String,
+    /// The unix time the post was created.
+    pub date: i64,
+}
+
+impl Post {
+    /// Construct a post from a row selected with [Posts::columns].
+    pub fn from_row(row: &AnyRow) -> Result<Post, Error> {
+        Ok(Post {
+            id: row.try_get::<i32, _>("id")?,
+            uuid: Uuid::parse_str(row.try_get::<String, _>("uuid")?.as_str()).unwrap_or_default(),
+            thread_uuid: Uuid::parse_str(row.try_get::<String, _>("thread_uuid")?.as_str()).unwrap_or_default(),
+            creator: Uuid::parse_str(row.try_get::<String, _>("creator")?.as_str()).unwrap_or_default(),
+            content: row.try_get("content")?,
+            date: row.try_get("date")?,
+        })
+    }
+}
+
+/// The table to store the replies to threads.
 pub struct Posts {}
 
+impl Posts {
+    /// Get the columns to select for a [Post].
+    pub fn columns(db: &Database) -> String {
+        format!("id, uuid, thread_uuid, creator, content, {} AS date", db.unix_time("date"))
+    }
+
+    /// Insert a new post.
+    pub async fn insert(db: &mut Database, uuid: Uuid, thread_uuid: Uuid, creator: Uuid, content: String) -> Result<AnyDone, Error> {
+        let query = db.format_query("INSERT INTO posts (uuid, thread_uuid, creator, content) VALUES (?, ?, ?, ?)");
+        sqlx::query(&query)
+            .bind(uuid.to_string())
+            .bind(thread_uuid.to_string())
+            .bind(creator.to_string())
+            .bind(content)
+            .execute(db.connection()).await
+    }
+
+    /// Insert a post imported from another forum, keeping the time it was created.
+    pub async fn insert_imported(db: &mut Database, uuid: Uuid, thread_uuid: Uuid, creator: Uuid, content: String, date: i64) -> Result<AnyDone, Error> {
+        let query = db.format_query(&format!("INSERT INTO posts (uuid, thread_uuid, creator, content, date) VALUES (?, ?, ?, ?, {})",
+                                             db.from_unix_time("?")));
+        sqlx::query(&query)
+            .bind(uuid.to_string())
+            .bind(thread_uuid.to_string())
+            .bind(creator.to_string())
+            .bind(content)
+            .bind(date)
+            .execute(db.connection()).await
+    }
+
+    /// Get a post by its uuid.
+    pub async fn get_by_uuid(db: &mut Database, uuid: Uuid) -> Result<Option<Post>, Error> {
+        let query = db.format_query(&format!("SELECT {} FROM posts WHERE uuid = ?", Posts::columns(db)));
+        let row = sqlx::query(&query)
+            .bind(uuid.to_string())
+            .fetch_optional(db.connection()).await?;
+        row.map(|row| Post::from_row(&row)).transpose()
+    }
+
+    /// Get a page of the posts in a thread, oldest first.
+    ///
+    /// # Params
+    /// after_id - Only posts with an id higher than this are returned. (Used as the pagination cursor)
+    pub async fn get_by_thread(db: &mut Database, thread_uuid: Uuid, after_id: Option<i32>, limit: i64) -> Result<Vec<Post>, Error> {
+        let query = db.format_query(&format!("SELECT {} FROM posts WHERE thread_uuid = ? AND id > ? ORDER BY id ASC LIMIT ?", Posts::columns(db)));
+        let rows = sqlx::query(&query)
+            .bind(thread_uuid.to_string())
+            .bind(after_id.unwrap_or(0))
+            .bind(limit)
+            .fetch_all(db.connection()).await?;
+        rows.iter().map(|row| Post::from_row(row)).collect()
+    }
+
+    /// Get the newest replies of a thread, newest first.
+    pub async fn get_latest_by_thread(db: &mut Database, thread_uuid: Uuid, limit: i64) -> Result<Vec<Post>, Error> {
+        let query = db.format_query(&format!("SELECT {} FROM posts WHERE thread_uuid = ? ORDER BY id DESC LIMIT ?", Posts::columns(db)));
+        let rows = sqlx::query(&query)
+            .bind(thread_uuid.to_string())
+            .bind(limit)
+            .fetch_all(db.connection()).await?;
+        rows.iter().map(|row| Post::from_row(row)).collect()
+    }
+
+    /// Move a post to another thread.
+    pub async fn set_thread(db: &mut Database, uuid: Uuid, thread_uuid: Uuid) -> Result<AnyDone, Error> {
+        let query = db.format_query("UPDATE posts SET thread_uuid = ? WHERE uuid = ?");
+        sqlx::query(&query)
+            .bind(thread_uuid.to_string())
+            .bind(uuid.to_string())
+            .execute(db.connection()).await
+    }
+
+    /// Update the content of a post.
+    pub async fn update_content(db: &mut Database, uuid: Uuid, content: String) -> Result<AnyDone, Error> {
+        let query = db.format_query("UPDATE posts SET content = ? WHERE uuid = ?");
+        sqlx::query(&query)
+            .bind(content)
+            .bind(uuid.to_string())
+            .execute(db.connection()).await
+    }
+
+    /// Get every post created by a user, oldest first.
+    pub async fn get_by_creator(db: &mut Database, creator: Uuid) -> Result<Vec<Post>, Error> {
+        let query = db.format_query(&format!("SELECT {} FROM posts WHERE creator = ? ORDER BY id ASC", Posts::columns(db)));
+        let rows = sqlx::query(&query)
+            .bind(creator.to_string())
+            .fetch_all(db.connection()).await?;
+        rows.iter().map(|row| Post::from_row(row)).collect()
+    }
+
+    /// Get the latest posts created by a user, newest first.
+    pub async fn get_latest_by_creator(db: &mut Database, creator: Uuid, limit: i64) -> Result<Vec<Post>, Error> {
+        let query = db.format_query(&format!("SELECT {} FROM posts WHERE creator = ? ORDER BY id DESC LIMIT ?", Posts::columns(db)));
+        let rows = sqlx::query(&query)
+            .bind(creator.to_string())
+            .bind(limit)
+            .fetch_all(db.connection()).await?;
+        rows.iter().map(|row| Post::from_row(row)).collect()
+    }
+
+    /// Delete a post.
+    pub async fn delete(db: &mut Database, uuid: Uuid) -> Result<AnyDone, Error> {
+        let query = db.format_query("DELETE FROM posts WHERE uuid = ?");
+        sqlx::query(&query)
+            .bind(uuid.to_string())
+            .execute(db.connection()).await
+    }
+
+    /// Delete every reply to a thread.
+    pub async fn delete_by_thread(db: &mut Database, thread_uuid: Uuid) -> Result<AnyDone, Error> {
+        let query = db.format_query("DELETE FROM posts WHERE thread_uuid = ?");
+        sqlx::query(&query)
+            .bind(thread_uuid.to_string())
+            .execute(db.connection()).await
+    }
+
+    /// Give every post of a user to another creator. (ex: the nil uuid when the user deletes their account)
+    pub async fn replace_creator(db: &mut Database, creator: Uuid, new_creator: Uuid) -> Result<AnyDone, Error> {
+        let query = db.format_query("UPDATE posts SET creator = ? WHERE creator = ?");
+        sqlx::query(&query)
+            .bind(new_creator.to_string())
+            .bind(creator.to_string())
+            .execute(db.connection()).await
+    }
+
+    /// Delete every post of a user, and every reply to the threads of the user.
+    pub async fn delete_by_creator(db: &mut Database, creator: Uuid) -> Result<AnyDone, Error> {
+        let query = db.format_query("DELETE FROM posts WHERE creator = ? OR thread_uuid IN (SELECT uuid FROM threads WHERE creator = ?)");
+        sqlx::query(&query)
+            .bind(creator.to_string())
+            .bind(creator.to_string())
+            .execute(db.connection()).await
+    }
+
+    /// Search the content of posts, newest first.
+    pub async fn search(db: &mut Database, text: &str, before_id: Option<i32>, limit: i64) -> Result<Vec<Post>, Error> {
+        let query = db.format_query(&format!("SELECT {} FROM posts WHERE LOWER(content) LIKE ? AND id < ? ORDER BY id DESC LIMIT ?", Posts::columns(db)));
+        let rows = sqlx::query(&query)
+            .bind(format!("%{}%", text.to_lowercase()))
+            .bind(before_id.unwrap_or(i32::MAX))
+            .bind(limit)
+            .fetch_all(db.connection()).await?;
+        rows.iter().map(|row| Post::from_row(row)).collect()
+    }
+}
+
 #[async_trait]
 impl Table for Posts {
     async fn create(db: &mut Database) -> Result<AnyDone, Error> {
@@ -229,7 +1089,7 @@ impl Table for Posts {
             }
             DatabaseType::MySQL => {
                 sqlx::query("CREATE TABLE IF NOT EXISTS posts (\
-                id INTEGER PRIMARY KEY,
+                id INTEGER PRIMARY KEY AUTO_INCREMENT,
                 uuid VARCHAR(40) NOT NULL,\
                 thread_uuid VARCHAR(40) NOT NULL,\
                 creator VARCHAR(40) NOT NULL,\
@@ -259,9 +1119,65 @@ impl Table for Posts {
     }
 }
 
+/// A forum from the forums table.
+#[derive(Debug, Clone, Serialize)]
+pub struct Forum {
+    pub id: i32,
+    pub uuid: Uuid,
+    pub name: String,
+    pub caption: String,
+}
+
+impl Forum {
+    /// Construct a forum from a row of the forums table.
+    pub fn from_row(row: &AnyRow) -> Result<Forum, Error> {
+        Ok(Forum {
+            id: row.try_get::<i32, _>("id")?,
+            uuid: Uuid::parse_str(row.try_get::<String, _>("uuid")?.as_str()).unwrap_or_default(),
+            name: row.try_get("name")?,
+            caption: row.try_get("caption")?,
+        })
+    }
+
+    /// Check if a user (or a guest when None) can see the forum and its threads.
+    ///
+    /// Every forum is currently public. Anything that lists forum content without going through a
+    /// logged in page (feeds, the API) should check this so restricted forums stay hidden once they exist.
+    pub fn visible_to(&self, _user: Option<&User>) -> bool {
+        true
+    }
+}
+
 pub struct Forums {}
 
 impl Forums {
+    /// Get all of the forums.
+    pub async fn get_all(db: &mut Database) -> Result<Vec<Forum>, Error> {
+        let rows = sqlx::query("SELECT id, uuid, name, caption FROM forums ORDER BY id")
+            .fetch_all(db.connection()).await?;
+        rows.iter().map(|row| Forum::from_row(row)).collect()
+    }
+
+    /// Get a forum by its uuid.
+    pub async fn get_by_uuid(db: &mut Database, uuid: Uuid) -> Result<Option<Forum>, Error> {
+        let query = db.format_query("SELECT id, uuid, name, caption FROM forums WHERE uuid = ?");
+        let row = sqlx::query(&query)
+            .bind(uuid.to_string())
+            .fetch_optional(db.connection()).await?;
+        row.map(|row| Forum::from_row(&row)).transpose()
+    }
+
+
+    /// Insert a forum imported from another forum.
+    pub async fn insert_imported(db: &mut Database, uuid: Uuid, name: String, caption: String) -> Result<AnyDone, Error> {
+        let query = db.format_query("INSERT INTO forums (uuid, name, caption) VALUES (?, ?, ?)");
+        sqlx::query(&query)
+            .bind(uuid.to_string())
+            .bind(name)
+            .bind(caption)
+            .execute(db.connection()).await
+    }
+
     pub async fn insert(db: &mut Database, uuid: Uuid, name: String, caption: String) {
         let tp = db.get_type();
         match tp {
@@ -299,7 +1215,7 @@ impl Table for Forums {
             }
             DatabaseType::MySQL => {
                 sqlx::query("CREATE TABLE IF NOT EXISTS forums (\
-                id INTEGER PRIMARY KEY,
+                id INTEGER PRIMARY KEY AUTO_INCREMENT,
                 uuid VARCHAR(40) NOT NULL,\
                 name VARCHAR(100) NOT NULL,\
                 caption TINYTEXT NOT NULL,\
@@ -329,6 +1245,16 @@ impl Table for Forums {
 
 pub struct BannedUsers {}
 
+impl BannedUsers {
+    /// Delete the ban history of a user.
+    pub async fn delete_by_user(db: &mut Database, user_uuid: Uuid) -> Result<AnyDone, Error> {
+        let query = db.format_query("DELETE FROM banned_users WHERE user_uuid = ?");
+        sqlx::query(&query)
+            .bind(user_uuid.to_string())
+            .execute(db.connection()).await
+    }
+}
+
 #[async_trait]
 impl Table for BannedUsers {
     async fn create(db: &mut Database) -> Result<AnyDone, Error> {
@@ -345,7 +1271,7 @@ impl Table for BannedUsers {
             }
             DatabaseType::MySQL => {
                 sqlx::query("CREATE TABLE IF NOT EXISTS banned_users (\
-                id INTEGER PRIMARY KEY,
+                id INTEGER PRIMARY KEY AUTO_INCREMENT,
                 user_uuid VARCHAR(40) NOT NULL,\
                 ban_date INTEGER NOT NULL,\
                 unban_date INTEGER NOT NULL,\
@@ -371,4 +1297,2194 @@ impl Table for BannedUsers {
     async fn exists(db: &mut Database) -> bool {
         todo!()
     }
-}
\ No newline at end of file
+}
+
+/// The two factor authentication data of a user.
+#[derive(Debug, Clone)]
+pub struct TwoFactorEntry {
+    pub user_uuid: Uuid,
+    /// The base32 encoded TOTP secret.
+    pub secret: String,
+    /// If two factor authentication has been verified and enabled.
+    pub enabled: bool,
+    /// The last TOTP time step that was used. This prevents a code from being used twice.
+    pub last_step: i64,
+}
+
+/// The table to store the TOTP secrets of users.
+pub struct TwoFactor {}
+
+impl TwoFactor {
+    /// Get the two factor data of a user.
+    pub async fn get(db: &mut Database, user_uuid: Uuid) -> Result<Option<TwoFactorEntry>, Error> {
+        let query = db.format_query("SELECT user_uuid, secret, enabled, last_step FROM two_factor WHERE user_uuid = ?");
+        let row = sqlx::query(&query)
+            .bind(user_uuid.to_string())
+            .fetch_optional(db.connection()).await?;
+
+        match row {
+            Some(row) => Ok(Some(TwoFactorEntry {
+                user_uuid,
+                secret: row.try_get("secret")?,
+                enabled: row.try_get("enabled")?,
+                last_step: row.try_get("last_step")?,
+            })),
+            None => Ok(None)
+        }
+    }
+
+    /// Insert a new (not yet enabled) secret for a user. Any existing secret is replaced.
+    pub async fn insert(db: &mut Database, user_uuid: Uuid, secret: String) -> Result<AnyDone, Error> {
+        TwoFactor::delete(db, user_uuid).await?;
+        let query = db.format_query("INSERT INTO two_factor (user_uuid, secret, enabled, last_step) VALUES (?, ?, ?, ?)");
+        sqlx::query(&query)
+            .bind(user_uuid.to_string())
+            .bind(secret)
+            .bind(false)
+            .bind(0_i64)
+            .execute(db.connection()).await
+    }
+
+    /// Mark two factor authentication as enabled for a user.
+    pub async fn enable(db: &mut Database, user_uuid: Uuid) -> Result<AnyDone, Error> {
+        let query = db.format_query("UPDATE two_factor SET enabled = ? WHERE user_uuid = ?");
+        sqlx::query(&query)
+            .bind(true)
+            .bind(user_uuid.to_string())
+            .execute(db.connection()).await
+    }
+
+    /// Update the last used time step of a user.
+    pub async fn set_last_step(db: &mut Database, user_uuid: Uuid, step: i64) -> Result<AnyDone, Error> {
+        let query = db.format_query("UPDATE two_factor SET last_step = ? WHERE user_uuid = ?");
+        sqlx::query(&query)
+            .bind(step)
+            .bind(user_uuid.to_string())
+            .execute(db.connection()).await
+    }
+
+    /// Remove the two factor data of a user. This disables two factor authentication.
+    pub async fn delete(db: &mut Database, user_uuid: Uuid) -> Result<AnyDone, Error> {
+        let query = db.format_query("DELETE FROM two_factor WHERE user_uuid = ?");
+        sqlx::query(&query)
+            .bind(user_uuid.to_string())
+            .execute(db.connection()).await
+    }
+}
+
+#[async_trait]
+impl Table for TwoFactor {
+    async fn create(db: &mut Database) -> Result<AnyDone, Error> {
+        let tp = db.get_type();
+        match tp {
+            DatabaseType::SQLite => {
+                sqlx::query("CREATE TABLE IF NOT EXISTS two_factor (\
+                id INTEGER PRIMARY KEY,
+                user_uuid VARCHAR(40) NOT NULL,\
+                secret VARCHAR(64) NOT NULL,\
+                enabled TINYINT NOT NULL,\
+                last_step BIGINT NOT NULL,\
+                date INTEGER DEFAULT CURRENT_TIMESTAMP\
+                )").execute(db.connection()).await
+            }
+            DatabaseType::MySQL => {
+                sqlx::query("CREATE TABLE IF NOT EXISTS two_factor (\
+                id INTEGER PRIMARY KEY AUTO_INCREMENT,
+                user_uuid VARCHAR(40) NOT NULL,\
+                secret VARCHAR(64) NOT NULL,\
+                enabled BOOL NOT NULL,\
+                last_step BIGINT NOT NULL,\
+                date TIMESTAMP DEFAULT CURRENT_TIMESTAMP\
+                )").execute(db.connection()).await
+            }
+            DatabaseType::PostgreSQL => {
+                sqlx::query("CREATE TABLE IF NOT EXISTS two_factor (\
+                id SERIAL PRIMARY KEY,
+                user_uuid VARCHAR(40) NOT NULL,\
+                secret VARCHAR(64) NOT NULL,\
+                enabled BOOL NOT NULL,\
+                last_step BIGINT NOT NULL,\
+                date TIMESTAMP DEFAULT CURRENT_TIMESTAMP\
+                )").execute(db.connection()).await
+            }
+        }
+    }
+
+    async fn drop(db: &mut Database) {
+        sqlx::query("DROP TABLE two_factor").execute(db.connection()).await.unwrap();
+    }
+
+    async fn exists(db: &mut Database) -> bool {
+        table_exists(db, "two_factor").await
+    }
+}
+
+/// The table to store the hashed two factor recovery codes of users.
+pub struct RecoveryCodes {}
+
+impl RecoveryCodes {
+    /// Replace the recovery codes of a user with new hashed codes.
+    pub async fn replace(db: &mut Database, user_uuid: Uuid, hashed_codes: Vec<String>) -> Result<(), Error> {
+        RecoveryCodes::delete_all(db, user_uuid).await?;
+        let query = db.format_query("INSERT INTO recovery_codes (user_uuid, code_hash) VALUES (?, ?)");
+        for code_hash in hashed_codes {
+            sqlx::query(&query)
+                .bind(user_uuid.to_string())
+                .bind(code_hash)
+                .execute(db.connection()).await?;
+        }
+        Ok(())
+    }
+
+    /// Use a recovery code. The code is removed if it exists so it can only be used once.
+    ///
+    /// # Returns
+    /// If the hashed code existed for the user.
+    pub async fn consume(db: &mut Database, user_uuid: Uuid, code_hash: String) -> Result<bool, Error> {
+        let query = db.format_query("DELETE FROM recovery_codes WHERE user_uuid = ? AND code_hash = ?");
+        let done = sqlx::query(&query)
+            .bind(user_uuid.to_string())
+            .bind(code_hash)
+            .execute(db.connection()).await?;
+        Ok(done.rows_affected() > 0)
+    }
+
+    /// Get the number of unused recovery codes of a user.
+    pub async fn count(db: &mut Database, user_uuid: Uuid) -> Result<i64, Error> {
+        let query = db.format_query("SELECT COUNT(*) AS amount FROM recovery_codes WHERE user_uuid = ?");
+        let row = sqlx::query(&query)
+            .bind(user_uuid.to_string())
+            .fetch_one(db.connection()).await?;
+        row.try_get("amount")
+    }
+
+    /// Remove all of the recovery codes of a user.
+    pub async fn delete_all(db: &mut Database, user_uuid: Uuid) -> Result<AnyDone, Error> {
+        let query = db.format_query("DELETE FROM recovery_codes WHERE user_uuid = ?");
+        sqlx::query(&query)
+            .bind(user_uuid.to_string())
+            .execute(db.connection()).await
+    }
+}
+
+#[async_trait]
+impl Table for RecoveryCodes {
+    async fn create(db: &mut Database) -> Result<AnyDone, Error> {
+        let tp = db.get_type();
+        match tp {
+            DatabaseType::SQLite => {
+                sqlx::query("CREATE TABLE IF NOT EXISTS recovery_codes (\
+                id INTEGER PRIMARY KEY,
+                user_uuid VARCHAR(40) NOT NULL,\
+                code_hash VARCHAR(64) NOT NULL,\
+                date INTEGER DEFAULT CURRENT_TIMESTAMP\
+                )").execute(db.connection()).await
+            }
+            DatabaseType::MySQL => {
+                sqlx::query("CREATE TABLE IF NOT EXISTS recovery_codes (\
+                id INTEGER PRIMARY KEY AUTO_INCREMENT,
+                user_uuid VARCHAR(40) NOT NULL,\
+                code_hash VARCHAR(64) NOT NULL,\
+                date TIMESTAMP DEFAULT CURRENT_TIMESTAMP\
+                )").execute(db.connection()).await
+            }
+            DatabaseType::PostgreSQL => {
+                sqlx::query("CREATE TABLE IF NOT EXISTS recovery_codes (\
+                id SERIAL PRIMARY KEY,
+                user_uuid VARCHAR(40) NOT NULL,\
+                code_hash VARCHAR(64) NOT NULL,\
+                date TIMESTAMP DEFAULT CURRENT_TIMESTAMP\
+                )").execute(db.connection()).await
+            }
+        }
+    }
+
+    async fn drop(db: &mut Database) {
+        sqlx::query("DROP TABLE recovery_codes").execute(db.connection()).await.unwrap();
+    }
+
+    async fn exists(db: &mut Database) -> bool {
+        table_exists(db, "recovery_codes").await
+    }
+}
+
+/// A login attempt from the login_attempts table.
+#[derive(Debug, Clone, Serialize)]
+pub struct LoginAttempt {
+    pub username: String,
+    pub ip: String,
+    pub successful: bool,
+    /// The unix time of the attempt.
+    pub attempt_time: i64,
+}
+
+/// The table that records login attempts for admin review.
+pub struct LoginAttempts {}
+
+impl LoginAttempts {
+    /// Record a login attempt.
+    pub async fn insert(db: &mut Database, username: &str, ip: &str, successful: bool) -> Result<AnyDone, Error> {
+        let query = db.format_query("INSERT INTO login_attempts (username, ip, successful, attempt_time) VALUES (?, ?, ?, ?)");
+        sqlx::query(&query)
+            .bind(username.to_string())
+            .bind(ip.to_string())
+            .bind(successful)
+            .bind(current_time())
+            .execute(db.connection()).await
+    }
+
+    /// Get the most recent login attempts, optionally only for a single username.
+    pub async fn get_recent(db: &mut Database, username: Option<&str>, limit: i64) -> Result<Vec<LoginAttempt>, Error> {
+        let rows = match username {
+            Some(username) => {
+                let query = db.format_query("SELECT username, ip, successful, attempt_time FROM login_attempts WHERE username = ? ORDER BY attempt_time DESC LIMIT ?");
+                sqlx::query(&query)
+                    .bind(username.to_string())
+                    .bind(limit)
+                    .fetch_all(db.connection()).await?
+            }
+            None => {
+                let query = db.format_query("SELECT username, ip, successful, attempt_time FROM login_attempts ORDER BY attempt_time DESC LIMIT ?");
+                sqlx::query(&query)
+                    .bind(limit)
+                    .fetch_all(db.connection()).await?
+            }
+        };
+
+        rows.iter().map(|row| Ok(LoginAttempt {
+            username: row.try_get("username")?,
+            ip: row.try_get("ip")?,
+            successful: row.try_get("successful")?,
+            attempt_time: row.try_get("attempt_time")?,
+        })).collect()
+    }
+
+    /// Delete the login attempts for a username.
+    pub async fn delete_by_username(db: &mut Database, username: &str) -> Result<AnyDone, Error> {
+        let query = db.format_query("DELETE FROM login_attempts WHERE username = ?");
+        sqlx::query(&query)
+            .bind(username.to_string())
+            .execute(db.connection()).await
+    }
+}
+
+#[async_trait]
+impl Table for LoginAttempts {
+    async fn create(db: &mut Database) -> Result<AnyDone, Error> {
+        let tp = db.get_type();
+        match tp {
+            DatabaseType::SQLite => {
+                sqlx::query("CREATE TABLE IF NOT EXISTS login_attempts (\
+                id INTEGER PRIMARY KEY,
+                username VARCHAR(40) NOT NULL,\
+                ip VARCHAR(64) NOT NULL,\
+                successful TINYINT NOT NULL,\
+                attempt_time BIGINT NOT NULL\
+                )").execute(db.connection()).await
+            }
+            DatabaseType::MySQL => {
+                sqlx::query("CREATE TABLE IF NOT EXISTS login_attempts (\
+                id INTEGER PRIMARY KEY AUTO_INCREMENT,
+                username VARCHAR(40) NOT NULL,\
+                ip VARCHAR(64) NOT NULL,\
+                successful BOOL NOT NULL,\
+                attempt_time BIGINT NOT NULL\
+                )").execute(db.connection()).await
+            }
+            DatabaseType::PostgreSQL => {
+                sqlx::query("CREATE TABLE IF NOT EXISTS login_attempts (\
+                id SERIAL PRIMARY KEY,
+                username VARCHAR(40) NOT NULL,\
+                ip VARCHAR(64) NOT NULL,\
+                successful BOOL NOT NULL,\
+                attempt_time BIGINT NOT NULL\
+                )").execute(db.connection()).await
+            }
+        }
+    }
+
+    async fn drop(db: &mut Database) {
+        sqlx::query("DROP TABLE login_attempts").execute(db.connection()).await.unwrap();
+    }
+
+    async fn exists(db: &mut Database) -> bool {
+        table_exists(db, "login_attempts").await
+    }
+}
+
+/// The table that stores rate limit buckets when the database rate limit store is used.
+pub struct RateLimitBuckets {}
+
+impl RateLimitBuckets {
+    /// Get the amount of tokens in a bucket and when it was last updated (unix time in milliseconds).
+    pub async fn get(db: &mut Database, bucket_key: &str) -> Result<Option<(f64, i64)>, Error> {
+        let query = db.format_query("SELECT tokens, updated FROM rate_limit_buckets WHERE bucket_key = ?");
+        let row = sqlx::query(&query)
+            .bind(bucket_key.to_string())
+            .fetch_optional(db.connection()).await?;
+
+        match row {
+            Some(row) => Ok(Some((row.try_get("tokens")?, row.try_get("updated")?))),
+            None => Ok(None)
+        }
+    }
+
+    /**
+       Refill a bucket for the time since it was last updated and take a token from it, in a single statement so
+       concurrent requests (from any instance) cannot spend the same token.
+
+       ## Params
+       capacity - The most tokens the bucket holds.<br>
+       refill_rate - The amount of tokens added per millisecond.<br>
+       now - The current unix time in milliseconds.
+
+       ## Returns
+       If a token was taken. False if the bucket is empty or does not exist.
+    */
+    pub async fn take(db: &mut Database, bucket_key: &str, capacity: f64, refill_rate: f64, now: i64) -> Result<bool, Error> {
+        // Clocks of different instances can be slightly apart, so time never runs backward for a bucket.
+        let refilled = db.least(&format!("tokens + {} * ?", db.greatest("? - updated", "0")), "?");
+        let query = db.format_query(&format!("UPDATE rate_limit_buckets SET tokens = {} - 1, updated = {} WHERE bucket_key = ? AND {} >= 1",
+                                             refilled, db.greatest("updated", "?"), refilled));
+        let done = sqlx::query(&query)
+            .bind(now)
+            .bind(refill_rate)
+            .bind(capacity)
+            .bind(now)
+            .bind(bucket_key.to_string())
+            .bind(now)
+            .bind(refill_rate)
+            .bind(capacity)
+            .execute(db.connection()).await?;
+        Ok(done.rows_affected() > 0)
+    }
+
+    /**
+       Insert a new bucket.
+
+       ## Returns
+       False if the bucket already exists, such as when another request created it first.
+    */
+    pub async fn insert(db: &mut Database, bucket_key: &str, tokens: f64, updated: i64) -> Result<bool, Error> {
+        let query = db.format_query("INSERT INTO rate_limit_buckets (bucket_key, tokens, updated) VALUES (?, ?, ?)");
+        let result = sqlx::query(&query)
+            .bind(bucket_key.to_string())
+            .bind(tokens)
+            .bind(updated)
+            .execute(db.connection()).await;
+
+        match result {
+            Ok(_) => Ok(true),
+            Err(Error::Database(_)) => Ok(false),
+            Err(err) => Err(err)
+        }
+    }
+
+    /// Remove buckets that have not been updated since the provided time (unix time in milliseconds).
+    pub async fn delete_older_than(db: &mut Database, updated: i64) -> Result<AnyDone, Error> {
+        let query = db.format_query("DELETE FROM rate_limit_buckets WHERE updated < ?");
+        sqlx::query(&query)
+            .bind(updated)
+            .execute(db.connection()).await
+    }
+}
+
+#[async_trait]
+impl Table for RateLimitBuckets {
+    async fn create(db: &mut Database) -> Result<AnyDone, Error> {
+        let tp = db.get_type();
+        match tp {
+            DatabaseType::SQLite => {
+                sqlx::query("CREATE TABLE IF NOT EXISTS rate_limit_buckets (\
+                bucket_key VARCHAR(200) PRIMARY KEY,
+                tokens REAL NOT NULL,\
+                updated BIGINT NOT NULL\
+                )").execute(db.connection()).await
+            }
+            DatabaseType::MySQL => {
+                sqlx::query("CREATE TABLE IF NOT EXISTS rate_limit_buckets (\
+                bucket_key VARCHAR(200) PRIMARY KEY,
+                tokens DOUBLE NOT NULL,\
+                updated BIGINT NOT NULL\
+                )").execute(db.connection()).await
+            }
+            DatabaseType::PostgreSQL => {
+                sqlx::query("CREATE TABLE IF NOT EXISTS rate_limit_buckets (\
+                bucket_key VARCHAR(200) PRIMARY KEY,
+                tokens DOUBLE PRECISION NOT NULL,\
+                updated BIGINT NOT NULL\
+                )").execute(db.connection()).await
+            }
+        }
+    }
+
+    async fn drop(db: &mut Database) {
+        sqlx::query("DROP TABLE rate_limit_buckets").execute(db.connection()).await.unwrap();
+    }
+
+    async fn exists(db: &mut Database) -> bool {
+        table_exists(db, "rate_limit_buckets").await
+    }
+}
+
+/// A webhook from the webhooks table.
+#[derive(Debug, Clone, Serialize)]
+pub struct Webhook {
+    pub uuid: Uuid,
+    /// The url the events are posted to.
+    pub url: String,
+    /// The secret used to sign the payloads.
+    #[serde(skip_serializing)]
+    pub secret: String,
+    /// The names of the subscribed events separated by spaces.
+    pub events: String,
+    pub enabled: bool,
+    /// The unix time the webhook was created.
+    pub created: i64,
+}
+
+impl Webhook {
+    /// Construct a webhook from a row selected with [Webhooks::COLUMNS].
+    pub fn from_row(row: &AnyRow) -> Result<Webhook, Error> {
+        Ok(Webhook {
+            uuid: Uuid::parse_str(row.try_get::<String, _>("webhook_uuid")?.as_str()).unwrap_or_default(),
+            url: row.try_get("url")?,
+            secret: row.try_get("secret")?,
+            events: row.try_get("events")?,
+            enabled: row.try_get("enabled")?,
+            created: row.try_get("created")?,
+        })
+    }
+
+    /// Check if the webhook is subscribed to an event.
+    pub fn subscribed_to(&self, event: &str) -> bool {
+        self.events.split(' ').any(|subscribed| subscribed == event)
+    }
+}
+
+/// The table for the webhooks registered by admins.
+pub struct Webhooks {}
+
+impl Webhooks {
+    /// The columns to select for a [Webhook].
+    pub const COLUMNS: &'static str = "webhook_uuid, url, secret, events, enabled, created";
+
+    /// Register a new webhook.
+    pub async fn insert(db: &mut Database, webhook_uuid: Uuid, url: String, secret: String, events: String) -> Result<AnyDone, Error> {
+        let query = db.format_query("INSERT INTO webhooks (webhook_uuid, url, secret, events, enabled, created) VALUES (?, ?, ?, ?, ?, ?)");
+        sqlx::query(&query)
+            .bind(webhook_uuid.to_string())
+            .bind(url)
+            .bind(secret)
+            .bind(events)
+            .bind(true)
+            .bind(current_time())
+            .execute(db.connection()).await
+    }
+
+    /// Get every webhook.
+    pub async fn get_all(db: &mut Database) -> Result<Vec<Webhook>, Error> {
+        let query = format!("SELECT {} FROM webhooks ORDER BY created", Webhooks::COLUMNS);
+        let rows = sqlx::query(&query).fetch_all(db.connection()).await?;
+        rows.iter().map(Webhook::from_row).collect()
+    }
+
+    /// Get a webhook by its uuid.
+    pub async fn get_by_uuid(db: &mut Database, webhook_uuid: Uuid) -> Result<Option<Webhook>, Error> {
+        let query = db.format_query(&format!("SELECT {} FROM webhooks WHERE webhook_uuid = ?", Webhooks::COLUMNS));
+        let row = sqlx::query(&query)
+            .bind(webhook_uuid.to_string())
+            .fetch_optional(db.connection()).await?;
+        row.map(|row| Webhook::from_row(&row)).transpose()
+    }
+
+    /// Enable or disable a webhook. Disabled webhooks do not queue new deliveries.
+    pub async fn set_enabled(db: &mut Database, webhook_uuid: Uuid, enabled: bool) -> Result<AnyDone, Error> {
+        let query = db.format_query("UPDATE webhooks SET enabled = ? WHERE webhook_uuid = ?");
+        sqlx::query(&query)
+            .bind(enabled)
+            .bind(webhook_uuid.to_string())
+            .execute(db.connection()).await
+    }
+
+    /// Delete a webhook and its delivery log.
+    pub async fn delete(db: &mut Database, webhook_uuid: Uuid) -> Result<AnyDone, Error> {
+        WebhookDeliveries::delete_by_webhook(db, webhook_uuid).await?;
+        let query = db.format_query("DELETE FROM webhooks WHERE webhook_uuid = ?");
+        sqlx::query(&query)
+            .bind(webhook_uuid.to_string())
+            .execute(db.connection()).await
+    }
+}
+
+#[async_trait]
+impl Table for Webhooks {
+    async fn create(db: &mut Database) -> Result<AnyDone, Error> {
+        let tp = db.get_type();
+        match tp {
+            DatabaseType::SQLite => {
+                sqlx::query("CREATE TABLE IF NOT EXISTS webhooks (\
+                id INTEGER PRIMARY KEY,
+                webhook_uuid VARCHAR(40) NOT NULL,\
+                url VARCHAR(255) NOT NULL,\
+                secret VARCHAR(64) NOT NULL,\
+                events VARCHAR(255) NOT NULL,\
+                enabled TINYINT NOT NULL,\
+                created BIGINT NOT NULL\
+                )").execute(db.connection()).await
+            }
+            DatabaseType::MySQL => {
+                sqlx::query("CREATE TABLE IF NOT EXISTS webhooks (\
+                id INTEGER PRIMARY KEY AUTO_INCREMENT,
+                webhook_uuid VARCHAR(40) NOT NULL,\
+                url VARCHAR(255) NOT NULL,\
+                secret VARCHAR(64) NOT NULL,\
+                events VARCHAR(255) NOT NULL,\
+                enabled BOOL NOT NULL,\
+                created BIGINT NOT NULL\
+                )").execute(db.connection()).await
+            }
+            DatabaseType::PostgreSQL => {
+                sqlx::query("CREATE TABLE IF NOT EXISTS webhooks (\
+                id SERIAL PRIMARY KEY,
+                webhook_uuid VARCHAR(40) NOT NULL,\
+                url VARCHAR(255) NOT NULL,\
+                secret VARCHAR(64) NOT NULL,\
+                events VARCHAR(255) NOT NULL,\
+                enabled BOOL NOT NULL,\
+                created BIGINT NOT NULL\
+                )").execute(db.connection()).await
+            }
+        }
+    }
+
+    async fn drop(db: &mut Database) {
+        sqlx::query("DROP TABLE webhooks").execute(db.connection()).await.unwrap();
+    }
+
+    async fn exists(db: &mut Database) -> bool {
+        table_exists(db, "webhooks").await
+    }
+}
+
+/// A queued or finished delivery from the webhook_deliveries table.
+#[derive(Debug, Clone, Serialize)]
+pub struct WebhookDelivery {
+    pub uuid: Uuid,
+    pub webhook_uuid: Uuid,
+    /// The name of the event.
+    pub event: String,
+    /// The JSON body that is posted.
+    pub payload: String,
+    /// pending, delivered or failed.
+    pub status: String,
+    /// The amount of times the delivery has been attempted.
+    pub attempts: i64,
+    /// The HTTP status of the last attempt. (0 if there was no response)
+    pub response_status: i64,
+    /// Why the last attempt failed.
+    pub last_error: String,
+    /// The unix time of the next attempt if the delivery is pending.
+    pub next_attempt: i64,
+    /// The unix time the delivery was queued.
+    pub created: i64,
+    /// The unix time of the last attempt.
+    pub updated: i64,
+}
+
+impl WebhookDelivery {
+    /// Construct a delivery from a row selected with [WebhookDeliveries::COLUMNS].
+    pub fn from_row(row: &AnyRow) -> Result<WebhookDelivery, Error> {
+        Ok(WebhookDelivery {
+            uuid: Uuid::parse_str(row.try_get::<String, _>("delivery_uuid")?.as_str()).unwrap_or_default(),
+            webhook_uuid: Uuid::parse_str(row.try_get::<String, _>("webhook_uuid")?.as_str()).unwrap_or_default(),
+            event: row.try_get("event")?,
+            payload: row.try_get("payload")?,
+            status: row.try_get("status")?,
+            attempts: row.try_get("attempts")?,
+            response_status: row.try_get("response_status")?,
+            last_error: row.try_get("last_error")?,
+            next_attempt: row.try_get("next_attempt")?,
+            created: row.try_get("created")?,
+            updated: row.try_get("updated")?,
+        })
+    }
+}
+
+/// The persistent queue and log of webhook deliveries.
+pub struct WebhookDeliveries {}
+
+impl WebhookDeliveries {
+    /// The columns to select for a [WebhookDelivery].
+    pub const COLUMNS: &'static str = "delivery_uuid, webhook_uuid, event, payload, status, attempts, response_status, last_error, next_attempt, created, updated";
+
+    /// Queue a delivery to be sent right away.
+    pub async fn insert(db: &mut Database, delivery_uuid: Uuid, webhook_uuid: Uuid, event: &str, payload: String) -> Result<AnyDone, Error> {
+        let now = current_time();
+        let query = db.format_query("INSERT INTO webhook_deliveries (delivery_uuid, webhook_uuid, event, payload, status, attempts, response_status, last_error, next_attempt, created, updated) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)");
+        sqlx::query(&query)
+            .bind(delivery_uuid.to_string())
+            .bind(webhook_uuid.to_string())
+            .bind(event.to_string())
+            .bind(payload)
+            .bind("pending")
+            .bind(0_i64)
+            .bind(0_i64)
+            .bind("")
+            .bind(now)
+            .bind(now)
+            .bind(now)
+            .execute(db.connection()).await
+    }
+
+    /// Get the pending deliveries that are due, oldest first.
+    pub async fn get_due(db: &mut Database, limit: i64) -> Result<Vec<WebhookDelivery>, Error> {
+        let query = db.format_query(&format!("SELECT {} FROM webhook_deliveries WHERE status = ? AND next_attempt <= ? ORDER BY next_attempt LIMIT ?", WebhookDeliveries::COLUMNS));
+        let rows = sqlx::query(&query)
+            .bind("pending")
+            .bind(current_time())
+            .bind(limit)
+            .fetch_all(db.connection()).await?;
+        rows.iter().map(WebhookDelivery::from_row).collect()
+    }
+
+    /// Get the most recent deliveries of a webhook.
+    pub async fn get_by_webhook(db: &mut Database, webhook_uuid: Uuid, limit: i64) -> Result<Vec<WebhookDelivery>, Error> {
+        let query = db.format_query(&format!("SELECT {} FROM webhook_deliveries WHERE webhook_uuid = ? ORDER BY created DESC LIMIT ?", WebhookDeliveries::COLUMNS));
+        let rows = sqlx::query(&query)
+            .bind(webhook_uuid.to_string())
+            .bind(limit)
+            .fetch_all(db.connection()).await?;
+        rows.iter().map(WebhookDelivery::from_row).collect()
+    }
+
+    /// Record the result of a delivery attempt.
+    pub async fn record_attempt(db: &mut Database, delivery_uuid: Uuid, status: &str, attempts: i64, response_status: i64, last_error: &str, next_attempt: i64) -> Result<AnyDone, Error> {
+        let query = db.format_query("UPDATE webhook_deliveries SET status = ?, attempts = ?, response_status = ?, last_error = ?, next_attempt = ?, updated = ? WHERE delivery_uuid = ?");
+        sqlx::query(&query)
+            .bind(status.to_string())
+            .bind(attempts)
+            .bind(response_status)
+            .bind(last_error.chars().take(255).collect::<String>())
+            .bind(next_attempt)
+            .bind(current_time())
+            .bind(delivery_uuid.to_string())
+            .execute(db.connection()).await
+    }
+
+    /// Queue a delivery of a webhook to be sent again right away.
+    pub async fn retry(db: &mut Database, delivery_uuid: Uuid, webhook_uuid: Uuid) -> Result<AnyDone, Error> {
+        let query = db.format_query("UPDATE webhook_deliveries SET status = ?, attempts = ?, next_attempt = ? WHERE delivery_uuid = ? AND webhook_uuid = ?");
+        sqlx::query(&query)
+            .bind("pending")
+            .bind(0_i64)
+            .bind(current_time())
+            .bind(delivery_uuid.to_string())
+            .bind(webhook_uuid.to_string())
+            .execute(db.connection()).await
+    }
+
+    /// Delete the deliveries of a webhook.
+    pub async fn delete_by_webhook(db: &mut Database, webhook_uuid: Uuid) -> Result<AnyDone, Error> {
+        let query = db.format_query("DELETE FROM webhook_deliveries WHERE webhook_uuid = ?");
+        sqlx::query(&query)
+            .bind(webhook_uuid.to_string())
+            .execute(db.connection()).await
+    }
+}
+
+#[async_trait]
+impl Table for WebhookDeliveries {
+    async fn create(db: &mut Database) -> Result<AnyDone, Error> {
+        let tp = db.get_type();
+        match tp {
+            DatabaseType::SQLite => {
+                sqlx::query("CREATE TABLE IF NOT EXISTS webhook_deliveries (\
+                id INTEGER PRIMARY KEY,
+                delivery_uuid VARCHAR(40) NOT NULL,\
+                webhook_uuid VARCHAR(40) NOT NULL,\
+                event VARCHAR(40) NOT NULL,\
+                payload TEXT NOT NULL,\
+                status VARCHAR(20) NOT NULL,\
+                attempts BIGINT NOT NULL,\
+                response_status BIGINT NOT NULL,\
+                last_error VARCHAR(255) NOT NULL,\
+                next_attempt BIGINT NOT NULL,\
+                created BIGINT NOT NULL,\
+                updated BIGINT NOT NULL\
+                )").execute(db.connection()).await
+            }
+            DatabaseType::MySQL => {
+                sqlx::query("CREATE TABLE IF NOT EXISTS webhook_deliveries (\
+                id INTEGER PRIMARY KEY AUTO_INCREMENT,
+                delivery_uuid VARCHAR(40) NOT NULL,\
+                webhook_uuid VARCHAR(40) NOT NULL,\
+                event VARCHAR(40) NOT NULL,\
+                payload TEXT NOT NULL,\
+                status VARCHAR(20) NOT NULL,\
+                attempts BIGINT NOT NULL,\
+                response_status BIGINT NOT NULL,\
+                last_error VARCHAR(255) NOT NULL,\
+                next_attempt BIGINT NOT NULL,\
+                created BIGINT NOT NULL,\
+                updated BIGINT NOT NULL\
+                )").execute(db.connection()).await
+            }
+            DatabaseType::PostgreSQL => {
+                sqlx::query("CREATE TABLE IF NOT EXISTS webhook_deliveries (\
+                id SERIAL PRIMARY KEY,
+                delivery_uuid VARCHAR(40) NOT NULL,\
+                webhook_uuid VARCHAR(40) NOT NULL,\
+                event VARCHAR(40) NOT NULL,\
+                payload TEXT NOT NULL,\
+                status VARCHAR(20) NOT NULL,\
+                attempts BIGINT NOT NULL,\
+                response_status BIGINT NOT NULL,\
+                last_error VARCHAR(255) NOT NULL,\
+                next_attempt BIGINT NOT NULL,\
+                created BIGINT NOT NULL,\
+                updated BIGINT NOT NULL\
+                )").execute(db.connection()).await
+            }
+        }
+    }
+
+    async fn drop(db: &mut Database) {
+        sqlx::query("DROP TABLE webhook_deliveries").execute(db.connection()).await.unwrap();
+    }
+
+    async fn exists(db: &mut Database) -> bool {
+        table_exists(db, "webhook_deliveries").await
+    }
+}
+
+/// The table that links the rows imported from another forum to the rows they were imported as.
+/// This lets an import that was stopped continue where it left off.
+pub struct ImportMap {}
+
+impl ImportMap {
+    /// Get the uuid a row of another forum was imported as.
+    ///
+    /// # Params
+    /// source - The forum software the row was imported from. (ex: phpbb)
+    /// kind - The kind of row. (ex: user)
+    /// source_id - The id of the row in the other forum.
+    pub async fn get(db: &mut Database, source: &str, kind: &str, source_id: &str) -> Result<Option<Uuid>, Error> {
+        let query = db.format_query("SELECT target_uuid FROM import_map WHERE source = ? AND kind = ? AND source_id = ?");
+        let row = sqlx::query(&query)
+            .bind(source.to_string())
+            .bind(kind.to_string())
+            .bind(source_id.to_string())
+            .fetch_optional(db.connection()).await?;
+
+        match row {
+            Some(row) => Ok(Some(Uuid::parse_str(row.try_get::<String, _>("target_uuid")?.as_str()).unwrap_or_default())),
+            None => Ok(None)
+        }
+    }
+
+    /// Record the uuid a row of another forum was imported as.
+    pub async fn insert(db: &mut Database, source: &str, kind: &str, source_id: &str, target_uuid: Uuid) -> Result<AnyDone, Error> {
+        let query = db.format_query("INSERT INTO import_map (source, kind, source_id, target_uuid) VALUES (?, ?, ?, ?)");
+        sqlx::query(&query)
+            .bind(source.to_string())
+            .bind(kind.to_string())
+            .bind(source_id.to_string())
+            .bind(target_uuid.to_string())
+            .execute(db.connection()).await
+    }
+}
+
+#[async_trait]
+impl Table for ImportMap {
+    async fn create(db: &mut Database) -> Result<AnyDone, Error> {
+        let tp = db.get_type();
+        match tp {
+            DatabaseType::SQLite => {
+                sqlx::query("CREATE TABLE IF NOT EXISTS import_map (\
+                id INTEGER PRIMARY KEY,
+                source VARCHAR(20) NOT NULL,\
+                kind VARCHAR(20) NOT NULL,\
+                source_id VARCHAR(40) NOT NULL,\
+                target_uuid VARCHAR(40) NOT NULL,\
+                date INTEGER DEFAULT CURRENT_TIMESTAMP\
+                )").execute(db.connection()).await
+            }
+            DatabaseType::MySQL => {
+                sqlx::query("CREATE TABLE IF NOT EXISTS import_map (\
+                id INTEGER PRIMARY KEY AUTO_INCREMENT,
+                source VARCHAR(20) NOT NULL,\
+                kind VARCHAR(20) NOT NULL,\
+                source_id VARCHAR(40) NOT NULL,\
+                target_uuid VARCHAR(40) NOT NULL,\
+                date TIMESTAMP DEFAULT CURRENT_TIMESTAMP\
+                )").execute(db.connection()).await
+            }
+            DatabaseType::PostgreSQL => {
+                sqlx::query("CREATE TABLE IF NOT EXISTS import_map (\
+                id SERIAL PRIMARY KEY,
+                source VARCHAR(20) NOT NULL,\
+                kind VARCHAR(20) NOT NULL,\
+                source_id VARCHAR(40) NOT NULL,\
+                target_uuid VARCHAR(40) NOT NULL,\
+                date TIMESTAMP DEFAULT CURRENT_TIMESTAMP\
+                )").execute(db.connection()).await
+            }
+        }
+    }
+
+    async fn drop(db: &mut Database) {
+        sqlx::query("DROP TABLE import_map").execute(db.connection()).await.unwrap();
+    }
+
+    async fn exists(db: &mut Database) -> bool {
+        table_exists(db, "import_map").await
+    }
+}
+
+/// A file attached to a thread or post from the attachments table.
+#[derive(Debug, Clone, Serialize)]
+pub struct Attachment {
+    pub id: i32,
+    pub uuid: Uuid,
+    pub thread_uuid: Uuid,
+    /// The post the file is attached to. None if it is attached to the thread itself.
+    pub post_uuid: Option<Uuid>,
+    /// The user that uploaded the file.
+    pub uploader: Uuid,
+    pub file_name: String,
+    pub content_type: String,
+    /// The size of the stored file in bytes.
+    pub size: i64,
+    /// The key of the file in the attachment storage.
+    #[serde(skip_serializing)]
+    pub storage_key: String,
+    /// The key of the thumbnail in the attachment storage. None if the file is not an image.
+    #[serde(skip_serializing)]
+    pub thumbnail_key: Option<String>,
+    pub downloads: i64,
+    /// The unix time the file was uploaded.
+    pub created: i64,
+}
+
+impl Attachment {
+    /// Construct an attachment from a row selected with [Attachments::COLUMNS].
+    pub fn from_row(row: &AnyRow) -> Result<Attachment, Error> {
+        Ok(Attachment {
+            id: row.try_get::<i32, _>("id")?,
+            uuid: Uuid::parse_str(row.try_get::<String, _>("attachment_uuid")?.as_str()).unwrap_or_default(),
+            thread_uuid: Uuid::parse_str(row.try_get::<String, _>("thread_uuid")?.as_str()).unwrap_or_default(),
+            post_uuid: row.try_get::<Option<String>, _>("post_uuid")?.and_then(|uuid| Uuid::parse_str(uuid.as_str()).ok()),
+            uploader: Uuid::parse_str(row.try_get::<String, _>("uploader")?.as_str()).unwrap_or_default(),
+            file_name: row.try_get("file_name")?,
+            content_type: row.try_get("content_type")?,
+            size: row.try_get("size")?,
+            storage_key: row.try_get("storage_key")?,
+            thumbnail_key: row.try_get("thumbnail_key")?,
+            downloads: row.try_get("downloads")?,
+            created: row.try_get("created")?,
+        })
+    }
+}
+
+/// The table for the files attached to threads and posts. The files themselves are kept in the attachment storage.
+pub struct Attachments {}
+
+impl Attachments {
+    /// The columns to select for an [Attachment].
+    pub const COLUMNS: &'static str = "id, attachment_uuid, thread_uuid, post_uuid, uploader, file_name, content_type, size, storage_key, \
+                                       thumbnail_key, downloads, created";
+
+    /// Insert a new attachment. The file must already be in the attachment storage.
+    pub async fn insert(db: &mut Database, attachment: &Attachment) -> Result<AnyDone, Error> {
+        let query = db.format_query("INSERT INTO attachments (attachment_uuid, thread_uuid, post_uuid, uploader, file_name, content_type, size, \
+                                     storage_key, thumbnail_key, downloads, created) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)");
+        sqlx::query(&query)
+            .bind(attachment.uuid.to_string())
+            .bind(attachment.thread_uuid.to_string())
+            .bind(attachment.post_uuid.map(|uuid| uuid.to_string()))
+            .bind(attachment.uploader.to_string())
+            .bind(attachment.file_name.clone())
+            .bind(attachment.content_type.clone())
+            .bind(attachment.size)
+            .bind(attachment.storage_key.clone())
+            .bind(attachment.thumbnail_key.clone())
+            .bind(0_i64)
+            .bind(current_time())
+            .execute(db.connection()).await
+    }
+
+    /// Get an attachment by its uuid.
+    pub async fn get_by_uuid(db: &mut Database, attachment_uuid: Uuid) -> Result<Option<Attachment>, Error> {
+        let query = db.format_query(&format!("SELECT {} FROM attachments WHERE attachment_uuid = ?", Attachments::COLUMNS));
+        let row = sqlx::query(&query)
+            .bind(attachment_uuid.to_string())
+            .fetch_optional(db.connection()).await?;
+        row.map(|row| Attachment::from_row(&row)).transpose()
+    }
+
+    /// Get every attachment of a thread and its posts, oldest first.
+    pub async fn get_by_thread(db: &mut Database, thread_uuid: Uuid) -> Result<Vec<Attachment>, Error> {
+        let query = db.format_query(&format!("SELECT {} FROM attachments WHERE thread_uuid = ? ORDER BY id ASC", Attachments::COLUMNS));
+        let rows = sqlx::query(&query)
+            .bind(thread_uuid.to_string())
+            .fetch_all(db.connection()).await?;
+        rows.iter().map(Attachment::from_row).collect()
+    }
+
+    /// Get every file uploaded by a user, oldest first.
+    pub async fn get_by_uploader(db: &mut Database, uploader: Uuid) -> Result<Vec<Attachment>, Error> {
+        let query = db.format_query(&format!("SELECT {} FROM attachments WHERE uploader = ? ORDER BY id ASC", Attachments::COLUMNS));
+        let rows = sqlx::query(&query)
+            .bind(uploader.to_string())
+            .fetch_all(db.connection()).await?;
+        rows.iter().map(Attachment::from_row).collect()
+    }
+
+    /// Get the attachments of the threads and posts created by a user, including the files others attached to them.
+    pub async fn get_by_content_of(db: &mut Database, creator: Uuid) -> Result<Vec<Attachment>, Error> {
+        let query = db.format_query(&format!("SELECT {} FROM attachments WHERE thread_uuid IN (SELECT uuid FROM threads WHERE creator = ?) \
+                                              OR post_uuid IN (SELECT uuid FROM posts WHERE creator = ?) ORDER BY id ASC", Attachments::COLUMNS));
+        let rows = sqlx::query(&query)
+            .bind(creator.to_string())
+            .bind(creator.to_string())
+            .fetch_all(db.connection()).await?;
+        rows.iter().map(Attachment::from_row).collect()
+    }
+
+    /// Count the files attached to a thread itself, or to one of its posts.
+    pub async fn count(db: &mut Database, thread_uuid: Uuid, post_uuid: Option<Uuid>) -> Result<i64, Error> {
+        let row = match post_uuid {
+            Some(post_uuid) => {
+                let query = db.format_query("SELECT COUNT(*) AS amount FROM attachments WHERE post_uuid = ?");
+                sqlx::query(&query)
+                    .bind(post_uuid.to_string())
+                    .fetch_one(db.connection()).await?
+            }
+            None => {
+                let query = db.format_query("SELECT COUNT(*) AS amount FROM attachments WHERE thread_uuid = ? AND post_uuid IS NULL");
+                sqlx::query(&query)
+                    .bind(thread_uuid.to_string())
+                    .fetch_one(db.connection()).await?
+            }
+        };
+        row.try_get("amount")
+    }
+
+    /// Count a download of an attachment.
+    pub async fn add_download(db: &mut Database, attachment_uuid: Uuid) -> Result<AnyDone, Error> {
+        let query = db.format_query("UPDATE attachments SET downloads = downloads + 1 WHERE attachment_uuid = ?");
+        sqlx::query(&query)
+            .bind(attachment_uuid.to_string())
+            .execute(db.connection()).await
+    }
+
+    /// Attach an attachment to another thread or post, when threads are merged or split.
+    pub async fn move_to(db: &mut Database, attachment_uuid: Uuid, thread_uuid: Uuid, post_uuid: Option<Uuid>) -> Result<AnyDone, Error> {
+        let query = db.format_query("UPDATE attachments SET thread_uuid = ?, post_uuid = ? WHERE attachment_uuid = ?");
+        sqlx::query(&query)
+            .bind(thread_uuid.to_string())
+            .bind(post_uuid.map(|uuid| uuid.to_string()))
+            .bind(attachment_uuid.to_string())
+            .execute(db.connection()).await
+    }
+
+    /// Give every file uploaded by a user to another uploader. (ex: the nil uuid when the user deletes their account)
+    pub async fn replace_uploader(db: &mut Database, uploader: Uuid, new_uploader: Uuid) -> Result<AnyDone, Error> {
+        let query = db.format_query("UPDATE attachments SET uploader = ? WHERE uploader = ?");
+        sqlx::query(&query)
+            .bind(new_uploader.to_string())
+            .bind(uploader.to_string())
+            .execute(db.connection()).await
+    }
+
+    /// Delete an attachment. The file must be deleted from the attachment storage separately.
+    pub async fn delete(db: &mut Database, attachment_uuid: Uuid) -> Result<AnyDone, Error> {
+        let query = db.format_query("DELETE FROM attachments WHERE attachment_uuid = ?");
+        sqlx::query(&query)
+            .bind(attachment_uuid.to_string())
+            .execute(db.connection()).await
+    }
+}
+
+#[async_trait]
+impl Table for Attachments {
+    async fn create(db: &mut Database) -> Result<AnyDone, Error> {
+        let tp = db.get_type();
+        match tp {
+            DatabaseType::SQLite => {
+                sqlx::query("CREATE TABLE IF NOT EXISTS attachments (\
+                id INTEGER PRIMARY KEY,
+                attachment_uuid VARCHAR(40) NOT NULL,\
+                thread_uuid VARCHAR(40) NOT NULL,\
+                post_uuid VARCHAR(40),\
+                uploader VARCHAR(40) NOT NULL,\
+                file_name VARCHAR(255) NOT NULL,\
+                content_type VARCHAR(100) NOT NULL,\
+                size BIGINT NOT NULL,\
+                storage_key VARCHAR(100) NOT NULL,\
+                thumbnail_key VARCHAR(100),\
+                downloads BIGINT NOT NULL,\
+                created BIGINT NOT NULL\
+                )").execute(db.connection()).await
+            }
+            DatabaseType::MySQL => {
+                sqlx::query("CREATE TABLE IF NOT EXISTS attachments (\
+                id INTEGER PRIMARY KEY AUTO_INCREMENT,
+                attachment_uuid VARCHAR(40) NOT NULL,\
+                thread_uuid VARCHAR(40) NOT NULL,\
+                post_uuid VARCHAR(40),\
+                uploader VARCHAR(40) NOT NULL,\
+                file_name VARCHAR(255) NOT NULL,\
+                content_type VARCHAR(100) NOT NULL,\
+                size BIGINT NOT NULL,\
+                storage_key VARCHAR(100) NOT NULL,\
+                thumbnail_key VARCHAR(100),\
+                downloads BIGINT NOT NULL,\
+                created BIGINT NOT NULL\
+                )").execute(db.connection()).await
+            }
+            DatabaseType::PostgreSQL => {
+                sqlx::query("CREATE TABLE IF NOT EXISTS attachments (\
+                id SERIAL PRIMARY KEY,
+                attachment_uuid VARCHAR(40) NOT NULL,\
+                thread_uuid VARCHAR(40) NOT NULL,\
+                post_uuid VARCHAR(40),\
+                uploader VARCHAR(40) NOT NULL,\
+                file_name VARCHAR(255) NOT NULL,\
+                content_type VARCHAR(100) NOT NULL,\
+                size BIGINT NOT NULL,\
+                storage_key VARCHAR(100) NOT NULL,\
+                thumbnail_key VARCHAR(100),\
+                downloads BIGINT NOT NULL,\
+                created BIGINT NOT NULL\
+                );").execute(db.connection()).await
+            }
+        }
+    }
+
+    async fn drop(db: &mut Database) {
+        sqlx::query("DROP TABLE attachments").execute(db.connection()).await.unwrap();
+    }
+
+    async fn exists(db: &mut Database) -> bool {
+        table_exists(db, "attachments").await
+    }
+}
+
+/// The avatar a user has chosen.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Avatar {
+    None,
+    /// An image the user uploaded, kept in the attachment storage.
+    Uploaded,
+    /// The Gravatar of the email of the user.
+    Gravatar,
+}
+
+impl Avatar {
+    /// The value stored in the database.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Avatar::None => "none",
+            Avatar::Uploaded => "uploaded",
+            Avatar::Gravatar => "gravatar"
+        }
+    }
+
+    /// Read a value stored in the database. Unknown values are no avatar.
+    pub fn parse(value: &str) -> Avatar {
+        match value {
+            "uploaded" => Avatar::Uploaded,
+            "gravatar" => Avatar::Gravatar,
+            _ => Avatar::None
+        }
+    }
+}
+
+/// The public profile of a user from the profiles table.
+#[derive(Debug, Clone, Serialize)]
+pub struct Profile {
+    pub user_uuid: Uuid,
+    pub avatar: Avatar,
+    pub bio: String,
+    /// The text shown under the posts of the user.
+    pub signature: String,
+    pub location: String,
+    pub website: String,
+    /// The unix time the profile was last changed.
+    pub updated: i64,
+}
+
+impl Profile {
+    /// The profile of a user that has not filled in their profile yet.
+    pub fn empty(user_uuid: Uuid) -> Profile {
+        Profile {
+            user_uuid,
+            avatar: Avatar::None,
+            bio: String::new(),
+            signature: String::new(),
+            location: String::new(),
+            website: String::new(),
+            updated: 0,
+        }
+    }
+}
+
+/// The table for the profiles of users. Users that have never edited their profile do not have a row.
+pub struct Profiles {}
+
+impl Profiles {
+    /// Get the profile of a user.
+    pub async fn get(db: &mut Database, user_uuid: Uuid) -> Result<Option<Profile>, Error> {
+        let query = db.format_query("SELECT avatar, bio, signature, location, website, updated FROM profiles WHERE user_uuid = ?");
+        let row = sqlx::query(&query)
+            .bind(user_uuid.to_string())
+            .fetch_optional(db.connection()).await?;
+
+        match row {
+            Some(row) => Ok(Some(Profile {
+                user_uuid,
+                avatar: Avatar::parse(row.try_get::<String, _>("avatar")?.as_str()),
+                bio: row.try_get("bio")?,
+                signature: row.try_get("signature")?,
+                location: row.try_get("location")?,
+                website: row.try_get("website")?,
+                updated: row.try_get("updated")?,
+            })),
+            None => Ok(None)
+        }
+    }
+
+    /// Save the profile of a user, replacing the existing profile.
+    pub async fn save(db: &mut Database, profile: &Profile) -> Result<AnyDone, Error> {
+        Profiles::delete(db, profile.user_uuid).await?;
+        let query = db.format_query("INSERT INTO profiles (user_uuid, avatar, bio, signature, location, website, updated) VALUES (?, ?, ?, ?, ?, ?, ?)");
+        sqlx::query(&query)
+            .bind(profile.user_uuid.to_string())
+            .bind(profile.avatar.as_str())
+            .bind(profile.bio.clone())
+            .bind(profile.signature.clone())
+            .bind(profile.location.clone())
+            .bind(profile.website.clone())
+            .bind(current_time())
+            .execute(db.connection()).await
+    }
+
+    /// Delete the profile of a user. An uploaded avatar must be deleted from the attachment storage separately.
+    pub async fn delete(db: &mut Database, user_uuid: Uuid) -> Result<AnyDone, Error> {
+        let query = db.format_query("DELETE FROM profiles WHERE user_uuid = ?");
+        sqlx::query(&query)
+            .bind(user_uuid.to_string())
+            .execute(db.connection()).await
+    }
+}
+
+#[async_trait]
+impl Table for Profiles {
+    async fn create(db: &mut Database) -> Result<AnyDone, Error> {
+        let tp = db.get_type();
+        match tp {
+            DatabaseType::SQLite => {
+                sqlx::query("CREATE TABLE IF NOT EXISTS profiles (\
+                id INTEGER PRIMARY KEY,
+                user_uuid VARCHAR(40) NOT NULL,\
+                avatar VARCHAR(20) NOT NULL,\
+                bio TEXT NOT NULL,\
+                signature TEXT NOT NULL,\
+                location VARCHAR(255) NOT NULL,\
+                website VARCHAR(255) NOT NULL,\
+                updated BIGINT NOT NULL\
+                )").execute(db.connection()).await
+            }
+            DatabaseType::MySQL => {
+                sqlx::query("CREATE TABLE IF NOT EXISTS profiles (\
+                id INTEGER PRIMARY KEY AUTO_INCREMENT,
+                user_uuid VARCHAR(40) NOT NULL,\
+                avatar VARCHAR(20) NOT NULL,\
+                bio TEXT NOT NULL,\
+                signature TEXT NOT NULL,\
+                location VARCHAR(255) NOT NULL,\
+                website VARCHAR(255) NOT NULL,\
+                updated BIGINT NOT NULL\
+                )").execute(db.connection()).await
+            }
+            DatabaseType::PostgreSQL => {
+                sqlx::query("CREATE TABLE IF NOT EXISTS profiles (\
+                id SERIAL PRIMARY KEY,
+                user_uuid VARCHAR(40) NOT NULL,\
+                avatar VARCHAR(20) NOT NULL,\
+                bio TEXT NOT NULL,\
+                signature TEXT NOT NULL,\
+                location VARCHAR(255) NOT NULL,\
+                website VARCHAR(255) NOT NULL,\
+                updated BIGINT NOT NULL\
+                );").execute(db.connection()).await
+            }
+        }
+    }
+
+    async fn drop(db: &mut Database) {
+        sqlx::query("DROP TABLE profiles").execute(db.connection()).await.unwrap();
+    }
+
+    async fn exists(db: &mut Database) -> bool {
+        table_exists(db, "profiles").await
+    }
+}
+
+/// A private conversation between two or more users from the conversations table.
+#[derive(Debug, Clone, Serialize)]
+pub struct Conversation {
+    pub id: i32,
+    pub uuid: Uuid,
+    pub subject: String,
+    /// The user that started the conversation.
+    pub creator: Uuid,
+    /// The unix time the conversation was started.
+    pub created: i64,
+    /// The unix time of the latest message.
+    pub updated: i64,
+}
+
+impl Conversation {
+    /// Construct a conversation from a row selected with [Conversations::COLUMNS].
+    pub fn from_row(row: &AnyRow) -> Result<Conversation, Error> {
+        Ok(Conversation {
+            id: row.try_get::<i32, _>("id")?,
+            uuid: Uuid::parse_str(row.try_get::<String, _>("conversation_uuid")?.as_str()).unwrap_or_default(),
+            subject: row.try_get("subject")?,
+            creator: Uuid::parse_str(row.try_get::<String, _>("creator")?.as_str()).unwrap_or_default(),
+            created: row.try_get("created")?,
+            updated: row.try_get("updated")?,
+        })
+    }
+}
+
+/// A conversation in the inbox of a user.
+#[derive(Debug, Clone, Serialize)]
+pub struct InboxEntry {
+    pub conversation: Conversation,
+    /// If the conversation has messages the user has not read.
+    pub unread: bool,
+}
+
+/// The table for private conversations. The members and messages are kept in their own tables.
+pub struct Conversations {}
+
+impl Conversations {
+    /// The columns to select for a [Conversation].
+    pub const COLUMNS: &'static str = "id, conversation_uuid, subject, creator, created, updated";
+
+    /// Insert a new conversation.
+    pub async fn insert(db: &mut Database, conversation_uuid: Uuid, subject: String, creator: Uuid) -> Result<AnyDone, Error> {
+        let now = current_time();
+        let query = db.format_query("INSERT INTO conversations (conversation_uuid, subject, creator, created, updated) VALUES (?, ?, ?, ?, ?)");
+        sqlx::query(&query)
+            .bind(conversation_uuid.to_string())
+            .bind(subject)
+            .bind(creator.to_string())
+            .bind(now)
+            .bind(now)
+            .execute(db.connection()).await
+    }
+
+    /// Get a conversation by its uuid.
+    pub async fn get_by_uuid(db: &mut Database, conversation_uuid: Uuid) -> Result<Option<Conversation>, Error> {
+        let query = db.format_query(&format!("SELECT {} FROM conversations WHERE conversation_uuid = ?", Conversations::COLUMNS));
+        let row = sqlx::query(&query)
+            .bind(conversation_uuid.to_string())
+            .fetch_optional(db.connection()).await?;
+        row.map(|row| Conversation::from_row(&row)).transpose()
+    }
+
+    /**
+       Get the inbox of a user, with the most recently updated conversations first.
+
+       Messages from users the member has blocked do not make a conversation unread.
+    */
+    pub async fn get_inbox(db: &mut Database, user_uuid: Uuid, limit: i64) -> Result<Vec<InboxEntry>, Error> {
+        let query = db.format_query("SELECT c.id, c.conversation_uuid, c.subject, c.creator, c.created, c.updated, \
+                                     (SELECT COUNT(*) FROM messages msg WHERE msg.conversation_uuid = c.conversation_uuid AND msg.id > m.last_read \
+                                     AND msg.sender <> ? AND msg.sender NOT IN (SELECT blocked_uuid FROM user_blocks WHERE user_uuid = ?)) AS unread \
+                                     FROM conversations c INNER JOIN conversation_members m ON m.conversation_uuid = c.conversation_uuid \
+                                     WHERE m.user_uuid = ? ORDER BY c.updated DESC, c.id DESC LIMIT ?");
+        let rows = sqlx::query(&query)
+            .bind(user_uuid.to_string())
+            .bind(user_uuid.to_string())
+            .bind(user_uuid.to_string())
+            .bind(limit)
+            .fetch_all(db.connection()).await?;
+        rows.iter().map(|row| Ok(InboxEntry {
+            conversation: Conversation::from_row(row)?,
+            unread: row.try_get::<i64, _>("unread")? > 0,
+        })).collect()
+    }
+
+    /// Count the conversations of a user with messages the user has not read.
+    pub async fn count_unread(db: &mut Database, user_uuid: Uuid) -> Result<i64, Error> {
+        let query = db.format_query("SELECT COUNT(*) AS amount FROM conversation_members m WHERE m.user_uuid = ? AND EXISTS \
+                                     (SELECT 1 FROM messages msg WHERE msg.conversation_uuid = m.conversation_uuid AND msg.id > m.last_read \
+                                     AND msg.sender <> ? AND msg.sender NOT IN (SELECT blocked_uuid FROM user_blocks WHERE user_uuid = ?))");
+        let row = sqlx::query(&query)
+            .bind(user_uuid.to_string())
+            .bind(user_uuid.to_string())
+            .bind(user_uuid.to_string())
+            .fetch_one(db.connection()).await?;
+        row.try_get("amount")
+    }
+
+    /// Set the time of the latest message of a conversation.
+    pub async fn touch(db: &mut Database, conversation_uuid: Uuid) -> Result<AnyDone, Error> {
+        let query = db.format_query("UPDATE conversations SET updated = ? WHERE conversation_uuid = ?");
+        sqlx::query(&query)
+            .bind(current_time())
+            .bind(conversation_uuid.to_string())
+            .execute(db.connection()).await
+    }
+
+    /// Delete a conversation with its members and messages.
+    pub async fn delete(db: &mut Database, conversation_uuid: Uuid) -> Result<(), Error> {
+        for table in ["messages", "conversation_members", "conversations"].iter() {
+            let query = db.format_query(&format!("DELETE FROM {} WHERE conversation_uuid = ?", table));
+            sqlx::query(&query)
+                .bind(conversation_uuid.to_string())
+                .execute(db.connection()).await?;
+        }
+        Ok(())
+    }
+
+    /// Delete every conversation that has no members left.
+    pub async fn delete_empty(db: &mut Database) -> Result<(), Error> {
+        let rows = sqlx::query("SELECT conversation_uuid FROM conversations WHERE conversation_uuid NOT IN (SELECT conversation_uuid FROM conversation_members)")
+            .fetch_all(db.connection()).await?;
+        for row in rows {
+            let conversation_uuid = Uuid::parse_str(row.try_get::<String, _>("conversation_uuid")?.as_str()).unwrap_or_default();
+            Conversations::delete(db, conversation_uuid).await?;
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Table for Conversations {
+    async fn create(db: &mut Database) -> Result<AnyDone, Error> {
+        let tp = db.get_type();
+        match tp {
+            DatabaseType::SQLite => {
+                sqlx::query("CREATE TABLE IF NOT EXISTS conversations (\
+                id INTEGER PRIMARY KEY,
+                conversation_uuid VARCHAR(40) NOT NULL,\
+                subject VARCHAR(255) NOT NULL,\
+                creator VARCHAR(40) NOT NULL,\
+                created BIGINT NOT NULL,\
+                updated BIGINT NOT NULL\
+                )").execute(db.connection()).await
+            }
+            DatabaseType::MySQL => {
+                sqlx::query("CREATE TABLE IF NOT EXISTS conversations (\
+                id INTEGER PRIMARY KEY AUTO_INCREMENT,
+                conversation_uuid VARCHAR(40) NOT NULL,\
+                subject VARCHAR(255) NOT NULL,\
+                creator VARCHAR(40) NOT NULL,\
+                created BIGINT NOT NULL,\
+                updated BIGINT NOT NULL\
+                )").execute(db.connection()).await
+            }
+            DatabaseType::PostgreSQL => {
+                sqlx::query("CREATE TABLE IF NOT EXISTS conversations (\
+                id SERIAL PRIMARY KEY,
+                conversation_uuid VARCHAR(40) NOT NULL,\
+                subject VARCHAR(255) NOT NULL,\
+                creator VARCHAR(40) NOT NULL,\
+                created BIGINT NOT NULL,\
+                updated BIGINT NOT NULL\
+                );").execute(db.connection()).await
+            }
+        }
+    }
+
+    async fn drop(db: &mut Database) {
+        sqlx::query("DROP TABLE conversations").execute(db.connection()).await.unwrap();
+    }
+
+    async fn exists(db: &mut Database) -> bool {
+        table_exists(db, "conversations").await
+    }
+}
+
+/// The table for the members of conversations.
+pub struct ConversationMembers {}
+
+impl ConversationMembers {
+    /// Add a user to a conversation. Everything already in the conversation is marked as read for them.
+    pub async fn insert(db: &mut Database, conversation_uuid: Uuid, user_uuid: Uuid) -> Result<AnyDone, Error> {
+        let query = db.format_query("INSERT INTO conversation_members (conversation_uuid, user_uuid, last_read, joined) VALUES (?, ?, \
+                                     (SELECT COALESCE(MAX(id), 0) FROM messages WHERE conversation_uuid = ?), ?)");
+        sqlx::query(&query)
+            .bind(conversation_uuid.to_string())
+            .bind(user_uuid.to_string())
+            .bind(conversation_uuid.to_string())
+            .bind(current_time())
+            .execute(db.connection()).await
+    }
+
+    /// Get the members of a conversation, in the order they joined.
+    pub async fn get_members(db: &mut Database, conversation_uuid: Uuid) -> Result<Vec<Uuid>, Error> {
+        let query = db.format_query("SELECT user_uuid FROM conversation_members WHERE conversation_uuid = ? ORDER BY id ASC");
+        let rows = sqlx::query(&query)
+            .bind(conversation_uuid.to_string())
+            .fetch_all(db.connection()).await?;
+        rows.iter().map(|row| Ok(Uuid::parse_str(row.try_get::<String, _>("user_uuid")?.as_str()).unwrap_or_default())).collect()
+    }
+
+    /// Check if a user is a member of a conversation.
+    pub async fn is_member(db: &mut Database, conversation_uuid: Uuid, user_uuid: Uuid) -> Result<bool, Error> {
+        let query = db.format_query("SELECT id FROM conversation_members WHERE conversation_uuid = ? AND user_uuid = ?");
+        let row = sqlx::query(&query)
+            .bind(conversation_uuid.to_string())
+            .bind(user_uuid.to_string())
+            .fetch_optional(db.connection()).await?;
+        Ok(row.is_some())
+    }
+
+    /// Count the conversations a user is a member of. This is what the inbox quota limits.
+    pub async fn count_by_user(db: &mut Database, user_uuid: Uuid) -> Result<i64, Error> {
+        let query = db.format_query("SELECT COUNT(*) AS amount FROM conversation_members WHERE user_uuid = ?");
+        let row = sqlx::query(&query)
+            .bind(user_uuid.to_string())
+            .fetch_one(db.connection()).await?;
+        row.try_get("amount")
+    }
+
+    /// Mark every message of a conversation as read by a user.
+    pub async fn mark_read(db: &mut Database, conversation_uuid: Uuid, user_uuid: Uuid) -> Result<AnyDone, Error> {
+        let query = db.format_query("UPDATE conversation_members SET last_read = \
+                                     (SELECT COALESCE(MAX(id), 0) FROM messages WHERE conversation_uuid = ?) WHERE conversation_uuid = ? AND user_uuid = ?");
+        sqlx::query(&query)
+            .bind(conversation_uuid.to_string())
+            .bind(conversation_uuid.to_string())
+            .bind(user_uuid.to_string())
+            .execute(db.connection()).await
+    }
+
+    /// Remove a user from a conversation.
+    pub async fn delete(db: &mut Database, conversation_uuid: Uuid, user_uuid: Uuid) -> Result<AnyDone, Error> {
+        let query = db.format_query("DELETE FROM conversation_members WHERE conversation_uuid = ? AND user_uuid = ?");
+        sqlx::query(&query)
+            .bind(conversation_uuid.to_string())
+            .bind(user_uuid.to_string())
+            .execute(db.connection()).await
+    }
+
+    /// Remove a user from every conversation. Conversations left without members should be deleted after.
+    pub async fn delete_by_user(db: &mut Database, user_uuid: Uuid) -> Result<AnyDone, Error> {
+        let query = db.format_query("DELETE FROM conversation_members WHERE user_uuid = ?");
+        sqlx::query(&query)
+            .bind(user_uuid.to_string())
+            .execute(db.connection()).await
+    }
+}
+
+#[async_trait]
+impl Table for ConversationMembers {
+    async fn create(db: &mut Database) -> Result<AnyDone, Error> {
+        let tp = db.get_type();
+        match tp {
+            DatabaseType::SQLite => {
+                sqlx::query("CREATE TABLE IF NOT EXISTS conversation_members (\
+                id INTEGER PRIMARY KEY,
+                conversation_uuid VARCHAR(40) NOT NULL,\
+                user_uuid VARCHAR(40) NOT NULL,\
+                last_read INTEGER NOT NULL,\
+                joined BIGINT NOT NULL\
+                )").execute(db.connection()).await
+            }
+            DatabaseType::MySQL => {
+                sqlx::query("CREATE TABLE IF NOT EXISTS conversation_members (\
+                id INTEGER PRIMARY KEY AUTO_INCREMENT,
+                conversation_uuid VARCHAR(40) NOT NULL,\
+                user_uuid VARCHAR(40) NOT NULL,\
+                last_read INTEGER NOT NULL,\
+                joined BIGINT NOT NULL\
+                )").execute(db.connection()).await
+            }
+            DatabaseType::PostgreSQL => {
+                sqlx::query("CREATE TABLE IF NOT EXISTS conversation_members (\
+                id SERIAL PRIMARY KEY,
+                conversation_uuid VARCHAR(40) NOT NULL,\
+                user_uuid VARCHAR(40) NOT NULL,\
+                last_read INTEGER NOT NULL,\
+                joined BIGINT NOT NULL\
+                );").execute(db.connection()).await
+            }
+        }
+    }
+
+    async fn drop(db: &mut Database) {
+        sqlx::query("DROP TABLE conversation_members").execute(db.connection()).await.unwrap();
+    }
+
+    async fn exists(db: &mut Database) -> bool {
+        table_exists(db, "conversation_members").await
+    }
+}
+
+/// A private message from the messages table.
+#[derive(Debug, Clone, Serialize)]
+pub struct Message {
+    pub id: i32,
+    pub uuid: Uuid,
+    pub conversation_uuid: Uuid,
+    pub sender: Uuid,
+    pub content: String,
+    /// The unix time the message was sent.
+    pub created: i64,
+}
+
+impl Message {
+    /// Construct a message from a row selected with [Messages::COLUMNS].
+    pub fn from_row(row: &AnyRow) -> Result<Message, Error> {
+        Ok(Message {
+            id: row.try_get::<i32, _>("id")?,
+            uuid: Uuid::parse_str(row.try_get::<String, _>("message_uuid")?.as_str()).unwrap_or_default(),
+            conversation_uuid: Uuid::parse_str(row.try_get::<String, _>("conversation_uuid")?.as_str()).unwrap_or_default(),
+            sender: Uuid::parse_str(row.try_get::<String, _>("sender")?.as_str()).unwrap_or_default(),
+            content: row.try_get("content")?,
+            created: row.try_get("created")?,
+        })
+    }
+}
+
+/// The table for the messages of private conversations.
+pub struct Messages {}
+
+impl Messages {
+    /// The columns to select for a [Message].
+    pub const COLUMNS: &'static str = "id, message_uuid, conversation_uuid, sender, content, created";
+
+    /// Insert a new message.
+    pub async fn insert(db: &mut Database, message_uuid: Uuid, conversation_uuid: Uuid, sender: Uuid, content: String) -> Result<AnyDone, Error> {
+        let query = db.format_query("INSERT INTO messages (message_uuid, conversation_uuid, sender, content, created) VALUES (?, ?, ?, ?, ?)");
+        sqlx::query(&query)
+            .bind(message_uuid.to_string())
+            .bind(conversation_uuid.to_string())
+            .bind(sender.to_string())
+            .bind(content)
+            .bind(current_time())
+            .execute(db.connection()).await
+    }
+
+    /// Get a message by its uuid.
+    pub async fn get_by_uuid(db: &mut Database, message_uuid: Uuid) -> Result<Option<Message>, Error> {
+        let query = db.format_query(&format!("SELECT {} FROM messages WHERE message_uuid = ?", Messages::COLUMNS));
+        let row = sqlx::query(&query)
+            .bind(message_uuid.to_string())
+            .fetch_optional(db.connection()).await?;
+        row.map(|row| Message::from_row(&row)).transpose()
+    }
+
+    /// Get the messages of a conversation, oldest first.
+    pub async fn get_by_conversation(db: &mut Database, conversation_uuid: Uuid) -> Result<Vec<Message>, Error> {
+        let query = db.format_query(&format!("SELECT {} FROM messages WHERE conversation_uuid = ? ORDER BY id ASC", Messages::COLUMNS));
+        let rows = sqlx::query(&query)
+            .bind(conversation_uuid.to_string())
+            .fetch_all(db.connection()).await?;
+        rows.iter().map(Message::from_row).collect()
+    }
+
+    /// Get every message sent by a user, oldest first.
+    pub async fn get_by_sender(db: &mut Database, sender: Uuid) -> Result<Vec<Message>, Error> {
+        let query = db.format_query(&format!("SELECT {} FROM messages WHERE sender = ? ORDER BY id ASC", Messages::COLUMNS));
+        let rows = sqlx::query(&query)
+            .bind(sender.to_string())
+            .fetch_all(db.connection()).await?;
+        rows.iter().map(Message::from_row).collect()
+    }
+
+    /// Give every message of a user to another sender. (ex: the nil uuid when the user deletes their account)
+    pub async fn replace_sender(db: &mut Database, sender: Uuid, new_sender: Uuid) -> Result<AnyDone, Error> {
+        let query = db.format_query("UPDATE messages SET sender = ? WHERE sender = ?");
+        sqlx::query(&query)
+            .bind(new_sender.to_string())
+            .bind(sender.to_string())
+            .execute(db.connection()).await
+    }
+
+    /// Delete a message.
+    pub async fn delete(db: &mut Database, message_uuid: Uuid) -> Result<AnyDone, Error> {
+        let query = db.format_query("DELETE FROM messages WHERE message_uuid = ?");
+        sqlx::query(&query)
+            .bind(message_uuid.to_string())
+            .execute(db.connection()).await
+    }
+
+    /// Delete every message sent by a user.
+    pub async fn delete_by_sender(db: &mut Database, sender: Uuid) -> Result<AnyDone, Error> {
+        let query = db.format_query("DELETE FROM messages WHERE sender = ?");
+        sqlx::query(&query)
+            .bind(sender.to_string())
+            .execute(db.connection()).await
+    }
+}
+
+#[async_trait]
+impl Table for Messages {
+    async fn create(db: &mut Database) -> Result<AnyDone, Error> {
+        let tp = db.get_type();
+        match tp {
+            DatabaseType::SQLite => {
+                sqlx::query("CREATE TABLE IF NOT EXISTS messages (\
+                id INTEGER PRIMARY KEY,
+                message_uuid VARCHAR(40) NOT NULL,\
+                conversation_uuid VARCHAR(40) NOT NULL,\
+                sender VARCHAR(40) NOT NULL,\
+                content TEXT NOT NULL,\
+                created BIGINT NOT NULL\
+                )").execute(db.connection()).await
+            }
+            DatabaseType::MySQL => {
+                sqlx::query("CREATE TABLE IF NOT EXISTS messages (\
+                id INTEGER PRIMARY KEY AUTO_INCREMENT,
+                message_uuid VARCHAR(40) NOT NULL,\
+                conversation_uuid VARCHAR(40) NOT NULL,\
+                sender VARCHAR(40) NOT NULL,\
+                content TEXT NOT NULL,\
+                created BIGINT NOT NULL\
+                )").execute(db.connection()).await
+            }
+            DatabaseType::PostgreSQL => {
+                sqlx::query("CREATE TABLE IF NOT EXISTS messages (\
+                id SERIAL PRIMARY KEY,
+                message_uuid VARCHAR(40) NOT NULL,\
+                conversation_uuid VARCHAR(40) NOT NULL,\
+                sender VARCHAR(40) NOT NULL,\
+                content TEXT NOT NULL,\
+                created BIGINT NOT NULL\
+                );").execute(db.connection()).await
+            }
+        }
+    }
+
+    async fn drop(db: &mut Database) {
+        sqlx::query("DROP TABLE messages").execute(db.connection()).await.unwrap();
+    }
+
+    async fn exists(db: &mut Database) -> bool {
+        table_exists(db, "messages").await
+    }
+}
+
+/// The table for the users each user has blocked. Blocked users cannot message the user, and their messages are hidden from them.
+pub struct UserBlocks {}
+
+impl UserBlocks {
+    /// Block a user. Blocking a user twice does nothing.
+    pub async fn insert(db: &mut Database, user_uuid: Uuid, blocked_uuid: Uuid) -> Result<(), Error> {
+        if UserBlocks::is_blocked(db, user_uuid, blocked_uuid).await? {
+            return Ok(());
+        }
+        let query = db.format_query("INSERT INTO user_blocks (user_uuid, blocked_uuid, created) VALUES (?, ?, ?)");
+        sqlx::query(&query)
+            .bind(user_uuid.to_string())
+            .bind(blocked_uuid.to_string())
+            .bind(current_time())
+            .execute(db.connection()).await?;
+        Ok(())
+    }
+
+    /// Check if a user has blocked another user.
+    pub async fn is_blocked(db: &mut Database, user_uuid: Uuid, blocked_uuid: Uuid) -> Result<bool, Error> {
+        let query = db.format_query("SELECT id FROM user_blocks WHERE user_uuid = ? AND blocked_uuid = ?");
+        let row = sqlx::query(&query)
+            .bind(user_uuid.to_string())
+            .bind(blocked_uuid.to_string())
+            .fetch_optional(db.connection()).await?;
+        Ok(row.is_some())
+    }
+
+    /// Get the users a user has blocked, in the order they were blocked.
+    pub async fn get_blocked(db: &mut Database, user_uuid: Uuid) -> Result<Vec<Uuid>, Error> {
+        let query = db.format_query("SELECT blocked_uuid FROM user_blocks WHERE user_uuid = ? ORDER BY id ASC");
+        let rows = sqlx::query(&query)
+            .bind(user_uuid.to_string())
+            .fetch_all(db.connection()).await?;
+        rows.iter().map(|row| Ok(Uuid::parse_str(row.try_get::<String, _>("blocked_uuid")?.as_str()).unwrap_or_default())).collect()
+    }
+
+    /// Unblock a user.
+    pub async fn delete(db: &mut Database, user_uuid: Uuid, blocked_uuid: Uuid) -> Result<AnyDone, Error> {
+        let query = db.format_query("DELETE FROM user_blocks WHERE user_uuid = ? AND blocked_uuid = ?");
+        sqlx::query(&query)
+            .bind(user_uuid.to_string())
+            .bind(blocked_uuid.to_string())
+            .execute(db.connection()).await
+    }
+
+    /// Delete the blocks made by a user and the blocks of the user made by others.
+    pub async fn delete_by_user(db: &mut Database, user_uuid: Uuid) -> Result<AnyDone, Error> {
+        let query = db.format_query("DELETE FROM user_blocks WHERE user_uuid = ? OR blocked_uuid = ?");
+        sqlx::query(&query)
+            .bind(user_uuid.to_string())
+            .bind(user_uuid.to_string())
+            .execute(db.connection()).await
+    }
+}
+
+#[async_trait]
+impl Table for UserBlocks {
+    async fn create(db: &mut Database) -> Result<AnyDone, Error> {
+        let tp = db.get_type();
+        match tp {
+            DatabaseType::SQLite => {
+                sqlx::query("CREATE TABLE IF NOT EXISTS user_blocks (\
+                id INTEGER PRIMARY KEY,
+                user_uuid VARCHAR(40) NOT NULL,\
+                blocked_uuid VARCHAR(40) NOT NULL,\
+                created BIGINT NOT NULL\
+                )").execute(db.connection()).await
+            }
+            DatabaseType::MySQL => {
+                sqlx::query("CREATE TABLE IF NOT EXISTS user_blocks (\
+                id INTEGER PRIMARY KEY AUTO_INCREMENT,
+                user_uuid VARCHAR(40) NOT NULL,\
+                blocked_uuid VARCHAR(40) NOT NULL,\
+                created BIGINT NOT NULL\
+                )").execute(db.connection()).await
+            }
+            DatabaseType::PostgreSQL => {
+                sqlx::query("CREATE TABLE IF NOT EXISTS user_blocks (\
+                id SERIAL PRIMARY KEY,
+                user_uuid VARCHAR(40) NOT NULL,\
+                blocked_uuid VARCHAR(40) NOT NULL,\
+                created BIGINT NOT NULL\
+                );").execute(db.connection()).await
+            }
+        }
+    }
+
+    async fn drop(db: &mut Database) {
+        sqlx::query("DROP TABLE user_blocks").execute(db.connection()).await.unwrap();
+    }
+
+    async fn exists(db: &mut Database) -> bool {
+        table_exists(db, "user_blocks").await
+    }
+}
+
+/// What a report is about.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReportTarget {
+    Thread,
+    Post,
+    /// The profile of a user.
+    User,
+    /// A private message.
+    Message,
+}
+
+impl ReportTarget {
+    /// The value stored in the database and used in forms.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReportTarget::Thread => "thread",
+            ReportTarget::Post => "post",
+            ReportTarget::User => "user",
+            ReportTarget::Message => "message"
+        }
+    }
+
+    /// Read a value stored in the database or sent in a form.
+    pub fn parse(value: &str) -> Option<ReportTarget> {
+        match value {
+            "thread" => Some(ReportTarget::Thread),
+            "post" => Some(ReportTarget::Post),
+            "user" => Some(ReportTarget::User),
+            "message" => Some(ReportTarget::Message),
+            _ => None
+        }
+    }
+}
+
+/// A report of content or a user from the reports table.
+#[derive(Debug, Clone, Serialize)]
+pub struct Report {
+    pub id: i32,
+    pub uuid: Uuid,
+    pub target_type: ReportTarget,
+    pub target_uuid: Uuid,
+    /// The user that made the report.
+    pub reporter: Uuid,
+    pub reason: String,
+    /// The unix time the report was made.
+    pub created: i64,
+    /// The action a moderator took to close the report. None while the report is open.
+    pub resolution: Option<String>,
+    /// The moderator that closed the report.
+    pub resolved_by: Option<Uuid>,
+    /// The unix time the report was closed.
+    pub resolved: Option<i64>,
+}
+
+impl Report {
+    /// Construct a report from a row selected with [Reports::COLUMNS].
+    pub fn from_row(row: &AnyRow) -> Result<Report, Error> {
+        Ok(Report {
+            id: row.try_get::<i32, _>("id")?,
+            uuid: Uuid::parse_str(row.try_get::<String, _>("report_uuid")?.as_str()).unwrap_or_default(),
+            target_type: ReportTarget::parse(row.try_get::<String, _>("target_type")?.as_str()).unwrap_or(ReportTarget::Post),
+            target_uuid: Uuid::parse_str(row.try_get::<String, _>("target_uuid")?.as_str()).unwrap_or_default(),
+            reporter: Uuid::parse_str(row.try_get::<String, _>("reporter")?.as_str()).unwrap_or_default(),
+            reason: row.try_get("reason")?,
+            created: row.try_get("created")?,
+            resolution: row.try_get("resolution")?,
+            resolved_by: row.try_get::<Option<String>, _>("resolved_by")?.and_then(|uuid| Uuid::parse_str(uuid.as_str()).ok()),
+            resolved: row.try_get("resolved")?,
+        })
+    }
+}
+
+/// The table for reports made by users. Moderators close every open report of a target at once.
+pub struct Reports {}
+
+impl Reports {
+    /// The columns to select for a [Report].
+    pub const COLUMNS: &'static str = "id, report_uuid, target_type, target_uuid, reporter, reason, created, resolution, resolved_by, resolved";
+
+    /// Insert a new open report.
+    pub async fn insert(db: &mut Database, report_uuid: Uuid, target_type: ReportTarget, target_uuid: Uuid, reporter: Uuid, reason: String) -> Result<AnyDone, Error> {
+        let query = db.format_query("INSERT INTO reports (report_uuid, target_type, target_uuid, reporter, reason, created) VALUES (?, ?, ?, ?, ?, ?)");
+        sqlx::query(&query)
+            .bind(report_uuid.to_string())
+            .bind(target_type.as_str())
+            .bind(target_uuid.to_string())
+            .bind(reporter.to_string())
+            .bind(reason)
+            .bind(current_time())
+            .execute(db.connection()).await
+    }
+
+    /// Check if a user already has an open report of a target.
+    pub async fn has_open_report(db: &mut Database, target_type: ReportTarget, target_uuid: Uuid, reporter: Uuid) -> Result<bool, Error> {
+        let query = db.format_query("SELECT id FROM reports WHERE target_type = ? AND target_uuid = ? AND reporter = ? AND resolved IS NULL");
+        let row = sqlx::query(&query)
+            .bind(target_type.as_str())
+            .bind(target_uuid.to_string())
+            .bind(reporter.to_string())
+            .fetch_optional(db.connection()).await?;
+        Ok(row.is_some())
+    }
+
+    /// Get every open report, oldest first.
+    pub async fn get_open(db: &mut Database) -> Result<Vec<Report>, Error> {
+        let query = format!("SELECT {} FROM reports WHERE resolved IS NULL ORDER BY id ASC", Reports::COLUMNS);
+        let rows = sqlx::query(&query)
+            .fetch_all(db.connection()).await?;
+        rows.iter().map(Report::from_row).collect()
+    }
+
+    /// Get the most recently closed reports, newest first.
+    pub async fn get_resolved(db: &mut Database, limit: i64) -> Result<Vec<Report>, Error> {
+        let query = db.format_query(&format!("SELECT {} FROM reports WHERE resolved IS NOT NULL ORDER BY resolved DESC, id DESC LIMIT ?", Reports::COLUMNS));
+        let rows = sqlx::query(&query)
+            .bind(limit)
+            .fetch_all(db.connection()).await?;
+        rows.iter().map(Report::from_row).collect()
+    }
+
+    /// Get every report made by a user, oldest first.
+    pub async fn get_by_reporter(db: &mut Database, reporter: Uuid) -> Result<Vec<Report>, Error> {
+        let query = db.format_query(&format!("SELECT {} FROM reports WHERE reporter = ? ORDER BY id ASC", Reports::COLUMNS));
+        let rows = sqlx::query(&query)
+            .bind(reporter.to_string())
+            .fetch_all(db.connection()).await?;
+        rows.iter().map(Report::from_row).collect()
+    }
+
+    /// Close every open report of a target, recording the action taken and the moderator that took it.
+    pub async fn resolve(db: &mut Database, target_type: ReportTarget, target_uuid: Uuid, resolution: &str, moderator: Uuid) -> Result<AnyDone, Error> {
+        let query = db.format_query("UPDATE reports SET resolution = ?, resolved_by = ?, resolved = ? WHERE target_type = ? AND target_uuid = ? AND resolved IS NULL");
+        sqlx::query(&query)
+            .bind(resolution)
+            .bind(moderator.to_string())
+            .bind(current_time())
+            .bind(target_type.as_str())
+            .bind(target_uuid.to_string())
+            .execute(db.connection()).await
+    }
+
+    /// Give every report made by a user to another reporter. (ex: the nil uuid when the user deletes their account)
+    pub async fn replace_reporter(db: &mut Database, reporter: Uuid, new_reporter: Uuid) -> Result<AnyDone, Error> {
+        let query = db.format_query("UPDATE reports SET reporter = ? WHERE reporter = ?");
+        sqlx::query(&query)
+            .bind(new_reporter.to_string())
+            .bind(reporter.to_string())
+            .execute(db.connection()).await
+    }
+}
+
+#[async_trait]
+impl Table for Reports {
+    async fn create(db: &mut Database) -> Result<AnyDone, Error> {
+        let tp = db.get_type();
+        match tp {
+            DatabaseType::SQLite => {
+                sqlx::query("CREATE TABLE IF NOT EXISTS reports (\
+                id INTEGER PRIMARY KEY,
+                report_uuid VARCHAR(40) NOT NULL,\
+                target_type VARCHAR(20) NOT NULL,\
+                target_uuid VARCHAR(40) NOT NULL,\
+                reporter VARCHAR(40) NOT NULL,\
+                reason TEXT NOT NULL,\
+                created BIGINT NOT NULL,\
+                resolution VARCHAR(20),\
+                resolved_by VARCHAR(40),\
+                resolved BIGINT\
+                )").execute(db.connection()).await
+            }
+            DatabaseType::MySQL => {
+                sqlx::query("CREATE TABLE IF NOT EXISTS reports (\
+                id INTEGER PRIMARY KEY AUTO_INCREMENT,
+                report_uuid VARCHAR(40) NOT NULL,\
+                target_type VARCHAR(20) NOT NULL,\
+                target_uuid VARCHAR(40) NOT NULL,\
+                reporter VARCHAR(40) NOT NULL,\
+                reason TEXT NOT NULL,\
+                created BIGINT NOT NULL,\
+                resolution VARCHAR(20),\
+                resolved_by VARCHAR(40),\
+                resolved BIGINT\
+                )").execute(db.connection()).await
+            }
+            DatabaseType::PostgreSQL => {
+                sqlx::query("CREATE TABLE IF NOT EXISTS reports (\
+                id SERIAL PRIMARY KEY,
+                report_uuid VARCHAR(40) NOT NULL,\
+                target_type VARCHAR(20) NOT NULL,\
+                target_uuid VARCHAR(40) NOT NULL,\
+                reporter VARCHAR(40) NOT NULL,\
+                reason TEXT NOT NULL,\
+                created BIGINT NOT NULL,\
+                resolution VARCHAR(20),\
+                resolved_by VARCHAR(40),\
+                resolved BIGINT\
+                );").execute(db.connection()).await
+            }
+        }
+    }
+
+    async fn drop(db: &mut Database) {
+        sqlx::query("DROP TABLE reports").execute(db.connection()).await.unwrap();
+    }
+
+    async fn exists(db: &mut Database) -> bool {
+        table_exists(db, "reports").await
+    }
+}
+
+/// An admin or moderator action from the audit log.
+#[derive(Debug, Clone, Serialize)]
+pub struct AuditEntry {
+    pub id: i32,
+    /// The user that took the action. The nil uuid for actions taken from the command line.
+    pub actor: Uuid,
+    pub action: String,
+    /// What the action was taken on, usually a uuid or the name of a settings section.
+    pub target: String,
+    /// The state of the target before the action as JSON, if it was recorded.
+    pub old_value: Option<String>,
+    /// The state of the target after the action as JSON, if it was recorded.
+    pub new_value: Option<String>,
+    pub ip: String,
+    /// The unix time of the action.
+    pub created: i64,
+}
+
+impl AuditEntry {
+    /// Construct an entry from a row selected with [AuditLog::COLUMNS].
+    pub fn from_row(row: &AnyRow) -> Result<AuditEntry, Error> {
+        Ok(AuditEntry {
+            id: row.try_get::<i32, _>("id")?,
+            actor: Uuid::parse_str(row.try_get::<String, _>("actor")?.as_str()).unwrap_or_default(),
+            action: row.try_get("action")?,
+            target: row.try_get("target")?,
+            old_value: row.try_get("old_value")?,
+            new_value: row.try_get("new_value")?,
+            ip: row.try_get("ip")?,
+            created: row.try_get("created")?,
+        })
+    }
+}
+
+/**
+   The append-only log of admin and moderator actions.
+
+   Entries are never changed. They are only deleted once they are older than the retention of the audit settings.
+*/
+pub struct AuditLog {}
+
+impl AuditLog {
+    /// The columns to select for an [AuditEntry].
+    pub const COLUMNS: &'static str = "id, actor, action, target, old_value, new_value, ip, created";
+
+    /// Add an entry to the log.
+    pub async fn insert(db: &mut Database, actor: Uuid, action: &str, target: &str, old_value: Option<String>, new_value: Option<String>, ip: &str) -> Result<AnyDone, Error> {
+        let query = db.format_query("INSERT INTO audit_log (actor, action, target, old_value, new_value, ip, created) VALUES (?, ?, ?, ?, ?, ?, ?)");
+        sqlx::query(&query)
+            .bind(actor.to_string())
+            .bind(action.to_string())
+            .bind(target.to_string())
+            .bind(old_value)
+            .bind(new_value)
+            .bind(ip.to_string())
+            .bind(current_time())
+            .execute(db.connection()).await
+    }
+
+    /**
+       Search the log, newest first.
+
+       ## Params
+       actor - Only entries of this user.<br>
+       action - Only entries of this action.<br>
+       from - Only entries made at or after this unix time.<br>
+       until - Only entries made before this unix time.<br>
+       limit - The most entries to get.
+    */
+    pub async fn search(db: &mut Database, actor: Option<Uuid>, action: Option<&str>, from: Option<i64>, until: Option<i64>, limit: i64) -> Result<Vec<AuditEntry>, Error> {
+        let mut conditions = vec!["1 = 1"];
+        if actor.is_some() { conditions.push("actor = ?"); }
+        if action.is_some() { conditions.push("action = ?"); }
+        if from.is_some() { conditions.push("created >= ?"); }
+        if until.is_some() { conditions.push("created < ?"); }
+
+        let sql = db.format_query(&format!("SELECT {} FROM audit_log WHERE {} ORDER BY created DESC, id DESC LIMIT ?", AuditLog::COLUMNS, conditions.join(" AND ")));
+        let mut query = sqlx::query(&sql);
+        if let Some(actor) = actor { query = query.bind(actor.to_string()); }
+        if let Some(action) = action { query = query.bind(action.to_string()); }
+        if let Some(from) = from { query = query.bind(from); }
+        if let Some(until) = until { query = query.bind(until); }
+        let rows = query.bind(limit).fetch_all(db.connection()).await?;
+        rows.iter().map(AuditEntry::from_row).collect()
+    }
+
+    /// Get every entry of a user, oldest first.
+    pub async fn get_by_actor(db: &mut Database, actor: Uuid) -> Result<Vec<AuditEntry>, Error> {
+        let query = db.format_query(&format!("SELECT {} FROM audit_log WHERE actor = ? ORDER BY id ASC", AuditLog::COLUMNS));
+        let rows = sqlx::query(&query)
+            .bind(actor.to_string())
+            .fetch_all(db.connection()).await?;
+        rows.iter().map(AuditEntry::from_row).collect()
+    }
+
+    /// Delete the entries made before a unix time.
+    pub async fn delete_older_than(db: &mut Database, created: i64) -> Result<AnyDone, Error> {
+        let query = db.format_query("DELETE FROM audit_log WHERE created < ?");
+        sqlx::query(&query)
+            .bind(created)
+            .execute(db.connection()).await
+    }
+}
+
+#[async_trait]
+impl Table for AuditLog {
+    async fn create(db: &mut Database) -> Result<AnyDone, Error> {
+        let tp = db.get_type();
+        match tp {
+            DatabaseType::SQLite => {
+                sqlx::query("CREATE TABLE IF NOT EXISTS audit_log (\
+                id INTEGER PRIMARY KEY,
+                actor VARCHAR(40) NOT NULL,\
+                action VARCHAR(40) NOT NULL,\
+                target VARCHAR(255) NOT NULL,\
+                old_value TEXT,\
+                new_value TEXT,\
+                ip VARCHAR(64) NOT NULL,\
+                created BIGINT NOT NULL\
+                )").execute(db.connection()).await
+            }
+            DatabaseType::MySQL => {
+                sqlx::query("CREATE TABLE IF NOT EXISTS audit_log (\
+                id INTEGER PRIMARY KEY AUTO_INCREMENT,
+                actor VARCHAR(40) NOT NULL,\
+                action VARCHAR(40) NOT NULL,\
+                target VARCHAR(255) NOT NULL,\
+                old_value TEXT,\
+                new_value TEXT,\
+                ip VARCHAR(64) NOT NULL,\
+                created BIGINT NOT NULL\
+                )").execute(db.connection()).await
+            }
+            DatabaseType::PostgreSQL => {
+                sqlx::query("CREATE TABLE IF NOT EXISTS audit_log (\
+                id SERIAL PRIMARY KEY,
+                actor VARCHAR(40) NOT NULL,\
+                action VARCHAR(40) NOT NULL,\
+                target VARCHAR(255) NOT NULL,\
+                old_value TEXT,\
+                new_value TEXT,\
+                ip VARCHAR(64) NOT NULL,\
+                created BIGINT NOT NULL\
+                );").execute(db.connection()).await
+            }
+        }
+    }
+
+    async fn drop(db: &mut Database) {
+        sqlx::query("DROP TABLE audit_log").execute(db.connection()).await.unwrap();
+    }
+
+    async fn exists(db: &mut Database) -> bool {
+        table_exists(db, "audit_log").await
+    }
+}
+
+/// The table that stores the version of the schema. It has a single row once the database has been versioned.
+pub struct SchemaVersion {}
+
+impl SchemaVersion {
+    /// Get the version of the schema, none if the database was created before the schema was versioned.
+    pub async fn get(db: &mut Database) -> Result<Option<i32>, Error> {
+        let row = sqlx::query("SELECT version FROM schema_version")
+            .fetch_optional(db.connection()).await;
+
+        match row {
+            Ok(Some(row)) => Ok(Some(row.try_get("version")?)),
+            Ok(None) => Ok(None),
+            // The table does not exist yet.
+            Err(Error::Database(_)) => Ok(None),
+            Err(err) => Err(err)
+        }
+    }
+
+    /// Set the version of the schema.
+    pub async fn set(db: &mut Database, version: i32) -> Result<(), Error> {
+        SchemaVersion::create(db).await?;
+        sqlx::query("DELETE FROM schema_version").execute(db.connection()).await?;
+
+        let query = db.format_query("INSERT INTO schema_version (version) VALUES (?)");
+        sqlx::query(&query)
+            .bind(version)
+            .execute(db.connection()).await?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Table for SchemaVersion {
+    async fn create(db: &mut Database) -> Result<AnyDone, Error> {
+        sqlx::query("CREATE TABLE IF NOT EXISTS schema_version (\
+        version INTEGER NOT NULL\
+        )").execute(db.connection()).await
+    }
+
+    async fn drop(db: &mut Database) {
+        sqlx::query("DROP TABLE schema_version").execute(db.connection()).await.unwrap();
+    }
+
+    async fn exists(db: &mut Database) -> bool {
+        table_exists(db, "schema_version").await
+    }
+}
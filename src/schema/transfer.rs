@@ -0,0 +1,427 @@
+use sqlx::any::AnyRow;
+use sqlx::{Connection, Error, Row};
+
+use crate::schema::database::Database;
+use crate::settings::DatabaseType;
+
+use self::ColumnKind::{BigInt, Bool, Int, Real, Text, Timestamp};
+
+/// How a column is read from one type of database and written to another.
+#[derive(Debug, Clone, Copy)]
+pub enum ColumnKind {
+    Text,
+    /// A 32 bit integer. Small integer columns are read with [Database::int_column].
+    Int,
+    BigInt,
+    Bool,
+    Real,
+    /// A timestamp column. It is copied as unix time so every type of database reads it the same way.
+    Timestamp,
+}
+
+/// A table and the columns that are copied. The first column is the key the rows are ordered by.
+pub struct TableSpec {
+    pub name: &'static str,
+    pub columns: &'static [(&'static str, ColumnKind)],
+    /// If the key column is a generated id that the database keeps a sequence for.
+    pub serial: bool,
+}
+
+/// Every table of the forum. New tables must be added here so they are reported on and copied between databases.
+pub const TABLES: [TableSpec; 23] = [
+    TableSpec { name: "users", serial: true, columns: &[("id", Int), ("uuid", Text), ("username", Text), ("email", Text), ("password", Text),
+        ("is_banned", Bool), ("is_admin", Bool), ("is_moderator", Bool), ("password_reset", Bool), ("date", Timestamp)] },
+    TableSpec { name: "sessions", serial: true, columns: &[("id", Int), ("session_uuid", Text), ("user_uuid", Text), ("session_creation", BigInt)] },
+    TableSpec { name: "api_tokens", serial: true, columns: &[("id", Int), ("token_uuid", Text), ("user_uuid", Text), ("name", Text),
+        ("token_hash", Text), ("scopes", Text), ("client_uuid", Text), ("created", BigInt), ("last_used", BigInt)] },
+    TableSpec { name: "oauth_clients", serial: true, columns: &[("id", Int), ("client_uuid", Text), ("owner_uuid", Text), ("name", Text),
+        ("redirect_uri", Text), ("secret_hash", Text), ("created", BigInt)] },
+    TableSpec { name: "threads", serial: true, columns: &[("id", Int), ("uuid", Text), ("forum_uuid", Text), ("name", Text), ("content", Text),
+        ("creator", Text), ("locked", Int), ("pinned", Int), ("date", Timestamp)] },
+    TableSpec { name: "posts", serial: true, columns: &[("id", Int), ("uuid", Text), ("thread_uuid", Text), ("creator", Text), ("content", Text),
+        ("date", Timestamp)] },
+    TableSpec { name: "forums", serial: true, columns: &[("id", Int), ("uuid", Text), ("name", Text), ("caption", Text), ("date", Timestamp)] },
+    TableSpec { name: "banned_users", serial: true, columns: &[("id", Int), ("user_uuid", Text), ("ban_date", Int), ("unban_date", Int),
+        ("date", Timestamp)] },
+    TableSpec { name: "two_factor", serial: true, columns: &[("id", Int), ("user_uuid", Text), ("secret", Text), ("enabled", Bool),
+        ("last_step", BigInt), ("date", Timestamp)] },
+    TableSpec { name: "recovery_codes", serial: true, columns: &[("id", Int), ("user_uuid", Text), ("code_hash", Text), ("date", Timestamp)] },
+    TableSpec { name: "login_attempts", serial: true, columns: &[("id", Int), ("username", Text), ("ip", Text), ("successful", Bool),
+        ("attempt_time", BigInt)] },
+    TableSpec { name: "rate_limit_buckets", serial: false, columns: &[("bucket_key", Text), ("tokens", Real), ("updated", BigInt)] },
+    TableSpec { name: "webhooks", serial: true, columns: &[("id", Int), ("webhook_uuid", Text), ("url", Text), ("secret", Text), ("events", Text),
+        ("enabled", Bool), ("created", BigInt)] },
+    TableSpec { name: "webhook_deliveries", serial: true, columns: &[("id", Int), ("delivery_uuid", Text), ("webhook_uuid", Text), ("event", Text),
+        ("payload", Text), ("status", Text), ("attempts", BigInt), ("response_status", BigInt), ("last_error", Text), ("next_attempt", BigInt),
+        ("created", BigInt), ("updated", BigInt)] },
+    TableSpec { name: "import_map", serial: true, columns: &[("id", Int), ("source", Text), ("kind", Text), ("source_id", Text),
+        ("target_uuid", Text), ("date", Timestamp)] },
+    TableSpec { name: "attachments", serial: true, columns: &[("id", Int), ("attachment_uuid", Text), ("thread_uuid", Text), ("post_uuid", Text),
+        ("uploader", Text), ("file_name", Text), ("content_type", Text), ("size", BigInt), ("storage_key", Text), ("thumbnail_key", Text),
+        ("downloads", BigInt), ("created", BigInt)] },
+    TableSpec { name: "profiles", serial: true, columns: &[("id", Int), ("user_uuid", Text), ("avatar", Text), ("bio", Text),
+        ("signature", Text), ("location", Text), ("website", Text), ("updated", BigInt)] },
+    TableSpec { name: "conversations", serial: true, columns: &[("id", Int), ("conversation_uuid", Text), ("subject", Text), ("creator", Text),
+        ("created", BigInt), ("updated", BigInt)] },
+    TableSpec { name: "conversation_members", serial: true, columns: &[("id", Int), ("conversation_uuid", Text), ("user_uuid", Text),
+        ("last_read", Int), ("joined", BigInt)] },
+    TableSpec { name: "messages", serial: true, columns: &[("id", Int), ("message_uuid", Text), ("conversation_uuid", Text), ("sender", Text),
+        ("content", Text), ("created", BigInt)] },
+    TableSpec { name: "user_blocks", serial: true, columns: &[("id", Int), ("user_uuid", Text), ("blocked_uuid", Text), ("created", BigInt)] },
+    TableSpec { name: "reports", serial: true, columns: &[("id", Int), ("report_uuid", Text), ("target_type", Text), ("target_uuid", Text),
+        ("reporter", Text), ("reason", Text), ("created", BigInt), ("resolution", Text), ("resolved_by", Text), ("resolved", BigInt)] },
+    TableSpec { name: "audit_log", serial: true, columns: &[("id", Int), ("actor", Text), ("action", Text), ("target", Text), ("old_value", Text),
+        ("new_value", Text), ("ip", Text), ("created", BigInt)] },
+];
+
+/// A value read from a column. Every value can be null.
+#[derive(Debug, PartialEq)]
+enum Value {
+    Text(Option<String>),
+    Int(Option<i32>),
+    BigInt(Option<i64>),
+    Bool(Option<bool>),
+    Real(Option<f64>),
+}
+
+/// The amount of rows copied for a table.
+#[derive(Debug, Clone)]
+pub struct CopiedTable {
+    pub name: &'static str,
+    pub rows: i64,
+}
+
+/// Build the select for a table. Timestamps and small integers are converted so every database returns the same types.
+fn select_query(db: &Database, table: &TableSpec, batch_size: i64, offset: i64) -> String {
+    let columns: Vec<String> = table.columns.iter()
+        .map(|(column, kind)| match kind {
+            Timestamp => format!("{} AS {}", db.unix_time(column), column),
+            Int => format!("{} AS {}", db.int_column(column), column),
+            _ => column.to_string()
+        })
+        .collect();
+
+    format!("SELECT {} FROM {} ORDER BY {} LIMIT {} OFFSET {}", columns.join(", "), table.name, table.columns[0].0, batch_size, offset)
+}
+
+/// Build the insert for the columns of a table.
+fn insert_query(db: &Database, table: &str, columns: &[(&str, ColumnKind)]) -> String {
+    let names: Vec<&str> = columns.iter().map(|(column, _)| *column).collect();
+    let values: Vec<String> = columns.iter()
+        .map(|(_, kind)| match kind {
+            Timestamp => db.from_unix_time("?"),
+            _ => String::from("?")
+        })
+        .collect();
+
+    db.format_query(&format!("INSERT INTO {} ({}) VALUES ({})", table, names.join(", "), values.join(", ")))
+}
+
+/// Read the values of a row in the order of the columns of the table.
+fn read_row(row: &AnyRow, table: &TableSpec) -> Result<Vec<Value>, Error> {
+    table.columns.iter()
+        .map(|(column, kind)| Ok(match kind {
+            Text => Value::Text(row.try_get(*column)?),
+            Int => Value::Int(row.try_get(*column)?),
+            BigInt | Timestamp => Value::BigInt(row.try_get(*column)?),
+            Bool => Value::Bool(row.try_get(*column)?),
+            Real => Value::Real(row.try_get(*column)?),
+        }))
+        .collect()
+}
+
+/// Count the rows of a table.
+pub async fn count_rows(db: &mut Database, table: &str) -> Result<i64, Error> {
+    let row = sqlx::query(&format!("SELECT COUNT(*) AS amount FROM {}", table))
+        .fetch_one(db.connection()).await?;
+    row.try_get("amount")
+}
+
+/// Read a batch of rows of a table in the order of its key.
+async fn read_rows(db: &mut Database, table: &TableSpec, batch_size: i64, offset: i64) -> Result<Vec<Vec<Value>>, Error> {
+    let rows = sqlx::query(&select_query(db, table, batch_size, offset))
+        .fetch_all(db.connection()).await?;
+    rows.iter().map(|row| read_row(row, table)).collect()
+}
+
+/// Write rows to a table in a single transaction.
+///
+/// # Params
+/// columns - The columns of the rows, in the order of their values. The other columns of the table keep their defaults.
+async fn write_rows(db: &mut Database, table: &TableSpec, columns: &[(&str, ColumnKind)], rows: Vec<Vec<Value>>) -> Result<(), Error> {
+    let insert = insert_query(db, table.name, columns);
+    let mut transaction = db.connection().begin().await?;
+    for row in rows {
+        let mut query = sqlx::query(&insert);
+        for value in row {
+            query = match value {
+                Value::Text(value) => query.bind(value),
+                Value::Int(value) => query.bind(value),
+                Value::BigInt(value) => query.bind(value),
+                Value::Bool(value) => query.bind(value),
+                Value::Real(value) => query.bind(value),
+            };
+        }
+        query.execute(&mut transaction).await?;
+    }
+    transaction.commit().await
+}
+
+/// Copy every row of a table in batches. Each batch is written in a single transaction.
+async fn copy_table(source: &mut Database, target: &mut Database, table: &TableSpec, batch_size: i64) -> Result<i64, Error> {
+    let mut copied = 0;
+
+    loop {
+        let rows = read_rows(source, table, batch_size, copied).await?;
+        let amount = rows.len() as i64;
+        if amount == 0 {
+            break;
+        }
+
+        write_rows(target, table, table.columns, rows).await?;
+        copied += amount;
+        println!("[INFO] {}: copied {} rows", table.name, copied);
+
+        if amount < batch_size {
+            break;
+        }
+    }
+
+    Ok(copied)
+}
+
+/// Convert a value to JSON.
+fn to_json(value: Value) -> serde_json::Value {
+    match value {
+        Value::Text(value) => value.map(serde_json::Value::from),
+        Value::Int(value) => value.map(serde_json::Value::from),
+        Value::BigInt(value) => value.map(serde_json::Value::from),
+        Value::Bool(value) => value.map(serde_json::Value::from),
+        Value::Real(value) => value.map(serde_json::Value::from),
+    }.unwrap_or(serde_json::Value::Null)
+}
+
+/// Convert JSON to a value of a column. Flags that were written as numbers are read as well.
+fn from_json(value: &serde_json::Value, kind: ColumnKind) -> Option<Value> {
+    if value.is_null() {
+        return Some(match kind {
+            Text => Value::Text(None),
+            Int => Value::Int(None),
+            BigInt | Timestamp => Value::BigInt(None),
+            Bool => Value::Bool(None),
+            Real => Value::Real(None),
+        });
+    }
+
+    Some(match kind {
+        Text => Value::Text(Some(value.as_str()?.to_string())),
+        Int => Value::Int(Some(value.as_i64()? as i32)),
+        BigInt | Timestamp => Value::BigInt(Some(value.as_i64()?)),
+        Bool => Value::Bool(Some(value.as_bool().or_else(|| value.as_i64().map(|value| value != 0))?)),
+        Real => Value::Real(Some(value.as_f64()?)),
+    })
+}
+
+/**
+   Read a batch of rows of a table as JSON objects keyed by column. Timestamps are unix time.
+
+   ## Returns
+   The rows, fewer than the batch size once the end of the table is reached.
+*/
+pub async fn export_rows(db: &mut Database, table: &TableSpec, batch_size: i64, offset: i64) -> Result<Vec<serde_json::Map<String, serde_json::Value>>, Error> {
+    let rows = read_rows(db, table, batch_size, offset).await?;
+    Ok(rows.into_iter()
+        .map(|row| table.columns.iter().map(|(column, _)| column.to_string()).zip(row.into_iter().map(to_json)).collect())
+        .collect())
+}
+
+/**
+   Write rows read by [export_rows] to a table in a single transaction.
+
+   ## Params
+   columns - The columns the rows have. Columns of the table that are missing, such as columns added by a newer
+   version of the schema, keep their defaults.
+*/
+pub async fn import_rows(db: &mut Database, table: &TableSpec, columns: &[String], rows: &[serde_json::Map<String, serde_json::Value>]) -> Result<(), String> {
+    let columns: Vec<(&str, ColumnKind)> = table.columns.iter().cloned()
+        .filter(|(column, _)| columns.iter().any(|name| name == column))
+        .collect();
+
+    let mut values = Vec::with_capacity(rows.len());
+    for row in rows {
+        let mut row_values = Vec::with_capacity(columns.len());
+        for (column, kind) in columns.iter() {
+            let value = from_json(row.get(*column).unwrap_or(&serde_json::Value::Null), *kind)
+                .ok_or_else(|| format!("The column {} of the table {} has an invalid value.", column, table.name))?;
+            row_values.push(value);
+        }
+        values.push(row_values);
+    }
+
+    write_rows(db, table, &columns, values).await
+        .map_err(|err| format!("Unable to write the table {}: {:?}", table.name, err))
+}
+
+/// Continue the generated ids of a PostgreSQL table after the copied ones, as the copied ids were set explicitly.
+async fn reset_sequence(target: &mut Database, table: &TableSpec) -> Result<(), Error> {
+    let key = table.columns[0].0;
+    sqlx::query(&format!("SELECT setval(pg_get_serial_sequence('{}', '{}'), COALESCE((SELECT MAX({}) FROM {}), 0) + 1, false)",
+                         table.name, key, key, table.name))
+        .execute(target.connection()).await?;
+    Ok(())
+}
+
+/// Check that a database has no rows in any table, so data can be copied into it without conflicts.
+pub async fn check_empty(db: &mut Database) -> Result<(), String> {
+    for table in TABLES.iter() {
+        let rows = count_rows(db, table.name).await
+            .map_err(|err| format!("Unable to read the table {} of the new database: {:?}", table.name, err))?;
+        if rows > 0 {
+            return Err(format!("The new database already has data in the table {}. Use an empty database.", table.name));
+        }
+    }
+    Ok(())
+}
+
+/// Delete every row of every table, such as the rows of a restore that failed part way.
+pub async fn clear_tables(db: &mut Database) -> Result<(), String> {
+    for table in TABLES.iter() {
+        sqlx::query(&format!("DELETE FROM {}", table.name)).execute(db.connection()).await
+            .map_err(|err| format!("Unable to clear the table {}: {:?}", table.name, err))?;
+    }
+    Ok(())
+}
+
+/// Finish a table once every row has been written with its id. PostgreSQL has to continue its ids after the written ones.
+pub async fn finish_table(db: &mut Database, table: &TableSpec) -> Result<(), String> {
+    if table.serial && matches!(db.get_type(), DatabaseType::PostgreSQL) {
+        reset_sequence(db, table).await
+            .map_err(|err| format!("Unable to reset the ids of the table {}: {:?}", table.name, err))?;
+    }
+    Ok(())
+}
+
+/**
+   Copy every table from one database to another and check that both have the same amount of rows afterward.
+
+   Both databases must use the latest schema and the target database must be empty. Ids, uuids and timestamps are
+   copied as they are.
+
+   ## Returns
+   The amount of rows copied for each table.
+*/
+pub async fn copy_database(source: &mut Database, target: &mut Database, batch_size: i64) -> Result<Vec<CopiedTable>, String> {
+    check_empty(target).await?;
+
+    let mut copied = Vec::new();
+    for table in TABLES.iter() {
+        let rows = copy_table(source, target, table, batch_size).await
+            .map_err(|err| format!("Unable to copy the table {}: {:?}", table.name, err))?;
+        finish_table(target, table).await?;
+
+        copied.push(CopiedTable {
+            name: table.name,
+            rows
+        });
+    }
+
+    // Verify the copy. Rows that were added to the old database while copying are caught here as well.
+    for table in copied.iter() {
+        let source_rows = count_rows(source, table.name).await
+            .map_err(|err| format!("Unable to count the rows of {} in the old database: {:?}", table.name, err))?;
+        let target_rows = count_rows(target, table.name).await
+            .map_err(|err| format!("Unable to count the rows of {} in the new database: {:?}", table.name, err))?;
+        if source_rows != target_rows {
+            return Err(format!("The table {} has {} rows in the old database but {} in the new one.", table.name, source_rows, target_rows));
+        }
+    }
+
+    Ok(copied)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::schema::tables::{Table, Users};
+    use crate::settings::SqlSettings;
+
+    use super::*;
+
+    fn users() -> &'static TableSpec {
+        TABLES.iter().find(|table| table.name == "users").unwrap()
+    }
+
+    #[test]
+    fn from_json_values() {
+        assert_eq!(from_json(&json!("text"), Text), Some(Value::Text(Some("text".to_string()))));
+        assert_eq!(from_json(&json!(42), Int), Some(Value::Int(Some(42))));
+        assert_eq!(from_json(&json!(1_600_000_000_000_i64), BigInt), Some(Value::BigInt(Some(1_600_000_000_000))));
+        assert_eq!(from_json(&json!(1_600_000_000), Timestamp), Some(Value::BigInt(Some(1_600_000_000))));
+        assert_eq!(from_json(&json!(1.5), Real), Some(Value::Real(Some(1.5))));
+        assert_eq!(from_json(&json!(true), Bool), Some(Value::Bool(Some(true))));
+    }
+
+    #[test]
+    fn from_json_numeric_flags() {
+        assert_eq!(from_json(&json!(1), Bool), Some(Value::Bool(Some(true))));
+        assert_eq!(from_json(&json!(0), Bool), Some(Value::Bool(Some(false))));
+    }
+
+    #[test]
+    fn from_json_null() {
+        assert_eq!(from_json(&serde_json::Value::Null, Text), Some(Value::Text(None)));
+        assert_eq!(from_json(&serde_json::Value::Null, Int), Some(Value::Int(None)));
+        assert_eq!(from_json(&serde_json::Value::Null, Timestamp), Some(Value::BigInt(None)));
+        assert_eq!(from_json(&serde_json::Value::Null, Bool), Some(Value::Bool(None)));
+        assert_eq!(from_json(&serde_json::Value::Null, Real), Some(Value::Real(None)));
+    }
+
+    #[test]
+    fn from_json_wrong_type() {
+        assert_eq!(from_json(&json!(1), Text), None);
+        assert_eq!(from_json(&json!("1"), Int), None);
+        assert_eq!(from_json(&json!("true"), Bool), None);
+        assert_eq!(from_json(&json!(1.5), BigInt), None);
+    }
+
+    #[test]
+    fn json_round_trip() {
+        for (value, kind) in vec![(json!("text"), Text), (json!(7), Int), (json!(-3), BigInt), (json!(false), Bool),
+                                  (json!(0.25), Real), (serde_json::Value::Null, Text)] {
+            assert_eq!(to_json(from_json(&value, kind).unwrap()), value);
+        }
+    }
+
+    #[actix_rt::test]
+    async fn import_and_export_rows() {
+        let mut db = Database::new_sqlite(&SqlSettings { file_location: ":memory:".to_string() }).await.unwrap();
+        Users::create(&mut db).await.unwrap();
+
+        let row = json!({"id": 3, "uuid": "7d1d0b8c-0d6f-4ae0-9a41-0a0c3c1fb7a5", "username": "ferris", "email": "ferris@example.com",
+            "password": "hash", "is_banned": 0, "is_admin": true, "is_moderator": false, "password_reset": false, "date": 1_600_000_000});
+        let row = row.as_object().unwrap().clone();
+        let columns: Vec<String> = row.keys().cloned().collect();
+        import_rows(&mut db, users(), &columns, &[row.clone()]).await.unwrap();
+
+        let exported = export_rows(&mut db, users(), 10, 0).await.unwrap();
+        assert_eq!(exported.len(), 1);
+        assert_eq!(exported[0]["date"], json!(1_600_000_000));
+        assert_eq!(exported[0]["is_banned"], json!(false));
+        assert_eq!(exported[0]["is_admin"], json!(true));
+        assert_eq!(exported[0]["username"], json!("ferris"));
+        assert_eq!(exported[0]["id"], json!(3));
+    }
+
+    #[actix_rt::test]
+    async fn import_invalid_value() {
+        let mut db = Database::new_sqlite(&SqlSettings { file_location: ":memory:".to_string() }).await.unwrap();
+        Users::create(&mut db).await.unwrap();
+
+        let row = json!({"id": "three", "username": "ferris"}).as_object().unwrap().clone();
+        let columns = vec!["id".to_string(), "username".to_string()];
+        let err = import_rows(&mut db, users(), &columns, &[row]).await.unwrap_err();
+        assert_eq!(err, "The column id of the table users has an invalid value.");
+        assert_eq!(count_rows(&mut db, "users").await.unwrap(), 0);
+    }
+}
@@ -1,15 +1,18 @@
 use serde::{Serialize, Deserialize};
-use std::{fs, fmt};
+use std::{env, fs, fmt};
+use std::sync::{Arc, RwLock};
+use std::collections::BTreeMap;
 
 use crate::setup::setup::SetupStage;
 use crate::setup::setup::SetupStage::{General};
 use std::fmt::Formatter;
 use crate::settings::DatabaseType::SQLite;
+use crate::auth::password_policy::BCRYPT_MAX_BYTES;
 
 /**
    Base Settings are the base settings for the website.
 */
-#[derive(Deserialize, Serialize, Debug)]
+#[derive(Deserialize, Serialize, Debug, Clone)]
 pub struct BaseSettings {
     /// The name of the website
     pub(crate) name: String,
@@ -43,6 +46,42 @@ pub struct BaseSettings {
     pub(crate) ssl_settings: Option<SSLSettings>,
     /// The settings for google reCAPTCHA v3.
     pub(crate) captcha_settings: Option<CaptchaSettings>,
+    /// The password policy used whenever a password is set.
+    #[serde(default)]
+    pub(crate) password_settings: PasswordSettings,
+    /// The settings for two factor authentication.
+    #[serde(default)]
+    pub(crate) two_factor_settings: TwoFactorSettings,
+    /// The settings for login brute-force protection.
+    #[serde(default)]
+    pub(crate) login_protection_settings: LoginProtectionSettings,
+    /// The settings for rate limiting.
+    #[serde(default)]
+    pub(crate) rate_limit_settings: RateLimitSettings,
+    /// The settings for the OAuth2 authorization flow.
+    #[serde(default)]
+    pub(crate) oauth_settings: OAuthSettings,
+    /// The settings for delivering webhooks.
+    #[serde(default)]
+    pub(crate) webhook_settings: WebhookSettings,
+    /// The settings for backups.
+    #[serde(default)]
+    pub(crate) backup_settings: BackupSettings,
+    /// The settings for personal data.
+    #[serde(default)]
+    pub(crate) privacy_settings: PrivacySettings,
+    /// The settings for files attached to threads and posts.
+    #[serde(default)]
+    pub(crate) attachment_settings: AttachmentSettings,
+    /// The settings for the profiles of users.
+    #[serde(default)]
+    pub(crate) profile_settings: ProfileSettings,
+    /// The settings for private messages.
+    #[serde(default)]
+    pub(crate) message_settings: MessageSettings,
+    /// The settings for the audit log.
+    #[serde(default)]
+    pub(crate) audit_settings: AuditSettings,
 }
 
 impl BaseSettings {
@@ -62,8 +101,26 @@ impl BaseSettings {
             captcha_settings: None,
             new_setup: true,
             setup_stage: Some(General),
+            password_settings: PasswordSettings::default(),
+            two_factor_settings: TwoFactorSettings::default(),
+            login_protection_settings: LoginProtectionSettings::default(),
+            rate_limit_settings: RateLimitSettings::default(),
+            oauth_settings: OAuthSettings::default(),
+            webhook_settings: WebhookSettings::default(),
+            backup_settings: BackupSettings::default(),
+            privacy_settings: PrivacySettings::default(),
+            attachment_settings: AttachmentSettings::default(),
+            profile_settings: ProfileSettings::default(),
+            message_settings: MessageSettings::default(),
+            audit_settings: AuditSettings::default(),
         }
     }
+
+    /// Get the absolute url of the forum built from the domain. (ex: https://forumrs.example.com)
+    pub fn base_url(&self) -> String {
+        let scheme = if self.use_sll { "https" } else { "http" };
+        format!("{}://{}", scheme, self.domain)
+    }
 }
 
 /// The types of databases that ForumRS supports.
@@ -90,7 +147,7 @@ impl DatabaseType {
 /**
    The settings for a mysql database.
 */
-#[derive(Deserialize, Serialize, Debug)]
+#[derive(Deserialize, Serialize, Debug, Clone)]
 pub struct MysqlSettings {
     /// The URL of the database.
     pub(crate) url: String,
@@ -107,14 +164,14 @@ pub struct MysqlSettings {
 /**
    The settings for a SQLite database.
 */
-#[derive(Deserialize, Serialize, Debug)]
+#[derive(Deserialize, Serialize, Debug, Clone)]
 pub struct SqlSettings {
     /// Location of the database file.
     pub(crate) file_location: String
 }
 
 /// The settings for a PostgreSQL database.
-#[derive(Deserialize, Serialize, Debug)]
+#[derive(Deserialize, Serialize, Debug, Clone)]
 pub struct PostgreSQLSettings {
     /// The URL of the database.
     pub(crate) url: String,
@@ -131,7 +188,7 @@ pub struct PostgreSQLSettings {
 /**
    The settings for SSL.
 */
-#[derive(Deserialize, Serialize, Debug)]
+#[derive(Deserialize, Serialize, Debug, Clone)]
 pub struct SSLSettings {
     /// The private key.
     pub(crate) private_key: String,
@@ -140,20 +197,645 @@ pub struct SSLSettings {
 }
 
 /// The settings for Google reCAPTCHA v3
-#[derive(Deserialize, Serialize, Debug)]
+#[derive(Deserialize, Serialize, Debug, Clone)]
 pub struct CaptchaSettings {
     pub(crate) site_key: String,
     pub(crate) secret_key: String,
 }
 
+/**
+   The settings for the password policy.
+
+   The defaults match the rules that were originally hardcoded into the setup process.
+*/
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(default)]
+pub struct PasswordSettings {
+    /// The minimum length of a password in characters.
+    pub(crate) min_length: u32,
+    /// The maximum length of a password in bytes. This cannot be more than 72, as bcrypt ignores anything past 72 bytes.
+    pub(crate) max_length: u32,
+    /// The minimum number of uppercase letters.
+    pub(crate) min_uppercase: u32,
+    /// The minimum number of lowercase letters.
+    pub(crate) min_lowercase: u32,
+    /// The minimum number of digits.
+    pub(crate) min_digits: u32,
+    /// The minimum number of special (non alphanumeric) characters.
+    pub(crate) min_special: u32,
+    /// If passwords found in the common password list should be denied.
+    pub(crate) deny_common: bool,
+    /// An optional file containing additional denied passwords (one per line).
+    pub(crate) deny_list_file: Option<String>,
+    /// The minimum strength score (0-4) a password must have. None to disable strength scoring.
+    pub(crate) min_strength: Option<u8>,
+}
+
+impl Default for PasswordSettings {
+    fn default() -> Self {
+        PasswordSettings {
+            min_length: 8,
+            max_length: 72,
+            min_uppercase: 2,
+            min_lowercase: 3,
+            min_digits: 2,
+            min_special: 1,
+            deny_common: true,
+            deny_list_file: None,
+            min_strength: None,
+        }
+    }
+}
+
+/// The settings for two factor authentication.
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct TwoFactorSettings {
+    /// If admins must have two factor authentication enabled to access the admin pages.
+    pub(crate) require_for_admins: bool,
+    /// If moderators must have two factor authentication enabled to use moderation tools.
+    pub(crate) require_for_moderators: bool,
+}
+
+/**
+   The settings for login brute-force protection.
+
+   Failed logins are tracked by account and by client IP. After `free_attempts` failures, every
+   further attempt has to wait `backoff_base_seconds * 2^n` seconds (up to `backoff_max_seconds`).
+   Once a lockout threshold is reached, the account or IP is locked for `lockout_minutes`.
+*/
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(default)]
+pub struct LoginProtectionSettings {
+    /// The amount of failed attempts allowed before the backoff starts.
+    pub(crate) free_attempts: u32,
+    /// The base amount of seconds for the exponential backoff.
+    pub(crate) backoff_base_seconds: u64,
+    /// The maximum amount of seconds between attempts.
+    pub(crate) backoff_max_seconds: u64,
+    /// The amount of failed attempts before an account is locked. (0 to disable)
+    pub(crate) account_lockout_threshold: u32,
+    /// The amount of failed attempts before a client IP is locked. (0 to disable)
+    pub(crate) ip_lockout_threshold: u32,
+    /// How long an account or IP stays locked.
+    pub(crate) lockout_minutes: u64,
+    /// How long failed attempts are remembered for.
+    pub(crate) attempt_window_minutes: u64,
+    /// If the client IP should be read from the X-Forwarded-For / Forwarded headers.
+    /// Only enable this when ForumRS is behind a reverse proxy.
+    pub(crate) trust_proxy_headers: bool,
+}
+
+impl Default for LoginProtectionSettings {
+    fn default() -> Self {
+        LoginProtectionSettings {
+            free_attempts: 3,
+            backoff_base_seconds: 2,
+            backoff_max_seconds: 300,
+            account_lockout_threshold: 10,
+            ip_lockout_threshold: 50,
+            lockout_minutes: 15,
+            attempt_window_minutes: 60,
+            trust_proxy_headers: false,
+        }
+    }
+}
+
+/// Where the rate limit buckets are stored.
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq)]
+pub enum RateLimitStoreType {
+    /// Buckets are kept in memory. This only works with a single instance of ForumRS.
+    Memory,
+    /// Buckets are kept in the database so multiple instances share the same limits.
+    Database
+}
+
+/// What a rate limit bucket is keyed by.
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq)]
+pub enum RateLimitKey {
+    /// The client IP.
+    Ip,
+    /// The logged in user. Falls back to the client IP for guests.
+    User
+}
+
+/// A token bucket rate limit for a group of routes.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct RateLimitRule {
+    /// The path prefixes of the routes in the group.
+    pub(crate) paths: Vec<String>,
+    /// The HTTP methods the rule applies to. Empty for all methods.
+    #[serde(default)]
+    pub(crate) methods: Vec<String>,
+    /// The maximum amount of requests that can be made in a burst.
+    pub(crate) capacity: u32,
+    /// The amount of seconds it takes to get back one request.
+    pub(crate) refill_seconds: f64,
+    /// What the bucket is keyed by.
+    pub(crate) key: RateLimitKey,
+}
+
+impl RateLimitRule {
+    fn new(paths: &[&str], methods: &[&str], capacity: u32, refill_seconds: f64, key: RateLimitKey) -> RateLimitRule {
+        RateLimitRule {
+            paths: paths.iter().map(|path| path.to_string()).collect(),
+            methods: methods.iter().map(|method| method.to_string()).collect(),
+            capacity,
+            refill_seconds,
+            key
+        }
+    }
+}
+
+/// The settings for rate limiting.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(default)]
+pub struct RateLimitSettings {
+    /// If rate limiting is enabled.
+    pub(crate) enabled: bool,
+    /// Where the rate limit buckets are stored.
+    pub(crate) store: RateLimitStoreType,
+    /// The rate limits by group name.
+    pub(crate) groups: BTreeMap<String, RateLimitRule>,
+}
+
+impl Default for RateLimitSettings {
+    fn default() -> Self {
+        let mut groups = BTreeMap::new();
+        groups.insert("login".to_string(), RateLimitRule::new(&["/auth/login"], &["POST"], 10, 6.0, RateLimitKey::Ip));
+        // Threads are created with POST /api/v1/forums/{uuid}/threads and replies with POST /api/v1/threads/{uuid}/posts.
+        groups.insert("thread_creation".to_string(), RateLimitRule::new(&["/api/v1/forums/"], &["POST"], 5, 60.0, RateLimitKey::User));
+        groups.insert("replies".to_string(), RateLimitRule::new(&["/api/v1/threads/"], &["POST"], 10, 15.0, RateLimitKey::User));
+        groups.insert("search".to_string(), RateLimitRule::new(&["/api/v1/search/"], &["GET"], 20, 3.0, RateLimitKey::Ip));
+        groups.insert("api".to_string(), RateLimitRule::new(&["/api/"], &[], 60, 1.0, RateLimitKey::User));
+        groups.insert("oauth_token".to_string(), RateLimitRule::new(&["/oauth/token"], &["POST"], 10, 6.0, RateLimitKey::Ip));
+        groups.insert("messages".to_string(), RateLimitRule::new(&["/auth/messages"], &["POST"], 10, 15.0, RateLimitKey::User));
+        groups.insert("reports".to_string(), RateLimitRule::new(&["/auth/report"], &["POST"], 5, 60.0, RateLimitKey::User));
+
+        RateLimitSettings {
+            enabled: true,
+            store: RateLimitStoreType::Memory,
+            groups
+        }
+    }
+}
+
+/**
+   The settings for the OAuth2 authorization code flow.
+
+   When enabled, users can register applications and third-party apps can ask users for access
+   to their account through `/oauth/authorize`.
+*/
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(default)]
+pub struct OAuthSettings {
+    /// If users can register applications and authorize them.
+    pub(crate) enabled: bool,
+    /// How long an authorization code can be exchanged for a token.
+    pub(crate) code_lifetime_seconds: u64,
+}
+
+impl Default for OAuthSettings {
+    fn default() -> Self {
+        OAuthSettings {
+            enabled: false,
+            code_lifetime_seconds: 600,
+        }
+    }
+}
+
+/**
+   The settings for delivering webhooks.
+
+   Deliveries that fail are retried after `backoff_base_seconds * 2^n` seconds (up to `backoff_max_seconds`)
+   until `max_attempts` is reached.
+*/
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(default)]
+pub struct WebhookSettings {
+    /// How often the delivery queue is checked.
+    pub(crate) poll_interval_seconds: u64,
+    /// How long to wait for the receiving server to respond.
+    pub(crate) timeout_seconds: u64,
+    /// The amount of times a delivery is attempted before it is marked as failed.
+    pub(crate) max_attempts: u32,
+    /// The base amount of seconds for the exponential backoff.
+    pub(crate) backoff_base_seconds: u64,
+    /// The maximum amount of seconds between attempts.
+    pub(crate) backoff_max_seconds: u64,
+}
+
+impl Default for WebhookSettings {
+    fn default() -> Self {
+        WebhookSettings {
+            poll_interval_seconds: 5,
+            timeout_seconds: 10,
+            max_attempts: 8,
+            backoff_base_seconds: 30,
+            backoff_max_seconds: 3600,
+        }
+    }
+}
+
+/**
+   The settings for backups.
+
+   Backups are written to `directory`, both when they are made from the admin panel or the command line and when
+   they are scheduled. After every scheduled backup, only the newest `keep` backups in the directory are kept.
+*/
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(default)]
+pub struct BackupSettings {
+    /// The directory backups are written to.
+    pub(crate) directory: String,
+    /// If a backup is made every `interval_hours`.
+    pub(crate) scheduled: bool,
+    /// The amount of hours between scheduled backups.
+    pub(crate) interval_hours: u64,
+    /// The amount of backups that are kept. Older backups are deleted after a scheduled backup. (0 keeps every backup)
+    pub(crate) keep: usize,
+}
+
+impl Default for BackupSettings {
+    fn default() -> Self {
+        BackupSettings {
+            directory: "backups".to_string(),
+            scheduled: false,
+            interval_hours: 24,
+            keep: 7,
+        }
+    }
+}
+
+/// What happens to the threads and posts of a user when their account is deleted.
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq)]
+pub enum DeletedContent {
+    /// The content is kept and shown as written by a deleted user.
+    Anonymize,
+    /// The posts of the user are deleted, as are their threads with every reply to them.
+    Remove
+}
+
+/// The settings for personal data.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(default)]
+pub struct PrivacySettings {
+    /// What happens to the content of a user when their account is deleted.
+    pub(crate) deleted_content: DeletedContent,
+}
+
+impl Default for PrivacySettings {
+    fn default() -> Self {
+        PrivacySettings {
+            deleted_content: DeletedContent::Anonymize,
+        }
+    }
+}
+
+/// Where attachments are stored.
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq)]
+pub enum AttachmentStorageType {
+    /// Files are kept in a directory on the server.
+    Local,
+    /// Files are kept in a bucket of an S3 compatible object storage.
+    S3
+}
+
+/// The settings for an S3 compatible object storage.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct S3Settings {
+    /// The url of the storage server. (ex: https://s3.us-east-1.amazonaws.com or http://127.0.0.1:9000)
+    pub(crate) endpoint: String,
+    pub(crate) bucket: String,
+    pub(crate) region: String,
+    pub(crate) access_key: String,
+    pub(crate) secret_key: String,
+    /// If the bucket is part of the path instead of the host name. Most self hosted storage servers need this.
+    #[serde(default)]
+    pub(crate) path_style: bool,
+}
+
+/**
+   The settings for files attached to threads and posts.
+
+   Only files with a type in `allowed_types` can be uploaded. The type of images is read from the file itself,
+   and images are stored re-encoded so their metadata (such as the EXIF location) is removed.
+*/
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(default)]
+pub struct AttachmentSettings {
+    /// If users can attach files.
+    pub(crate) enabled: bool,
+    /// Where the files are stored.
+    pub(crate) storage: AttachmentStorageType,
+    /// The directory files are stored in when the storage is local.
+    pub(crate) directory: String,
+    /// The settings of the object storage when the storage is S3.
+    pub(crate) s3_settings: Option<S3Settings>,
+    /// The largest file that can be uploaded, in bytes.
+    pub(crate) max_size: usize,
+    /// The most files that can be attached to a single thread or post.
+    pub(crate) max_per_post: i64,
+    /// The MIME types that can be uploaded.
+    pub(crate) allowed_types: Vec<String>,
+    /// The largest width and height of image thumbnails, in pixels.
+    pub(crate) thumbnail_size: u32,
+}
+
+impl Default for AttachmentSettings {
+    fn default() -> Self {
+        AttachmentSettings {
+            enabled: true,
+            storage: AttachmentStorageType::Local,
+            directory: "attachments".to_string(),
+            s3_settings: None,
+            max_size: 8 * 1024 * 1024,
+            max_per_post: 10,
+            allowed_types: ["image/png", "image/jpeg", "image/gif", "application/pdf", "text/plain", "application/zip"]
+                .iter().map(|content_type| content_type.to_string()).collect(),
+            thumbnail_size: 320,
+        }
+    }
+}
+
+/// The settings for the profiles of users.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(default)]
+pub struct ProfileSettings {
+    /// If users can use the Gravatar of their email as their avatar.
+    pub(crate) gravatar: bool,
+    /// The width and height avatars are shown and stored with, in pixels.
+    pub(crate) avatar_size: u32,
+    /// The largest image that can be uploaded as an avatar, in bytes.
+    pub(crate) max_avatar_size: usize,
+    /// The most characters a bio can have.
+    pub(crate) max_bio_length: usize,
+    /// The most characters a signature can have.
+    pub(crate) max_signature_length: usize,
+    /// The most lines a signature can have.
+    pub(crate) max_signature_lines: usize,
+}
+
+impl Default for ProfileSettings {
+    fn default() -> Self {
+        ProfileSettings {
+            gravatar: true,
+            avatar_size: 128,
+            max_avatar_size: 1024 * 1024,
+            max_bio_length: 1000,
+            max_signature_length: 300,
+            max_signature_lines: 4,
+        }
+    }
+}
+
+/**
+   The settings for private messages.
+
+   The inbox quota limits how many conversations a user can be a member of. Users with a full inbox cannot
+   start conversations or be added to new ones until they leave some.
+*/
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(default)]
+pub struct MessageSettings {
+    /// If users can send private messages.
+    pub(crate) enabled: bool,
+    /// The most conversations a user can be a member of.
+    pub(crate) max_conversations: i64,
+    /// The most users a conversation can be started with, not counting the user that starts it.
+    pub(crate) max_recipients: usize,
+    /// The most characters a message can have.
+    pub(crate) max_message_length: usize,
+}
+
+impl Default for MessageSettings {
+    fn default() -> Self {
+        MessageSettings {
+            enabled: true,
+            max_conversations: 200,
+            max_recipients: 10,
+            max_message_length: 10000,
+        }
+    }
+}
+
+/**
+   The settings for the audit log of admin and moderator actions.
+
+   Entries older than `retention_days` are deleted once an hour. A retention of 0 keeps every entry.
+*/
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(default)]
+pub struct AuditSettings {
+    /// How many days entries are kept in the audit log.
+    pub(crate) retention_days: u32,
+}
+
+impl Default for AuditSettings {
+    fn default() -> Self {
+        AuditSettings {
+            retention_days: 365,
+        }
+    }
+}
+
+/// The settings file that is used when `FORUMRS_SETTINGS` is not set.
+pub const DEFAULT_SETTINGS_FILE: &str = "settings.toml";
+/// The prefix of the environment variables that override settings.
+const ENV_PREFIX: &str = "FORUMRS_";
+/// The keys of secret values. Secrets can be read from a separate file and are redacted when the settings are printed.
+const SECRET_KEYS: [&str; 2] = ["password", "secret_key"];
+
+/// The validated settings of the running forum. This is only set once the forum server starts.
+static LIVE_SETTINGS: RwLock<Option<Arc<BaseSettings>>> = RwLock::new(None);
+
+/// A value that is set by the environment or a secrets file instead of the settings file.
+struct SettingOverride {
+    /// The path of the key in the settings. (ex: mysql_settings.password)
+    path: Vec<String>,
+    value: toml::Value,
+}
+
+/// Get the value at a path of a TOML table.
+fn get_path<'a>(value: &'a toml::Value, path: &[String]) -> Option<&'a toml::Value> {
+    path.iter().try_fold(value, |value, key| value.get(key.as_str()))
+}
+
+/// Set (or remove when None) the value at a path of a TOML table, creating any missing tables.
+fn set_path(value: &mut toml::Value, path: &[String], new_value: Option<toml::Value>) {
+    let (last, parents) = match path.split_last() {
+        Some(split) => split,
+        None => return
+    };
+
+    let mut current = value;
+    for key in parents {
+        let table = match current.as_table_mut() {
+            Some(table) => table,
+            None => return
+        };
+        current = table.entry(key.clone()).or_insert_with(|| toml::Value::Table(toml::value::Table::new()));
+    }
+
+    if let Some(table) = current.as_table_mut() {
+        match new_value {
+            Some(new_value) => { table.insert(last.clone(), new_value); }
+            None => { table.remove(last); }
+        }
+    }
+}
+
+/// Read a secret from a file. Trailing new lines are removed.
+fn read_secret_file(file: &str) -> Result<toml::Value, String> {
+    let secret = fs::read_to_string(file).map_err(|err| format!("Unable to read the secrets file {}: {}", file, err))?;
+    Ok(toml::Value::String(secret.trim_end_matches(|c| c == '\n' || c == '\r').to_string()))
+}
+
+/// Replace every secret in a TOML table with a placeholder.
+fn redact(value: &mut toml::Value) {
+    if let Some(table) = value.as_table_mut() {
+        for (key, value) in table.iter_mut() {
+            if SECRET_KEYS.contains(&key.as_str()) && value.is_str() {
+                *value = toml::Value::String("<redacted>".to_string());
+            } else {
+                redact(value);
+            }
+        }
+    }
+}
+
+/**
+   Prepare settings to be written to the settings file. Values that still match their override are put back to the
+   value of the file (or removed if the file does not have them), and the secrets file keys of the file are kept.
+*/
+fn restore_file_values(value: &mut toml::Value, file: &toml::Value, overrides: &[SettingOverride]) {
+    for setting_override in overrides {
+        if get_path(value, &setting_override.path) == Some(&setting_override.value) {
+            set_path(value, &setting_override.path, get_path(file, &setting_override.path).cloned());
+        }
+    }
+
+    if let Some(table) = file.as_table() {
+        for (section, section_value) in table {
+            for secret in SECRET_KEYS.iter() {
+                let file_key = format!("{}_file", secret);
+                if let Some(secret_file) = section_value.get(file_key.as_str()) {
+                    set_path(value, &[section.clone(), file_key], Some(secret_file.clone()));
+                }
+            }
+        }
+    }
+}
+
 /**
     The Manager that allows you to read and write settings.
 
-    Settings are stored in the settings.toml file.
+    Settings are stored in the settings.toml file, or the file in the `FORUMRS_SETTINGS` environment variable.
+    On top of the file, these are applied in order:
+
+    - Secrets files. A secret (such as `password` of `mysql_settings`) can be read from a separate file by setting
+      `password_file` in the same section of the settings file.
+    - Environment variables. Any setting can be overridden with `FORUMRS_<KEY>`, where nested keys are separated by
+      two underscores (ex: `FORUMRS_PORT` or `FORUMRS_MYSQL_SETTINGS__URL`). Secrets can also be read from a file with
+      a `_FILE` suffix (ex: `FORUMRS_MYSQL_SETTINGS__PASSWORD_FILE`).
+
+    Overridden values are never written back to the settings file.
+
+    Once the forum server starts, the settings are kept in memory and only replaced by [SettingsManager::reload],
+    so a settings file that is being edited (or is invalid) never affects requests that are running.
 */
 pub struct SettingsManager {}
 
 impl SettingsManager {
+    /// Get the location of the settings file.
+    pub fn settings_path() -> String {
+        env::var("FORUMRS_SETTINGS").ok().filter(|path| !path.is_empty()).unwrap_or_else(|| DEFAULT_SETTINGS_FILE.to_string())
+    }
+
+    /// Read the settings file without any overrides.
+    fn read_file() -> Result<toml::Value, String> {
+        let path = SettingsManager::settings_path();
+        let contents = fs::read_to_string(path.as_str()).map_err(|err| format!("Unable to read the settings file {}: {}", path, err))?;
+        toml::from_str(contents.as_str()).map_err(|_| String::from("Invalid settings file! It is not a TOML file or is missing a section."))
+    }
+
+    /// Get every value that overrides the settings file, in the order they are applied.
+    fn overrides(file: &toml::Value) -> Result<Vec<SettingOverride>, String> {
+        // FORUMRS_SETTINGS and the headless setup variables are not settings.
+        let variables: Vec<(String, String)> = env::vars()
+            .filter(|(key, _)| key.starts_with(ENV_PREFIX) && key != "FORUMRS_SETTINGS" && !key.starts_with("FORUMRS_SETUP_"))
+            .collect();
+        SettingsManager::overrides_from(file, variables)
+    }
+
+    /// Get the overrides of the secrets files referenced by the settings file and of the provided environment variables.
+    fn overrides_from(file: &toml::Value, mut variables: Vec<(String, String)>) -> Result<Vec<SettingOverride>, String> {
+        let mut overrides = Vec::new();
+
+        // Secrets files referenced from the settings file.
+        if let Some(table) = file.as_table() {
+            for (section, value) in table {
+                for secret in SECRET_KEYS.iter() {
+                    if let Some(secret_file) = value.get(format!("{}_file", secret).as_str()).and_then(|file| file.as_str()) {
+                        overrides.push(SettingOverride {
+                            path: vec![section.clone(), secret.to_string()],
+                            value: read_secret_file(secret_file)?,
+                        });
+                    }
+                }
+            }
+        }
+
+        // Environment variables.
+        variables.sort();
+
+        for (key, raw) in variables {
+            let mut path: Vec<String> = key[ENV_PREFIX.len()..].split("__").map(|part| part.to_lowercase()).collect();
+
+            let last = path.last().cloned().unwrap_or_default();
+            let secret_file = last.strip_suffix("_file").filter(|secret| SECRET_KEYS.contains(secret)).map(|secret| secret.to_string());
+            let value = if let Some(secret) = secret_file {
+                *path.last_mut().unwrap() = secret;
+                read_secret_file(raw.as_str())?
+            } else if SECRET_KEYS.contains(&last.as_str()) || get_path(file, &path).map(|value| value.is_str()).unwrap_or(false) {
+                // Keep strings as strings, so a password like 1234 is not read as a number.
+                toml::Value::String(raw)
+            } else {
+                toml::from_str::<toml::Value>(format!("value = {}", raw).as_str()).ok()
+                    .and_then(|table| table.get("value").cloned())
+                    .unwrap_or(toml::Value::String(raw))
+            };
+
+            overrides.push(SettingOverride { path, value });
+        }
+
+        Ok(overrides)
+    }
+
+    /// Read the settings file with every override applied.
+    pub fn read_effective() -> Result<BaseSettings, String> {
+        let file = SettingsManager::read_file()?;
+        let mut effective = file.clone();
+        for setting_override in SettingsManager::overrides(&file)? {
+            set_path(&mut effective, &setting_override.path, Some(setting_override.value));
+        }
+        effective.try_into().map_err(|err| format!("Invalid settings file! It is not a TOML file or is missing a section. ({})", err))
+    }
+
+    /// Get the settings as JSON with every secret redacted, so changes to them can be recorded.
+    pub fn redacted_json(settings: &BaseSettings) -> serde_json::Value {
+        let mut value = toml::Value::try_from(settings).unwrap();
+        redact(&mut value);
+        serde_json::to_value(&value).unwrap()
+    }
+
+    /// Get the settings as TOML with every secret redacted, so the effective configuration can be shared safely.
+    pub fn redacted(settings: &BaseSettings) -> String {
+        let mut value = toml::Value::try_from(settings).unwrap();
+        redact(&mut value);
+        toml::to_string(&value).unwrap()
+    }
+
     /**
      This function validates the settings from the Settings.toml file.
 
@@ -161,12 +843,7 @@ impl SettingsManager {
      An empty result if ok, an error message if not.
     */
     pub fn validate_settings() -> Result<(), String> {
-        let setting_res = toml::from_str(fs::read_to_string("settings.toml").unwrap().as_str());
-        if setting_res.is_err() {
-            return Err(String::from("Invalid settings file! It is not a TOML file or is missing a section."));
-        }
-
-        let settings : BaseSettings = setting_res.unwrap();
+        let settings = SettingsManager::read_effective()?;
 
         if settings.new_setup && settings.setup_stage.is_some() {
             return Ok(());
@@ -198,35 +875,338 @@ impl SettingsManager {
             return Err(String::from("SSL is marked as being used, but there are no SSL settings present."));
         }
 
+        let password_settings = &settings.password_settings;
+        if password_settings.min_length > password_settings.max_length {
+            return Err(String::from("The minimum password length cannot be larger than the maximum password length."));
+        }
+
+        if password_settings.max_length > BCRYPT_MAX_BYTES {
+            return Err(format!("The maximum password length cannot be larger than {} bytes, as bcrypt ignores the rest of the password.", BCRYPT_MAX_BYTES));
+        }
+
+        if password_settings.min_strength.unwrap_or(0) > 4 {
+            return Err(String::from("The minimum password strength must be between 0 and 4."));
+        }
+
+        for (name, rule) in &settings.rate_limit_settings.groups {
+            if rule.capacity < 1 || rule.refill_seconds <= 0.0 {
+                return Err(format!("The rate limit group {} must have a capacity of at least 1 and a positive refill time.", name));
+            }
+        }
+
+        if settings.webhook_settings.max_attempts < 1 || settings.webhook_settings.poll_interval_seconds < 1 {
+            return Err(String::from("Webhooks must have at least 1 delivery attempt and a poll interval of at least 1 second."));
+        }
+
+        if settings.backup_settings.scheduled && settings.backup_settings.interval_hours < 1 {
+            return Err(String::from("Scheduled backups must have an interval of at least 1 hour."));
+        }
+
+        let attachment_settings = &settings.attachment_settings;
+        if attachment_settings.storage == AttachmentStorageType::S3 && attachment_settings.s3_settings.is_none() {
+            return Err(String::from("Attachments are marked as stored in S3, but there are no S3 settings present."));
+        }
+
+        if attachment_settings.max_size < 1 || attachment_settings.thumbnail_size < 1 {
+            return Err(String::from("Attachments must have a maximum size and a thumbnail size of at least 1."));
+        }
+
+        if settings.profile_settings.avatar_size < 1 || settings.profile_settings.max_avatar_size < 1 {
+            return Err(String::from("Avatars must have a size and a maximum upload size of at least 1."));
+        }
+
+        let message_settings = &settings.message_settings;
+        if message_settings.max_conversations < 1 || message_settings.max_recipients < 1 || message_settings.max_message_length < 1 {
+            return Err(String::from("Private messages must allow at least 1 conversation, 1 recipient and 1 character."));
+        }
+
         Ok(())
     }
 
     /**
-        Get the settings from the settings.toml file.
-        The file will be read every time and there is not validate. Call #validate_settings() to
-        validate the settings file first.
+        Get the settings from the settings file with every override applied.
+        Before the forum server starts the file will be read every time and there is not validate. Call
+        #validate_settings() to validate the settings file first.
     */
     pub fn get_settings() -> BaseSettings {
-        let setting : BaseSettings = toml::from_str(fs::read_to_string("settings.toml").unwrap().as_str()).unwrap();
-        setting
+        SettingsManager::current().as_ref().clone()
+    }
+
+    /// Get the settings without copying them. This is the in-memory settings of the running forum, or the settings file before it starts.
+    pub fn current() -> Arc<BaseSettings> {
+        if let Some(settings) = LIVE_SETTINGS.read().unwrap().as_ref() {
+            return settings.clone();
+        }
+        Arc::new(SettingsManager::read_effective().unwrap())
+    }
+
+    /// Keep the settings in memory for the running forum. They are only changed by [SettingsManager::reload] and [SettingsManager::save_settings].
+    pub fn start_live(settings: BaseSettings) {
+        *LIVE_SETTINGS.write().unwrap() = Some(Arc::new(settings));
+    }
+
+    /**
+       Validate the settings file and swap it in for the settings of the running forum.
+       If the settings are invalid, the current settings are kept.
+
+       Settings that only apply after a restart keep their current values in memory until the forum is restarted,
+       so requests never switch to a new database (or the other restart only settings) part way through.
+
+       ## Returns
+       The settings that changed but only apply after a restart.
+    */
+    pub fn reload() -> Result<Vec<&'static str>, String> {
+        SettingsManager::validate_settings()?;
+        let mut settings = SettingsManager::read_effective()?;
+        if settings.new_setup {
+            return Err(String::from("The settings are for a forum that has not been set up."));
+        }
+
+        let mut live = LIVE_SETTINGS.write().unwrap();
+        let restart_required = match live.as_ref() {
+            Some(current) => {
+                let restart_required = SettingsManager::restart_required(current, &settings);
+                SettingsManager::keep_restart_only(current, &mut settings);
+                restart_required
+            }
+            None => Vec::new()
+        };
+        *live = Some(Arc::new(settings));
+
+        Ok(restart_required)
+    }
+
+    /// Copy the settings reported by [SettingsManager::restart_required] from the running settings into new settings.
+    fn keep_restart_only(current: &BaseSettings, new: &mut BaseSettings) {
+        new.ip = current.ip.clone();
+        new.port = current.port;
+        new.database_type = current.database_type.clone();
+        new.sql_settings = current.sql_settings.clone();
+        new.mysql_settings = current.mysql_settings.clone();
+        new.postgre_settings = current.postgre_settings.clone();
+        new.use_sll = current.use_sll;
+        new.ssl_settings = current.ssl_settings.clone();
+        new.rate_limit_settings.store = current.rate_limit_settings.store.clone();
+        // The request body limit is set when the server starts, so only a smaller max size applies right away.
+        new.attachment_settings.max_size = new.attachment_settings.max_size.min(current.attachment_settings.max_size);
+    }
+
+    /// Get the settings that changed between two versions of the settings that cannot change while the forum is running.
+    fn restart_required(old: &BaseSettings, new: &BaseSettings) -> Vec<&'static str> {
+        fn changed<T: Serialize>(old: &T, new: &T) -> bool {
+            serde_json::to_value(old).ok() != serde_json::to_value(new).ok()
+        }
+
+        let mut restart_required = Vec::new();
+        if old.ip != new.ip || old.port != new.port {
+            restart_required.push("ip and port");
+        }
+        if changed(&old.database_type, &new.database_type) || changed(&old.sql_settings, &new.sql_settings)
+            || changed(&old.mysql_settings, &new.mysql_settings) || changed(&old.postgre_settings, &new.postgre_settings) {
+            restart_required.push("database");
+        }
+        if old.use_sll != new.use_sll || changed(&old.ssl_settings, &new.ssl_settings) {
+            restart_required.push("ssl");
+        }
+        if old.rate_limit_settings.store != new.rate_limit_settings.store {
+            restart_required.push("rate limit store");
+        }
+        if new.attachment_settings.max_size > old.attachment_settings.max_size {
+            restart_required.push("attachment max size");
+        }
+        restart_required
     }
 
     /**
     Save settings to the settings file.
+
+    Values that are the same as their override are saved as they are in the settings file, so secrets and
+    environment variables never end up in the file. Secrets file keys (ex: `password_file`) are kept.
     */
     pub fn save_settings(base_settings: &BaseSettings) {
         if toml::to_string(base_settings).is_err() {
             println!("{:?}", base_settings);
             println!("{}", toml::to_string(base_settings).unwrap_err());
         }
-        let str_setting = toml::to_string(base_settings).unwrap();
-        fs::write("settings.toml", str_setting);
+        let mut value = toml::Value::try_from(base_settings).unwrap();
+
+        if let Ok(file) = SettingsManager::read_file() {
+            let overrides = SettingsManager::overrides(&file).unwrap_or_else(|err| {
+                println!("[WARN] {}", err);
+                Vec::new()
+            });
+            restore_file_values(&mut value, &file, &overrides);
+        }
+
+        let str_setting = toml::to_string(&value).unwrap();
+        if let Err(err) = fs::write(SettingsManager::settings_path(), str_setting) {
+            println!("[ERROR] Unable to save the settings file {}: {}", SettingsManager::settings_path(), err);
+            return;
+        }
+
+        // Settings saved by the running forum apply right away.
+        if LIVE_SETTINGS.read().unwrap().is_some() {
+            if let Err(err) = SettingsManager::reload() {
+                println!("[WARN] The saved settings could not be applied: {}", err);
+            }
+        }
     }
 
     /**
     Check if the settings file exists.
     */
     pub fn settings_exist() -> bool {
-        fs::read("settings.toml").is_ok()
+        fs::read(SettingsManager::settings_path()).is_ok()
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn path(keys: &[&str]) -> Vec<String> {
+        keys.iter().map(|key| key.to_string()).collect()
     }
-}
\ No newline at end of file
+
+    fn file() -> toml::Value {
+        toml::from_str(r#"
+            name = "Forum"
+            port = 8080
+
+            [mysql_settings]
+            url = "localhost"
+            password = "from-file"
+        "#).unwrap()
+    }
+
+    /// Write a secret to a temporary file.
+    fn secret_file(contents: &str) -> String {
+        let file = env::temp_dir().join(format!("forumrs-secret-{}", uuid::Uuid::new_v4()));
+        fs::write(&file, contents).unwrap();
+        file.to_string_lossy().to_string()
+    }
+
+    fn variables(variables: &[(&str, &str)]) -> Vec<(String, String)> {
+        variables.iter().map(|(key, value)| (key.to_string(), value.to_string())).collect()
+    }
+
+    #[test]
+    fn get_and_set_nested_paths() {
+        let mut value = file();
+        assert_eq!(get_path(&value, &path(&["mysql_settings", "url"])), Some(&toml::Value::String("localhost".to_string())));
+        assert_eq!(get_path(&value, &path(&["mysql_settings", "missing"])), None);
+
+        set_path(&mut value, &path(&["ssl_settings", "public_key"]), Some(toml::Value::String("cert.pem".to_string())));
+        assert_eq!(value["ssl_settings"]["public_key"].as_str(), Some("cert.pem"));
+
+        set_path(&mut value, &path(&["mysql_settings", "password"]), None);
+        assert_eq!(get_path(&value, &path(&["mysql_settings", "password"])), None);
+
+        // A value cannot be set below something that is not a table.
+        set_path(&mut value, &path(&["port", "inner"]), Some(toml::Value::Integer(1)));
+        assert_eq!(value["port"].as_integer(), Some(8080));
+    }
+
+    #[test]
+    fn redact_secrets() {
+        let mut value: toml::Value = toml::from_str(r#"
+            password = "top-level"
+
+            [mysql_settings]
+            password = "hunter2"
+            password_file = "/run/secrets/mysql"
+
+            [attachment_settings.s3]
+            secret_key = "abc"
+            access_key = "visible"
+        "#).unwrap();
+        redact(&mut value);
+
+        assert_eq!(value["password"].as_str(), Some("<redacted>"));
+        assert_eq!(value["mysql_settings"]["password"].as_str(), Some("<redacted>"));
+        assert_eq!(value["mysql_settings"]["password_file"].as_str(), Some("/run/secrets/mysql"));
+        assert_eq!(value["attachment_settings"]["s3"]["secret_key"].as_str(), Some("<redacted>"));
+        assert_eq!(value["attachment_settings"]["s3"]["access_key"].as_str(), Some("visible"));
+    }
+
+    #[test]
+    fn environment_overrides_are_typed() {
+        let overrides = SettingsManager::overrides_from(&file(), variables(&[
+            ("FORUMRS_PORT", "9090"),
+            ("FORUMRS_USE_SSL", "true"),
+            ("FORUMRS_NAME", "1234"),
+            ("FORUMRS_MYSQL_SETTINGS__PASSWORD", "5678"),
+            ("FORUMRS_MYSQL_SETTINGS__URL", "db.example.com"),
+            ("FORUMRS_RATE_LIMIT_SETTINGS__GROUPS__LOGIN__CAPACITY", "5"),
+        ])).unwrap();
+        let found: Vec<(Vec<String>, toml::Value)> = overrides.into_iter().map(|setting_override| (setting_override.path, setting_override.value)).collect();
+
+        assert_eq!(found, vec![
+            (path(&["mysql_settings", "password"]), toml::Value::String("5678".to_string())),
+            (path(&["mysql_settings", "url"]), toml::Value::String("db.example.com".to_string())),
+            // Strings in the file stay strings.
+            (path(&["name"]), toml::Value::String("1234".to_string())),
+            (path(&["port"]), toml::Value::Integer(9090)),
+            (path(&["rate_limit_settings", "groups", "login", "capacity"]), toml::Value::Integer(5)),
+            (path(&["use_ssl"]), toml::Value::Boolean(true)),
+        ]);
+    }
+
+    #[test]
+    fn secrets_are_read_from_files() {
+        let env_secret = secret_file("from-env-file\r\n");
+        let file_secret = secret_file("from-settings-file\n");
+        let mut settings_file = file();
+        set_path(&mut settings_file, &path(&["postgre_settings", "password_file"]), Some(toml::Value::String(file_secret.clone())));
+
+        let overrides = SettingsManager::overrides_from(&settings_file, variables(&[
+            ("FORUMRS_MYSQL_SETTINGS__PASSWORD_FILE", env_secret.as_str()),
+            ("FORUMRS_MYSQL_SETTINGS__URL_FILE", "not-a-secret"),
+        ])).unwrap();
+        fs::remove_file(&env_secret).unwrap();
+        fs::remove_file(&file_secret).unwrap();
+        let found: Vec<(Vec<String>, toml::Value)> = overrides.into_iter().map(|setting_override| (setting_override.path, setting_override.value)).collect();
+
+        assert_eq!(found, vec![
+            // Secrets files from the settings file are applied before the environment.
+            (path(&["postgre_settings", "password"]), toml::Value::String("from-settings-file".to_string())),
+            (path(&["mysql_settings", "password"]), toml::Value::String("from-env-file".to_string())),
+            // Only secrets can be read from a file.
+            (path(&["mysql_settings", "url_file"]), toml::Value::String("not-a-secret".to_string())),
+        ]);
+    }
+
+    #[test]
+    fn missing_secret_file() {
+        let result = SettingsManager::overrides_from(&file(), variables(&[("FORUMRS_MYSQL_SETTINGS__PASSWORD_FILE", "/nonexistent/secret")]));
+        assert!(result.err().unwrap().starts_with("Unable to read the secrets file /nonexistent/secret"));
+    }
+
+    #[test]
+    fn saving_strips_overrides() {
+        let mut settings_file = file();
+        set_path(&mut settings_file, &path(&["mysql_settings", "password"]), None);
+        set_path(&mut settings_file, &path(&["mysql_settings", "password_file"]), Some(toml::Value::String("/run/secrets/mysql".to_string())));
+        let overrides = vec![
+            SettingOverride { path: path(&["mysql_settings", "password"]), value: toml::Value::String("from-secret".to_string()) },
+            SettingOverride { path: path(&["port"]), value: toml::Value::Integer(9090) },
+            SettingOverride { path: path(&["name"]), value: toml::Value::String("Env Forum".to_string()) },
+        ];
+
+        // The effective settings as they are saved, with the name changed by an admin.
+        let mut value: toml::Value = toml::from_str(r#"
+            name = "Renamed Forum"
+            port = 9090
+
+            [mysql_settings]
+            url = "localhost"
+            password = "from-secret"
+        "#).unwrap();
+        restore_file_values(&mut value, &settings_file, &overrides);
+
+        assert_eq!(value["port"].as_integer(), Some(8080));
+        assert_eq!(value["name"].as_str(), Some("Renamed Forum"));
+        assert_eq!(get_path(&value, &path(&["mysql_settings", "password"])), None);
+        assert_eq!(value["mysql_settings"]["password_file"].as_str(), Some("/run/secrets/mysql"));
+        assert!(!toml::to_string(&value).unwrap().contains("from-secret"));
+    }
+}
@@ -0,0 +1,341 @@
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::schema::database::Database;
+use crate::schema::migrations;
+use crate::settings::{BaseSettings, DatabaseType, SettingsManager};
+use crate::setup::setup;
+use crate::setup::setup_router::{AuthAccountCreation, AuthGeneralForm, AuthSecurityForm, AuthStorageForm};
+
+/// The setup file that is used when `FORUMRS_SETUP_FILE` is not set.
+pub const DEFAULT_SETUP_FILE: &str = "setup.toml";
+/// The prefix of the environment variables that override the setup file.
+const ENV_PREFIX: &str = "FORUMRS_SETUP_";
+
+/// What to do when the database from the storage settings already exists.
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ExistingDatabase {
+    /// Stop the setup. This is the default so data is never deleted by accident.
+    Fail,
+    /// Keep the data of the database and apply the pending schema upgrades. The admin account is only added when the
+    /// database has none.
+    Keep,
+    /// Delete the database and start from an empty one.
+    Reset,
+}
+
+impl Default for ExistingDatabase {
+    fn default() -> Self {
+        ExistingDatabase::Fail
+    }
+}
+
+impl FromStr for ExistingDatabase {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "fail" => Ok(ExistingDatabase::Fail),
+            "keep" => Ok(ExistingDatabase::Keep),
+            "reset" => Ok(ExistingDatabase::Reset),
+            _ => Err(format!("Unknown existing database option {}. Use fail, keep or reset.", s))
+        }
+    }
+}
+
+/// The values of the general stage.
+#[derive(Deserialize, Debug)]
+#[serde(default)]
+pub struct GeneralConfig {
+    pub name: String,
+    pub ip: String,
+    pub port: u32,
+    pub domain: String,
+}
+
+impl Default for GeneralConfig {
+    fn default() -> Self {
+        GeneralConfig {
+            name: String::new(),
+            ip: "127.0.0.1".to_string(),
+            port: 8080,
+            domain: String::new(),
+        }
+    }
+}
+
+/// The values of the security stage. SSL and reCAPTCHA are enabled when their keys are given.
+#[derive(Deserialize, Default, Debug)]
+#[serde(default)]
+pub struct SecurityConfig {
+    pub ssl_private_key: Option<String>,
+    pub ssl_public_key: Option<String>,
+    pub captcha_site_key: Option<String>,
+    pub captcha_secret_key: Option<String>,
+}
+
+/// The values of the storage stage.
+#[derive(Deserialize, Debug)]
+#[serde(default)]
+pub struct StorageConfig {
+    /// The type of database: sqlite, mysql or postgresql.
+    pub database: String,
+    /// The location of the database file. (SQLite)
+    pub sqlite_file: String,
+    /// The url of the database server. (MySQL and PostgreSQL)
+    pub url: Option<String>,
+    pub port: Option<u32>,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub database_name: Option<String>,
+    pub existing: ExistingDatabase,
+}
+
+impl Default for StorageConfig {
+    fn default() -> Self {
+        StorageConfig {
+            database: "sqlite".to_string(),
+            sqlite_file: "forumrs.db".to_string(),
+            url: None,
+            port: None,
+            username: None,
+            password: None,
+            database_name: None,
+            existing: ExistingDatabase::Fail,
+        }
+    }
+}
+
+impl StorageConfig {
+    /// Convert the storage values to the form of the storage stage.
+    pub fn form(&self) -> Result<AuthStorageForm, String> {
+        let db_type = match self.database.to_lowercase().as_str() {
+            "sqlite" => DatabaseType::SQLite,
+            "mysql" => DatabaseType::MySQL,
+            "postgresql" => DatabaseType::PostgreSQL,
+            other => return Err(format!("Unknown database type {}. Use sqlite, mysql or postgresql.", other))
+        };
+        let is_mysql = matches!(db_type, DatabaseType::MySQL);
+        let is_postgre = matches!(db_type, DatabaseType::PostgreSQL);
+
+        Ok(AuthStorageForm {
+            dbType: db_type,
+            sqlName: Some(self.sqlite_file.clone()),
+            mysqlURL: self.url.clone().filter(|_| is_mysql),
+            mysqlPort: self.port.filter(|_| is_mysql),
+            mysqlUsername: self.username.clone().filter(|_| is_mysql),
+            mysqlPassword: self.password.clone().filter(|_| is_mysql),
+            mysqlDbName: self.database_name.clone().filter(|_| is_mysql),
+            postURL: self.url.clone().filter(|_| is_postgre),
+            postPort: self.port.filter(|_| is_postgre),
+            postUsername: self.username.clone().filter(|_| is_postgre),
+            postPassword: self.password.clone().filter(|_| is_postgre),
+            postDbName: self.database_name.clone().filter(|_| is_postgre),
+        })
+    }
+}
+
+/// The admin account created by the account creation stage.
+#[derive(Deserialize, Default, Debug)]
+#[serde(default)]
+pub struct AdminConfig {
+    pub username: String,
+    pub email: String,
+    pub password: String,
+}
+
+/**
+   Every value the setup wizard asks for, so ForumRS can be set up without a browser.
+
+   This is read from a TOML file with a `[general]`, `[security]`, `[storage]` and `[admin]` section, and every
+   value can be overridden by a `FORUMRS_SETUP_<SECTION>_<NAME>` environment variable (ex: `FORUMRS_SETUP_ADMIN_PASSWORD`).
+   The values are validated with the same checks as the setup wizard.
+*/
+#[derive(Deserialize, Default, Debug)]
+#[serde(default)]
+pub struct HeadlessSetup {
+    pub general: GeneralConfig,
+    pub security: SecurityConfig,
+    pub storage: StorageConfig,
+    pub admin: AdminConfig,
+}
+
+/// Read an environment variable of the setup. Empty variables count as not set.
+fn env_value(section: &str, name: &str) -> Option<String> {
+    env::var(format!("{}{}_{}", ENV_PREFIX, section, name)).ok().filter(|value| !value.is_empty())
+}
+
+/// Read an environment variable of the setup that must be a number.
+fn env_number(section: &str, name: &str) -> Result<Option<u32>, String> {
+    match env_value(section, name) {
+        Some(value) => value.parse::<u32>().map(Some)
+            .map_err(|_| format!("{}{}_{} must be a number.", ENV_PREFIX, section, name)),
+        None => Ok(None)
+    }
+}
+
+impl HeadlessSetup {
+    /// Get the location of the setup file. This is `FORUMRS_SETUP_FILE` or setup.toml.
+    pub fn file_location() -> String {
+        env::var("FORUMRS_SETUP_FILE").unwrap_or_else(|_| DEFAULT_SETUP_FILE.to_string())
+    }
+
+    /**
+       Load the headless setup from the setup file and the environment.
+
+       ## Returns
+       None if there is no setup file and no setup environment variables, in which case the setup wizard should be used.
+    */
+    pub fn load() -> Result<Option<HeadlessSetup>, String> {
+        let file = HeadlessSetup::file_location();
+        let from_file = Path::new(file.as_str()).exists();
+        let from_env = env::vars().any(|(key, _)| key.starts_with(ENV_PREFIX) && key != "FORUMRS_SETUP_FILE");
+
+        if !from_file && !from_env {
+            return Ok(None);
+        }
+
+        let mut headless: HeadlessSetup = if from_file {
+            let contents = fs::read_to_string(file.as_str()).map_err(|err| format!("Unable to read the setup file {}: {}", file, err))?;
+            toml::from_str(contents.as_str()).map_err(|err| format!("Invalid setup file {}: {}", file, err))?
+        } else {
+            HeadlessSetup::default()
+        };
+
+        headless.apply_env()?;
+        Ok(Some(headless))
+    }
+
+    /// Override the values with the setup environment variables.
+    pub fn apply_env(&mut self) -> Result<(), String> {
+        if let Some(value) = env_value("GENERAL", "NAME") { self.general.name = value; }
+        if let Some(value) = env_value("GENERAL", "IP") { self.general.ip = value; }
+        if let Some(value) = env_number("GENERAL", "PORT")? { self.general.port = value; }
+        if let Some(value) = env_value("GENERAL", "DOMAIN") { self.general.domain = value; }
+
+        if let Some(value) = env_value("SECURITY", "SSL_PRIVATE_KEY") { self.security.ssl_private_key = Some(value); }
+        if let Some(value) = env_value("SECURITY", "SSL_PUBLIC_KEY") { self.security.ssl_public_key = Some(value); }
+        if let Some(value) = env_value("SECURITY", "CAPTCHA_SITE_KEY") { self.security.captcha_site_key = Some(value); }
+        if let Some(value) = env_value("SECURITY", "CAPTCHA_SECRET_KEY") { self.security.captcha_secret_key = Some(value); }
+
+        if let Some(value) = env_value("STORAGE", "DATABASE") { self.storage.database = value; }
+        if let Some(value) = env_value("STORAGE", "SQLITE_FILE") { self.storage.sqlite_file = value; }
+        if let Some(value) = env_value("STORAGE", "URL") { self.storage.url = Some(value); }
+        if let Some(value) = env_number("STORAGE", "PORT")? { self.storage.port = Some(value); }
+        if let Some(value) = env_value("STORAGE", "USERNAME") { self.storage.username = Some(value); }
+        if let Some(value) = env_value("STORAGE", "PASSWORD") { self.storage.password = Some(value); }
+        if let Some(value) = env_value("STORAGE", "DATABASE_NAME") { self.storage.database_name = Some(value); }
+        if let Some(value) = env_value("STORAGE", "EXISTING") { self.storage.existing = ExistingDatabase::from_str(value.as_str())?; }
+
+        if let Some(value) = env_value("ADMIN", "USERNAME") { self.admin.username = value; }
+        if let Some(value) = env_value("ADMIN", "EMAIL") { self.admin.email = value; }
+        if let Some(value) = env_value("ADMIN", "PASSWORD") { self.admin.password = value; }
+
+        Ok(())
+    }
+
+    /**
+       Run every stage of the setup and save the settings.
+
+       Nothing is saved unless every stage is valid. The database is only changed after the admin account
+       has been validated.
+
+       ## Returns
+       The settings of the forum that has been set up.
+    */
+    pub async fn run(self) -> Result<BaseSettings, String> {
+        let mut settings = if SettingsManager::settings_exist() {
+            SettingsManager::get_settings()
+        } else {
+            BaseSettings::create_default()
+        };
+
+        if !settings.new_setup {
+            return Err(String::from("ForumRS has already been set up."));
+        }
+
+        setup::apply_general(&mut settings, &AuthGeneralForm {
+            name: self.general.name.clone(),
+            ip: self.general.ip.clone(),
+            port: self.general.port.to_string(),
+            domain: self.general.domain.clone(),
+        }).map_err(|err| format!("General: {}", err))?;
+
+        setup::apply_security(&mut settings, &AuthSecurityForm {
+            useSSL: self.security.ssl_private_key.as_ref().or(self.security.ssl_public_key.as_ref()).map(|_| "on".to_string()),
+            privateKey: self.security.ssl_private_key.clone(),
+            publicKey: self.security.ssl_public_key.clone(),
+            useCaptch: self.security.captcha_site_key.as_ref().or(self.security.captcha_secret_key.as_ref()).map(|_| "on".to_string()),
+            siteKey: self.security.captcha_site_key.clone(),
+            secretKey: self.security.captcha_secret_key.clone(),
+        }).map_err(|err| format!("Security: {}", err))?;
+
+        let existing = setup::apply_storage(&mut settings, &self.storage.form()?).await
+            .map_err(|err| format!("Storage: {}", err))?;
+
+        let account = AuthAccountCreation {
+            username: self.admin.username.clone(),
+            email: self.admin.email.clone(),
+            password: self.admin.password.clone(),
+            confirmPassword: self.admin.password.clone(),
+        };
+
+        if existing && self.storage.existing == ExistingDatabase::Keep {
+            println!("[INFO] Keeping the existing database.");
+            let report = setup::inspect_existing(&settings).await?;
+            for table in report.tables.iter() {
+                match table.rows {
+                    Some(rows) => println!("[INFO] {}: {} rows", table.name, rows),
+                    None => println!("[INFO] {}: missing, it will be created", table.name)
+                }
+            }
+
+            // The existing data needs an admin that can log in.
+            if report.admins == 0 {
+                setup::validate_account(&settings, &account)
+                    .map_err(|err| format!("Admin: The existing database has no admin account, so one is added: {}", err))?;
+            }
+
+            let mut db = Database::connect(&settings).await.map_err(|err| format!("Unable to connect to the database: {:?}", err))?;
+            let result = migrate_existing(&mut db, &account, report.admins == 0).await;
+            db.close().await;
+            result?;
+        } else {
+            if existing && self.storage.existing == ExistingDatabase::Fail {
+                return Err(String::from("Storage: The database already exists. Set existing to keep to use its data or reset to delete it."));
+            }
+
+            setup::validate_account(&settings, &account).map_err(|err| format!("Admin: {}", err))?;
+
+            if existing {
+                println!("[INFO] Deleting the existing database.");
+                setup::reset_database(&settings).await.map_err(|err| format!("Unable to reset the existing database: {:?}", err))?;
+            }
+
+            let mut db = setup::create_database(&settings).await.map_err(|err| format!("Unable to set up the database: {:?}", err))?;
+            setup::create_admin_account(&mut db, &account).await;
+            db.close().await;
+        }
+
+        settings.new_setup = false;
+        settings.setup_stage = None;
+        SettingsManager::save_settings(&settings);
+
+        Ok(settings)
+    }
+}
+
+/// Apply the pending schema upgrades to an existing database and add the admin account if it needs one.
+async fn migrate_existing(db: &mut Database, account: &AuthAccountCreation, add_admin: bool) -> Result<(), String> {
+    migrations::upgrade(db).await?;
+
+    if add_admin {
+        setup::add_admin_account(db, account).await.map_err(|err| format!("Admin: {}", err))?;
+        println!("[INFO] Added the admin account {} to the existing database.", account.username);
+    }
+
+    Ok(())
+}
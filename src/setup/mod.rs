@@ -1,2 +1,3 @@
+pub mod headless;
 pub mod setup;
 pub mod setup_router;
\ No newline at end of file
@@ -1,16 +1,46 @@
 use std::fmt;
 use std::fmt::Formatter;
+use std::path::Path;
+use std::time::Duration;
 
-#[derive(Deserialize, Serialize, Debug, PartialEq)]
+use regex::Regex;
+use sqlx::{Connection, MySqlConnection, PgConnection};
+use uuid::Uuid;
+
+use crate::auth::password_policy::PasswordPolicy;
+use crate::schema::database::Database;
+use crate::schema::migrations;
+use crate::schema::migrations::{MigrationReport, SCHEMA_VERSION};
+use crate::schema::tables::{Forums, SchemaVersion, Users};
+use crate::settings::{BaseSettings, CaptchaSettings, DatabaseType, MysqlSettings, PostgreSQLSettings, SqlSettings, SSLSettings};
+use crate::setup::setup_router::{AuthAccountCreation, AuthGeneralForm, AuthSecurityForm, AuthStorageForm};
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
 pub enum SetupStage {
     General,
     Security,
     Storage,
     ExistingStorage,
     AccountCreation,
+    Review,
     Finished
 }
 
+impl SetupStage {
+    /// The position of the stage in the setup process. Earlier stages can be visited again until setup is finished.
+    pub fn position(&self) -> u32 {
+        match self {
+            SetupStage::General => 0,
+            SetupStage::Security => 1,
+            SetupStage::Storage => 2,
+            SetupStage::ExistingStorage => 3,
+            SetupStage::AccountCreation => 4,
+            SetupStage::Review => 5,
+            SetupStage::Finished => 6
+        }
+    }
+}
+
 /**
     Convert the SetupStage enum to string.
 */
@@ -18,4 +48,442 @@ impl fmt::Display for SetupStage {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         write!(f, "{}", format!("{:?}", self).to_string().to_lowercase())
     }
-}
\ No newline at end of file
+}
+
+/**
+   The admin account picked during setup.
+
+   Nothing is written to the database until the review is confirmed, so the account is kept in memory until then.
+*/
+#[derive(Debug, Clone)]
+pub enum SetupAccount {
+    /// Create this admin account in a new database.
+    New(AuthAccountCreation),
+    /// Keep the existing database and the accounts in it. An admin account can be added when it has none.
+    Existing(Option<AuthAccountCreation>)
+}
+
+impl SetupAccount {
+    /// Get the admin account that will be created, if there is one.
+    pub fn admin(&self) -> Option<&AuthAccountCreation> {
+        match self {
+            SetupAccount::New(account) => Some(account),
+            SetupAccount::Existing(account) => account.as_ref()
+        }
+    }
+}
+
+/**
+   A value given to a setup stage that is not valid.
+
+   The same checks are used by the setup wizard and the command line, so the code is the `err` query
+   parameter of the page of the stage and the message is what is shown on the command line.
+*/
+#[derive(Debug)]
+pub struct SetupError {
+    pub code: u32,
+    pub message: String,
+}
+
+impl SetupError {
+    fn new(code: u32, message: &str) -> SetupError {
+        SetupError {
+            code,
+            message: message.to_string()
+        }
+    }
+}
+
+impl fmt::Display for SetupError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+/// Validate the general stage and apply it to the settings.
+pub fn apply_general(settings: &mut BaseSettings, form: &AuthGeneralForm) -> Result<(), SetupError> {
+    if form.name.len() < 1 {
+        return Err(SetupError::new(1, "The name of the forum cannot be empty."));
+    }
+
+    // Validate the ip address via regex.
+    let ip_regex = Regex::new(r"^(?:(?:25[0-5]|2[0-4][0-9]|1[0-9][0-9]|[1-9][0-9]|[0-9])\.){3}(?:25[0-5]|2[0-4][0-9]|1[0-9][0-9]|[1-9][0-9]|[0-9])$").unwrap();
+    if form.ip.len() < 1 || !ip_regex.is_match(form.ip.as_str()) {
+        return Err(SetupError::new(2, "The ip must be a valid IPv4 address."));
+    }
+
+    if form.domain.len() < 1 {
+        return Err(SetupError::new(4, "The domain cannot be empty."));
+    }
+
+    // Check if the port is an integer and if the port number is even valid.
+    let port_num = match form.port.parse::<u32>() {
+        Ok(port) if port >= 1 && port <= 65535 => port,
+        _ => return Err(SetupError::new(3, "The port must be a number between 1 and 65535."))
+    };
+
+    settings.name = form.name.clone();
+    settings.ip = form.ip.clone();
+    settings.port = port_num;
+    settings.domain = form.domain.clone();
+
+    Ok(())
+}
+
+/// Validate the security stage and apply it to the settings.
+pub fn apply_security(settings: &mut BaseSettings, form: &AuthSecurityForm) -> Result<(), SetupError> {
+    if form.useSSL.as_deref() == Some("on") {
+        // The keys need to exist.
+        if form.privateKey.is_none() || form.publicKey.is_none() {
+            return Err(SetupError::new(1, "SSL requires both a private key and a public key."));
+        }
+        let private_key = form.privateKey.as_ref().unwrap().clone();
+        let public_key = form.publicKey.as_ref().unwrap().clone();
+
+        let key = Regex::new(r"^.*\.(pem|PEM|asn1|ASN1)$").unwrap();
+        if !key.is_match(private_key.as_str()) {
+            return Err(SetupError::new(2, "The private key must be a .pem or .asn1 file."));
+        }
+
+        if !key.is_match(public_key.as_str()) {
+            return Err(SetupError::new(3, "The public key must be a .pem or .asn1 file."));
+        }
+
+        if !Path::new(private_key.as_str()).exists() {
+            return Err(SetupError::new(4, "The private key file does not exist."));
+        }
+
+        if !Path::new(public_key.as_str()).exists() {
+            return Err(SetupError::new(5, "The public key file does not exist."));
+        }
+
+        settings.use_sll = true;
+        settings.ssl_settings = Some(SSLSettings {
+            private_key,
+            public_key
+        });
+    } else {
+        settings.use_sll = false;
+    }
+
+    if form.useCaptch.as_deref() == Some("on") {
+        // The keys need to exist.
+        if form.siteKey.is_none() || form.secretKey.is_none() {
+            return Err(SetupError::new(6, "reCAPTCHA requires both a site key and a secret key."));
+        }
+        let site_key = form.siteKey.as_ref().unwrap().clone();
+        let secret_key = form.secretKey.as_ref().unwrap().clone();
+
+        // I think the keys are always 40 in length.
+        if site_key.len() != 40 || secret_key.len() != 40 {
+            return Err(SetupError::new(7, "The reCAPTCHA keys must be 40 characters long."));
+        }
+
+        settings.use_captcha = true;
+        settings.captcha_settings = Some(CaptchaSettings {
+            site_key,
+            secret_key
+        });
+    } else {
+        settings.use_captcha = false;
+    }
+
+    Ok(())
+}
+
+/**
+   Validate the storage stage and apply it to the settings.
+   For MySQL and PostgreSQL this connects to the server to make sure ForumRS can access it.
+
+   ## Returns
+   If the database already exists.
+*/
+pub async fn apply_storage(settings: &mut BaseSettings, form: &AuthStorageForm) -> Result<bool, SetupError> {
+    match form.dbType.clone() {
+        DatabaseType::SQLite => {
+            let sql_name = match &form.sqlName {
+                Some(sql_name) => sql_name.clone(),
+                None => return Err(SetupError::new(1, "SQLite requires the location of the database file."))
+            };
+
+            let sql_regex = Regex::new(r"^.*\.(db)$").unwrap();
+            if !sql_regex.is_match(&sql_name) {
+                return Err(SetupError::new(1, "The SQLite database file must end in .db."));
+            }
+
+            settings.database_type = DatabaseType::SQLite;
+            settings.sql_settings = Some(SqlSettings {
+                file_location: sql_name.clone()
+            });
+
+            Ok(Path::new(sql_name.as_str()).exists())
+        },
+        DatabaseType::MySQL => {
+            if form.mysqlURL.is_none() || form.mysqlDbName.is_none() || form.mysqlPassword.is_none() || form.mysqlPort.is_none()
+                || form.mysqlUsername.is_none() {
+                return Err(SetupError::new(2, "MySQL requires a url, port, username, password and database name."));
+            }
+
+            let mysql_settings = MysqlSettings {
+                url: form.mysqlURL.as_ref().unwrap().clone(),
+                port: form.mysqlPort.unwrap(),
+                username: form.mysqlUsername.as_ref().unwrap().clone(),
+                password: form.mysqlPassword.as_ref().unwrap().clone(),
+                database_name: form.mysqlDbName.as_ref().unwrap().clone()
+            };
+
+            let connection = MySqlConnection::connect(&format!("mysql://{}:{}@{}:{}", mysql_settings.username, mysql_settings.password,
+                                                                mysql_settings.url, mysql_settings.port)).await;
+
+            let mut con = match connection {
+                Ok(con) => con,
+                Err(connection_err) => {
+                    if connection_err.as_database_error().is_some() {
+                        println!("[WARN] The following error occurred when connecting to the MySQL database: {:?}", connection_err.as_database_error().unwrap().message());
+                    }
+                    else {
+                        println!("[WARN] The following error occurred when connecting to the MySQL database: {:?}", connection_err);
+                        println!("[WARN] Check to make sure ForumRS can access the specified MySQL server.");
+                    }
+                    return Err(SetupError::new(3, "Unable to connect to the MySQL server."));
+                }
+            };
+
+            let found_database = sqlx::query("SELECT SCHEMA_NAME FROM INFORMATION_SCHEMA.SCHEMATA WHERE SCHEMA_NAME = ?")
+                .bind(mysql_settings.database_name.clone())
+                .fetch_one(&mut con).await;
+
+            con.close().await.unwrap();
+
+            settings.database_type = DatabaseType::MySQL;
+            settings.mysql_settings = Some(mysql_settings);
+
+            Ok(found_database.is_ok())
+        },
+        DatabaseType::PostgreSQL => {
+            if form.postURL.is_none() || form.postDbName.is_none() || form.postPassword.is_none() || form.postPort.is_none()
+                || form.postUsername.is_none() {
+                return Err(SetupError::new(4, "PostgreSQL requires a url, port, username, password and database name."));
+            }
+
+            let post_settings = PostgreSQLSettings {
+                url: form.postURL.as_ref().unwrap().clone(),
+                port: form.postPort.unwrap(),
+                username: form.postUsername.as_ref().unwrap().clone(),
+                password: form.postPassword.as_ref().unwrap().clone(),
+                database_name: form.postDbName.as_ref().unwrap().clone()
+            };
+
+            let connection = PgConnection::connect(&format!("postgresql://{}:{}@{}:{}", post_settings.username, post_settings.password,
+                                                            post_settings.url, post_settings.port)).await;
+
+            let mut con = match connection {
+                Ok(con) => con,
+                Err(connection_err) => {
+                    if connection_err.as_database_error().is_some() {
+                        println!("[WARN] The following error occurred when connecting to the PostgreSQL database: {:?}", connection_err.as_database_error().unwrap().message());
+                    }
+                    else {
+                        println!("[WARN] The following error occurred when connecting to the PostgreSQL database: {:?}", connection_err);
+                        println!("[WARN] Check to make sure ForumRS can access the specified PostgreSQL server.");
+                    }
+                    return Err(SetupError::new(5, "Unable to connect to the PostgreSQL server."));
+                }
+            };
+
+            let found_database = sqlx::query(&format!("SELECT datname FROM pg_catalog.pg_database WHERE datname = '{}';", post_settings.database_name))
+                .fetch_one(&mut con).await;
+
+            con.close().await.unwrap();
+
+            settings.database_type = DatabaseType::PostgreSQL;
+            settings.postgre_settings = Some(post_settings);
+
+            Ok(found_database.is_ok())
+        }
+    }
+}
+
+/// Validate the admin account of the account creation stage.
+pub fn validate_account(settings: &BaseSettings, form: &AuthAccountCreation) -> Result<(), SetupError> {
+    if form.username.len() < 1 {
+        return Err(SetupError::new(1, "The username cannot be empty."));
+    }
+
+    if form.password.len() < 1 {
+        return Err(SetupError::new(2, "The password cannot be empty."));
+    }
+
+    // Validate the password against the password policy.
+    let policy = PasswordPolicy::new(&settings.password_settings);
+    if let Err(err) = policy.validate(form.password.as_str(), &[form.username.as_str(), form.email.as_str()]) {
+        return Err(SetupError {
+            code: err.error_code(),
+            message: err.to_string()
+        });
+    }
+
+    if form.password != form.confirmPassword {
+        return Err(SetupError::new(3, "The passwords do not match."));
+    }
+
+    // TODO validate email.
+    if form.email.len() < 1 {
+        return Err(SetupError::new(4, "The email cannot be empty."));
+    }
+
+    Ok(())
+}
+
+/**
+   Validate every stage before the furthest one reached again. An earlier stage may have been changed since,
+   or the settings file may have been edited by hand.
+
+   ## Returns
+   The stage the setup process should continue from.
+*/
+pub async fn revalidate(settings: &BaseSettings, account: Option<&SetupAccount>) -> SetupStage {
+    let furthest = settings.setup_stage.clone().unwrap_or(SetupStage::General);
+    let mut scratch = settings.clone();
+
+    if furthest.position() > SetupStage::General.position()
+        && apply_general(&mut scratch, &AuthGeneralForm::from_settings(settings)).is_err() {
+        return SetupStage::General;
+    }
+
+    if furthest.position() > SetupStage::Security.position()
+        && apply_security(&mut scratch, &AuthSecurityForm::from_settings(settings)).is_err() {
+        return SetupStage::Security;
+    }
+
+    if furthest.position() > SetupStage::Storage.position() {
+        let existing = match apply_storage(&mut scratch, &AuthStorageForm::from_settings(settings)).await {
+            Ok(existing) => existing,
+            Err(_) => return SetupStage::Storage
+        };
+
+        // The account only makes sense for the database it was picked for.
+        match account {
+            Some(SetupAccount::New(_)) if existing => return SetupStage::ExistingStorage,
+            Some(SetupAccount::Existing(_)) if !existing => return SetupStage::AccountCreation,
+            None if existing && furthest.position() >= SetupStage::AccountCreation.position() => return SetupStage::ExistingStorage,
+            None if furthest.position() > SetupStage::AccountCreation.position() => return SetupStage::AccountCreation,
+            _ => {}
+        }
+    }
+
+    if furthest.position() > SetupStage::AccountCreation.position() {
+        if let Some(form) = account.and_then(|account| account.admin()) {
+            if validate_account(settings, form).is_err() {
+                return SetupStage::AccountCreation;
+            }
+        }
+    }
+
+    furthest
+}
+
+/// Delete the existing database from the storage settings so setup can start from an empty one.
+pub async fn reset_database(settings: &BaseSettings) -> Result<(), sqlx::Error> {
+    match settings.database_type {
+        DatabaseType::SQLite => {
+            std::fs::remove_file(Path::new(settings.sql_settings.as_ref().unwrap().file_location.as_str()))?;
+        },
+        DatabaseType::MySQL => {
+            let mysql_settings = settings.mysql_settings.as_ref().unwrap();
+            let mut connection = MySqlConnection::connect(&format!("mysql://{}:{}@{}:{}", mysql_settings.username, mysql_settings.password,
+                                                               mysql_settings.url, mysql_settings.port)).await?;
+
+            sqlx::query(&format!("DROP DATABASE IF EXISTS {};", mysql_settings.database_name))
+                .execute(&mut connection).await?;
+            connection.close().await?;
+        },
+        DatabaseType::PostgreSQL => {
+            let post_settings = settings.postgre_settings.as_ref().unwrap();
+            let mut connection = PgConnection::connect(&format!("postgresql://{}:{}@{}:{}", post_settings.username, post_settings.password,
+                                                            post_settings.url, post_settings.port)).await?;
+
+            // Drop the database if it exists.
+            sqlx::query(&format!("DROP DATABASE IF EXISTS \"{}\";", post_settings.database_name))
+                .execute(&mut connection).await?;
+            connection.close().await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Create the database from the storage settings (if needed) and all of the tables of the forum.
+pub async fn create_database(settings: &BaseSettings) -> Result<Database, sqlx::Error> {
+    let mut db = match settings.database_type {
+        DatabaseType::SQLite => {
+            Database::new_sqlite(settings.sql_settings.as_ref().unwrap()).await?
+        }
+        DatabaseType::MySQL => {
+            crate::schema::dbsetup::create_schema_mysql(settings.mysql_settings.as_ref().unwrap()).await;
+            Database::new_mysql(settings.mysql_settings.as_ref().unwrap()).await?
+        }
+        DatabaseType::PostgreSQL => {
+            crate::schema::dbsetup::create_schema_postgre(settings.postgre_settings.as_ref().unwrap()).await;
+            std::thread::sleep(Duration::from_millis(100));
+            Database::new_postgre(settings.postgre_settings.as_ref().unwrap()).await?
+        }
+    };
+
+    crate::schema::dbsetup::setup_database(&mut db).await?;
+    SchemaVersion::set(&mut db, SCHEMA_VERSION).await?;
+
+    Ok(db)
+}
+
+/**
+   Insert the admin account and the default forums. The account should be validated with [validate_account] first.
+
+   ## Returns
+   The uuid of the admin account.
+*/
+pub async fn create_admin_account(db: &mut Database, form: &AuthAccountCreation) -> Uuid {
+    let admin_uuid = insert_admin(db, form).await;
+
+    // Insert default forums.
+    Forums::insert(db, Uuid::new_v4(), "Announcements".to_string(), "General announcements for the website.".to_string()).await;
+    Forums::insert(db, Uuid::new_v4(), "General".to_string(), "General forum discussions.".to_string()).await;
+
+    admin_uuid
+}
+
+/// Insert the admin user.
+async fn insert_admin(db: &mut Database, form: &AuthAccountCreation) -> Uuid {
+    let hashed_password = bcrypt::hash(form.password.clone(), bcrypt::DEFAULT_COST).unwrap();
+
+    let admin_uuid = Uuid::new_v4();
+    Users::insert(db, admin_uuid, form.username.clone(), form.email.clone(), hashed_password, false, true).await;
+
+    admin_uuid
+}
+
+/**
+   Add an admin account to an existing database that was migrated. The account should be validated with
+   [validate_account] first.
+
+   ## Returns
+   The uuid of the admin account.
+*/
+pub async fn add_admin_account(db: &mut Database, form: &AuthAccountCreation) -> Result<Uuid, SetupError> {
+    match Users::get_by_username(db, form.username.as_str()).await {
+        Ok(None) => Ok(insert_admin(db, form).await),
+        Ok(Some(_)) => Err(SetupError::new(13, "The username is already taken by an account in the existing database.")),
+        Err(err) => {
+            println!("[WARN] Unable to check the existing accounts: {:?}", err);
+            Err(SetupError::new(14, "Unable to read the accounts of the existing database."))
+        }
+    }
+}
+
+/// Connect to the existing database from the storage settings and look at what it holds without changing it.
+pub async fn inspect_existing(settings: &BaseSettings) -> Result<MigrationReport, String> {
+    let mut db = Database::connect(settings).await.map_err(|err| format!("Unable to connect to the database: {:?}", err))?;
+    let report = migrations::inspect(&mut db).await.map_err(|err| format!("Unable to read the database: {:?}", err));
+    db.close().await;
+    report
+}
@@ -1,26 +1,25 @@
 use std::borrow::Borrow;
-use std::path::Path;
 use std::str::FromStr;
 use std::sync::{Arc, Mutex};
 
 use actix_web::{get, http, HttpMessage, HttpRequest, HttpResponse, post, Responder, web};
-use actix_web::rt::Arbiter;
 use actix_web::web::{Bytes, Form};
 use handlebars::Handlebars;
-use regex::Regex;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use serde_json::json;
-use sqlx::{Connection, MySqlConnection, PgConnection};
 use uuid::Uuid;
-use bcrypt;
 
-use crate::settings::{BaseSettings, CaptchaSettings, DatabaseType, MysqlSettings, PostgreSQLSettings, SettingsManager, SqlSettings, SSLSettings};
-use crate::settings::DatabaseType::{MySQL, PostgreSQL, SQLite};
-use crate::setup::setup::SetupStage::{ExistingStorage, Finished, General, Security, Storage, AccountCreation};
+use crate::settings::{BaseSettings, DatabaseType, SettingsManager};
+use crate::setup::setup;
+use crate::setup::setup::{SetupAccount, SetupStage};
+use crate::setup::setup::SetupStage::{ExistingStorage, Finished, General, Security, Storage, AccountCreation, Review};
 use crate::state::SetupForumRSState;
 use crate::schema::database::Database;
-use std::time::Duration;
-use crate::schema::tables::{Users, Forums};
+use crate::schema::migrations;
+use crate::schema::tables::Users;
+use crate::auth::login_protection;
+use crate::auth::session;
+use crate::auth::password_policy::PasswordPolicy;
 
 /// The welcome (index) page for the setup process.
 #[get("/")]
@@ -72,12 +71,21 @@ pub async fn auth_login(data: actix_web::web::Data<SetupForumRSState>, form: web
         return HttpResponse::Found().header("Location", "/login").finish();
     }
 
+    // Throttle guesses of the setup code by client IP.
+    let protection_settings = SettingsManager::get_settings().login_protection_settings;
+    let ip_key = login_protection::ip_key(login_protection::client_ip(&req, &protection_settings).as_str());
+    if let Err(err) = data.login_throttle.lock().unwrap().check(&[ip_key.as_str()], &protection_settings) {
+        return HttpResponse::Found().header("Location", format!("/login?err=2&wait={}", err.retry_after())).finish();
+    }
+
     // If the UUID from the form is not valid.
     if Uuid::from_str(form.login_code.as_str()).is_err() {
+        data.login_throttle.lock().unwrap().record_failure(ip_key.as_str(), protection_settings.ip_lockout_threshold, &protection_settings);
         return HttpResponse::Found().header("Location", "/login?err=1").finish();
     }
 
     if Uuid::parse_str(form.login_code.as_str()).unwrap().to_string() == data.setup_code.to_string() {
+        data.login_throttle.lock().unwrap().record_success(ip_key.as_str());
         let new_session_id = Uuid::new_v4();
         *data.setup_session.lock().unwrap() = Some(new_session_id);
 
@@ -92,6 +100,9 @@ pub async fn auth_login(data: actix_web::web::Data<SetupForumRSState>, form: web
             .header("Location", format!("/{}", SettingsManager::get_settings().setup_stage.unwrap()))
             .finish()
     } else {
+        if data.login_throttle.lock().unwrap().record_failure(ip_key.as_str(), protection_settings.ip_lockout_threshold, &protection_settings) {
+            println!("[WARN] Too many invalid setup codes were entered by {}. Their attempts are locked.", ip_key);
+        }
         return HttpResponse::Found().header("Location", "/login?err=1").finish();
     }
 }
@@ -121,85 +132,159 @@ fn check_login(data: &actix_web::web::Data<SetupForumRSState>, req: HttpRequest)
     Err(HttpResponse::Found().del_cookie(&req.cookie("session").unwrap()).header("Location", "/login").finish())
 }
 
-#[get("/general")]
-pub async fn general(data: actix_web::web::Data<SetupForumRSState>, req: HttpRequest) -> impl Responder {
-    // Check if the user is logged in.
-    let loggedin = check_login(&data, req);
-    if loggedin.is_err() {
-        return loggedin.unwrap_err();
+/**
+   Check to see if the stage can be visited by the user.
+
+   Stages up to the furthest one reached can be visited again until setup is finished. The existing storage stage is
+   only shown while the choice is being made.
+*/
+fn can_visit(stage: &SetupStage, furthest: &SetupStage) -> bool {
+    match stage {
+        Finished => *furthest == Finished,
+        _ if *furthest == Finished => false,
+        ExistingStorage => *furthest == ExistingStorage,
+        AccountCreation => furthest.position() >= AccountCreation.position(),
+        _ => stage.position() <= furthest.position()
     }
+}
 
-    // If the user is at the wrong stage, take them to the correct one.
-    if !(SettingsManager::get_settings().setup_stage.unwrap() == General) {
-        return HttpResponse::Found().header("Location", format!("/{}", SettingsManager::get_settings().setup_stage.unwrap())).finish();
+/// Check to see if the user can visit the stage. If not a response is compiled that takes them to the stage they are at.
+fn check_stage(stage: SetupStage) -> Result<(), HttpResponse> {
+    let furthest = SettingsManager::get_settings().setup_stage.unwrap();
+    if can_visit(&stage, &furthest) {
+        return Ok(());
     }
 
-    let result: String = (&data.hbs).render("setup/general", &json!({"test": "test"})).unwrap();
+    Err(HttpResponse::Found().header("Location", format!("/{}", furthest)).finish())
+}
 
-    HttpResponse::Ok().body(result)
+/// Build the step indicator shown at the top of every stage.
+fn steps(current: SetupStage) -> serde_json::Value {
+    let furthest = SettingsManager::get_settings().setup_stage.unwrap();
+
+    let steps: Vec<serde_json::Value> = vec![(General, "General"), (Security, "Security"), (Storage, "Storage"),
+                                             (AccountCreation, "Admin Account"), (Review, "Review")]
+        .into_iter()
+        .map(|(stage, name)| {
+            // The existing storage stage is part of the storage step.
+            let active = stage == current || (stage == Storage && current == ExistingStorage);
+            json!({
+                "name": name,
+                "url": format!("/{}", stage),
+                "active": active,
+                "completed": stage.position() < furthest.position(),
+                "available": !active && can_visit(&stage, &furthest),
+            })
+        })
+        .collect();
+
+    json!(steps)
 }
 
-#[derive(Deserialize)]
-pub struct AuthGeneralForm {
-    name: String,
-    ip: String,
-    port: String,
-    domain: String
+/**
+   Save a stage that was submitted and take the user to where they should continue.
+
+   The first time a stage is submitted the user moves on to the next one. When an earlier stage is changed, the stages
+   after it are validated again and the user continues from the first one that is no longer valid.
+*/
+async fn continue_setup(data: &actix_web::web::Data<SetupForumRSState>, mut settings: BaseSettings, next: SetupStage) -> HttpResponse {
+    let furthest = settings.setup_stage.clone().unwrap();
+    if next.position() > furthest.position() {
+        settings.setup_stage = Some(next);
+    } else {
+        let account = data.setup_account.lock().unwrap().clone();
+        settings.setup_stage = Some(setup::revalidate(&settings, account.as_ref()).await);
+    }
+
+    // The existing database has to be picked again when the user is sent back before the review.
+    let stage = settings.setup_stage.clone().unwrap();
+    if stage.position() < Review.position() {
+        let mut account = data.setup_account.lock().unwrap();
+        if matches!(*account, Some(SetupAccount::Existing(_))) {
+            *account = None;
+        }
+    }
+
+    SettingsManager::save_settings(&settings);
+
+    HttpResponse::Found().header("Location", format!("/{}", stage)).finish()
 }
 
-#[post("/auth/general")]
-pub async fn auth_general(data: actix_web::web::Data<SetupForumRSState>, form: web::Form<AuthGeneralForm>, req: HttpRequest) -> impl Responder {
+#[get("/general")]
+pub async fn general(data: actix_web::web::Data<SetupForumRSState>, req: HttpRequest) -> impl Responder {
     // Check if the user is logged in.
     let loggedin = check_login(&data, req);
     if loggedin.is_err() {
         return loggedin.unwrap_err();
     }
 
-    // If the user is at the wrong stage, take them to the correct one.
-    if !(SettingsManager::get_settings().setup_stage.unwrap() == General) {
-        return HttpResponse::Found().header("Location", format!("/{}", SettingsManager::get_settings().setup_stage.unwrap())).finish();
+    // If the user cannot visit this stage, take them to the correct one.
+    if let Err(response) = check_stage(General) {
+        return response;
     }
 
-    if form.name.len() < 1 {
-        return HttpResponse::Found().header("Location", "/general?err=1").finish();
-    }
+    let settings = SettingsManager::get_settings();
+    let values = if settings.setup_stage.clone().unwrap() == General {
+        AuthGeneralForm::default()
+    } else {
+        AuthGeneralForm::from_settings(&settings)
+    };
 
-    if form.ip.len() < 1 {
-        return HttpResponse::Found().header("Location", "/general?err=2").finish();
-    }
+    let result: String = (&data.hbs).render("setup/general", &json!({"steps": steps(General), "values": values})).unwrap();
+
+    HttpResponse::Ok().body(result)
+}
 
-    if form.domain.len() < 1 {
-        return HttpResponse::Found().header("Location", "/general?err=4").finish();
+#[derive(Deserialize, Serialize)]
+pub struct AuthGeneralForm {
+    pub(crate) name: String,
+    pub(crate) ip: String,
+    pub(crate) port: String,
+    pub(crate) domain: String
+}
+
+impl AuthGeneralForm {
+    /// Fill in the form from the saved settings.
+    pub fn from_settings(settings: &BaseSettings) -> AuthGeneralForm {
+        AuthGeneralForm {
+            name: settings.name.clone(),
+            ip: settings.ip.clone(),
+            port: settings.port.to_string(),
+            domain: settings.domain.clone()
+        }
     }
+}
 
-    // Validate the ip address via regex.
-    let ip_regex = Regex::new(r"^(?:(?:25[0-5]|2[0-4][0-9]|1[0-9][0-9]|[1-9][0-9]|[0-9])\.){3}(?:25[0-5]|2[0-4][0-9]|1[0-9][0-9]|[1-9][0-9]|[0-9])$").unwrap();
-    if !ip_regex.is_match(form.ip.as_str()) {
-        return HttpResponse::Found().header("Location", "/general?err=2").finish();
+impl Default for AuthGeneralForm {
+    fn default() -> Self {
+        AuthGeneralForm {
+            name: String::new(),
+            ip: "127.0.0.1".to_string(),
+            port: "8080".to_string(),
+            domain: String::new()
+        }
     }
+}
 
-    // Check if the port is an integer.
-    let port_num_opt = form.port.parse::<u32>();
-    if port_num_opt.is_err() {
-        return HttpResponse::Found().header("Location", "/general?err=3").finish();
+#[post("/auth/general")]
+pub async fn auth_general(data: actix_web::web::Data<SetupForumRSState>, form: web::Form<AuthGeneralForm>, req: HttpRequest) -> impl Responder {
+    // Check if the user is logged in.
+    let loggedin = check_login(&data, req);
+    if loggedin.is_err() {
+        return loggedin.unwrap_err();
     }
 
-    // Check if the port number is even valid.
-    let port_num = port_num_opt.unwrap();
-    if port_num > 65535 || port_num < 1 {
-        return HttpResponse::Found().header("Location", "/general?err=3").finish();
+    // If the user cannot visit this stage, take them to the correct one.
+    if let Err(response) = check_stage(General) {
+        return response;
     }
 
     let mut settings = SettingsManager::get_settings();
-    settings.name = form.name.clone();
-    settings.ip = form.ip.clone();
-    settings.port = port_num;
-    settings.domain = form.domain.clone();
-    settings.setup_stage = Some(Security);
-
-    SettingsManager::save_settings(&settings);
+    if let Err(err) = setup::apply_general(&mut settings, &form) {
+        return HttpResponse::Found().header("Location", format!("/general?err={}", err.code)).finish();
+    }
 
-    return HttpResponse::Found().header("Location", "/security").finish();
+    continue_setup(&data, settings, Security).await
 }
 
 #[get("/security")]
@@ -210,17 +295,24 @@ pub async fn security(data: actix_web::web::Data<SetupForumRSState>, req: HttpRe
         return loggedin.unwrap_err();
     }
 
-    // If the user is at the wrong stage, take them to the correct one.
-    if !(SettingsManager::get_settings().setup_stage.unwrap() == Security) {
-        return HttpResponse::Found().header("Location", format!("/{}", SettingsManager::get_settings().setup_stage.unwrap())).finish();
+    // If the user cannot visit this stage, take them to the correct one.
+    if let Err(response) = check_stage(Security) {
+        return response;
     }
 
-    let result: String = (&data.hbs).render("setup/security", &json!({"test": "test"})).unwrap();
+    let settings = SettingsManager::get_settings();
+    let values = if settings.setup_stage.clone().unwrap() == Security {
+        AuthSecurityForm::default()
+    } else {
+        AuthSecurityForm::from_settings(&settings)
+    };
+
+    let result: String = (&data.hbs).render("setup/security", &json!({"steps": steps(Security), "values": values})).unwrap();
 
     HttpResponse::Ok().body(result)
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Serialize, Debug)]
 #[allow(non_snake_case)]
 pub struct AuthSecurityForm {
     pub(crate) useSSL: Option<String>,
@@ -231,89 +323,59 @@ pub struct AuthSecurityForm {
     pub(crate) secretKey: Option<String>,
 }
 
-#[post("/auth/security")]
-pub async fn auth_security(data: actix_web::web::Data<SetupForumRSState>, form: Form<AuthSecurityForm>, req: HttpRequest) -> impl Responder {
-    // Check if the user is logged in.
-    let loggedin = check_login(&data, req);
-    if loggedin.is_err() {
-        return loggedin.unwrap_err();
-    }
-
-    // If the user is at the wrong stage, take them to the correct one.
-    if !(SettingsManager::get_settings().setup_stage.unwrap() == Security) {
-        return HttpResponse::Found().header("Location", format!("/{}", SettingsManager::get_settings().setup_stage.unwrap())).finish();
-    }
-
-    let mut settings = SettingsManager::get_settings();
-
-    if form.useSSL.is_some() && form.useSSL.as_ref().unwrap() == "on" {
-        // The keys need to exist.
-        if form.privateKey.is_none() || form.publicKey.is_none() {
-            return HttpResponse::Found().header("Location", "/security?err=1").finish();
-        }
-        let private_key = form.privateKey.as_ref().unwrap().clone();
-        let public_key = form.publicKey.as_ref().unwrap().clone();
-
-        let key = Regex::new(r"^.*\.(pem|PEM|asn1|ASN1)$").unwrap();
-        if !key.is_match(private_key.as_str()) {
-            return HttpResponse::Found().header("Location", "/security?err=2").finish();
-        }
-
-        if !key.is_match(public_key.as_str()) {
-            return HttpResponse::Found().header("Location", "/security?err=3").finish();
-        }
+impl AuthSecurityForm {
+    /// Fill in the form from the saved settings. Keys that are not in use keep their defaults.
+    pub fn from_settings(settings: &BaseSettings) -> AuthSecurityForm {
+        let mut form = AuthSecurityForm::default();
 
-        if !Path::new(private_key.as_str()).exists() {
-            return HttpResponse::Found().header("Location", "/security?err=4").finish();
+        form.useSSL = if settings.use_sll { Some("on".to_string()) } else { None };
+        if let Some(ssl_settings) = &settings.ssl_settings {
+            form.privateKey = Some(ssl_settings.private_key.clone());
+            form.publicKey = Some(ssl_settings.public_key.clone());
         }
 
-        if !Path::new(public_key.as_str()).exists() {
-            return HttpResponse::Found().header("Location", "/security?err=5").finish();
+        form.useCaptch = if settings.use_captcha { Some("on".to_string()) } else { None };
+        if let Some(captcha_settings) = &settings.captcha_settings {
+            form.siteKey = Some(captcha_settings.site_key.clone());
+            form.secretKey = Some(captcha_settings.secret_key.clone());
         }
 
-        settings.use_sll = true;
-
-        let ssl_settings = SSLSettings {
-            private_key,
-            public_key
-        };
-
-        settings.ssl_settings = Some(ssl_settings);
-    } else {
-        settings.use_sll = false;
+        form
     }
+}
 
-    if form.useCaptch.is_some() && form.useCaptch.as_ref().unwrap() == "on" {
-        // The keys need to exist.
-        if form.siteKey.is_none() || form.secretKey.is_none() {
-            return HttpResponse::Found().header("Location", "/security?err=6").finish();
-        }
-        let site_key = form.siteKey.as_ref().unwrap().clone();
-        let secret_key = form.secretKey.as_ref().unwrap().clone();
-
-        // I think the keys are always 40 in length.
-        if site_key.len() != 40 || secret_key.len() != 40 {
-            return HttpResponse::Found().header("Location", "/security?err=7").finish();
+impl Default for AuthSecurityForm {
+    fn default() -> Self {
+        AuthSecurityForm {
+            useSSL: Some("on".to_string()),
+            privateKey: Some("privkey.pem".to_string()),
+            publicKey: Some("fullchain.pem".to_string()),
+            useCaptch: Some("on".to_string()),
+            siteKey: None,
+            secretKey: None
         }
-
-        settings.use_captcha = true;
-
-        let captcha_settings = CaptchaSettings {
-            site_key,
-            secret_key
-        };
-
-        settings.captcha_settings = Some(captcha_settings);
     }
-    else {
-        settings.use_captcha = false;
+}
+
+#[post("/auth/security")]
+pub async fn auth_security(data: actix_web::web::Data<SetupForumRSState>, form: Form<AuthSecurityForm>, req: HttpRequest) -> impl Responder {
+    // Check if the user is logged in.
+    let loggedin = check_login(&data, req);
+    if loggedin.is_err() {
+        return loggedin.unwrap_err();
     }
 
-    settings.setup_stage = Some(Storage);
+    // If the user cannot visit this stage, take them to the correct one.
+    if let Err(response) = check_stage(Security) {
+        return response;
+    }
 
-    SettingsManager::save_settings(&settings);
+    let mut settings = SettingsManager::get_settings();
+    if let Err(err) = setup::apply_security(&mut settings, &form) {
+        return HttpResponse::Found().header("Location", format!("/security?err={}", err.code)).finish();
+    }
 
-    HttpResponse::Found().header("Location", format!("/{}", SettingsManager::get_settings().setup_stage.unwrap())).finish()
+    continue_setup(&data, settings, Storage).await
 }
 
 #[get("/storage")]
@@ -324,18 +386,25 @@ pub async fn storage(data: actix_web::web::Data<SetupForumRSState>, req: HttpReq
         return loggedin.unwrap_err();
     }
 
-    // If the user is at the wrong stage, take them to the correct one.
-    if !(SettingsManager::get_settings().setup_stage.unwrap() == Storage) {
-        return HttpResponse::Found().header("Location", format!("/{}", SettingsManager::get_settings().setup_stage.unwrap())).finish();
+    // If the user cannot visit this stage, take them to the correct one.
+    if let Err(response) = check_stage(Storage) {
+        return response;
     }
 
-    let result: String = (&data.hbs).render("setup/storage", &json!({"test": "test"})).unwrap();
+    let settings = SettingsManager::get_settings();
+    let values = if settings.setup_stage.clone().unwrap() == Storage {
+        AuthStorageForm::default()
+    } else {
+        AuthStorageForm::from_settings(&settings)
+    };
+
+    let result: String = (&data.hbs).render("setup/storage", &json!({"steps": steps(Storage), "values": values})).unwrap();
 
     HttpResponse::Ok().body(result)
 }
 
 /// The form data for the auth.
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Serialize, Debug)]
 #[allow(non_snake_case)]
 pub struct AuthStorageForm {
     pub(crate) dbType: DatabaseType,
@@ -355,6 +424,64 @@ pub struct AuthStorageForm {
     pub(crate) postDbName: Option<String>,
 }
 
+impl AuthStorageForm {
+    /// Fill in the form from the saved settings. Databases that are not in use keep their defaults.
+    pub fn from_settings(settings: &BaseSettings) -> AuthStorageForm {
+        let mut form = AuthStorageForm::default();
+        form.dbType = settings.database_type.clone();
+
+        if let Some(sql_settings) = &settings.sql_settings {
+            form.sqlName = Some(sql_settings.file_location.clone());
+        }
+
+        if let Some(mysql_settings) = &settings.mysql_settings {
+            form.mysqlURL = Some(mysql_settings.url.clone());
+            form.mysqlPort = Some(mysql_settings.port);
+            form.mysqlUsername = Some(mysql_settings.username.clone());
+            form.mysqlPassword = Some(mysql_settings.password.clone());
+            form.mysqlDbName = Some(mysql_settings.database_name.clone());
+        }
+
+        if let Some(post_settings) = &settings.postgre_settings {
+            form.postURL = Some(post_settings.url.clone());
+            form.postPort = Some(post_settings.port);
+            form.postUsername = Some(post_settings.username.clone());
+            form.postPassword = Some(post_settings.password.clone());
+            form.postDbName = Some(post_settings.database_name.clone());
+        }
+
+        form
+    }
+
+    /// Describe the database the form points to, used to tell if it changed.
+    pub(crate) fn database(&self) -> String {
+        match self.dbType {
+            DatabaseType::SQLite => format!("sqlite:{:?}", self.sqlName),
+            DatabaseType::MySQL => format!("mysql:{:?}:{:?}:{:?}:{:?}", self.mysqlURL, self.mysqlPort, self.mysqlUsername, self.mysqlDbName),
+            DatabaseType::PostgreSQL => format!("postgresql:{:?}:{:?}:{:?}:{:?}", self.postURL, self.postPort, self.postUsername, self.postDbName)
+        }
+    }
+}
+
+impl Default for AuthStorageForm {
+    fn default() -> Self {
+        AuthStorageForm {
+            dbType: DatabaseType::SQLite,
+            sqlName: Some("data.db".to_string()),
+            mysqlURL: Some("localhost".to_string()),
+            mysqlPort: Some(3306),
+            mysqlUsername: None,
+            mysqlPassword: None,
+            mysqlDbName: Some("ForumRS".to_string()),
+            postURL: Some("localhost".to_string()),
+            postPort: Some(5432),
+            postUsername: None,
+            postPassword: None,
+            postDbName: Some("ForumRS".to_string())
+        }
+    }
+}
+
 /// The backed process for setting up the storage of the program.
 #[post("/auth/storage")]
 pub async fn auth_storage(data: actix_web::web::Data<SetupForumRSState>, form: Form<AuthStorageForm>, req: HttpRequest) -> impl Responder {
@@ -364,153 +491,33 @@ pub async fn auth_storage(data: actix_web::web::Data<SetupForumRSState>, form: F
         return loggedin.unwrap_err();
     }
 
-    // If the user is at the wrong stage, take them to the correct one.
-    if !(SettingsManager::get_settings().setup_stage.unwrap() == Storage) {
-        return HttpResponse::Found().header("Location", format!("/{}", SettingsManager::get_settings().setup_stage.unwrap())).finish();
+    // If the user cannot visit this stage, take them to the correct one.
+    if let Err(response) = check_stage(Storage) {
+        return response;
     }
 
     let mut settings = SettingsManager::get_settings();
+    let previous = AuthStorageForm::from_settings(&settings);
+    let existing = match setup::apply_storage(&mut settings, &form).await {
+        Ok(existing) => existing,
+        Err(err) => return HttpResponse::Found().header("Location", format!("/storage?err={}", err.code)).finish()
+    };
 
-    match form.dbType.clone() {
-        DatabaseType::SQLite => {
-            if form.sqlName.is_none() {
-                return HttpResponse::Found().header("Location", "/storage?err=1").finish();
-            }
-
-            let sql_name = form.sqlName.as_ref().unwrap().clone();
-
-            let sql_regex = Regex::new(r"^.*\.(db)$").unwrap();
-            if !sql_regex.is_match(&sql_name) {
-                return HttpResponse::Found().header("Location", "/storage?err=1").finish();
-            }
-
-            settings.database_type = SQLite;
-            settings.sql_settings = Some(SqlSettings {
-                file_location: sql_name.clone()
-            });
-
-
-            if Path::new(sql_name.as_str()).exists() {
-                settings.setup_stage = Some(ExistingStorage);
-                SettingsManager::save_settings(&settings);
-                return HttpResponse::Found().header("Location", "/existingstorage").finish();
-            }
-
-            settings.setup_stage = Some(AccountCreation);
-            SettingsManager::save_settings(&settings);
-            HttpResponse::Found().header("Location", "/accountcreation").finish();
-        },
-        DatabaseType::MySQL => {
-            if form.mysqlURL.is_none() || form.mysqlDbName.is_none() || form.mysqlPassword.is_none() || form.mysqlPort.is_none()
-                || form.mysqlUsername.is_none() {
-                return HttpResponse::Found().header("Location", "/storage?err=2").finish();
-            }
-
-            let mysql_url = form.mysqlURL.as_ref().unwrap().clone();
-            let mysql_port = form.mysqlPort.as_ref().unwrap().clone();
-            let mysql_username = form.mysqlUsername.as_ref().unwrap().clone();
-            let mysql_password = form.mysqlPassword.as_ref().unwrap().clone();
-            let mysql_db_name = form.mysqlDbName.as_ref().unwrap().clone();
-
-            let connection = MySqlConnection::connect(&format!("mysql://{}:{}@{}:{}", mysql_username, mysql_password,
-            mysql_url, mysql_port)).await;
-
-            if connection.is_err() {
-                let connection_err = connection.unwrap_err();
-                if connection_err.as_database_error().is_some() {
-                    println!("[WARN] The following error occurred when connecting to the MySQL database: {:?}", connection_err.as_database_error().unwrap().message());
-                }
-                else {
-                    println!("[WARN] The following error occurred when connecting to the MySQL database: {:?}", connection_err);
-                    println!("[WARN] Check to make sure ForumRS can access the specified MySQL server.");
-                }
-
-                return HttpResponse::Found().header("Location", "/storage?err=3").finish();
-            }
-
-            settings.database_type = MySQL;
-            settings.mysql_settings = Some(MysqlSettings {
-                url: mysql_url,
-                port: mysql_port,
-                username: mysql_username,
-                password: mysql_password,
-                database_name: mysql_db_name.clone()
-            });
-
-            let mut con = connection.unwrap();
-            let found_database = sqlx::query("SELECT SCHEMA_NAME FROM INFORMATION_SCHEMA.SCHEMATA WHERE SCHEMA_NAME = ?")
-                .bind(mysql_db_name.clone())
-                .fetch_one(&mut con).await;
-
-            con.close().await.unwrap();
-
-            if found_database.is_ok() {
-                settings.setup_stage = Some(ExistingStorage);
-                    SettingsManager::save_settings(&settings);
-                    return HttpResponse::Found().header("Location", "/existingstorage").finish();
-            }
-
-            settings.setup_stage = Some(AccountCreation);
-            SettingsManager::save_settings(&settings);
-            return HttpResponse::Found().header("Location", "/accountcreation").finish();
-        },
-        DatabaseType::PostgreSQL => {
-            if form.postURL.is_none() || form.postDbName.is_none() || form.postPassword.is_none() || form.postPort.is_none()
-                || form.postUsername.is_none() {
-                return HttpResponse::Found().header("Location", "/storage?err=4").finish();
-            }
-
-            let post_url = form.postURL.as_ref().unwrap().clone();
-            let post_port = form.postPort.as_ref().unwrap().clone();
-            let post_username = form.postUsername.as_ref().unwrap().clone();
-            let post_password = form.postPassword.as_ref().unwrap().clone();
-            let post_db_name = form.postDbName.as_ref().unwrap().clone();
-
-            let connection = PgConnection::connect(&format!("postgresql://{}:{}@{}:{}", post_username, post_password,
-                                                            post_url, post_port)).await;
-
-            if connection.is_err() {
-                let connection_err = connection.unwrap_err();
-                if connection_err.as_database_error().is_some() {
-                    println!("[WARN] The following error occurred when connecting to the PostgreSQL database: {:?}", connection_err.as_database_error().unwrap().message());
-                }
-                else {
-                    println!("[WARN] The following error occurred when connecting to the PostgreSQL database: {:?}", connection_err);
-                    println!("[WARN] Check to make sure ForumRS can access the specified PostgreSQL server.");
-                }
-
-                return HttpResponse::Found().header("Location", "/storage?err=5").finish();
-            }
-
-            settings.database_type = PostgreSQL;
-            settings.postgre_settings = Some(PostgreSQLSettings {
-                url: post_url,
-                port: post_port,
-                username: post_username,
-                password: post_password,
-                database_name: post_db_name.clone()
-            });
-
-            let mut con = connection.unwrap();
-            let found_database = sqlx::query(&format!("SELECT datname FROM pg_catalog.pg_database WHERE datname = '{}';", post_db_name))
-                .bind(post_db_name.clone())
-                .fetch_one(&mut con).await;
-
-            con.close().await.unwrap();
-
-            if found_database.is_ok() {
-                settings.setup_stage = Some(ExistingStorage);
-                SettingsManager::save_settings(&settings);
-                return HttpResponse::Found().header("Location", "/existingstorage").finish();
-            }
+    let next = if existing { ExistingStorage } else { AccountCreation };
 
-            settings.setup_stage = Some(AccountCreation);
-            SettingsManager::save_settings(&settings);
-            return HttpResponse::Found().header("Location", "/accountcreation").finish();
+    // A different database means the admin account has to be picked again.
+    let furthest = settings.setup_stage.clone().unwrap();
+    if furthest.position() > Storage.position() && previous.database() != form.database() {
+        let mut account = data.setup_account.lock().unwrap();
+        if matches!(*account, Some(SetupAccount::Existing(_))) {
+            *account = None;
         }
+        settings.setup_stage = Some(next);
+        SettingsManager::save_settings(&settings);
+        return HttpResponse::Found().header("Location", format!("/{}", settings.setup_stage.unwrap())).finish();
     }
 
-    HttpResponse::Found().header("Location", "/accountcreation").finish()
+    continue_setup(&data, settings, next).await
 }
 
 #[get("/existingstorage")]
@@ -521,12 +528,12 @@ pub async fn existing_storage(data: actix_web::web::Data<SetupForumRSState>, req
         return loggedin.unwrap_err();
     }
 
-    // If the user is at the wrong stage, take them to the correct one.
-    if !(SettingsManager::get_settings().setup_stage.unwrap() == ExistingStorage) {
-        return HttpResponse::Found().header("Location", format!("/{}", SettingsManager::get_settings().setup_stage.unwrap())).finish();
+    // If the user cannot visit this stage, take them to the correct one.
+    if let Err(response) = check_stage(ExistingStorage) {
+        return response;
     }
 
-    let result: String = (&data.hbs).render("setup/existingstorage", &json!({"test": "test"})).unwrap();
+    let result: String = (&data.hbs).render("setup/existingstorage", &json!({"steps": steps(ExistingStorage)})).unwrap();
 
     HttpResponse::Ok().body(result)
 }
@@ -539,17 +546,33 @@ pub async fn auth_existing_storage_migrate(data: actix_web::web::Data<SetupForum
         return loggedin.unwrap_err();
     }
 
-    // If the user is at the wrong stage, take them to the correct one.
-    if !(SettingsManager::get_settings().setup_stage.unwrap() == ExistingStorage) {
-        return HttpResponse::Found().header("Location", format!("/{}", SettingsManager::get_settings().setup_stage.unwrap())).finish();
+    // If the user cannot visit this stage, take them to the correct one.
+    if let Err(response) = check_stage(ExistingStorage) {
+        return response;
     }
 
     let mut settings = SettingsManager::get_settings();
-    settings.setup_stage = Some(Finished);
+    let report = match setup::inspect_existing(&settings).await {
+        Ok(report) => report,
+        Err(err) => {
+            println!("[WARN] {}", err);
+            return HttpResponse::Found().header("Location", "/existingstorage?err=1").finish();
+        }
+    };
+
+    if !report.supported {
+        return HttpResponse::Found().header("Location", "/existingstorage?err=2").finish();
+    }
+
+    // The existing database already has its accounts. Nothing is changed until the review is confirmed.
+    *data.setup_account.lock().unwrap() = Some(SetupAccount::Existing(None));
+
+    // An admin account has to be added when the existing data has none.
+    settings.setup_stage = Some(if report.admins == 0 { AccountCreation } else { Review });
 
     SettingsManager::save_settings(&settings);
 
-    HttpResponse::Found().header("Location", "/finished").finish()
+    HttpResponse::Found().header("Location", format!("/{}", settings.setup_stage.unwrap())).finish()
 }
 
 #[post("/auth/existingstorage/reset")]
@@ -560,37 +583,16 @@ pub async fn auth_existing_storage_reset(data: actix_web::web::Data<SetupForumRS
         return loggedin.unwrap_err();
     }
 
-    // If the user is at the wrong stage, take them to the correct one.
-    if !(SettingsManager::get_settings().setup_stage.unwrap() == ExistingStorage) {
-        return HttpResponse::Found().header("Location", format!("/{}", SettingsManager::get_settings().setup_stage.unwrap())).finish();
+    // If the user cannot visit this stage, take them to the correct one.
+    if let Err(response) = check_stage(ExistingStorage) {
+        return response;
     }
 
     let mut settings = SettingsManager::get_settings();
 
-    match settings.database_type {
-        DatabaseType::SQLite => {
-            std::fs::remove_file(Path::new(settings.sql_settings.as_ref().unwrap().file_location.as_str())).unwrap();
-        },
-        DatabaseType::MySQL => {
-            let mysql_settings = settings.mysql_settings.as_ref().unwrap();
-            let mut connection = MySqlConnection::connect(&format!("mysql://{}:{}@{}:{}", mysql_settings.username, mysql_settings.password,
-                                                               mysql_settings.url, mysql_settings.port)).await.unwrap();
-
-            let found_database = sqlx::query(&format!("DROP DATABASE IF EXISTS {};", mysql_settings.database_name))
-                .execute(&mut connection).await.unwrap();
-            connection.close().await.unwrap();
-        },
-        DatabaseType::PostgreSQL => {
-            let post_settings = settings.postgre_settings.as_ref().unwrap();
-            let mut connection = PgConnection::connect(&format!("postgresql://{}:{}@{}:{}", post_settings.username, post_settings.password,
-                                                            post_settings.url, post_settings.port)).await.unwrap();
-
-            // Drop the database if it exists.
-            let found_database = sqlx::query(&format!("DROP DATABASE IF EXISTS \"{}\";", post_settings.database_name))
-                .execute(&mut connection).await.unwrap();
-
-            connection.close().await.unwrap();
-        }
+    if let Err(err) = setup::reset_database(&settings).await {
+        println!("[WARN] Unable to reset the existing database: {:?}", err);
+        return HttpResponse::Found().header("Location", "/existingstorage").finish();
     }
 
     settings.setup_stage = Some(AccountCreation);
@@ -608,17 +610,30 @@ pub async fn account_creation(data: actix_web::web::Data<SetupForumRSState>, req
         return loggedin.unwrap_err();
     }
 
-    // If the user is at the wrong stage, take them to the correct one.
-    if !(SettingsManager::get_settings().setup_stage.unwrap() == AccountCreation) {
-        return HttpResponse::Found().header("Location", format!("/{}", SettingsManager::get_settings().setup_stage.unwrap())).finish();
+    // If the user cannot visit this stage, take them to the correct one.
+    if let Err(response) = check_stage(AccountCreation) {
+        return response;
     }
 
-    let result: String = (&data.hbs).render("setup/accountcreation", &json!({"test": "test"})).unwrap();
+    // The password is never filled back in.
+    let account = data.setup_account.lock().unwrap().clone();
+    let values = match account.as_ref().and_then(|account| account.admin()) {
+        Some(admin) => json!({"username": admin.username, "email": admin.email}),
+        None => json!({"username": "admin", "email": ""})
+    };
+
+    let policy = PasswordPolicy::new(&SettingsManager::get_settings().password_settings);
+    let result: String = (&data.hbs).render("setup/accountcreation", &json!({
+        "password_requirements": policy.describe(),
+        "steps": steps(AccountCreation),
+        "values": values,
+        "existing": matches!(account, Some(SetupAccount::Existing(_))),
+    })).unwrap();
 
     HttpResponse::Ok().body(result)
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, Clone)]
 #[allow(non_snake_case)]
 pub struct AuthAccountCreation {
     pub username: String,
@@ -635,71 +650,227 @@ pub async fn auth_account_creation(data: actix_web::web::Data<SetupForumRSState>
         return loggedin.unwrap_err();
     }
 
-    // If the user is at the wrong stage, take them to the correct one.
-    if !(SettingsManager::get_settings().setup_stage.unwrap() == AccountCreation) {
-        return HttpResponse::Found().header("Location", format!("/{}", SettingsManager::get_settings().setup_stage.unwrap())).finish();
+    // If the user cannot visit this stage, take them to the correct one.
+    if let Err(response) = check_stage(AccountCreation) {
+        return response;
+    }
+
+    let settings = SettingsManager::get_settings();
+    if let Err(err) = setup::validate_account(&settings, &form) {
+        return HttpResponse::Found().header("Location", format!("/accountcreation?err={}", err.code)).finish();
     }
 
-    if form.username.len() < 1 {
-        return HttpResponse::Found().header("Location", "/accountcreation?err=1").finish();
+    // The account is created once the review is confirmed. When the existing database is kept it is added to it.
+    {
+        let mut account = data.setup_account.lock().unwrap();
+        *account = match *account {
+            Some(SetupAccount::Existing(_)) => Some(SetupAccount::Existing(Some(form.into_inner()))),
+            _ => Some(SetupAccount::New(form.into_inner()))
+        };
     }
 
-    if form.password.len() < 1 {
-        return HttpResponse::Found().header("Location", "/accountcreation?err=2").finish();
+    continue_setup(&data, settings, Review).await
+}
+
+/// Describe the storage settings for the review page. Passwords are not shown.
+fn storage_summary(settings: &BaseSettings) -> Vec<serde_json::Value> {
+    match settings.database_type {
+        DatabaseType::SQLite => {
+            let sql_settings = settings.sql_settings.as_ref().unwrap();
+            vec![json!({"label": "Database File", "value": sql_settings.file_location})]
+        }
+        DatabaseType::MySQL => {
+            let mysql_settings = settings.mysql_settings.as_ref().unwrap();
+            vec![
+                json!({"label": "Database URL", "value": mysql_settings.url}),
+                json!({"label": "Database Port", "value": mysql_settings.port}),
+                json!({"label": "Database Username", "value": mysql_settings.username}),
+                json!({"label": "Database Name", "value": mysql_settings.database_name}),
+            ]
+        }
+        DatabaseType::PostgreSQL => {
+            let post_settings = settings.postgre_settings.as_ref().unwrap();
+            vec![
+                json!({"label": "Database URL", "value": post_settings.url}),
+                json!({"label": "Database Port", "value": post_settings.port}),
+                json!({"label": "Database Username", "value": post_settings.username}),
+                json!({"label": "Database Name", "value": post_settings.database_name}),
+            ]
+        }
     }
+}
 
-    // Validate the ip address via regex.
-    let password_regex = Regex::new(r"^(?=.*[A-Z].*[A-Z])(?=.*[!@#$&*%^])(?=.*[0-9].*[0-9])(?=.*[a-z].*[a-z].*[a-z]).{8,}$").unwrap();
-    if !password_regex.is_match(form.password.as_str()) {
-        return HttpResponse::Found().header("Location", "/accountcreation?err=2").finish();
+/// Validate the stages again before the review is shown or confirmed. If one is no longer valid the user is sent back to it.
+async fn check_review(data: &actix_web::web::Data<SetupForumRSState>) -> Result<BaseSettings, HttpResponse> {
+    let mut settings = SettingsManager::get_settings();
+    let account = data.setup_account.lock().unwrap().clone();
+    let stage = setup::revalidate(&settings, account.as_ref()).await;
+    if stage == Review {
+        return Ok(settings);
     }
 
-    if form.password != form.confirmPassword {
-        return HttpResponse::Found().header("Location", "/accountcreation?err=3").finish();
+    settings.setup_stage = Some(stage.clone());
+    SettingsManager::save_settings(&settings);
+    Err(HttpResponse::Found().header("Location", format!("/{}", stage)).finish())
+}
+
+#[get("/review")]
+pub async fn review(data: actix_web::web::Data<SetupForumRSState>, req: HttpRequest) -> impl Responder {
+    // Check if the user is logged in.
+    let loggedin = check_login(&data, req);
+    if loggedin.is_err() {
+        return loggedin.unwrap_err();
     }
 
-    // TODO validate email.
-    if form.email.len() < 1 {
-        return HttpResponse::Found().header("Location", "/accountcreation?err=4").finish();
+    // If the user cannot visit this stage, take them to the correct one.
+    if let Err(response) = check_stage(Review) {
+        return response;
     }
 
-    // Start off by setting up the db.
-    let mut settings = SettingsManager::get_settings();
+    let settings = match check_review(&data).await {
+        Ok(settings) => settings,
+        Err(response) => return response
+    };
 
-    let mut db;
+    let setup_account = data.setup_account.lock().unwrap().clone();
+    let existing = matches!(setup_account, Some(SetupAccount::Existing(_)));
+    let account = match setup_account.as_ref().and_then(|account| account.admin()) {
+        Some(admin) => json!({"existing": existing, "username": admin.username, "email": admin.email}),
+        None => json!({"existing": existing})
+    };
 
-    match settings.database_type {
-        SQLite => {
-            db = Database::new_sqlite(settings.sql_settings.as_ref().unwrap()).await.unwrap();
-            crate::schema::dbsetup::setup_database(&mut db).await;
+    // Show what is in the existing database and what will change before it is migrated.
+    let mut needs_admin = false;
+    let migration = if existing {
+        match setup::inspect_existing(&settings).await {
+            Ok(report) => {
+                needs_admin = report.admins == 0 && account.get("username").is_none();
+                json!(report)
+            }
+            Err(err) => {
+                println!("[WARN] {}", err);
+                json!(null)
+            }
         }
-        MySQL => {
-            crate::schema::dbsetup::create_schema_mysql(settings.mysql_settings.as_ref().unwrap()).await;
-            db = Database::new_mysql(settings.mysql_settings.as_ref().unwrap()).await.unwrap();
-            crate::schema::dbsetup::setup_database(&mut db).await;
+    } else {
+        json!(null)
+    };
+
+    let result: String = (&data.hbs).render("setup/review", &json!({
+        "steps": steps(Review),
+        "settings": {
+            "name": settings.name,
+            "domain": settings.domain,
+            "ip": settings.ip,
+            "port": settings.port,
+            "use_ssl": settings.use_sll,
+            "ssl_settings": if settings.use_sll { json!(settings.ssl_settings) } else { json!(null) },
+            "use_captcha": settings.use_captcha,
+            "site_key": settings.captcha_settings.as_ref().filter(|_| settings.use_captcha).map(|captcha| captcha.site_key.clone()),
+            "database_type": settings.database_type.to_string(),
+        },
+        "storage": storage_summary(&settings),
+        "account": account,
+        "migration": migration,
+        "needs_admin": needs_admin,
+    })).unwrap();
+
+    HttpResponse::Ok().body(result)
+}
+
+/// Apply the pending schema upgrades to the existing database and add the admin account if one was given.
+async fn migrate_existing(data: &actix_web::web::Data<SetupForumRSState>, settings: &BaseSettings, admin: Option<&AuthAccountCreation>) -> Result<(), HttpResponse> {
+    let mut db = match Database::connect(settings).await {
+        Ok(db) => db,
+        Err(err) => {
+            println!("[ERROR] Unable to connect to the existing database: {:?}", err);
+            return Err(HttpResponse::Found().header("Location", "/review?err=4").finish());
         }
-        PostgreSQL => {
-            crate::schema::dbsetup::create_schema_postgre(settings.postgre_settings.as_ref().unwrap()).await;
-            std::thread::sleep(Duration::from_millis(100));
-            db = Database::new_postgre(settings.postgre_settings.as_ref().unwrap()).await.unwrap();
-            crate::schema::dbsetup::setup_database(&mut db).await;
+    };
+
+    let result = migrate_database(data, &mut db, admin).await;
+    db.close().await;
+    result.map_err(|location| HttpResponse::Found().header("Location", location).finish())
+}
+
+/// Migrate the connected database. On failure the page the user is sent to is returned.
+async fn migrate_database(data: &actix_web::web::Data<SetupForumRSState>, db: &mut Database, admin: Option<&AuthAccountCreation>) -> Result<(), String> {
+    let report = match migrations::inspect(db).await {
+        Ok(report) => report,
+        Err(err) => {
+            println!("[ERROR] Unable to read the existing database: {:?}", err);
+            return Err(String::from("/review?err=4"));
         }
     };
 
-    // TODO verify data
+    if !report.supported {
+        return Err(String::from("/review?err=2"));
+    }
+
+    // The admin has to be able to log in after setup.
+    if report.admins == 0 && admin.is_none() {
+        return Err(String::from("/review?err=3"));
+    }
+
+    if let Err(err) = migrations::upgrade(db).await {
+        println!("[ERROR] {}", err);
+        return Err(String::from("/review?err=4"));
+    }
+
+    if let Some(admin) = admin {
+        let admin_uuid = setup::add_admin_account(db, admin).await
+            .map_err(|err| format!("/accountcreation?err={}", err.code))?;
+        *data.admin_account.lock().unwrap() = Some(admin_uuid);
+    }
+
+    Ok(())
+}
+
+/// Confirm the review. The database and the admin account are only created, or the existing database migrated, now.
+#[post("/auth/review")]
+pub async fn auth_review(data: actix_web::web::Data<SetupForumRSState>, req: HttpRequest) -> impl Responder {
+    // Check if the user is logged in.
+    let loggedin = check_login(&data, req);
+    if loggedin.is_err() {
+        return loggedin.unwrap_err();
+    }
+
+    // If the user cannot visit this stage, take them to the correct one.
+    if let Err(response) = check_stage(Review) {
+        return response;
+    }
+
+    let mut settings = match check_review(&data).await {
+        Ok(settings) => settings,
+        Err(response) => return response
+    };
 
-    let hashed_password = bcrypt::hash(form.password.clone(), bcrypt::DEFAULT_COST).unwrap();
+    let account = data.setup_account.lock().unwrap().clone();
+    if let Some(SetupAccount::New(account)) = account {
+        let mut db = match setup::create_database(&settings).await {
+            Ok(db) => db,
+            Err(err) => {
+                println!("[ERROR] Unable to create the database: {:?}", err);
+                return HttpResponse::Found().header("Location", "/review?err=1").finish();
+            }
+        };
 
-    // Insert the admin user.
-    Users::insert(&mut db, Uuid::new_v4(), form.username.clone(), form.email.clone(), hashed_password, false, true).await;
+        let admin_uuid = setup::create_admin_account(&mut db, &account).await;
+        db.close().await;
+        *data.admin_account.lock().unwrap() = Some(admin_uuid);
+    } else if let Some(SetupAccount::Existing(admin)) = account {
+        if let Err(response) = migrate_existing(&data, &settings, admin.as_ref()).await {
+            return response;
+        }
+    }
 
-    // Insert default forums.
-    Forums::insert(&mut db, Uuid::new_v4(), "Announcements".to_string(), "General announcements for the website.".to_string()).await;
-    Forums::insert(&mut db, Uuid::new_v4(), "General".to_string(), "General forum discussions.".to_string()).await;
+    settings.setup_stage = Some(Finished);
+    SettingsManager::save_settings(&settings);
 
-    unimplemented!()
+    HttpResponse::Found().header("Location", "/finished").finish()
 }
 
+
 #[get("/finished")]
 pub async fn finished(data: actix_web::web::Data<SetupForumRSState>, req: HttpRequest) -> impl Responder {
     // Check if the user is logged in.
@@ -708,12 +879,12 @@ pub async fn finished(data: actix_web::web::Data<SetupForumRSState>, req: HttpRe
         return loggedin.unwrap_err();
     }
 
-    // If the user is at the wrong stage, take them to the correct one.
-    if !(SettingsManager::get_settings().setup_stage.unwrap() == Finished) {
-        return HttpResponse::Found().header("Location", format!("/{}", SettingsManager::get_settings().setup_stage.unwrap())).finish();
+    // If the user cannot visit this stage, take them to the correct one.
+    if let Err(response) = check_stage(Finished) {
+        return response;
     }
 
-    let result: String = (&data.hbs).render("setup/finished", &json!({"test": "test"})).unwrap();
+    let result: String = (&data.hbs).render("setup/finished", &json!({"steps": steps(Finished)})).unwrap();
 
     HttpResponse::Ok().body(result)
 }
@@ -721,14 +892,14 @@ pub async fn finished(data: actix_web::web::Data<SetupForumRSState>, req: HttpRe
 #[post("/auth/finished")]
 pub async fn auth_finished(data: actix_web::web::Data<SetupForumRSState>, req: HttpRequest) -> impl Responder {
     // Check if the user is logged in.
-    let loggedin = check_login(&data, req);
+    let loggedin = check_login(&data, req.clone());
     if loggedin.is_err() {
         return loggedin.unwrap_err();
     }
 
-    // If the user is at the wrong stage, take them to the correct one.
-    if !(SettingsManager::get_settings().setup_stage.unwrap() == Finished) {
-        return HttpResponse::Found().header("Location", format!("/{}", SettingsManager::get_settings().setup_stage.unwrap())).finish();
+    // If the user cannot visit this stage, take them to the correct one.
+    if let Err(response) = check_stage(Finished) {
+        return response;
     }
 
     let mut settings = SettingsManager::get_settings();
@@ -737,9 +908,47 @@ pub async fn auth_finished(data: actix_web::web::Data<SetupForumRSState>, req: H
 
     SettingsManager::save_settings(&settings);
 
-    println!("[INFO] Configuration complete. Please restart the server to launch ForumRS.");
+    // Log the admin in to the forum with the account they just created.
+    // When an existing database was kept there is no new account, so they log in normally.
+    let mut builder = HttpResponse::Ok();
+    let mut logged_in = false;
+    let admin_account = data.admin_account.lock().unwrap().take();
+    if let Some(admin_uuid) = admin_account {
+        match Database::connect(&settings).await {
+            Ok(mut db) => {
+                if let Ok(Some(admin)) = Users::get_by_uuid(&mut db, admin_uuid).await {
+                    if let Ok(session_id) = session::create_session(&mut db, &admin).await {
+                        builder.cookie(session::session_cookie(session_id));
+                        logged_in = true;
+                    }
+                }
+                db.close().await;
+            }
+            Err(err) => println!("[WARN] Unable to log in the admin account after setup: {:?}", err)
+        }
+    }
+
+    // The forum uses the ip and port from the general stage, which can be different from the setup server.
+    let (setup_ip, setup_port) = &data.setup_address;
+    let forum_url = if *setup_ip == settings.ip && *setup_port == settings.port {
+        String::from("/")
+    } else {
+        let connection_info = req.connection_info();
+        let host = connection_info.host().split(':').next().unwrap_or("localhost").to_string();
+        format!("{}://{}:{}/", connection_info.scheme(), host, settings.port)
+    };
+
+    println!("[INFO] Configuration complete. Switching to the forum.");
 
-    Arbiter::current().stop();
+    let result: String = (&data.hbs).render("setup/switching", &json!({
+        "forum_url": if logged_in { forum_url.clone() } else { format!("{}login", forum_url) },
+        "logged_in": logged_in,
+    })).unwrap();
 
-    std::process::exit(0);
-}
\ No newline at end of file
+    // The setup server finishes sending this page before it shuts down.
+    if let Some(finished) = data.setup_finished.lock().unwrap().take() {
+        let _ = finished.send(());
+    }
+
+    builder.body(result)
+}
@@ -1,7 +1,14 @@
+use futures::channel::oneshot;
 use handlebars::Handlebars;
+use std::collections::HashMap;
 use std::sync::{Mutex, Arc};
+use std::time::Instant;
 use uuid::Uuid;
 
+use crate::auth::api_token::Scope;
+use crate::auth::login_protection::LoginThrottle;
+use crate::setup::setup::SetupAccount;
+
 /// This is the state of the Application for the Setup process.
 #[derive(Clone)]
 pub struct SetupForumRSState {
@@ -11,4 +18,70 @@ pub struct SetupForumRSState {
     pub setup_code: Uuid,
     /// The session id of the setup user.
     pub setup_session: Arc<Mutex<Option<Uuid>>>,
-}
\ No newline at end of file
+    /// The failed attempts at entering the setup code.
+    pub login_throttle: Arc<Mutex<LoginThrottle>>,
+    /// The admin account picked during setup. It is created when the review is confirmed.
+    pub setup_account: Arc<Mutex<Option<SetupAccount>>>,
+    /// The admin account created during setup. They are logged in to the forum when setup is finished.
+    pub admin_account: Arc<Mutex<Option<Uuid>>>,
+    /// The ip and port the setup server is running on. (Constant)
+    pub setup_address: (String, u32),
+    /// Tells the setup server to shut down and start the forum. This is taken when setup is finished.
+    pub setup_finished: Arc<Mutex<Option<oneshot::Sender<()>>>>,
+}
+
+/// A login that passed the password check but still needs a two factor code.
+#[derive(Clone, Debug)]
+pub struct PendingLogin {
+    /// The uuid of the user logging in.
+    pub user_uuid: Uuid,
+    /// When the password was verified.
+    pub created: Instant,
+}
+
+/// An OAuth authorization request waiting for the user to approve or deny it.
+#[derive(Clone, Debug)]
+pub struct OAuthConsent {
+    /// The application asking for access.
+    pub client_uuid: Uuid,
+    /// The user that was asked.
+    pub user_uuid: Uuid,
+    /// Where the user is sent back to.
+    pub redirect_uri: String,
+    /// The scopes the application asked for.
+    pub scopes: Vec<Scope>,
+    /// The state parameter of the application, returned untouched.
+    pub state: Option<String>,
+    /// When the consent page was shown.
+    pub created: Instant,
+}
+
+/// An OAuth authorization code that can be exchanged for an API token.
+#[derive(Clone, Debug)]
+pub struct AuthorizationCode {
+    /// The application the code was issued to.
+    pub client_uuid: Uuid,
+    /// The user that approved the application.
+    pub user_uuid: Uuid,
+    /// The redirect uri the code was sent to. It must match when the code is exchanged.
+    pub redirect_uri: String,
+    /// The scopes the user approved.
+    pub scopes: Vec<Scope>,
+    /// When the code was issued.
+    pub created: Instant,
+}
+
+/// This is the state of the Application for the forums.
+#[derive(Clone)]
+pub struct ForumRSState {
+    /// The instance of Handlebars (Constant)
+    pub hbs: Handlebars<'static>,
+    /// Logins waiting on a two factor code, keyed by the id stored in the pending login cookie.
+    pub pending_logins: Arc<Mutex<HashMap<Uuid, PendingLogin>>>,
+    /// The failed login attempts by account and client IP.
+    pub login_throttle: Arc<Mutex<LoginThrottle>>,
+    /// OAuth authorization requests waiting for the user, keyed by the id in the consent form.
+    pub oauth_consents: Arc<Mutex<HashMap<Uuid, OAuthConsent>>>,
+    /// OAuth authorization codes that have not been exchanged yet, keyed by the hash of the code.
+    pub authorization_codes: Arc<Mutex<HashMap<String, AuthorizationCode>>>,
+}
@@ -0,0 +1,250 @@
+use std::time::Duration;
+
+use actix_web::client::Client;
+use actix_web::rt::time::delay_for;
+
+use crate::schema::database::Database;
+use crate::schema::tables::{current_time, Webhook, WebhookDeliveries, WebhookDelivery, Webhooks};
+use crate::settings::{SettingsManager, WebhookSettings};
+use crate::webhooks::{DELIVERY_HEADER, EVENT_HEADER, sign, SIGNATURE_HEADER};
+
+/// The maximum amount of deliveries sent every time the queue is checked.
+const BATCH_SIZE: i64 = 50;
+
+/// Get the amount of seconds to wait before the next attempt of a delivery.
+fn backoff(attempts: i64, settings: &WebhookSettings) -> u64 {
+    let exponent = (attempts - 1).max(0).min(32) as u32;
+    settings.backoff_base_seconds.saturating_mul(2_u64.saturating_pow(exponent)).min(settings.backoff_max_seconds)
+}
+
+/// Post a delivery to its webhook.
+///
+/// ## Returns
+/// The HTTP status of the response. Any status outside of 2xx counts as a failure, in which case the
+/// error has the status (0 if there was no response) and the reason.
+async fn send(client: &Client, webhook: &Webhook, delivery: &WebhookDelivery) -> Result<i64, (i64, String)> {
+    let response = client.post(webhook.url.as_str())
+        .header("Content-Type", "application/json")
+        .header("User-Agent", "ForumRS-Webhooks")
+        .header(EVENT_HEADER, delivery.event.as_str())
+        .header(DELIVERY_HEADER, delivery.uuid.to_string())
+        .header(SIGNATURE_HEADER, sign(webhook.secret.as_str(), delivery.payload.as_str()))
+        .send_body(delivery.payload.clone())
+        .await
+        .map_err(|err| (0, err.to_string()))?;
+
+    let status = response.status();
+    if status.is_success() {
+        Ok(status.as_u16() as i64)
+    } else {
+        Err((status.as_u16() as i64, format!("The server responded with {}.", status)))
+    }
+}
+
+/**
+   Send every delivery in the queue that is due.
+
+   Failed deliveries are rescheduled with an exponential backoff until the maximum amount of attempts,
+   after which they are marked as failed. Every attempt is recorded in the delivery log.
+
+   ## Returns
+   The amount of deliveries that were attempted.
+*/
+pub async fn deliver_due(db: &mut Database, client: &Client, settings: &WebhookSettings) -> Result<usize, sqlx::Error> {
+    let deliveries = WebhookDeliveries::get_due(db, BATCH_SIZE).await?;
+
+    for delivery in &deliveries {
+        let attempts = delivery.attempts + 1;
+        let webhook = match Webhooks::get_by_uuid(db, delivery.webhook_uuid).await? {
+            Some(webhook) => webhook,
+            None => {
+                WebhookDeliveries::record_attempt(db, delivery.uuid, "failed", attempts, 0, "The webhook no longer exists.", 0).await?;
+                continue;
+            }
+        };
+
+        match send(client, &webhook, delivery).await {
+            Ok(status) => {
+                WebhookDeliveries::record_attempt(db, delivery.uuid, "delivered", attempts, status, "", 0).await?;
+            }
+            Err((status, err)) => {
+                if attempts >= settings.max_attempts as i64 {
+                    println!("[WARN] The {} delivery {} to {} failed after {} attempts: {}", delivery.event, delivery.uuid, webhook.url, attempts, err);
+                    WebhookDeliveries::record_attempt(db, delivery.uuid, "failed", attempts, status, err.as_str(), 0).await?;
+                } else {
+                    let next_attempt = current_time() + backoff(attempts, settings) as i64;
+                    WebhookDeliveries::record_attempt(db, delivery.uuid, "pending", attempts, status, err.as_str(), next_attempt).await?;
+                }
+            }
+        }
+    }
+
+    Ok(deliveries.len())
+}
+
+/// Run the webhook delivery worker. This checks the queue forever and should be spawned once when the forum starts.
+pub async fn run_worker() {
+    loop {
+        // The settings are read every time so changes apply without a restart.
+        let settings = SettingsManager::get_settings();
+        let webhook_settings = settings.webhook_settings.clone();
+        delay_for(Duration::from_secs(webhook_settings.poll_interval_seconds)).await;
+
+        let mut db = match Database::connect(&settings).await {
+            Ok(db) => db,
+            Err(err) => {
+                println!("[WARN] The webhook worker is unable to connect to the database: {:?}", err);
+                continue;
+            }
+        };
+
+        let client = Client::builder()
+            .timeout(Duration::from_secs(webhook_settings.timeout_seconds))
+            .finish();
+        if let Err(err) = deliver_due(&mut db, &client, &webhook_settings).await {
+            println!("[WARN] An error occurred while delivering webhooks: {:?}", err);
+        }
+        db.close().await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use actix_web::{App, HttpRequest, HttpResponse, HttpServer, web};
+    use actix_web::dev::Server;
+    use actix_web::http::StatusCode;
+    use uuid::Uuid;
+
+    use crate::schema::tables::Table;
+    use crate::settings::SqlSettings;
+
+    use super::*;
+
+    /// What the stand-in server received, and the status it responds with.
+    struct StandIn {
+        status: StatusCode,
+        received: Vec<(String, String)>,
+    }
+
+    /// Start a local HTTP server that records every webhook it receives.
+    fn start_stand_in(status: StatusCode) -> (Server, String, Arc<Mutex<StandIn>>) {
+        let state = Arc::new(Mutex::new(StandIn { status, received: Vec::new() }));
+        let server_state = state.clone();
+        let server = HttpServer::new(move || {
+            let state = server_state.clone();
+            App::new().route("/hook", web::post().to(move |req: HttpRequest, body: String| {
+                let state = state.clone();
+                async move {
+                    let mut state = state.lock().unwrap();
+                    let signature = req.headers().get(SIGNATURE_HEADER).and_then(|value| value.to_str().ok()).unwrap_or_default();
+                    state.received.push((signature.to_string(), body));
+                    HttpResponse::build(state.status).finish()
+                }
+            }))
+        })
+            .workers(1)
+            .bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/hook", server.addrs()[0]);
+        (server.run(), url, state)
+    }
+
+    /// Open an empty database with the webhook tables.
+    async fn open_test_database() -> Database {
+        let mut db = Database::new_sqlite(&SqlSettings { file_location: ":memory:".to_string() }).await.unwrap();
+        Webhooks::create(&mut db).await.unwrap();
+        WebhookDeliveries::create(&mut db).await.unwrap();
+        db
+    }
+
+    /// Register a webhook for the url and queue one delivery to it.
+    async fn queue_delivery(db: &mut Database, url: String) -> Uuid {
+        let webhook_uuid = Uuid::new_v4();
+        Webhooks::insert(db, webhook_uuid, url, "secret".to_string(), "thread.created".to_string()).await.unwrap();
+        WebhookDeliveries::insert(db, Uuid::new_v4(), webhook_uuid, "thread.created", "{\"id\":1}".to_string()).await.unwrap();
+        webhook_uuid
+    }
+
+    fn settings(max_attempts: u32, backoff_base_seconds: u64) -> WebhookSettings {
+        WebhookSettings {
+            poll_interval_seconds: 1,
+            timeout_seconds: 5,
+            max_attempts,
+            backoff_base_seconds,
+            backoff_max_seconds: 3600,
+        }
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_the_max() {
+        let settings = WebhookSettings { backoff_max_seconds: 100, ..settings(8, 30) };
+        assert_eq!(backoff(1, &settings), 30);
+        assert_eq!(backoff(2, &settings), 60);
+        assert_eq!(backoff(3, &settings), 100);
+        assert_eq!(backoff(64, &settings), 100);
+    }
+
+    #[actix_rt::test]
+    async fn successful_delivery_is_signed_and_recorded() {
+        let (server, url, state) = start_stand_in(StatusCode::OK);
+        let mut db = open_test_database().await;
+        let webhook_uuid = queue_delivery(&mut db, url).await;
+
+        assert_eq!(deliver_due(&mut db, &Client::default(), &settings(3, 30)).await.unwrap(), 1);
+
+        let received = state.lock().unwrap().received.clone();
+        assert_eq!(received, vec![(sign("secret", "{\"id\":1}"), "{\"id\":1}".to_string())]);
+        let delivery = &WebhookDeliveries::get_by_webhook(&mut db, webhook_uuid, 10).await.unwrap()[0];
+        assert_eq!(delivery.status, "delivered");
+        assert_eq!(delivery.attempts, 1);
+        assert_eq!(delivery.response_status, 200);
+
+        db.close().await;
+        server.stop(true).await;
+    }
+
+    #[actix_rt::test]
+    async fn failed_delivery_is_retried_later() {
+        let (server, url, _) = start_stand_in(StatusCode::INTERNAL_SERVER_ERROR);
+        let mut db = open_test_database().await;
+        let webhook_uuid = queue_delivery(&mut db, url).await;
+
+        let before = current_time();
+        assert_eq!(deliver_due(&mut db, &Client::default(), &settings(3, 30)).await.unwrap(), 1);
+
+        let delivery = &WebhookDeliveries::get_by_webhook(&mut db, webhook_uuid, 10).await.unwrap()[0];
+        assert_eq!(delivery.status, "pending");
+        assert_eq!(delivery.attempts, 1);
+        assert_eq!(delivery.response_status, 500);
+        assert!(delivery.next_attempt >= before + 30 && delivery.next_attempt <= current_time() + 30);
+
+        // The retry is not due yet.
+        assert_eq!(deliver_due(&mut db, &Client::default(), &settings(3, 30)).await.unwrap(), 0);
+
+        db.close().await;
+        server.stop(true).await;
+    }
+
+    #[actix_rt::test]
+    async fn delivery_fails_after_the_max_attempts() {
+        let (server, url, state) = start_stand_in(StatusCode::SERVICE_UNAVAILABLE);
+        let mut db = open_test_database().await;
+        let webhook_uuid = queue_delivery(&mut db, url).await;
+
+        // Without a backoff every retry is due right away.
+        let settings = settings(2, 0);
+        assert_eq!(deliver_due(&mut db, &Client::default(), &settings).await.unwrap(), 1);
+        assert_eq!(deliver_due(&mut db, &Client::default(), &settings).await.unwrap(), 1);
+        assert_eq!(deliver_due(&mut db, &Client::default(), &settings).await.unwrap(), 0);
+
+        assert_eq!(state.lock().unwrap().received.len(), 2);
+        let delivery = &WebhookDeliveries::get_by_webhook(&mut db, webhook_uuid, 10).await.unwrap()[0];
+        assert_eq!(delivery.status, "failed");
+        assert_eq!(delivery.attempts, 2);
+        assert_eq!(delivery.response_status, 503);
+
+        db.close().await;
+        server.stop(true).await;
+    }
+}
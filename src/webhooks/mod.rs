@@ -0,0 +1,175 @@
+use std::fmt;
+use std::fmt::Formatter;
+use std::str::FromStr;
+
+use hmac::{Hmac, Mac, NewMac};
+use rand::Rng;
+use serde_json::{json, Value};
+use sha2::Sha256;
+use uuid::Uuid;
+
+use crate::schema::database::Database;
+use crate::schema::tables::{current_time, User, WebhookDeliveries, Webhooks};
+
+pub mod delivery;
+
+/// The header with the name of the event.
+pub const EVENT_HEADER: &str = "X-ForumRS-Event";
+/// The header with the id of the delivery. The id stays the same when a delivery is retried.
+pub const DELIVERY_HEADER: &str = "X-ForumRS-Delivery";
+/// The header with the HMAC-SHA256 signature of the body, formatted as `sha256=<hex>`.
+pub const SIGNATURE_HEADER: &str = "X-ForumRS-Signature";
+
+/// The events webhooks can subscribe to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WebhookEvent {
+    ThreadCreated,
+    PostCreated,
+    UserRegistered,
+    UserBanned,
+}
+
+impl WebhookEvent {
+    /// Every event.
+    pub const ALL: [WebhookEvent; 4] = [WebhookEvent::ThreadCreated, WebhookEvent::PostCreated, WebhookEvent::UserRegistered, WebhookEvent::UserBanned];
+
+    /// The name of the event used in payloads, forms and the database.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            WebhookEvent::ThreadCreated => "thread.created",
+            WebhookEvent::PostCreated => "post.created",
+            WebhookEvent::UserRegistered => "user.registered",
+            WebhookEvent::UserBanned => "user.banned"
+        }
+    }
+
+    /// A description of the event for the admin pages.
+    pub fn description(&self) -> &'static str {
+        match self {
+            WebhookEvent::ThreadCreated => "A thread was created.",
+            WebhookEvent::PostCreated => "A reply was posted to a thread.",
+            WebhookEvent::UserRegistered => "A user created an account.",
+            WebhookEvent::UserBanned => "A user was banned."
+        }
+    }
+}
+
+impl fmt::Display for WebhookEvent {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl FromStr for WebhookEvent {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        WebhookEvent::ALL.iter().copied().find(|event| event.as_str() == s)
+            .ok_or_else(|| format!("Unknown event: {}", s))
+    }
+}
+
+/// Generate a new random secret for signing payloads.
+pub fn generate_secret() -> String {
+    let bytes: [u8; 32] = rand::thread_rng().gen();
+    hex::encode(bytes)
+}
+
+/// Sign a payload with the secret of a webhook.
+/// Receivers should compute the same signature over the raw body and compare it to the signature header.
+pub fn sign(secret: &str, body: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).unwrap();
+    mac.update(body.as_bytes());
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+/// Build the `data` of a user event. Only what is public on the profile of the user is sent, never the email.
+pub fn user_data(user: &User) -> Value {
+    json!({
+        "uuid": user.uuid,
+        "username": user.username,
+        "date": user.date,
+        "is_banned": user.is_banned,
+    })
+}
+
+/**
+   Queue a delivery of an event to every enabled webhook subscribed to it.
+
+   The deliveries are sent by the delivery worker, so this does not wait on the receiving servers.
+   Errors are logged instead of returned so a webhook problem never fails the action that caused the event.
+
+   ## Params
+   data - The object describing what happened. This is the `data` field of the payload.
+*/
+pub async fn trigger(db: &mut Database, event: WebhookEvent, data: Value) {
+    let webhooks = match Webhooks::get_all(db).await {
+        Ok(webhooks) => webhooks,
+        Err(err) => {
+            println!("[WARN] Unable to load the webhooks for the {} event: {:?}", event, err);
+            return;
+        }
+    };
+
+    for webhook in webhooks.iter().filter(|webhook| webhook.enabled && webhook.subscribed_to(event.as_str())) {
+        let delivery_uuid = Uuid::new_v4();
+        let payload = json!({
+            "id": delivery_uuid,
+            "event": event.as_str(),
+            "created_at": current_time(),
+            "data": data,
+        });
+
+        if let Err(err) = WebhookDeliveries::insert(db, delivery_uuid, webhook.uuid, event.as_str(), payload.to_string()).await {
+            println!("[WARN] Unable to queue the {} event for the webhook {}: {:?}", event, webhook.url, err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sign_matches_a_known_hmac() {
+        // HMAC-SHA256 of "The quick brown fox jumps over the lazy dog" with the key "key".
+        assert_eq!(sign("key", "The quick brown fox jumps over the lazy dog"),
+                   "sha256=f7bc83f430538424b13298e6aa6fb143ef4d59a14946175997479dbc2d1a3cd8");
+    }
+
+    #[test]
+    fn sign_depends_on_the_secret_and_body() {
+        assert_ne!(sign("one", "{}"), sign("two", "{}"));
+        assert_ne!(sign("one", "{}"), sign("one", "[]"));
+    }
+
+    #[test]
+    fn events_round_trip_through_their_names() {
+        for event in WebhookEvent::ALL.iter() {
+            assert_eq!(WebhookEvent::from_str(event.as_str()), Ok(*event));
+        }
+        assert!(WebhookEvent::from_str("user.deleted").is_err());
+    }
+
+    #[test]
+    fn user_data_leaves_out_private_fields() {
+        let user = User {
+            id: 1,
+            uuid: Uuid::new_v4(),
+            username: "tester".to_string(),
+            email: "tester@example.com".to_string(),
+            password: "hash".to_string(),
+            is_banned: true,
+            is_admin: true,
+            is_moderator: false,
+            password_reset: false,
+            date: 10,
+        };
+        let data = user_data(&user);
+        assert_eq!(data["username"], "tester");
+        assert_eq!(data["is_banned"], true);
+        assert!(data.get("email").is_none());
+        assert!(data.get("is_admin").is_none());
+        assert!(data.get("password").is_none());
+    }
+}